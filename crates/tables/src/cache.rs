@@ -41,6 +41,44 @@ pub struct Cache<O, C> {
     origin: O,
     cache: C,
     last_origin_version: u64,
+    /// Counts the writes through the cache, see [`Cache::install`].
+    writes: u64,
+}
+
+/// Copy of the origin which is fetched apart from the cache, e.g. without
+/// holding the lock the cache is kept under.
+pub struct Detached<O> {
+    origin: O,
+    writes: u64,
+}
+
+/// Entries of a [`Detached`] origin, ready to be installed into the cache.
+pub struct Fetched<O, E> {
+    origin: O,
+    writes: u64,
+    version: u64,
+    entries: Vec<E>,
+}
+
+impl<O> Detached<O> {
+    pub async fn fetch<E, Err>(mut self) -> Result<Fetched<O, E>, Err>
+    where
+        for<'a> O: TableFetch<Entry<'a> = E, Error = Err>
+            + TableVersion<Error = Err>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let version = self.origin.version().await?;
+        let entries = self.origin.fetch().await?.into_iter().collect();
+
+        Ok(Fetched {
+            origin: self.origin,
+            writes: self.writes,
+            version,
+            entries,
+        })
+    }
 }
 
 impl<O, C> Cache<O, C> {
@@ -49,12 +87,50 @@ impl<O, C> Cache<O, C> {
             origin,
             cache,
             last_origin_version: next_version(),
+            writes: 0,
+        }
+    }
+
+    pub fn detach(&self) -> Detached<O>
+    where
+        O: Clone,
+    {
+        Detached {
+            origin: self.origin.clone(),
+            writes: self.writes,
+        }
+    }
+
+    /// Replaces the cached entries with the fetched ones. Returns false and keeps
+    /// the cache when it was written to after the detach, the fetch may miss that write.
+    pub async fn install<E, Err>(&mut self, fetched: Fetched<O, E>) -> Result<bool, Err>
+    where
+        E: Send + Sync,
+        C: TableExtend<E, Error = Err> + TableClear<Error = Err> + Send + Sync,
+    {
+        if fetched.writes != self.writes {
+            return Ok(false);
         }
+
+        self.cache.clear().await?;
+        self.cache.extend_owned(fetched.entries).await?;
+        self.origin = fetched.origin;
+        self.last_origin_version = fetched.version;
+
+        Ok(true)
     }
 
     pub fn mark_as_dirty(&mut self) {
         self.last_origin_version = next_version();
     }
+
+    pub fn origin(&self) -> &O {
+        &self.origin
+    }
+
+    pub fn origin_mut(&mut self) -> &mut O {
+        &mut self.origin
+    }
 }
 
 #[async_trait]
@@ -152,6 +228,7 @@ where
     {
         let entries = entries.into_iter().cloned().collect::<Vec<_>>();
 
+        self.writes += 1;
         try_cache!(self.cache.extend(&entries).await);
         Ok(try_origin!(self.origin.extend(&entries).await))
     }
@@ -178,11 +255,40 @@ where
     {
         let entries = entries.into_iter().cloned().collect::<Vec<_>>();
 
+        self.writes += 1;
         try_cache!(self.cache.update(from_row, &entries).await);
         Ok(try_origin!(self.origin.update(from_row, &entries).await))
     }
 }
 
+#[async_trait]
+impl<O, C> TableDelete for Cache<O, C>
+where
+    C: TableDelete + Send + Sync,
+    O: TableDelete + Send + Sync,
+{
+    type Error = Error<O::Error, C::Error>;
+
+    async fn delete(&mut self, rows: Vec<usize>) -> Result<(), Self::Error> {
+        self.writes += 1;
+        try_cache!(self.cache.delete(rows.clone()).await);
+        Ok(try_origin!(self.origin.delete(rows).await))
+    }
+}
+
+#[async_trait]
+impl<O, C> TableFlush for Cache<O, C>
+where
+    O: TableFlush + Send + Sync,
+    C: Send + Sync,
+{
+    type Error = Error<O::Error, Infallible>;
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.origin.flush().await.map_err(|e| Error::Origin(e))
+    }
+}
+
 #[async_trait]
 impl<O, C> TableVersion for Cache<O, C>
 where
@@ -250,4 +356,55 @@ mod test {
 
         assert_ne!(version, table.version().await.unwrap());
     }
+
+    #[tokio::test]
+    async fn delete() {
+        let clock_origin: InMemTable<_, ReadClone> = [0, 1, 2].into();
+        let clock_cache: InMemTable<usize> = [].into();
+
+        let mut table = Cache::new(clock_origin, clock_cache);
+        table.refresh().await.unwrap();
+
+        table.delete(vec![1]).await.unwrap();
+
+        let output: Vec<_> = table.read().unwrap().into_iter().cloned().collect();
+        assert_eq!(output, vec![0, 2]);
+
+        let origin: Vec<_> = table.origin.read().unwrap().collect();
+        assert_eq!(origin, vec![0, 2]);
+    }
+
+    #[tokio::test]
+    async fn install_detached_fetch() {
+        let clock_origin: InMemTable<_, ReadClone> = [0, 1, 2].into();
+        let clock_cache: InMemTable<usize> = [].into();
+
+        let mut table = Cache::new(clock_origin, clock_cache);
+        table.refresh().await.unwrap();
+        table.origin.extend(&[3]).await.unwrap();
+
+        let fetched = table.detach().fetch().await.unwrap();
+        assert!(table.install(fetched).await.unwrap());
+
+        let output: Vec<_> = table.read().unwrap().into_iter().cloned().collect();
+        assert_eq!(output, vec![0, 1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn install_skipped_after_write() {
+        let clock_origin: InMemTable<_, ReadClone> = [0, 1, 2].into();
+        let clock_cache: InMemTable<usize> = [].into();
+
+        let mut table = Cache::new(clock_origin, clock_cache);
+        table.refresh().await.unwrap();
+
+        let detached = table.detach();
+        table.update(0, &[5]).await.unwrap();
+
+        let fetched = detached.fetch().await.unwrap();
+        assert!(!table.install(fetched).await.unwrap());
+
+        let output: Vec<_> = table.read().unwrap().into_iter().cloned().collect();
+        assert_eq!(output, vec![5, 1, 2]);
+    }
 }
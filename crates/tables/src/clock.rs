@@ -4,6 +4,7 @@ use chrono::{DateTime, Duration, Utc};
 use log::info;
 use pretty_type_name::pretty_type_name;
 
+#[derive(Clone)]
 pub struct Clock<I> {
     inner: I,
     ttl: Duration,
@@ -20,6 +21,19 @@ impl<I> Clock<I> {
             last_cache_update: Utc::now(),
         }
     }
+
+    pub fn inner(&self) -> &I {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.inner
+    }
+
+    /// Time left until the version is checked again, the data can't change before that.
+    pub fn freshness(&self, now: DateTime<Utc>) -> Duration {
+        (self.ttl - (now - self.last_cache_update)).max(Duration::zero())
+    }
 }
 
 #[async_trait]
@@ -143,3 +157,21 @@ impl<I: TableClear + Send + 'static> TableClear for Clock<I> {
         self.inner.clear().await
     }
 }
+
+#[async_trait]
+impl<I: TableDelete + Send + 'static> TableDelete for Clock<I> {
+    type Error = I::Error;
+
+    async fn delete(&mut self, rows: Vec<usize>) -> Result<(), Self::Error> {
+        self.inner.delete(rows).await
+    }
+}
+
+#[async_trait]
+impl<I: TableFlush + Send + 'static> TableFlush for Clock<I> {
+    type Error = I::Error;
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush().await
+    }
+}
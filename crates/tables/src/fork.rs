@@ -12,6 +12,8 @@ macro_rules! fork {
             Extend(ErrorExtend),
             Update(ErrorUpdate),
             Clear(ErrorClear),
+            Delete(ErrorDelete),
+            Flush(ErrorFlush),
         }
 
         impl std::fmt::Display for Error {
@@ -21,6 +23,8 @@ macro_rules! fork {
                     Error::Extend(e) => write!(f, "extend error: {}", e),
                     Error::Update(e) => write!(f, "update error: {}", e),
                     Error::Clear(e) => write!(f, "clear error: {}", e),
+                    Error::Delete(e) => write!(f, "delete error: {}", e),
+                    Error::Flush(e) => write!(f, "flush error: {}", e),
                 }
             }
         }
@@ -91,6 +95,36 @@ macro_rules! fork {
 
         impl std::error::Error for ErrorClear { }
 
+        #[derive(std::fmt::Debug)]
+        pub enum ErrorDelete {
+            Origin(<$or_ty as TableDelete>::Error),
+        }
+
+        impl std::fmt::Display for ErrorDelete {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    ErrorDelete::Origin(e) => write!(f, "origin error: {}", e),
+                }
+            }
+        }
+
+        impl std::error::Error for ErrorDelete { }
+
+        #[derive(std::fmt::Debug)]
+        pub enum ErrorFlush {
+            Origin(<$or_ty as TableFlush>::Error),
+        }
+
+        impl std::fmt::Display for ErrorFlush {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    ErrorFlush::Origin(e) => write!(f, "origin error: {}", e),
+                }
+            }
+        }
+
+        impl std::error::Error for ErrorFlush { }
+
         pub struct $fork_name {
             pub $or_name: $or_ty,
             $(pub $sub_name: $sub_ty),+
@@ -119,8 +153,7 @@ macro_rules! fork {
 
                 log::debug!("Updating subscribers...");
                 let now = tokio::time::Instant::now();
-                $(self.$sub_name.clear().await.map_err(|e| Error::Clear(ErrorClear::$sub_name(e)))?;)+
-                $(self.$sub_name.extend(entries.clone()).await.map_err(|e| Error::Extend(ErrorExtend::$sub_name(e)))?;)+
+                $(self.$sub_name.rebuild(entries.clone()).await.map_err(|e| Error::Extend(ErrorExtend::$sub_name(e)))?;)+
                 log::debug!("Updated subscribers in {:?}", now.elapsed());
 
                 Ok(entries)
@@ -171,6 +204,33 @@ macro_rules! fork {
             }
         }
 
+        #[async_trait::async_trait]
+        impl TableDelete for $fork_name {
+            type Error = Error;
+
+            async fn delete(&mut self, rows: Vec<usize>) -> Result<(), Self::Error> {
+                log::debug!("Deleting from origin...");
+                let now = tokio::time::Instant::now();
+                self.$or_name.delete(rows).await.map_err(|e| Error::Delete(ErrorDelete::Origin(e)))?;
+                log::debug!("Deleted from origin in {:?}", now.elapsed());
+
+                // Rows are shifted after deletion, so subscribers have to be rebuilt
+                let _ = self.fetch().await?;
+
+                Ok(())
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl TableFlush for $fork_name {
+            type Error = Error;
+
+            async fn flush(&mut self) -> Result<(), Self::Error> {
+                // Subscribers are in memory, only the origin writes in the background
+                self.$or_name.flush().await.map_err(|e| Error::Flush(ErrorFlush::Origin(e)))
+            }
+        }
+
         }
         use $fork_mod::$fork_name;
     };
@@ -1,5 +1,7 @@
 pub mod range;
 pub mod serde_impl;
+pub mod sheet_ids;
+pub mod wal;
 
 use async_trait::async_trait;
 use google_sheets4::{
@@ -9,14 +11,29 @@ use google_sheets4::{
     Error as SheetsError, FieldMask, Sheets,
 };
 use lazy_static::lazy_static;
-use log::info;
+use log::{info, warn};
 use regex::Regex;
-use serde::{Deserialize, Serialize};
-use std::{error::Error as StdError, fmt::Display, str::FromStr, sync::Arc};
-use tokio::time::Instant;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    cmp::Ordering as CmpOrdering,
+    error::Error as StdError,
+    fmt::Display,
+    io,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::{
+    sync::oneshot,
+    task::{JoinError, JoinHandle},
+    time::Instant,
+};
 
 use self::range::SheetRange;
-use self::serde_impl::{Error as SerdeError, RowDeserializer, RowSerializer};
+use self::serde_impl::{Error as SerdeError, NumberFormat, RowDeserializer, RowSerializer};
+use self::wal::{Ack, Wal, WalOp};
 use crate::{next_version, prelude::*, TableVersion};
 
 lazy_static! {
@@ -29,8 +46,11 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     InvalidResponse,
     InvalidMeta,
+    ColumnOutOfRange(usize),
     Sheets(SheetsError),
     Serde(SerdeError),
+    Wal(io::Error),
+    Dispatch(JoinError),
 }
 
 impl Display for Error {
@@ -38,45 +58,175 @@ impl Display for Error {
         match self {
             Error::InvalidResponse => f.write_str("invalid response"),
             Error::InvalidMeta => f.write_str("invalid meta"),
+            Error::ColumnOutOfRange(column) => {
+                write!(f, "column {} is out of the data range", column)
+            }
             Error::Sheets(e) => f.write_str(&format!("sheets error: {}", e)),
             Error::Serde(e) => f.write_str(&format!("serde error: {}", e)),
+            Error::Wal(e) => f.write_str(&format!("wal error: {}", e)),
+            Error::Dispatch(e) => f.write_str(&format!("dispatch error: {}", e)),
         }
     }
 }
 
 impl StdError for Error {}
 
+impl Error {
+    /// Google answers 403 when the spreadsheet isn't shared with the service account.
+    pub fn is_permission_denied(&self) -> bool {
+        match self {
+            Error::Sheets(SheetsError::BadRequest(response)) => {
+                let error = &response["error"];
+                error["code"] == 403 || error["status"] == "PERMISSION_DENIED"
+            }
+            Error::Sheets(SheetsError::Failure(response)) => response.status() == 403,
+            _ => false,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Error::Wal(value)
+    }
+}
+
 #[derive(Default, Deserialize)]
 struct SheetArgsInput {
-    pub id: i32,
+    pub id: Option<i32>,
     pub data_range: SheetRange,
     pub format_range: Option<SheetRange>,
     pub meta_range: Option<SheetRange>,
+    pub ack_cell: Option<SheetRange>,
+    pub row_limit: Option<usize>,
+    #[serde(default)]
+    pub write_header: bool,
+    #[serde(default)]
+    pub validation: Validation,
+}
+
+/// What to do with fetched entries which break their invariants, see [`Validate`].
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Validation {
+    #[default]
+    Off,
+    Warn,
+    Repair,
 }
 
 #[derive(Deserialize, Default, Clone)]
 #[serde(from = "SheetArgsInput")]
 pub struct SheetArgs {
     pub id: i32,
+    /// The id was left out of the config and is taken from the tab name, see [`sheet_ids`].
+    pub auto_id: bool,
     pub data_range: SheetRange,
     pub format_range: SheetRange,
     pub meta_range: Option<SheetRange>,
+    /// Cell on the data's tab, above the data range, which every logged write also sets
+    /// to its id. Without it the writes pending after a crash can't be told apart from
+    /// the ones that landed, so they are dropped instead of replayed, see [`wal::replay`].
+    pub ack_cell: Option<SheetRange>,
+    /// Row count after which every write warns that the sheet should be archived.
+    pub row_limit: Option<usize>,
+    /// Write the field names just above the data range on the first extend into an empty sheet.
+    pub write_header: bool,
+    /// Only applies to sheets built [`Sheet::with_validation`].
+    pub validation: Validation,
 }
 
 impl From<SheetArgsInput> for SheetArgs {
     fn from(value: SheetArgsInput) -> Self {
         Self {
-            id: value.id,
+            id: value.id.unwrap_or_default(),
+            auto_id: value.id.is_none(),
             format_range: value.format_range.unwrap_or(value.data_range.clone()),
             data_range: value.data_range,
             meta_range: value.meta_range,
+            ack_cell: value.ack_cell,
+            row_limit: value.row_limit,
+            write_header: value.write_header,
+            validation: value.validation,
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// Cells of the meta range. Only the hash is required, the sheet's version is
+/// detected by it, the rest are optional diagnostics in the cells after it.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
 pub struct MetaEntry {
+    #[serde(deserialize_with = "deserialize_hash")]
     pub hash: String,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
+    pub updated_at: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
+    pub row_count: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
+    pub schema_version: Option<u32>,
+}
+
+/// Sheets may render a numeric-looking hash as a number, so both are
+/// accepted and normalized to the same string.
+fn deserialize_hash<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct HashVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for HashVisitor {
+        type Value = String;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a string or a number")
+        }
+
+        fn visit_str<E>(self, v: &str) -> std::result::Result<String, E> {
+            Ok(v.trim().to_owned())
+        }
+
+        fn visit_u64<E>(self, v: u64) -> std::result::Result<String, E> {
+            Ok(v.to_string())
+        }
+
+        fn visit_i64<E>(self, v: i64) -> std::result::Result<String, E> {
+            Ok(v.to_string())
+        }
+
+        fn visit_f64<E>(self, v: f64) -> std::result::Result<String, E> {
+            if v.fract() == 0.0 && v.abs() < u64::MAX as f64 {
+                Ok(format!("{:.0}", v))
+            } else {
+                Ok(v.to_string())
+            }
+        }
+    }
+
+    deserializer.deserialize_any(HashVisitor)
+}
+
+/// The diagnostics are written by hand, so a cell which doesn't parse, e.g.
+/// "n/a", reads as missing instead of failing the version check.
+fn deserialize_lenient<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: std::str::FromStr,
+{
+    Ok(deserialize_hash(deserializer)
+        .ok()
+        .filter(|text| !text.is_empty())
+        .and_then(|text| text.parse().ok()))
+}
+
+/// A single-cell meta range holds the hash only.
+fn meta_entry(row: Option<&[serde_json::Value]>) -> Result<MetaEntry> {
+    match row {
+        Some(row) => {
+            let mut deserializer = RowDeserializer::new(row);
+            MetaEntry::deserialize(&mut deserializer).map_err(|e| Error::Serde(e))
+        }
+        None => Ok(MetaEntry::default()),
+    }
 }
 
 #[derive(Clone)]
@@ -85,10 +235,281 @@ pub struct Sheet<E> {
     spreadsheet_id: String,
     args: SheetArgs,
     version: u64,
-    version_hash: String,
+    meta: MetaEntry,
+    row_count: Arc<AtomicUsize>,
+    wal: Option<Wal>,
+    /// Background writes which may still be in flight, see [`TableFlush::flush`].
+    /// Each caller keeps its own, a remade sheet doesn't collect the writes of the source.
+    in_flight: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    /// Errors of the writes which ended before a flush, kept until one collects them.
+    failed: Arc<Mutex<Vec<Error>>>,
+    /// Closes once the last dispatched write is over, the next one waits for it.
+    last_write: Arc<Mutex<Option<oneshot::Receiver<()>>>>,
+    validator: Option<fn(&mut E, bool) -> Vec<String>>,
+    /// Read from the spreadsheet's locale on the first fetch.
+    number_format: Option<NumberFormat>,
     _marker: std::marker::PhantomData<E>,
 }
 
+/// Checks the row count against the limit, warning when it's exceeded.
+fn check_row_limit(range: &SheetRange, rows: usize, limit: Option<usize>) -> bool {
+    match limit {
+        Some(limit) if rows > limit => {
+            warn!(
+                "Sheet {} has {} rows, exceeding the limit of {}. Consider archiving it.",
+                range.to_string(),
+                rows,
+                limit
+            );
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Row the next entries are appended at, never above the data range
+/// so a malformed or overlapping table range can't overwrite the header.
+fn last_available_row(data_range: &SheetRange, table_range: Option<&str>) -> Result<usize> {
+    let Some(table_range) = table_range else {
+        return Ok(data_range.r_start);
+    };
+
+    let row = RANGE_LASTROW_REGEX
+        .captures(table_range)
+        .and_then(|captures| {
+            captures
+                .get(1)
+                .and_then(|m| m.as_str().parse::<usize>().ok())
+        })
+        .ok_or(Error::InvalidResponse)?;
+
+    if row < data_range.r_start {
+        warn!(
+            "Table range {} ends above the data range {}, appending at its start",
+            table_range,
+            data_range.to_string()
+        );
+        return Ok(data_range.r_start);
+    }
+
+    Ok(row)
+}
+
+/// Checks the fetched entries according to the mode, returns the number of broken ones.
+fn validate_entries<E>(
+    range: &SheetRange,
+    entries: &mut [E],
+    validator: fn(&mut E, bool) -> Vec<String>,
+    mode: Validation,
+) -> usize {
+    if mode == Validation::Off {
+        return 0;
+    }
+
+    let repair = mode == Validation::Repair;
+    let mut broken = 0;
+    for (row, entry) in entries.iter_mut().enumerate() {
+        let issues = validator(entry, repair);
+        if issues.is_empty() {
+            continue;
+        }
+
+        broken += 1;
+        warn!(
+            "Entry {} of {} is inconsistent ({}), {}",
+            row,
+            range.to_string(),
+            issues.join(", "),
+            if repair { "repaired" } else { "left as is" }
+        );
+    }
+
+    broken
+}
+
+/// Pads a short row with empty cells and truncates a long one to `width`,
+/// returning how the original length compared to it.
+fn normalize_row(row: &mut Vec<serde_json::Value>, width: usize) -> CmpOrdering {
+    let ordering = row.len().cmp(&width);
+    row.resize(width, serde_json::Value::String(String::new()));
+    ordering
+}
+
+/// Range of a single cell, `row` and `column` are relative to the data range.
+fn cell_range(data_range: &SheetRange, row: usize, column: usize) -> Result<SheetRange> {
+    let column = data_range.c_start + column;
+    if column >= data_range.c_end {
+        return Err(Error::ColumnOutOfRange(column - data_range.c_start));
+    }
+
+    let row = data_range.r_start + row;
+    Ok(data_range
+        .with_rows(row, row + 1)
+        .with_cols(column, column + 1))
+}
+
+/// Cell value parsed the way it would be if typed into the sheet.
+fn user_entered_value(value: &str) -> sheets4::ExtendedValue {
+    match value {
+        "TRUE" => sheets4::ExtendedValue {
+            bool_value: Some(true),
+            ..Default::default()
+        },
+        "FALSE" => sheets4::ExtendedValue {
+            bool_value: Some(false),
+            ..Default::default()
+        },
+        value => match value.parse::<f64>() {
+            Ok(number) => sheets4::ExtendedValue {
+                number_value: Some(number),
+                ..Default::default()
+            },
+            Err(_) => sheets4::ExtendedValue {
+                string_value: Some(value.to_owned()),
+                ..Default::default()
+            },
+        },
+    }
+}
+
+/// Writes the header into the row above the data range, if the sheet asks for it
+/// and the data range is still empty. There is no room for it above the first row.
+fn header_request(
+    args: &SheetArgs,
+    row_from: usize,
+    header_present: bool,
+    header: RowData,
+) -> Option<sheets4::Request> {
+    let r_start = args.data_range.r_start;
+    if !args.write_header || header_present || row_from != r_start || r_start == 0 {
+        return None;
+    }
+
+    let width = header.values.as_ref().map_or(0, |values| values.len());
+    let c_start = args.data_range.c_start;
+
+    Some(sheets4::Request {
+        update_cells: Some(sheets4::UpdateCellsRequest {
+            fields: Some(FieldMask::from_str("userEnteredValue").unwrap()),
+            range: Some(
+                args.data_range
+                    .with_rows(r_start - 1, r_start)
+                    .with_cols(c_start, c_start + width)
+                    .as_grid_range(args.id),
+            ),
+            rows: Some(vec![header]),
+            start: None,
+        }),
+        ..Default::default()
+    })
+}
+
+/// Applies the number formats the serializer attached to the cells, a column
+/// at a time, so the formats of the other cells in the sheet are kept.
+fn number_format_requests(
+    args: &SheetArgs,
+    from_row: usize,
+    rows: &[RowData],
+) -> Vec<sheets4::Request> {
+    let Some(first) = rows.first().and_then(|row| row.values.as_ref()) else {
+        return vec![];
+    };
+
+    let c_start = args.data_range.c_start;
+    first
+        .iter()
+        .enumerate()
+        .filter(|(_, cell)| cell.user_entered_format.is_some())
+        .map(|(column, _)| sheets4::Request {
+            update_cells: Some(sheets4::UpdateCellsRequest {
+                fields: Some(FieldMask::from_str("userEnteredFormat.numberFormat").unwrap()),
+                range: Some(
+                    args.data_range
+                        .with_rows(from_row, from_row + rows.len())
+                        .with_cols(c_start + column, c_start + column + 1)
+                        .as_grid_range(args.id),
+                ),
+                rows: Some(
+                    rows.iter()
+                        .map(|row| RowData {
+                            values: Some(vec![sheets4::CellData {
+                                user_entered_format: row
+                                    .values
+                                    .as_ref()
+                                    .and_then(|values| values.get(column))
+                                    .and_then(|cell| cell.user_entered_format.clone()),
+                                ..Default::default()
+                            }]),
+                        })
+                        .collect(),
+                ),
+                start: None,
+            }),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn cell_update_request(
+    args: &SheetArgs,
+    row: usize,
+    column: usize,
+    value: &str,
+) -> Result<sheets4::Request> {
+    Ok(sheets4::Request {
+        update_cells: Some(sheets4::UpdateCellsRequest {
+            fields: Some(FieldMask::from_str("userEnteredValue").unwrap()),
+            range: Some(cell_range(&args.data_range, row, column)?.as_grid_range(args.id)),
+            rows: Some(vec![RowData {
+                values: Some(vec![sheets4::CellData {
+                    user_entered_value: Some(user_entered_value(value)),
+                    ..Default::default()
+                }]),
+            }]),
+            start: None,
+        }),
+        ..Default::default()
+    })
+}
+
+/// Records the id of the write in the ack cell, in the same batch so they land together.
+fn ack_request(args: &SheetArgs, id: u64) -> Option<sheets4::Request> {
+    let cell = args.ack_cell.as_ref()?;
+
+    Some(sheets4::Request {
+        update_cells: Some(sheets4::UpdateCellsRequest {
+            fields: Some(FieldMask::from_str("userEnteredValue").unwrap()),
+            range: Some(cell.as_grid_range(args.id)),
+            rows: Some(vec![RowData {
+                values: Some(vec![sheets4::CellData {
+                    // A string, so the id isn't formatted like a number when read back
+                    user_entered_value: Some(sheets4::ExtendedValue {
+                        string_value: Some(id.to_string()),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }]),
+            }]),
+            start: None,
+        }),
+        ..Default::default()
+    })
+}
+
+/// Reads the ack cell as fetched, an empty one means no logged write landed yet.
+fn parse_ack(cell: Option<&serde_json::Value>) -> Result<Ack> {
+    match cell {
+        None => Ok(Ack::Nothing),
+        Some(serde_json::Value::String(s)) if s.trim().is_empty() => Ok(Ack::Nothing),
+        Some(serde_json::Value::String(s)) => s
+            .trim()
+            .parse()
+            .map(Ack::Upto)
+            .map_err(|_| Error::InvalidMeta),
+        Some(_) => Err(Error::InvalidMeta),
+    }
+}
+
 impl<E> Sheet<E> {
     pub fn new(
         hub: Arc<Sheets<HttpsConnector<HttpConnector>>>,
@@ -100,23 +521,124 @@ impl<E> Sheet<E> {
             spreadsheet_id,
             args,
             version: 0,
-            version_hash: "".to_owned(),
+            meta: MetaEntry::default(),
+            row_count: Arc::new(AtomicUsize::new(0)),
+            wal: None,
+            in_flight: Arc::new(Mutex::new(vec![])),
+            failed: Arc::new(Mutex::new(vec![])),
+            last_write: Arc::new(Mutex::new(None)),
+            validator: None,
+            number_format: None,
             _marker: std::marker::PhantomData,
         }
     }
 
-    pub fn remake<T>(self) -> Sheet<T> {
+    /// Records every write in the log before it's dispatched, see [`Sheet::replay_wal`].
+    pub fn with_wal(mut self, wal: Option<Wal>) -> Self {
+        self.wal = wal;
+        self
+    }
+
+    /// Checks the entries on fetch as configured by [`SheetArgs::validation`].
+    pub fn with_validation(mut self) -> Self
+    where
+        E: Validate,
+    {
+        self.validator = Some(E::validate);
+        self
+    }
+
+    /// The same sheet read as other entries, validated if this one is.
+    pub fn remake<T: Validate>(self) -> Sheet<T> {
         Sheet {
             hub: self.hub,
             spreadsheet_id: self.spreadsheet_id,
             args: self.args,
             version: self.version,
-            version_hash: self.version_hash,
+            meta: self.meta,
+            row_count: self.row_count,
+            wal: self.wal,
+            in_flight: Arc::new(Mutex::new(vec![])),
+            failed: Arc::new(Mutex::new(vec![])),
+            last_write: self.last_write,
+            validator: self
+                .validator
+                .map(|_| T::validate as fn(&mut T, bool) -> Vec<String>),
+            number_format: self.number_format,
             _marker: std::marker::PhantomData,
         }
     }
 
-    async fn update_cells(&mut self, requests: Vec<sheets4::Request>) -> Result<()> {
+    /// Number separators of the spreadsheet's locale, the values are formatted with them.
+    pub async fn fetch_number_format(&self) -> Result<NumberFormat> {
+        let (_, spreadsheet) = self
+            .hub
+            .spreadsheets()
+            .get(&self.spreadsheet_id)
+            .param("fields", "properties.locale")
+            .doit()
+            .await
+            .map_err(|e| Error::Sheets(e))?;
+
+        Ok(spreadsheet
+            .properties
+            .and_then(|properties| properties.locale)
+            .map(|locale| NumberFormat::from_locale(&locale))
+            .unwrap_or_default())
+    }
+
+    /// Cells of a single row as returned by the Sheets API, `row` is relative to the data range.
+    pub async fn fetch_raw_row(&self, row: usize) -> Result<Vec<serde_json::Value>> {
+        let row = self.args.data_range.r_start + row;
+        let range = self.args.data_range.with_rows(row, row + 1);
+
+        let values = self
+            .hub
+            .spreadsheets()
+            .values_get(&self.spreadsheet_id, &range.to_string())
+            .doit()
+            .await
+            .map_err(|e| Error::Sheets(e))?
+            .1
+            .values;
+
+        Ok(values
+            .and_then(|rows| rows.into_iter().next())
+            .unwrap_or_default())
+    }
+
+    /// Key of the sheet's writes in the log.
+    fn wal_key(&self) -> String {
+        format!(
+            "{}/{}",
+            self.spreadsheet_id,
+            self.args.data_range.to_string()
+        )
+    }
+
+    /// Meta cells as of the last version check, empty without a meta range.
+    pub fn meta(&self) -> &MetaEntry {
+        &self.meta
+    }
+
+    /// Last known number of rows in the sheet, including the ones above the data range.
+    pub fn row_count(&self) -> usize {
+        self.row_count.load(Ordering::Relaxed)
+    }
+
+    fn track_row_count(&self, rows: usize) -> bool {
+        self.row_count.store(rows, Ordering::Relaxed);
+        check_row_limit(&self.args.data_range, rows, self.args.row_limit)
+    }
+
+    /// Sends the requests in a single batch, along with the ack of the write `id` if it's logged.
+    async fn update_cells(
+        &mut self,
+        mut requests: Vec<sheets4::Request>,
+        id: Option<u64>,
+    ) -> Result<()> {
+        requests.extend(id.and_then(|id| ack_request(&self.args, id)));
+
         let request = sheets4::BatchUpdateSpreadsheetRequest {
             include_spreadsheet_in_response: Some(false),
             requests: Some(requests),
@@ -151,18 +673,51 @@ impl<E> Sheet<E> {
             .map_err(|e| Error::Sheets(e))?
             .1;
 
-        if let Some(range) = response.table_range {
-            return RANGE_LASTROW_REGEX
-                .captures(&range)
-                .and_then(|captures| {
-                    captures
-                        .get(1)
-                        .and_then(|m| m.as_str().parse::<usize>().ok())
-                })
-                .ok_or(Error::InvalidResponse);
-        } else {
-            return Ok(self.args.data_range.r_start);
+        last_available_row(&self.args.data_range, response.table_range.as_deref())
+    }
+
+    /// Whether the row above the data range has anything in it.
+    async fn fetch_header_present(&self) -> Result<bool> {
+        let r_start = self.args.data_range.r_start;
+        if r_start == 0 {
+            return Ok(false);
         }
+
+        let range = self.args.data_range.with_rows(r_start - 1, r_start);
+        let values = self
+            .hub
+            .spreadsheets()
+            .values_get(&self.spreadsheet_id, &range.to_string())
+            .doit()
+            .await
+            .map_err(|e| Error::Sheets(e))?
+            .1
+            .values;
+
+        Ok(values.map_or(false, |rows| {
+            rows.iter()
+                .flatten()
+                .any(|cell| !matches!(cell, serde_json::Value::String(s) if s.is_empty()))
+        }))
+    }
+
+    /// Last logged write the sheet shows, see [`SheetArgs::ack_cell`].
+    async fn fetch_ack(&self) -> Result<Ack> {
+        let Some(ref cell) = self.args.ack_cell else {
+            return Ok(Ack::Unknown);
+        };
+
+        let values = self
+            .hub
+            .spreadsheets()
+            .values_get(&self.spreadsheet_id, &cell.to_string())
+            .doit()
+            .await
+            .map_err(|e| Error::Sheets(e))?
+            .1
+            .values;
+
+        parse_ack(values.as_ref().and_then(|rows| rows.first()?.first()))
     }
 
     async fn fetch_version(&mut self) -> Result<()> {
@@ -174,7 +729,7 @@ impl<E> Sheet<E> {
             }
         };
 
-        let mut values = self
+        let values = self
             .hub
             .spreadsheets()
             .values_get(&self.spreadsheet_id, &range.to_string())
@@ -184,29 +739,48 @@ impl<E> Sheet<E> {
             .1
             .values;
 
-        let meta_hash = match values {
-            Some(ref mut row) => {
-                let mut deserializer = RowDeserializer::new(&mut row[0]);
-                let meta =
-                    MetaEntry::deserialize(&mut deserializer).map_err(|e| Error::Serde(e))?;
-                meta.hash
-            }
-            None => "".to_owned(),
-        };
+        let meta = meta_entry(
+            values
+                .as_ref()
+                .and_then(|rows| rows.first())
+                .map(|row| row.as_slice()),
+        )?;
 
-        if meta_hash != self.version_hash {
+        if meta.hash != self.meta.hash {
             self.version = next_version();
-            self.version_hash = meta_hash;
         }
+        self.meta = meta;
 
         Ok(())
     }
 }
 
 impl<E: Serialize> Sheet<E> {
-    async fn extend_impl(mut self, entries: Vec<E>) -> Result<()> {
+    /// Sends the write, `id` is the one it's logged under, if any.
+    async fn apply(self, id: Option<u64>, op: WalOp<E>) -> Result<()> {
+        match op {
+            WalOp::Extend(entries) => self.extend_impl(id, entries).await,
+            WalOp::Update(from_row, entries) => self.update_impl(id, from_row, entries).await,
+            WalOp::Delete(rows) => self.delete_impl(id, rows).await,
+            WalOp::Cell(row, column, value) => self.cell_impl(id, row, column, value).await,
+        }
+    }
+
+    async fn cell_impl(
+        mut self,
+        id: Option<u64>,
+        row: usize,
+        column: usize,
+        value: String,
+    ) -> Result<()> {
+        let request = cell_update_request(&self.args, row, column, &value)?;
+        self.update_cells(vec![request], id).await
+    }
+
+    async fn extend_impl(mut self, id: Option<u64>, entries: Vec<E>) -> Result<()> {
         let row_from = self.fetch_last_available_row().await?;
 
+        let mut header = None;
         let row_data = entries
             .into_iter()
             .map(|entry| {
@@ -214,11 +788,21 @@ impl<E: Serialize> Sheet<E> {
                 entry
                     .serialize(&mut serializer)
                     .map_err(|e| Error::Serde(e))?;
+                header.get_or_insert_with(|| serializer.header_row());
                 Ok(serializer.into())
             })
             .collect::<Result<Vec<_>>>()?;
 
+        let write_header = match header {
+            Some(header) if self.args.write_header && row_from == self.args.data_range.r_start => {
+                let header_present = self.fetch_header_present().await?;
+                header_request(&self.args, row_from, header_present, header)
+            }
+            _ => None,
+        };
+
         let row_to = row_from + row_data.len();
+        self.track_row_count(row_to);
 
         let data_range = self
             .args
@@ -271,6 +855,9 @@ impl<E: Serialize> Sheet<E> {
             ..Default::default()
         };
 
+        // The pasted formats are overridden by the ones of the fields
+        let number_formats = number_format_requests(&self.args, row_from, &row_data);
+
         let update_cells = sheets4::Request {
             update_cells: Some(sheets4::UpdateCellsRequest {
                 fields: Some(FieldMask::from_str("userEnteredValue").unwrap()),
@@ -281,18 +868,24 @@ impl<E: Serialize> Sheet<E> {
             ..Default::default()
         };
 
-        self.update_cells(vec![
-            insert_dimension,
-            paste_normal,
-            update_cells,
-            paste_data_validation,
-        ])
-        .await?;
+        let requests = write_header
+            .into_iter()
+            .chain([insert_dimension, paste_normal, update_cells])
+            .chain(number_formats)
+            .chain([paste_data_validation])
+            .collect();
+
+        self.update_cells(requests, id).await?;
 
         Ok(())
     }
 
-    async fn update_impl(mut self, mut from_row: usize, entries: Vec<E>) -> Result<()> {
+    async fn update_impl(
+        mut self,
+        id: Option<u64>,
+        mut from_row: usize,
+        entries: Vec<E>,
+    ) -> Result<()> {
         from_row += self.args.data_range.r_start;
 
         let rows = entries
@@ -306,6 +899,8 @@ impl<E: Serialize> Sheet<E> {
             })
             .collect::<Result<Vec<RowData>>>()?;
 
+        let number_formats = number_format_requests(&self.args, from_row, &rows);
+
         let request = sheets4::Request {
             update_cells: Some(sheets4::UpdateCellsRequest {
                 fields: Some(FieldMask::from_str("userEnteredValue").unwrap()),
@@ -321,7 +916,113 @@ impl<E: Serialize> Sheet<E> {
             ..Default::default()
         };
 
-        self.update_cells(vec![request]).await
+        self.update_cells([request].into_iter().chain(number_formats).collect(), id)
+            .await
+    }
+
+    async fn delete_impl(mut self, id: Option<u64>, mut rows: Vec<usize>) -> Result<()> {
+        rows.sort_unstable();
+        rows.dedup();
+
+        // Delete from the bottom, so the indices of the remaining rows stay valid
+        let requests = rows
+            .into_iter()
+            .rev()
+            .map(|row| {
+                let row = (row + self.args.data_range.r_start) as i32;
+                sheets4::Request {
+                    delete_dimension: Some(sheets4::DeleteDimensionRequest {
+                        range: Some(sheets4::DimensionRange {
+                            dimension: Some("ROWS".to_owned()),
+                            end_index: Some(row + 1),
+                            sheet_id: Some(self.args.id),
+                            start_index: Some(row),
+                        }),
+                    }),
+                    ..Default::default()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if requests.is_empty() {
+            return Ok(());
+        }
+
+        self.update_cells(requests, id).await
+    }
+}
+
+impl<E: Serialize + Send + Sync + Clone + 'static> Sheet<E> {
+    /// Logs the write ahead, if the sheet has a log, and dispatches it in the background.
+    /// The writes of a sheet land one at a time in the order they were dispatched,
+    /// the row indices of each one only hold after the ones before it.
+    async fn dispatch(&self, op: WalOp<E>) -> Result<()> {
+        let id = match &self.wal {
+            Some(wal) => Some(wal.begin(&self.wal_key(), &op).await?),
+            None => None,
+        };
+
+        let (over, this_write) = oneshot::channel::<()>();
+        let last_write = self.last_write.lock().unwrap().replace(this_write);
+
+        let sheet = self.clone();
+        let failed = self.failed.clone();
+        let task = tokio::task::spawn(async move {
+            if let Some(last_write) = last_write {
+                // Closed whichever way the last write ended
+                let _ = last_write.await;
+            }
+
+            let wal = sheet.wal.clone();
+            let landed = sheet.apply(id, op).await;
+            drop(over);
+
+            let result = match (landed, wal, id) {
+                (Ok(()), Some(wal), Some(id)) => wal.complete(id).await.map_err(Error::from),
+                (landed, _, _) => landed,
+            };
+            if let Err(e) = result {
+                failed.lock().unwrap().push(e);
+            }
+        });
+
+        // Finished writes left their errors behind, so their handles can go
+        let mut in_flight = self.in_flight.lock().unwrap();
+        in_flight.retain(|task| !task.is_finished());
+        in_flight.push(task);
+
+        Ok(())
+    }
+
+    /// Overwrites a single cell and waits for the write to land.
+    pub async fn write_cell(&mut self, row: usize, column: usize, value: &str) -> Result<()> {
+        // A column out of the data range is rejected before it's logged
+        cell_range(&self.args.data_range, row, column)?;
+
+        self.dispatch(WalOp::Cell(row, column, value.to_owned()))
+            .await?;
+        self.flush().await
+    }
+}
+
+impl<E: Serialize + DeserializeOwned + Send + Sync + Clone + 'static> Sheet<E> {
+    /// Re-issues the writes which were logged but never landed, e.g. because of a crash.
+    pub async fn replay_wal(&mut self) -> Result<usize> {
+        let Some(journal) = self.wal.clone() else {
+            return Ok(0);
+        };
+
+        let key = self.wal_key();
+        let ack = self.fetch_ack().await?;
+        let count = wal::replay(&journal, &key, ack, |id, op| {
+            self.clone().apply(Some(id), op)
+        })
+        .await?;
+        if count > 0 {
+            info!("Replayed {} unfinished writes to {}", count, key);
+        }
+
+        Ok(count)
     }
 }
 
@@ -332,6 +1033,15 @@ impl<'de, E: Deserialize<'de> + Send + Sync> TableFetch for Sheet<E> {
     type Ok<'a> = Vec<E> where Self: 'a, E: 'a;
 
     async fn fetch(&mut self) -> Result<Self::Ok<'_>> {
+        let number_format = match self.number_format {
+            Some(number_format) => number_format,
+            None => {
+                let number_format = self.fetch_number_format().await?;
+                info!("Parsing numbers as {:?}", number_format);
+                *self.number_format.insert(number_format)
+            }
+        };
+
         info!("Fetching sheet data...");
         let now = Instant::now();
         let range = self
@@ -351,10 +1061,23 @@ impl<'de, E: Deserialize<'de> + Send + Sync> TableFetch for Sheet<E> {
         info!("Deserializing sheet data...");
         let now = Instant::now();
         if let Some(values) = range.values {
-            let result = values
+            self.track_row_count(self.args.data_range.r_start + values.len());
+
+            let width = self.args.data_range.c_end - self.args.data_range.c_start;
+            let (mut padded, mut truncated) = (0, 0);
+
+            let mut result = values
                 .into_iter()
                 .map(|mut data| {
-                    let mut deserializer = RowDeserializer::new(&mut data);
+                    // Merged cells and columns outside of the struct would misalign the fields
+                    match normalize_row(&mut data, width) {
+                        CmpOrdering::Less => padded += 1,
+                        CmpOrdering::Greater => truncated += 1,
+                        CmpOrdering::Equal => (),
+                    }
+
+                    let mut deserializer =
+                        RowDeserializer::new(&mut data).with_number_format(number_format);
                     match E::deserialize(&mut deserializer) {
                         Ok(entry) => Some(entry),
                         Err(_) => None,
@@ -363,6 +1086,27 @@ impl<'de, E: Deserialize<'de> + Send + Sync> TableFetch for Sheet<E> {
                 .filter_map(|e| e)
                 .collect::<Vec<_>>();
 
+            if let Some(validator) = self.validator {
+                validate_entries(
+                    &self.args.data_range,
+                    &mut result,
+                    validator,
+                    self.args.validation,
+                );
+            }
+
+            if padded > 0 {
+                info!("Padded {} rows to the width of {} columns", padded, width);
+            }
+            if truncated > 0 {
+                warn!(
+                    "Truncated {} rows of {} to the width of {} columns",
+                    truncated,
+                    self.args.data_range.to_string(),
+                    width
+                );
+            }
+
             info!("Sheet data deserialized in {:?}", now.elapsed());
             Ok(result)
         } else {
@@ -383,9 +1127,7 @@ impl<E: Serialize + Send + Sync + Clone + 'static> TableExtend<E> for Sheet<E> {
         E: 'a,
     {
         let entries = entries.into_iter().cloned().collect();
-        tokio::task::spawn(self.clone().extend_impl(entries));
-
-        Ok(())
+        self.dispatch(WalOp::Extend(entries)).await
     }
 }
 
@@ -400,9 +1142,50 @@ impl<E: Serialize + Send + Sync + Clone + 'static> TableUpdate<E> for Sheet<E> {
         E: 'a,
     {
         let entries = entries.into_iter().cloned().collect();
-        tokio::task::spawn(self.clone().update_impl(from_row, entries));
+        self.dispatch(WalOp::Update(from_row, entries)).await
+    }
+}
 
-        Ok(())
+#[async_trait]
+impl<E: Serialize + Send + Sync + Clone + 'static> TableDelete for Sheet<E> {
+    type Error = Error;
+
+    async fn delete(&mut self, rows: Vec<usize>) -> Result<()> {
+        self.dispatch(WalOp::Delete(rows)).await
+    }
+}
+
+#[async_trait]
+impl<E: Send + Sync> TableFlush for Sheet<E> {
+    type Error = Error;
+
+    async fn flush(&mut self) -> Result<()> {
+        let tasks = std::mem::take(&mut *self.in_flight.lock().unwrap());
+
+        let mut errors = vec![];
+        for task in tasks {
+            if let Err(e) = task.await {
+                errors.push(Error::Dispatch(e));
+            }
+        }
+        errors.append(&mut self.failed.lock().unwrap());
+
+        let mut result = Ok(());
+        for e in errors {
+            warn!(
+                "Write to {} failed: {}",
+                self.args.data_range.to_string(),
+                e
+            );
+            result = result.and(Err(e));
+        }
+
+        // Only the writes still pending are kept, so the log doesn't grow for good
+        if let (Ok(()), Some(wal)) = (&result, &self.wal) {
+            wal.compact().await?;
+        }
+
+        result
     }
 }
 
@@ -444,6 +1227,12 @@ mod tests {
         c: (),
     }
 
+    impl Validate for EmptyEntry {
+        fn validate(&mut self, _: bool) -> Vec<String> {
+            vec![]
+        }
+    }
+
     #[tokio::test]
     async fn fetch() {
         let hub = Arc::new(build_hub().await);
@@ -451,7 +1240,7 @@ mod tests {
             hub.clone(),
             TEST_SPREADSHEET_ID.to_owned(),
             SheetArgsInput {
-                id: 1068945262,
+                id: Some(1068945262),
                 data_range: SheetRange::from_str("Read!A2:C").unwrap(),
                 meta_range: Some(SheetRange::from_str("Meta!B2").unwrap()),
                 ..Default::default()
@@ -477,7 +1266,7 @@ mod tests {
             hub.clone(),
             TEST_SPREADSHEET_ID.to_owned(),
             SheetArgsInput {
-                id: 697802184,
+                id: Some(697802184),
                 data_range: SheetRange::from_str("Update!A2:C").unwrap(),
                 meta_range: Some(SheetRange::from_str("Meta!B3").unwrap()),
                 ..Default::default()
@@ -540,7 +1329,7 @@ mod tests {
             hub.clone(),
             TEST_SPREADSHEET_ID.to_owned(),
             SheetArgsInput {
-                id: 3715267,
+                id: Some(3715267),
                 data_range: SheetRange::from_str("Extend!A2:C").unwrap(),
                 meta_range: Some(SheetRange::from_str("Meta!B4").unwrap()),
                 ..Default::default()
@@ -583,6 +1372,348 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn single_cell_update() {
+        let args = SheetArgs {
+            id: 7,
+            data_range: SheetRange::from_str("Sales!B3:E").unwrap(),
+            ..Default::default()
+        };
+
+        let request = cell_update_request(&args, 2, 1, "12.5").unwrap();
+        let update = request.update_cells.unwrap();
+        let range = update.range.unwrap();
+
+        assert_eq!(range.sheet_id, Some(7));
+        assert_eq!(range.start_row_index, Some(4));
+        assert_eq!(range.end_row_index, Some(5));
+        assert_eq!(range.start_column_index, Some(2));
+        assert_eq!(range.end_column_index, Some(3));
+
+        let cell = update.rows.unwrap()[0].values.as_ref().unwrap()[0].clone();
+        assert_eq!(cell.user_entered_value.unwrap().number_value, Some(12.5));
+
+        let text = user_entered_value("Hat");
+        assert_eq!(text.string_value.as_deref(), Some("Hat"));
+        assert_eq!(user_entered_value("TRUE").bool_value, Some(true));
+
+        assert!(matches!(
+            cell_update_request(&args, 0, 4, "x"),
+            Err(Error::ColumnOutOfRange(4))
+        ));
+    }
+
+    #[test]
+    fn ack_cell_round_trip() {
+        let args = SheetArgs {
+            id: 7,
+            data_range: SheetRange::from_str("Sales!B3:E").unwrap(),
+            ..Default::default()
+        };
+        assert!(ack_request(&args, 12).is_none());
+
+        let args = SheetArgs {
+            ack_cell: Some(SheetRange::from_str("Sales!G1").unwrap()),
+            ..args
+        };
+        let update = ack_request(&args, 12).unwrap().update_cells.unwrap();
+        let range = update.range.unwrap();
+        assert_eq!(range.sheet_id, Some(7));
+        assert_eq!(range.start_row_index, Some(0));
+        assert_eq!(range.start_column_index, Some(6));
+
+        let cell = update.rows.unwrap()[0].values.as_ref().unwrap()[0].clone();
+        let id = cell.user_entered_value.unwrap().string_value.unwrap();
+        let ack = |cell: serde_json::Value| parse_ack(Some(&cell)).ok();
+        assert_eq!(ack(serde_json::json!(id)), Some(Ack::Upto(12)));
+        assert_eq!(ack(serde_json::json!("")), Some(Ack::Nothing));
+        assert_eq!(ack(serde_json::json!("1,2")), None);
+        assert_eq!(parse_ack(None).ok(), Some(Ack::Nothing));
+    }
+
+    #[test]
+    fn header_only_on_first_extend() {
+        let args = SheetArgs {
+            id: 7,
+            data_range: SheetRange::from_str("Sales!B3:E").unwrap(),
+            write_header: true,
+            ..Default::default()
+        };
+
+        let mut serializer = RowSerializer::default();
+        TestEntry {
+            string: "Hat".to_owned(),
+            int: 1.0,
+            boolean: true,
+        }
+        .serialize(&mut serializer)
+        .unwrap();
+        let header = serializer.header_row();
+
+        // Empty sheet, the header goes right above the data range
+        let request = header_request(&args, 2, false, header.clone()).unwrap();
+        let update = request.update_cells.unwrap();
+        let range = update.range.unwrap();
+
+        assert_eq!(range.start_row_index, Some(1));
+        assert_eq!(range.end_row_index, Some(2));
+        assert_eq!(range.start_column_index, Some(1));
+        assert_eq!(range.end_column_index, Some(4));
+
+        let names: Vec<_> = update.rows.unwrap()[0]
+            .values
+            .clone()
+            .unwrap()
+            .into_iter()
+            .map(|cell| cell.user_entered_value.unwrap().string_value.unwrap())
+            .collect();
+        assert_eq!(names, vec!["string", "int", "boolean"]);
+
+        // Later extends, an existing header, or a sheet not asking for one
+        assert!(header_request(&args, 5, false, header.clone()).is_none());
+        assert!(header_request(&args, 2, true, header.clone()).is_none());
+        let args = SheetArgs {
+            write_header: false,
+            ..args
+        };
+        assert!(header_request(&args, 2, false, header).is_none());
+    }
+
+    #[test]
+    fn number_formats_only_for_annotated_columns() {
+        use serde_impl::Money;
+
+        #[derive(Serialize)]
+        struct PricedEntry {
+            string: String,
+            price: Money,
+            int: f64,
+        }
+
+        let args = SheetArgs {
+            id: 7,
+            data_range: SheetRange::from_str("Sales!B3:E").unwrap(),
+            ..Default::default()
+        };
+
+        let rows: Vec<RowData> = [1.5, 20.0]
+            .into_iter()
+            .map(|price| {
+                let mut serializer = RowSerializer::default();
+                PricedEntry {
+                    string: "Hat".to_owned(),
+                    price: Money(price),
+                    int: 1.0,
+                }
+                .serialize(&mut serializer)
+                .unwrap();
+                serializer.into()
+            })
+            .collect();
+
+        let requests = number_format_requests(&args, 4, &rows);
+        assert_eq!(requests.len(), 1);
+
+        let update = requests[0].update_cells.clone().unwrap();
+        assert_eq!(
+            update.fields,
+            Some(FieldMask::from_str("userEnteredFormat.numberFormat").unwrap())
+        );
+        let range = update.range.unwrap();
+        assert_eq!(
+            (range.start_row_index, range.end_row_index),
+            (Some(4), Some(6))
+        );
+        assert_eq!(
+            (range.start_column_index, range.end_column_index),
+            (Some(2), Some(3))
+        );
+        for row in update.rows.unwrap() {
+            let cell = &row.values.unwrap()[0];
+            assert!(cell.user_entered_value.is_none());
+            let format = cell.user_entered_format.clone().unwrap();
+            assert_eq!(
+                format.number_format.unwrap().pattern,
+                Some("#,##0.00".to_owned())
+            );
+        }
+
+        // Plain entries keep the formats of the sheet
+        let mut serializer = RowSerializer::default();
+        TestEntry {
+            string: "Hat".to_owned(),
+            int: 1.0,
+            boolean: true,
+        }
+        .serialize(&mut serializer)
+        .unwrap();
+        assert!(number_format_requests(&args, 4, &[serializer.into()]).is_empty());
+    }
+
+    #[test]
+    fn short_and_long_rows_normalized() {
+        use serde_json::json;
+
+        let mut short = vec![json!("A")];
+        let mut long = vec![
+            json!("B"),
+            json!("2"),
+            json!("TRUE"),
+            json!("x"),
+            json!("y"),
+        ];
+        let mut exact = vec![json!("C"), json!("3"), json!("FALSE")];
+
+        assert_eq!(normalize_row(&mut short, 3), CmpOrdering::Less);
+        assert_eq!(normalize_row(&mut long, 3), CmpOrdering::Greater);
+        assert_eq!(normalize_row(&mut exact, 3), CmpOrdering::Equal);
+
+        assert_eq!(short, vec![json!("A"), json!(""), json!("")]);
+        assert_eq!(long, vec![json!("B"), json!("2"), json!("TRUE")]);
+
+        let entry = TestEntry::deserialize(&mut RowDeserializer::new(&mut long)).unwrap();
+        assert_eq!(
+            entry,
+            TestEntry {
+                string: "B".to_owned(),
+                int: 2.0,
+                boolean: true
+            }
+        );
+    }
+
+    #[test]
+    fn row_limit_warning() {
+        let range = SheetRange::from_str("Sales!A2:C").unwrap();
+
+        assert!(!check_row_limit(&range, 1_000, None));
+        assert!(!check_row_limit(&range, 1_000, Some(1_000)));
+        assert!(check_row_limit(&range, 1_001, Some(1_000)));
+    }
+
+    #[test]
+    fn last_row_never_inside_header() {
+        let range = SheetRange::from_str("Sales!B3:E").unwrap();
+
+        assert_eq!(last_available_row(&range, None).unwrap(), 2);
+        assert_eq!(
+            last_available_row(&range, Some("Sales!B3:E10")).unwrap(),
+            10
+        );
+        // The header row itself, or a range overlapping it
+        assert_eq!(last_available_row(&range, Some("Sales!B1:E1")).unwrap(), 2);
+        assert_eq!(last_available_row(&range, Some("Sales!A1:C2")).unwrap(), 2);
+        assert!(matches!(
+            last_available_row(&range, Some("garbage")),
+            Err(Error::InvalidResponse)
+        ));
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Bounded(u32);
+
+    impl Validate for Bounded {
+        fn validate(&mut self, repair: bool) -> Vec<String> {
+            if self.0 <= 10 {
+                return vec![];
+            }
+            if repair {
+                self.0 = 10;
+            }
+            vec!["above 10".to_owned()]
+        }
+    }
+
+    #[test]
+    fn validation_modes() {
+        let range = SheetRange::from_str("Products!A2:C").unwrap();
+        let fetched = || vec![Bounded(5), Bounded(12), Bounded(30)];
+
+        let mut entries = fetched();
+        assert_eq!(
+            validate_entries(&range, &mut entries, Bounded::validate, Validation::Off),
+            0
+        );
+
+        assert_eq!(
+            validate_entries(&range, &mut entries, Bounded::validate, Validation::Warn),
+            2
+        );
+        assert_eq!(entries, fetched());
+
+        assert_eq!(
+            validate_entries(&range, &mut entries, Bounded::validate, Validation::Repair),
+            2
+        );
+        assert_eq!(entries, vec![Bounded(5), Bounded(10), Bounded(10)]);
+    }
+
+    #[test]
+    fn meta_hash_number_or_string() {
+        let meta_hash = |row: Option<&[serde_json::Value]>| meta_entry(row).map(|meta| meta.hash);
+        let number = meta_hash(Some(&[serde_json::json!(1234567890)])).unwrap();
+        let float = meta_hash(Some(&[serde_json::json!(1234567890.0)])).unwrap();
+        let string = meta_hash(Some(&[serde_json::json!(" 1234567890 ")])).unwrap();
+
+        assert_eq!(number, "1234567890");
+        assert_eq!(number, float);
+        assert_eq!(number, string);
+        assert_eq!(meta_hash(None).unwrap(), "");
+    }
+
+    #[test]
+    fn multi_field_meta_round_trip() {
+        let meta = MetaEntry {
+            hash: "1234567890".to_owned(),
+            updated_at: Some("2024-05-01 12:00:00".to_owned()),
+            row_count: Some(42),
+            schema_version: Some(3),
+        };
+
+        let mut serializer = RowSerializer::default();
+        meta.serialize(&mut serializer).unwrap();
+        let row: Vec<_> = sheets4::RowData::from(serializer)
+            .values
+            .unwrap()
+            .into_iter()
+            // Read back as formatted, the way the meta range is fetched
+            .map(|cell| {
+                let value = cell.user_entered_value.unwrap();
+                match (value.string_value, value.number_value) {
+                    (Some(s), _) => serde_json::json!(s),
+                    (None, Some(n)) => serde_json::json!(n.to_string()),
+                    _ => serde_json::Value::Null,
+                }
+            })
+            .collect();
+
+        assert_eq!(row.len(), 4);
+        assert_eq!(meta_entry(Some(&row)).unwrap(), meta);
+
+        // A single-cell meta range of older sheets
+        let single = meta_entry(Some(&row[..1])).unwrap();
+        assert_eq!(single.hash, meta.hash);
+        assert_eq!(single.updated_at, None);
+        assert_eq!(single.row_count, None);
+        assert_eq!(single.schema_version, None);
+    }
+
+    #[test]
+    fn unparsable_meta_diagnostics_are_missing() {
+        let row = [
+            serde_json::json!("1234567890"),
+            serde_json::json!(45412.5),
+            serde_json::json!("n/a"),
+            serde_json::json!(""),
+        ];
+
+        let meta = meta_entry(Some(&row)).unwrap();
+        assert_eq!(meta.hash, "1234567890");
+        assert_eq!(meta.updated_at, Some("45412.5".to_owned()));
+        assert_eq!(meta.row_count, None);
+        assert_eq!(meta.schema_version, None);
+    }
+
     fn assert_entries(rows: &[TestEntry]) {
         assert_eq!(rows.len(), 3);
         assert_eq!(
@@ -6,10 +6,75 @@ use serde_json::Value as CellValue;
 
 use super::error::{Error, Result};
 
+/// Separators of formatted numbers, they depend on the spreadsheet's locale.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NumberFormat {
+    pub decimal: char,
+    /// Whitespace grouping is always dropped, so it doesn't need to be set.
+    pub grouping: Option<char>,
+}
+
+impl NumberFormat {
+    /// Both a comma and a dot are taken as the decimal separator.
+    pub const LENIENT: Self = Self {
+        decimal: ',',
+        grouping: None,
+    };
+
+    /// Format of the locale as reported by the spreadsheet properties, e.g. `en_US` or `de`.
+    pub fn from_locale(locale: &str) -> Self {
+        let language = locale
+            .split(['_', '-'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+
+        match language.as_str() {
+            "en" | "ja" | "zh" | "ko" | "he" | "iw" | "th" | "hi" | "ms" | "fil" | "ga" => Self {
+                decimal: '.',
+                grouping: Some(','),
+            },
+            "de" | "es" | "it" | "nl" | "pt" | "id" | "tr" | "da" | "el" | "ro" | "hr" | "sl"
+            | "sr" | "vi" => Self {
+                decimal: ',',
+                grouping: Some('.'),
+            },
+            // Russian, Czech, French and the like group digits with spaces
+            _ => Self {
+                decimal: ',',
+                grouping: None,
+            },
+        }
+    }
+
+    pub fn parse(&self, text: &str) -> Option<f64> {
+        let mut s: String = text
+            .chars()
+            .filter(|c| !c.is_whitespace() && Some(*c) != self.grouping)
+            .map(|c| if c == self.decimal { '.' } else { c })
+            .collect();
+
+        let percent = s.ends_with('%');
+        if percent {
+            s.pop();
+        }
+
+        let n = s.parse::<f64>().ok()?;
+        Some(if percent { n / 100.0 } else { n })
+    }
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self::LENIENT
+    }
+}
+
 #[derive(Clone)]
 pub struct RowDeserializer<'a> {
     data: &'a [CellValue],
     seq_began: bool,
+    number_format: NumberFormat,
 }
 
 impl<'a> RowDeserializer<'a> {
@@ -17,8 +82,14 @@ impl<'a> RowDeserializer<'a> {
         Self {
             data,
             seq_began: false,
+            number_format: NumberFormat::LENIENT,
         }
     }
+
+    pub const fn with_number_format(mut self, number_format: NumberFormat) -> Self {
+        self.number_format = number_format;
+        self
+    }
 }
 
 impl<'a> RowDeserializer<'a> {
@@ -37,9 +108,10 @@ impl<'a> RowDeserializer<'a> {
     }
 
     fn is_next_empty(&self) -> bool {
-        match &self.data[0] {
-            CellValue::Null => true,
-            CellValue::String(s) => s.len() == 0,
+        // Sheets API omits trailing empty cells
+        match self.data.first() {
+            None | Some(CellValue::Null) => true,
+            Some(CellValue::String(s)) => s.len() == 0,
             _ => false,
         }
     }
@@ -57,19 +129,10 @@ impl<'a> RowDeserializer<'a> {
     }
 
     fn parse_f64(&mut self) -> Result<f64> {
+        let format = self.number_format;
         match self.next()? {
             CellValue::Number(n) if n.is_f64() => Ok(n.as_f64().unwrap()),
-            CellValue::String(s) => {
-                let mut s = s.replace(',', ".");
-                s.retain(|c| !c.is_whitespace());
-                match &s {
-                    s if s.ends_with('%') => s[..s.len() - 1]
-                        .parse::<f64>()
-                        .map(|n| n / 100.0)
-                        .map_err(|_| Error::ExpectedDouble),
-                    _ => s.parse::<f64>().map_err(|_| Error::ExpectedDouble),
-                }
-            }
+            CellValue::String(s) => format.parse(s).ok_or(Error::ExpectedDouble),
             _ => Err(Error::ExpectedDouble),
         }
     }
@@ -127,11 +190,20 @@ impl<'a, 'b, 'de> de::SeqAccess<'de> for RowSeqAccess<'a, 'b> {
 impl<'a, 'b, 'de> Deserializer<'de> for &'a mut RowDeserializer<'b> {
     type Error = Error;
 
-    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        match self.next()? {
+            CellValue::Null => visitor.visit_unit(),
+            CellValue::Bool(v) => visitor.visit_bool(*v),
+            CellValue::Number(n) if n.is_u64() => visitor.visit_u64(n.as_u64().unwrap()),
+            CellValue::Number(n) if n.is_i64() => visitor.visit_i64(n.as_i64().unwrap()),
+            CellValue::Number(n) => visitor.visit_f64(n.as_f64().ok_or(Error::ExpectedDouble)?),
+            CellValue::String(s) => visitor.visit_str(s.trim()),
+            CellValue::Array(_) => Err(Error::UnexpectedSequence),
+            CellValue::Object(_) => Err(Error::UnexpectedMap),
+        }
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
@@ -465,6 +537,30 @@ mod test {
         assert_eq!(test_struct.variants, TestEnum::Variant1);
     }
 
+    #[test]
+    fn de_locale_numbers() {
+        let parse = |locale: &str, text: &str| {
+            let data = vec![json!(text)];
+            let mut deserializer =
+                RowDeserializer::new(&data).with_number_format(NumberFormat::from_locale(locale));
+            f64::deserialize(&mut deserializer).unwrap()
+        };
+
+        assert_eq!(parse("en_US", "1,000"), 1000.0);
+        assert_eq!(parse("en_US", "1,000.50"), 1000.5);
+        assert_eq!(parse("de_DE", "1.000,50"), 1000.5);
+        assert_eq!(parse("de", "1.000"), 1000.0);
+        assert_eq!(parse("ru_RU", "1 000,50"), 1000.5);
+        assert_eq!(parse("en_GB", "12.5%"), 0.125);
+    }
+
+    #[test]
+    fn de_lenient_numbers() {
+        assert_eq!(NumberFormat::LENIENT.parse("1,5"), Some(1.5));
+        assert_eq!(NumberFormat::LENIENT.parse("1.5"), Some(1.5));
+        assert_eq!(NumberFormat::LENIENT.parse("1.000,50"), None);
+    }
+
     #[test]
     fn de_err() {
         #[derive(Deserialize)]
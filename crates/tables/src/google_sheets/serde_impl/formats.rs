@@ -0,0 +1,44 @@
+use google_sheets4::api as sheets4;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Amount of money, the sheet shows it with two decimals and grouped thousands.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Money(pub f64);
+
+/// Quantity, the sheet shows it as a whole number.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Qty(pub f64);
+
+macro_rules! impl_formatted {
+    ($name: ident) => {
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_newtype_struct(stringify!($name), &self.0)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                f64::deserialize(deserializer).map($name)
+            }
+        }
+    };
+}
+
+impl_formatted!(Money);
+impl_formatted!(Qty);
+
+/// Number format of the cells serialized from the newtype, the rest of
+/// the cells keep the format they have in the sheet.
+pub fn number_format(newtype: &str) -> Option<sheets4::NumberFormat> {
+    let (type_, pattern) = match newtype {
+        "Money" => ("NUMBER", "#,##0.00"),
+        "Qty" => ("NUMBER", "0"),
+        _ => return None,
+    };
+
+    Some(sheets4::NumberFormat {
+        type_: Some(type_.to_owned()),
+        pattern: Some(pattern.to_owned()),
+    })
+}
@@ -1,7 +1,9 @@
 pub mod de;
 pub mod error;
+pub mod formats;
 pub mod ser;
 
-pub use de::RowDeserializer;
+pub use de::{NumberFormat, RowDeserializer};
 pub use error::Error;
+pub use formats::{Money, Qty};
 pub use ser::RowSerializer;
@@ -2,13 +2,35 @@ use google_sheets4::api as sheets4;
 use serde::{ser, Serializer};
 
 use super::error::{Error, Result};
+use super::formats::number_format;
 
 #[derive(Default)]
 pub struct RowSerializer {
     pub data: Vec<sheets4::CellData>,
+    /// Name of the struct field every cell came from.
+    pub header: Vec<&'static str>,
     seq_began: bool,
 }
 
+impl RowSerializer {
+    pub fn header_row(&self) -> sheets4::RowData {
+        sheets4::RowData {
+            values: Some(
+                self.header
+                    .iter()
+                    .map(|name| sheets4::CellData {
+                        user_entered_value: Some(sheets4::ExtendedValue {
+                            string_value: Some(name.to_string()),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
 impl From<RowSerializer> for sheets4::RowData {
     fn from(serializer: RowSerializer) -> Self {
         sheets4::RowData {
@@ -120,11 +142,19 @@ impl Serializer for &mut RowSerializer {
         self.serialize_str(variant)
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(self, _name: &'static str, value: &T) -> Result<Self::Ok>
+    fn serialize_newtype_struct<T: ?Sized>(self, name: &'static str, value: &T) -> Result<Self::Ok>
     where
         T: serde::Serialize,
     {
-        value.serialize(self)
+        value.serialize(&mut *self)?;
+
+        if let (Some(number_format), Some(cell)) = (number_format(name), self.data.last_mut()) {
+            cell.user_entered_format = Some(sheets4::CellFormat {
+                number_format: Some(number_format),
+                ..Default::default()
+            });
+        }
+        Ok(())
     }
 
     fn serialize_newtype_variant<T: ?Sized>(
@@ -216,11 +246,13 @@ macro_rules! impl_ser_struct {
             type Ok = ();
             type Error = Error;
 
-            fn serialize_field<T: ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<()>
+            fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
             where
                 T: serde::Serialize,
             {
-                value.serialize(&mut **self)
+                value.serialize(&mut **self)?;
+                self.header.push(key);
+                Ok(())
             }
 
             fn end(self) -> Result<Self::Ok> {
@@ -387,4 +419,73 @@ mod test {
             "123456".to_owned()
         );
     }
+
+    #[test]
+    fn se_header() {
+        #[derive(Serialize)]
+        struct TestStruct {
+            name: String,
+            amount: f64,
+            currency: Option<String>,
+            sold: bool,
+        }
+
+        let test_data = TestStruct {
+            name: "Hat".to_owned(),
+            amount: 12.5,
+            currency: None,
+            sold: false,
+        };
+
+        let mut serializer = RowSerializer::default();
+        test_data.serialize(&mut serializer).unwrap();
+
+        assert_eq!(
+            serializer.header,
+            vec!["name", "amount", "currency", "sold"]
+        );
+        assert_eq!(serializer.header.len(), serializer.data.len());
+    }
+
+    #[test]
+    fn se_number_formats() {
+        use super::super::formats::{Money, Qty};
+
+        #[derive(Serialize)]
+        struct TestStruct {
+            name: String,
+            price: Money,
+            amount: Qty,
+            share: f64,
+        }
+
+        let test_data = TestStruct {
+            name: "Hat".to_owned(),
+            price: Money(1250.5),
+            amount: Qty(3.0),
+            share: 0.1,
+        };
+
+        let mut serializer = RowSerializer::default();
+        test_data.serialize(&mut serializer).unwrap();
+        let data = serializer.data;
+
+        let pattern = |i: usize| {
+            data[i]
+                .user_entered_format
+                .as_ref()
+                .and_then(|format| format.number_format.as_ref())
+                .and_then(|format| format.pattern.clone())
+        };
+        assert_eq!(pattern(0), None);
+        assert_eq!(pattern(1), Some("#,##0.00".to_owned()));
+        assert_eq!(pattern(2), Some("0".to_owned()));
+        assert_eq!(pattern(3), None);
+
+        // The value is written as is, only its display changes
+        let number = |i: usize| data[i].user_entered_value.as_ref().unwrap().number_value;
+        assert_eq!(number(1), Some(1250.5));
+        assert_eq!(number(2), Some(3.0));
+        assert_eq!(serializer.header, vec!["name", "price", "amount", "share"]);
+    }
 }
@@ -0,0 +1,164 @@
+use std::{collections::HashMap, error::Error as StdError, fmt::Display};
+
+use google_sheets4::{
+    api::SheetProperties, hyper::client::HttpConnector, hyper_rustls::HttpsConnector, Sheets,
+};
+
+use super::{Error, Result, SheetArgs};
+
+#[derive(Debug, PartialEq)]
+pub enum ResolveError {
+    UnknownTab(String),
+    Mismatch {
+        tab: String,
+        configured: i32,
+        actual: i32,
+    },
+}
+
+impl Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResolveError::UnknownTab(tab) => write!(f, "there is no tab named {}", tab),
+            ResolveError::Mismatch {
+                tab,
+                configured,
+                actual,
+            } => write!(
+                f,
+                "tab {} has id {}, but {} is configured",
+                tab, actual, configured
+            ),
+        }
+    }
+}
+
+impl StdError for ResolveError {}
+
+/// Sheet ids of the spreadsheet tabs by their names.
+#[derive(Default, Debug)]
+pub struct SheetIds {
+    ids: HashMap<String, i32>,
+}
+
+impl SheetIds {
+    pub async fn fetch(
+        hub: &Sheets<HttpsConnector<HttpConnector>>,
+        spreadsheet_id: &str,
+    ) -> Result<Self> {
+        let (_, spreadsheet) = hub
+            .spreadsheets()
+            .get(spreadsheet_id)
+            .param("fields", "sheets.properties(sheetId,title)")
+            .doit()
+            .await
+            .map_err(Error::Sheets)?;
+
+        Ok(Self::from_properties(
+            spreadsheet
+                .sheets
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|sheet| sheet.properties),
+        ))
+    }
+
+    pub fn from_properties(properties: impl IntoIterator<Item = SheetProperties>) -> Self {
+        let ids = properties
+            .into_iter()
+            .filter_map(|props| Some((props.title?, props.sheet_id?)))
+            .collect();
+
+        Self { ids }
+    }
+
+    /// Checks the id against the tab named in the data range,
+    /// or fills it in if it was left out of the config.
+    pub fn resolve(&self, args: &mut SheetArgs) -> std::result::Result<(), ResolveError> {
+        let tab = &args.data_range.sheet_name;
+        let actual = *self
+            .ids
+            .get(tab)
+            .ok_or_else(|| ResolveError::UnknownTab(tab.clone()))?;
+
+        if args.auto_id {
+            args.id = actual;
+            args.auto_id = false;
+        } else if args.id != actual {
+            return Err(ResolveError::Mismatch {
+                tab: tab.clone(),
+                configured: args.id,
+                actual,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::google_sheets::range::SheetRange;
+
+    fn ids() -> SheetIds {
+        SheetIds::from_properties([
+            SheetProperties {
+                sheet_id: Some(0),
+                title: Some("Items".to_owned()),
+                ..Default::default()
+            },
+            SheetProperties {
+                sheet_id: Some(1068945262),
+                title: Some("Sales".to_owned()),
+                ..Default::default()
+            },
+        ])
+    }
+
+    fn sheet_args(id: Option<i32>, range: &str) -> SheetArgs {
+        SheetArgs {
+            id: id.unwrap_or_default(),
+            auto_id: id.is_none(),
+            data_range: SheetRange::from_str(range).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matching_id_passes() {
+        let mut args = sheet_args(Some(1068945262), "Sales!A2:J");
+        assert_eq!(ids().resolve(&mut args), Ok(()));
+        assert_eq!(args.id, 1068945262);
+    }
+
+    #[test]
+    fn detects_mismatch() {
+        // The id of Items configured for the Sales tab
+        let mut args = sheet_args(Some(0), "Sales!A2:J");
+        assert_eq!(
+            ids().resolve(&mut args),
+            Err(ResolveError::Mismatch {
+                tab: "Sales".to_owned(),
+                configured: 0,
+                actual: 1068945262,
+            })
+        );
+
+        let mut args = sheet_args(Some(0), "Orders!A2:J");
+        assert_eq!(
+            ids().resolve(&mut args),
+            Err(ResolveError::UnknownTab("Orders".to_owned()))
+        );
+    }
+
+    #[test]
+    fn fills_missing_id() {
+        let mut args = sheet_args(None, "Sales!A2:J");
+        assert_eq!(ids().resolve(&mut args), Ok(()));
+        assert_eq!(args.id, 1068945262);
+        assert!(!args.auto_id);
+    }
+}
@@ -0,0 +1,436 @@
+use log::warn;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    future::Future,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Write that is dispatched to a sheet.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum WalOp<E> {
+    Extend(Vec<E>),
+    Update(usize, Vec<E>),
+    Delete(Vec<usize>),
+    /// Value of a single cell by its row and column in the data range.
+    Cell(usize, usize, String),
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+enum WalLine {
+    Begin {
+        id: u64,
+        sheet: String,
+        op: serde_json::Value,
+    },
+    Done {
+        done: u64,
+    },
+    /// Left by a compaction, so the ids keep growing once the lines before it are gone.
+    Next {
+        next: u64,
+    },
+}
+
+/// Last logged write the sheet itself shows, read from its ack cell.
+/// The writes of a sheet land one at a time in the order they were logged,
+/// so every write up to the acked one either landed or failed for good.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ack {
+    /// The sheet has no ack cell, a pending write may have landed or not.
+    Unknown,
+    /// None of the logged writes landed yet.
+    Nothing,
+    Upto(u64),
+}
+
+/// Append-only log of sheet writes. Every write is recorded before it's dispatched
+/// and marked as done once it lands, so the ones lost in a crash can be re-issued.
+#[derive(Clone)]
+pub struct Wal {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Wal {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let next_id = read_lines(&path)?
+            .iter()
+            .map(|line| match line {
+                WalLine::Begin { id, .. } => *id + 1,
+                WalLine::Done { done } => *done + 1,
+                WalLine::Next { next } => *next,
+            })
+            .max()
+            .unwrap_or(0);
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+
+        // A torn last line would swallow the next one appended to it
+        if file.metadata()?.len() > 0 {
+            let mut last = [0];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last != *b"\n" {
+                file.write_all(b"\n")?;
+            }
+        }
+
+        Ok(Self {
+            path,
+            file: Arc::new(Mutex::new(file)),
+            next_id: Arc::new(AtomicU64::new(next_id)),
+        })
+    }
+
+    /// Records the write and returns its id.
+    pub async fn begin<E: Serialize>(&self, sheet: &str, op: &WalOp<E>) -> io::Result<u64> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.append(&WalLine::Begin {
+            id,
+            sheet: sheet.to_owned(),
+            op: serde_json::to_value(op)?,
+        })
+        .await?;
+        Ok(id)
+    }
+
+    pub async fn complete(&self, id: u64) -> io::Result<()> {
+        self.append(&WalLine::Done { done: id }).await
+    }
+
+    /// Writes of the sheet which were never marked as done, in the order they were made.
+    pub fn pending<E: DeserializeOwned>(&self, sheet: &str) -> io::Result<Vec<(u64, WalOp<E>)>> {
+        pending_lines(read_lines(&self.path)?)
+            .into_iter()
+            .filter_map(|line| match line {
+                WalLine::Begin { id, sheet: s, op } if s == sheet => Some((id, op)),
+                _ => None,
+            })
+            .map(|(id, op)| Ok((id, serde_json::from_value(op)?)))
+            .collect()
+    }
+
+    /// Rewrites the log with the pending writes only. The new log replaces
+    /// the old one at once, so a crash midway leaves one of them intact.
+    pub async fn compact(&self) -> io::Result<()> {
+        let (path, file, next_id) = (self.path.clone(), self.file.clone(), self.next_id.clone());
+
+        blocking(move || {
+            // Appends wait for the swap, so none of them ends up in the old file
+            let mut file = file.lock().unwrap();
+
+            let lines = read_lines(&path)?;
+            let pending = pending_lines(lines.iter().cloned());
+            if pending.len() + 1 >= lines.len() {
+                return Ok(());
+            }
+
+            let next = WalLine::Next {
+                next: next_id.load(Ordering::Relaxed),
+            };
+            let compacted = path.with_extension("compact");
+            let mut out = File::create(&compacted)?;
+            for line in pending.iter().chain([&next]) {
+                serde_json::to_writer(&mut out, line)?;
+                out.write_all(b"\n")?;
+            }
+            out.sync_all()?;
+            fs::rename(&compacted, &path)?;
+
+            *file = OpenOptions::new().read(true).append(true).open(&path)?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Appends the line and syncs it to the disk off the async threads.
+    async fn append(&self, line: &WalLine) -> io::Result<()> {
+        let mut bytes = serde_json::to_vec(line)?;
+        bytes.push(b'\n');
+
+        let file = self.file.clone();
+        blocking(move || {
+            let mut file = file.lock().unwrap();
+            file.write_all(&bytes)?;
+            file.sync_data()
+        })
+        .await
+    }
+}
+
+async fn blocking<T, F>(f: F) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Begin lines of the writes which were never marked as done, in the order they were made.
+fn pending_lines(lines: impl IntoIterator<Item = WalLine>) -> Vec<WalLine> {
+    let mut pending = BTreeMap::new();
+
+    for line in lines {
+        match line {
+            WalLine::Begin { id, .. } => {
+                pending.insert(id, line);
+            }
+            WalLine::Done { done } => {
+                pending.remove(&done);
+            }
+            WalLine::Next { .. } => (),
+        }
+    }
+
+    pending.into_values().collect()
+}
+
+/// Re-issues pending writes of the sheet one by one, marking each as done when it succeeds.
+/// Writes which the sheet already acked are only marked as done, re-issuing an extend or
+/// a delete twice would duplicate or remove rows, and an old update would undo newer ones.
+pub async fn replay<E, Err, F, Fut>(
+    wal: &Wal,
+    sheet: &str,
+    ack: Ack,
+    mut apply: F,
+) -> Result<usize, Err>
+where
+    E: Serialize + DeserializeOwned,
+    Err: From<io::Error>,
+    F: FnMut(u64, WalOp<E>) -> Fut,
+    Fut: Future<Output = Result<(), Err>>,
+{
+    let mut count = 0;
+
+    for (id, op) in wal.pending::<E>(sheet)? {
+        match ack {
+            Ack::Upto(acked) if id <= acked => (),
+            Ack::Upto(_) | Ack::Nothing => {
+                apply(id, op).await?;
+                count += 1;
+            }
+            // Without an ack it's safer to leave the write out than to risk applying it twice
+            Ack::Unknown => warn!(
+                "Dropped write {} to {}, the sheet can't tell whether it landed: {}",
+                id,
+                sheet,
+                serde_json::to_string(&op).map_err(io::Error::from)?
+            ),
+        }
+        wal.complete(id).await?;
+    }
+
+    Ok(count)
+}
+
+fn read_lines(path: &Path) -> io::Result<Vec<WalLine>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+
+    let mut lines = vec![];
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        // A crash in the middle of an append leaves a torn line, the ones after it are fine
+        match serde_json::from_str(&line) {
+            Ok(line) => lines.push(line),
+            Err(e) => warn!(
+                "Skipped malformed line {} of {}: {}",
+                number + 1,
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wal_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("tables-wal-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    async fn replay_all(wal: &Wal, sheet: &str, ack: Ack) -> (usize, Vec<(u64, WalOp<u32>)>) {
+        let mut issued = vec![];
+        let count = replay(wal, sheet, ack, |id, op: WalOp<u32>| {
+            issued.push((id, op));
+            async { Ok::<_, io::Error>(()) }
+        })
+        .await
+        .unwrap();
+        (count, issued)
+    }
+
+    #[tokio::test]
+    async fn replay_incomplete_writes() {
+        let path = wal_path("replay");
+
+        {
+            let wal = Wal::open(&path).unwrap();
+            let landed = wal
+                .begin("sales", &WalOp::Extend(vec![1u32]))
+                .await
+                .unwrap();
+            wal.begin("sales", &WalOp::Update(3, vec![2u32]))
+                .await
+                .unwrap();
+            wal.begin("orders", &WalOp::<u32>::Delete(vec![0]))
+                .await
+                .unwrap();
+            wal.complete(landed).await.unwrap();
+            // Crash before the update and the delete land
+        }
+
+        let wal = Wal::open(&path).unwrap();
+        let (count, issued) = replay_all(&wal, "sales", Ack::Upto(0)).await;
+
+        assert_eq!(count, 1);
+        assert_eq!(issued, vec![(1, WalOp::Update(3, vec![2]))]);
+
+        // Replayed writes are done, other sheets are untouched
+        assert!(wal.pending::<u32>("sales").unwrap().is_empty());
+        assert_eq!(
+            wal.pending::<u32>("orders").unwrap(),
+            vec![(2, WalOp::Delete(vec![0]))]
+        );
+
+        // Ids keep growing after the restart
+        let id = wal
+            .begin("sales", &WalOp::Extend(vec![4u32]))
+            .await
+            .unwrap();
+        assert_eq!(id, 3);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn acked_writes_not_reissued() {
+        let path = wal_path("acked");
+        let wal = Wal::open(&path).unwrap();
+
+        for op in [WalOp::Extend(vec![1u32]), WalOp::Delete(vec![0])] {
+            wal.begin("sales", &op).await.unwrap();
+        }
+        wal.begin("sales", &WalOp::Update(0, vec![2u32]))
+            .await
+            .unwrap();
+        // The extend and the delete landed, the crash came before they were marked as done
+
+        let (count, issued) = replay_all(&wal, "sales", Ack::Upto(1)).await;
+        assert_eq!(count, 1);
+        assert_eq!(issued, vec![(2, WalOp::Update(0, vec![2]))]);
+        assert!(wal.pending::<u32>("sales").unwrap().is_empty());
+
+        // A sheet without an ack cell drops what it can't tell about
+        wal.begin("orders", &WalOp::Extend(vec![3u32]))
+            .await
+            .unwrap();
+        let (count, issued) = replay_all(&wal, "orders", Ack::Unknown).await;
+        assert_eq!(count, 0);
+        assert!(issued.is_empty());
+        assert!(wal.pending::<u32>("orders").unwrap().is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn malformed_lines_skipped() {
+        let path = wal_path("malformed");
+
+        {
+            let wal = Wal::open(&path).unwrap();
+            wal.begin("sales", &WalOp::Extend(vec![1u32]))
+                .await
+                .unwrap();
+        }
+        // A torn append, with a later write after it
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"id\": 1, \"sh")
+            .unwrap();
+
+        let wal = Wal::open(&path).unwrap();
+        wal.begin("sales", &WalOp::<u32>::Delete(vec![0]))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            wal.pending::<u32>("sales").unwrap(),
+            vec![(0, WalOp::Extend(vec![1])), (1, WalOp::Delete(vec![0]))]
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn compaction_keeps_pending_writes() {
+        let path = wal_path("compact");
+        let wal = Wal::open(&path).unwrap();
+
+        for value in 0..4u32 {
+            let id = wal
+                .begin("sales", &WalOp::Extend(vec![value]))
+                .await
+                .unwrap();
+            wal.complete(id).await.unwrap();
+        }
+        wal.begin("orders", &WalOp::<u32>::Delete(vec![2]))
+            .await
+            .unwrap();
+
+        wal.compact().await.unwrap();
+        assert_eq!(read_lines(&path).unwrap().len(), 2);
+        assert_eq!(
+            wal.pending::<u32>("orders").unwrap(),
+            vec![(4, WalOp::Delete(vec![2]))]
+        );
+
+        // Appends go to the compacted log, and the ids survive a restart
+        let id = wal
+            .begin("sales", &WalOp::Extend(vec![5u32]))
+            .await
+            .unwrap();
+        wal.complete(id).await.unwrap();
+        drop(wal);
+
+        let wal = Wal::open(&path).unwrap();
+        let id = wal
+            .begin("sales", &WalOp::Extend(vec![6u32]))
+            .await
+            .unwrap();
+        assert_eq!(id, 6);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
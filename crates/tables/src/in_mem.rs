@@ -15,6 +15,17 @@ pub struct InMemTable<E, M = ReadRef> {
     _marker: std::marker::PhantomData<M>,
 }
 
+// Derived Clone would require the marker to be Clone
+impl<E: Clone, M> Clone for InMemTable<E, M> {
+    fn clone(&self) -> Self {
+        Self {
+            rows: self.rows.clone(),
+            version: self.version,
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
 impl<M, E> InMemTable<E, M> {
     pub fn new(rows: Vec<E>) -> Self {
         Self {
@@ -148,6 +159,34 @@ impl<M: Send, E: Send> TableClear for InMemTable<E, M> {
     }
 }
 
+#[async_trait]
+impl<M: Send, E: Send> TableFlush for InMemTable<E, M> {
+    type Error = Infallible;
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<M: Send, E: Send> TableDelete for InMemTable<E, M> {
+    type Error = Infallible;
+
+    async fn delete(&mut self, mut rows: Vec<usize>) -> Result<(), Self::Error> {
+        rows.sort_unstable();
+        rows.dedup();
+
+        for row in rows.into_iter().rev() {
+            if row < self.rows.len() {
+                self.rows.remove(row);
+            }
+        }
+
+        self.version = next_version();
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl<E: Send + Sync, M: Send + Sync> TableVersion for InMemTable<E, M> {
     type Error = Infallible;
@@ -191,6 +230,19 @@ mod tests {
         assert_ne!(version, table.version().await.unwrap());
     }
 
+    #[tokio::test]
+    async fn delete() {
+        let mut table: InMemTable<usize> = [0, 1, 2, 3, 4].into();
+        let version = table.version().await.unwrap();
+
+        table.delete(vec![3, 1, 3, 10]).await.unwrap();
+
+        let output: Vec<_> = table.fetch().await.unwrap().into_iter().cloned().collect();
+
+        assert_eq!(output, vec![0, 2, 4]);
+        assert_ne!(version, table.version().await.unwrap());
+    }
+
     #[tokio::test]
     async fn update() {
         let mut table: InMemTable<usize> = [].into();
@@ -6,7 +6,7 @@ use std::{
 
 use async_trait::async_trait;
 
-use crate::{TableClear, TableExtend, TableUpdate};
+use crate::{TableClear, TableExtend, TableRebuild, TableUpdate};
 
 pub struct Index<K, E, V = E> {
     map: HashMap<K, Vec<(usize, V)>>,
@@ -40,6 +40,16 @@ where
         self.map.iter()
     }
 
+    /// Rebuilds the index from scratch in a single pass over the entries.
+    pub fn build_from<T>(&mut self, entries: T)
+    where
+        T: IntoIterator<Item = E>,
+    {
+        let entries = entries.into_iter();
+        self.map = HashMap::with_capacity(entries.size_hint().0);
+        self._extend(entries);
+    }
+
     fn _extend<T>(&mut self, entries: T)
     where
         T: IntoIterator<Item = E>,
@@ -105,6 +115,23 @@ where
     }
 }
 
+#[async_trait]
+impl<K, E, V> TableRebuild<E> for Index<K, E, V>
+where
+    K: Send + Hash + PartialEq + Ord,
+    E: Send + Clone,
+    V: From<E> + Send,
+{
+    async fn rebuild<'a, T>(&'a mut self, entries: T) -> Result<(), Infallible>
+    where
+        T: IntoIterator<Item = &'a E> + Clone + Send + Sync,
+        E: 'a,
+    {
+        self.build_from(entries.into_iter().cloned());
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl<K, E, V> TableUpdate<E> for Index<K, E, V>
 where
@@ -156,3 +183,34 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    static KEY_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    fn counted_key(_: usize, entry: &u32) -> u32 {
+        KEY_CALLS.fetch_add(1, Ordering::Relaxed);
+        entry % 10
+    }
+
+    #[test]
+    fn build_from_in_one_pass() {
+        const N: u32 = 10_000;
+        let mut index = Index::<u32, u32>::new(counted_key);
+
+        index.build_from(0..N);
+        assert_eq!(KEY_CALLS.load(Ordering::Relaxed), N as usize);
+        assert_eq!(index.group(&3).map(|group| group.len()), Some(1_000));
+        assert_eq!(index.get_with_row(&3), Some(&(3, 3)));
+
+        // Building again replaces the previous entries
+        index.build_from(0..5);
+        assert_eq!(KEY_CALLS.load(Ordering::Relaxed), N as usize + 5);
+        assert_eq!(index.group(&3).map(|group| group.len()), Some(1));
+        assert_eq!(index.get(&7), None);
+    }
+}
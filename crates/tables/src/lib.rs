@@ -13,7 +13,10 @@ use std::{
 };
 
 pub mod prelude {
-    pub use crate::{TableClear, TableExtend, TableFetch, TableRead, TableUpdate, TableVersion};
+    pub use crate::{
+        TableClear, TableDelete, TableExtend, TableFetch, TableFlush, TableRead, TableRebuild,
+        TableUpdate, TableVersion, Validate,
+    };
 }
 
 #[async_trait]
@@ -109,6 +112,18 @@ pub trait TableUpdate<E: Send> {
     }
 }
 
+#[async_trait]
+pub trait TableRebuild<E: Send>: TableExtend<E> {
+    /// Replaces all the entries at once, cheaper than a clear followed by an extend.
+    async fn rebuild<'a, T>(
+        &'a mut self,
+        entries: T,
+    ) -> Result<(), <Self as TableExtend<E>>::Error>
+    where
+        T: IntoIterator<Item = &'a E> + Clone + Send + Sync,
+        E: 'a;
+}
+
 #[async_trait]
 pub trait TableClear {
     type Error: StdError + Send;
@@ -116,6 +131,22 @@ pub trait TableClear {
     async fn clear(&mut self) -> Result<(), Self::Error>;
 }
 
+#[async_trait]
+pub trait TableDelete {
+    type Error: StdError + Send;
+
+    /// Deletes the rows, shifting the following rows up.
+    async fn delete(&mut self, rows: Vec<usize>) -> Result<(), Self::Error>;
+}
+
+#[async_trait]
+pub trait TableFlush {
+    type Error: StdError + Send;
+
+    /// Waits until the writes issued so far have landed.
+    async fn flush(&mut self) -> Result<(), Self::Error>;
+}
+
 #[async_trait]
 pub trait TableVersion {
     type Error: StdError + Send;
@@ -123,6 +154,12 @@ pub trait TableVersion {
     async fn version(&mut self) -> Result<u64, Self::Error>;
 }
 
+/// Invariants of an entry which the sheet itself can't enforce.
+pub trait Validate {
+    /// Describes every broken invariant, repairing the entry if `repair` is set.
+    fn validate(&mut self, repair: bool) -> Vec<String>;
+}
+
 static VERSION: AtomicU64 = AtomicU64::new(0);
 
 pub(crate) fn next_version() -> u64 {
@@ -1,5 +1,16 @@
 use std::collections::HashMap;
 
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Folds case and strips diacritics, so "Café" and "cafe" or "Йогурт" and
+/// "иогурт" compare equal. Query words are expected to be normalized with it too.
+pub fn normalize(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
 pub trait Searchable: Sized {
     fn fill_haystack(&self, query: &mut Searcher);
 }
@@ -19,13 +30,17 @@ impl Searcher {
         self.groups
             .entry(group)
             .or_default()
-            .extend(haystack.chars());
+            .push_str(&normalize(&haystack));
     }
 
     pub fn write_many(&mut self, group: String, haystack: impl IntoIterator<Item = String>) {
-        self.groups.entry(group).or_default().extend(haystack);
+        let haystack_group = self.groups.entry(group).or_default();
+        for haystack in haystack {
+            haystack_group.push_str(&normalize(&haystack));
+        }
     }
 
+    /// The query is compared as is, see [`normalize`].
     pub fn search_one(&self, group: &str, query: &str) -> bool {
         self.groups
             .get(group)
@@ -52,3 +67,37 @@ impl<E: Searchable> From<E> for Searcher {
         query
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(query: &str) -> Vec<String> {
+        query.split(' ').map(normalize).collect()
+    }
+
+    #[test]
+    fn accents_are_ignored() {
+        let mut searcher = Searcher::new();
+        searcher.write("name".to_owned(), "Café Crème".to_owned());
+
+        assert!(searcher.search_all("name", words("cafe creme").iter()));
+        assert!(searcher.search_all("name", words("CAFÉ").iter()));
+
+        let mut searcher = Searcher::new();
+        searcher.write("name".to_owned(), "cafe".to_owned());
+
+        assert!(searcher.search_one("name", &normalize("Café")));
+        assert!(!searcher.search_one("name", &normalize("cafés")));
+    }
+
+    #[test]
+    fn cyrillic_is_folded() {
+        let mut searcher = Searcher::new();
+        searcher.write_many("name".to_owned(), ["Йогурт ".to_owned(), "ЁЛКА".to_owned()]);
+
+        assert!(searcher.search_all("name", words("иогурт елка").iter()));
+        assert!(searcher.search_all("name", words("йогурт ёлка").iter()));
+        assert!(!searcher.search_any("name", words("чай").iter()));
+    }
+}
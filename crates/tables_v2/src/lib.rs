@@ -2,6 +2,8 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use google_sheets4::{self as s4, hyper::client::HttpConnector, hyper_rustls::HttpsConnector};
+use serde::de::DeserializeOwned;
+use tables::google_sheets::serde_impl::{Error as SerdeError, RowDeserializer};
 use tokio::{sync::RwLock, task::JoinSet};
 
 pub struct LocalSpreadsheet {
@@ -20,7 +22,7 @@ impl LocalSpreadsheet {
         self.sheets.get(sheet_name)
     }
 
-    pub fn get_mut(&self, sheet_name: &str) -> Option<&mut LocalSheet> {
+    pub fn get_mut(&mut self, sheet_name: &str) -> Option<&mut LocalSheet> {
         self.sheets.get_mut(sheet_name)
     }
 }
@@ -52,17 +54,23 @@ impl LocalSheet {
         self.height
     }
 
+    /// Widens the sheet to `new_width`, every cell keeps its column. A narrower
+    /// width leaves the sheet as is, columns of earlier requests aren't dropped.
     pub fn fit(&mut self, new_width: usize) {
+        if new_width <= self.width {
+            return;
+        }
+
         let mut new_values = vec![Value::None; new_width * self.height];
 
         for y in 0..self.height {
             for x in 0..self.width {
-                let rev_x = self.width - x - 1;
-                new_values[y * new_width + x] = self.values[y * self.width + rev_x].clone();
+                new_values[y * new_width + x] = self.values[y * self.width + x].clone();
             }
         }
 
         self.values = new_values;
+        self.width = new_width;
     }
 
     pub fn inflate(&mut self, new_width: usize) {
@@ -94,14 +102,47 @@ impl LocalSheet {
         &self.values[y * self.width + x]
     }
 
+    pub fn rows(&self) -> impl Iterator<Item = &[Value]> {
+        self.values.chunks(self.width.max(1)).take(self.height)
+    }
+
+    /// Deserializes every row the same way `Sheet<E>::fetch` does.
+    pub fn rows_as<'a, E: DeserializeOwned + 'a>(
+        &'a self,
+    ) -> impl Iterator<Item = Result<E, SerdeError>> + 'a {
+        self.rows().map(deserialize_row)
+    }
+
     pub fn get_column(&self, x: usize) -> impl Iterator<Item = &Value> {
         self.values.iter().step_by(self.width).skip(x)
     }
 
+    pub fn try_get(&self, x: usize, y: usize) -> Option<&Value> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        self.values.get(y * self.width + x)
+    }
+
     pub fn set(&mut self, x: usize, y: usize, value: Value) {
         self.values[y * self.width + x] = value;
     }
 
+    pub fn try_set(&mut self, x: usize, y: usize, value: Value) -> Result<(), OutOfBounds> {
+        if x >= self.width || y >= self.height {
+            return Err(OutOfBounds { x, y });
+        }
+
+        match self.values.get_mut(y * self.width + x) {
+            Some(cell) => {
+                *cell = value;
+                Ok(())
+            }
+            None => Err(OutOfBounds { x, y }),
+        }
+    }
+
     pub fn push(&mut self, row: Vec<Value>) {
         if row.len() != self.width {
             panic!("self.width != {}", row.len());
@@ -111,6 +152,17 @@ impl LocalSheet {
         self.height += 1;
     }
 
+    /// Like `push`, but a wider row widens the sheet and a narrower one is padded,
+    /// the new cells are `Value::None` either way.
+    pub fn push_fit(&mut self, mut row: Vec<Value>) {
+        if row.len() > self.width {
+            self.fit(row.len());
+        }
+
+        row.resize(self.width, Value::None);
+        self.push(row);
+    }
+
     pub fn append_column(&mut self, column: Vec<Value>) {
         if column.len() != self.height {
             panic!("self.height != {}", column.len());
@@ -135,7 +187,13 @@ impl LocalSheet {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutOfBounds {
+    pub x: usize,
+    pub y: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
 pub enum Value {
     #[default]
     None,
@@ -144,6 +202,94 @@ pub enum Value {
     Bool(bool),
 }
 
+impl Value {
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Self::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(bool) => Some(*bool),
+            _ => None,
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        matches!(self, Self::None)
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Number(_) => "number",
+            Self::String(_) => "string",
+            Self::Bool(_) => "bool",
+        }
+    }
+}
+
+// Sheets API hands formatted strings to `Sheet<E>::fetch`, so numbers are
+// passed as strings too and parsed by the deserializer into the field's type.
+impl From<&Value> for serde_json::Value {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::None => serde_json::Value::Null,
+            Value::Number(number) => serde_json::Value::String(number.to_string()),
+            Value::String(string) => serde_json::Value::String(string.clone()),
+            Value::Bool(bool) => serde_json::Value::Bool(*bool),
+        }
+    }
+}
+
+pub fn deserialize_row<E: DeserializeOwned>(row: &[Value]) -> Result<E, SerdeError> {
+    let cells: Vec<serde_json::Value> = row.iter().map(|value| value.into()).collect();
+    let mut deserializer = RowDeserializer::new(&cells);
+    E::deserialize(&mut deserializer)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueTypeError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl std::fmt::Display for ValueTypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "expected a {} value, found {}", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for ValueTypeError {}
+
+macro_rules! impl_try_from_value {
+    ($ty:ty, $expected:literal, $accessor:expr) => {
+        impl TryFrom<Value> for $ty {
+            type Error = ValueTypeError;
+
+            fn try_from(value: Value) -> Result<Self, Self::Error> {
+                $accessor(&value).ok_or(ValueTypeError {
+                    expected: $expected,
+                    found: value.type_name(),
+                })
+            }
+        }
+    };
+}
+
+impl_try_from_value!(f64, "number", Value::as_number);
+impl_try_from_value!(bool, "bool", Value::as_bool);
+impl_try_from_value!(String, "string", |v: &Value| v.as_str().map(str::to_owned));
+
 impl From<s4::api::ExtendedValue> for Value {
     fn from(value: s4::api::ExtendedValue) -> Self {
         match value {
@@ -185,6 +331,58 @@ pub struct SelectRequest {
     pub operators: Vec<SelectOperator>,
 }
 
+/// Copies the fetched cells into the local sheet, widening it to the longest row.
+/// Sheets omits the trailing empty cells, so the rows of a grid differ in length.
+fn fill_grid(
+    local_sheet: &mut LocalSheet,
+    sheet_name: &str,
+    grid: s4::api::GridData,
+) -> Result<(), RequestError> {
+    let s4::api::GridData {
+        start_row: Some(start_row),
+        start_column: Some(start_col),
+        row_data: Some(rows),
+        ..
+    } = grid
+    else {
+        return Err(RequestError::InvalidSheet(sheet_name.to_owned()));
+    };
+
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let start_row = start_row as usize;
+    let start_col = start_col as usize;
+
+    let width = start_col
+        + rows
+            .iter()
+            .map(|row| row.values.as_ref().map_or(0, Vec::len))
+            .max()
+            .unwrap_or(0);
+    local_sheet.fit(width);
+
+    let height = start_row + rows.len();
+    local_sheet.prepare(height);
+
+    for (idx, row) in rows.into_iter().enumerate() {
+        let Some(row) = row.values else {
+            continue;
+        };
+
+        for (x, cell) in row.into_iter().enumerate() {
+            let value = cell.effective_value.map_or(Value::None, |v| v.into());
+
+            local_sheet
+                .try_set(start_col + x, start_row + idx, value)
+                .map_err(|e| RequestError::CellOutOfBounds(sheet_name.to_owned(), e))?;
+        }
+    }
+
+    Ok(())
+}
+
 impl SelectRequest {
     pub(crate) async fn execute(
         self,
@@ -252,33 +450,7 @@ impl SelectRequest {
             let local_sheet = local_spreadsheet.get_or_create(&sheet_name);
 
             for grid in grids {
-                let s4::api::GridData { start_row: Some(start_row), start_column: Some(start_col), row_data: Some(rows), ..} = grid else {
-                    return Err(RequestError::InvalidSheet(sheet_name));
-                };
-
-                if rows.len() == 0 {
-                    continue;
-                }
-
-                let start_row = start_row as usize;
-                let start_col = start_col as usize;
-
-                let width = start_col + rows[0].values.as_ref().unwrap().len();
-                local_sheet.fit(width);
-
-                let height = start_row + rows.len();
-                local_sheet.prepare(height);
-
-                for (idx, row) in rows.into_iter().enumerate() {
-                    if let Some(row) = row.values {
-                        let values: Vec<Value> = row
-                            .into_iter()
-                            .map(|cell| cell.effective_value.map_or(Value::None, |v| v.into()))
-                            .collect();
-
-                        local_sheet.replace(start_col, idx + start_row, values)
-                    }
-                }
+                fill_grid(local_sheet, &sheet_name, grid)?;
             }
         }
 
@@ -419,6 +591,7 @@ pub enum RequestError {
     NoSheetsInSpreadsheet,
     NoDataInSheet(String),
     InvalidSheet(String),
+    CellOutOfBounds(String, OutOfBounds),
     Sheets4Error(s4::Error),
 }
 
@@ -563,3 +736,221 @@ let cursor = spreadsheet.commit_cursor();
 
 cursor.commit().await?;
 */
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct TestEntry {
+        name: String,
+        amount: u32,
+        price: f64,
+        visible: bool,
+        comment: Option<String>,
+    }
+
+    #[test]
+    fn rows_deserialize() {
+        let mut sheet = LocalSheet::new(5, 0);
+        sheet.push(vec![
+            Value::String("Hat".to_owned()),
+            Value::Number(3.0),
+            Value::Number(10.5),
+            Value::Bool(true),
+            Value::None,
+        ]);
+        sheet.push(vec![
+            Value::String("Scarf".to_owned()),
+            Value::Number(1.0),
+            Value::Number(7.0),
+            Value::Bool(false),
+            Value::String("Wool".to_owned()),
+        ]);
+
+        assert_eq!(sheet.rows().count(), 2);
+        assert_eq!(sheet.rows().nth(1).unwrap()[0].as_str(), Some("Scarf"));
+
+        let entries: Vec<TestEntry> = sheet.rows_as().collect::<Result<_, _>>().unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                TestEntry {
+                    name: "Hat".to_owned(),
+                    amount: 3,
+                    price: 10.5,
+                    visible: true,
+                    comment: None,
+                },
+                TestEntry {
+                    name: "Scarf".to_owned(),
+                    amount: 1,
+                    price: 7.0,
+                    visible: false,
+                    comment: Some("Wool".to_owned()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn push_fit_pads_rows() {
+        let mut sheet = LocalSheet::new(2, 0);
+        sheet.push(vec![Value::Number(1.0), Value::Number(2.0)]);
+
+        // Wider, the existing rows get padded
+        sheet.push_fit(vec![
+            Value::Number(3.0),
+            Value::Number(4.0),
+            Value::Number(5.0),
+        ]);
+        assert_eq!((sheet.width(), sheet.height()), (3, 2));
+
+        // Narrower, the row itself gets padded
+        sheet.push_fit(vec![Value::Number(6.0)]);
+        assert_eq!((sheet.width(), sheet.height()), (3, 3));
+
+        let rows: Vec<_> = sheet.rows().map(|row| row.to_vec()).collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Number(1.0), Value::Number(2.0), Value::None],
+                vec![Value::Number(3.0), Value::Number(4.0), Value::Number(5.0)],
+                vec![Value::Number(6.0), Value::None, Value::None],
+            ]
+        );
+    }
+
+    #[test]
+    fn push_fit_into_empty_sheet() {
+        let mut sheet = LocalSheet::empty();
+        sheet.push_fit(vec![Value::Bool(true), Value::None]);
+        sheet.push_fit(vec![]);
+
+        assert_eq!((sheet.width(), sheet.height()), (2, 2));
+        assert_eq!(sheet.try_get(0, 0), Some(&Value::Bool(true)));
+        assert_eq!(sheet.try_get(0, 1), Some(&Value::None));
+    }
+
+    #[test]
+    fn fit_only_widens() {
+        let mut sheet = LocalSheet::new(2, 0);
+        sheet.push(vec![Value::Number(1.0), Value::Number(2.0)]);
+        sheet.push(vec![Value::Number(3.0), Value::Number(4.0)]);
+
+        sheet.fit(3);
+        assert_eq!((sheet.width(), sheet.height()), (3, 2));
+        assert_eq!(sheet.try_get(1, 1), Some(&Value::Number(4.0)));
+        assert_eq!(sheet.try_get(2, 1), Some(&Value::None));
+
+        sheet.fit(1);
+        assert_eq!(sheet.width(), 3);
+        assert_eq!(sheet.try_get(1, 0), Some(&Value::Number(2.0)));
+    }
+
+    #[test]
+    fn bounds_checked_access() {
+        let mut sheet = LocalSheet::new(2, 2);
+
+        assert_eq!(sheet.try_set(1, 1, Value::Bool(true)), Ok(()));
+        assert_eq!(sheet.try_get(1, 1), Some(&Value::Bool(true)));
+        assert_eq!(sheet.try_get(0, 0), Some(&Value::None));
+
+        assert_eq!(sheet.try_get(2, 0), None);
+        assert_eq!(sheet.try_get(0, 2), None);
+        assert_eq!(
+            sheet.try_set(2, 1, Value::Number(1.0)),
+            Err(OutOfBounds { x: 2, y: 1 })
+        );
+        assert_eq!(
+            sheet.try_set(0, 5, Value::Number(1.0)),
+            Err(OutOfBounds { x: 0, y: 5 })
+        );
+    }
+
+    fn row(values: &[f64]) -> s4::api::RowData {
+        s4::api::RowData {
+            values: Some(
+                values
+                    .iter()
+                    .map(|&number| s4::api::CellData {
+                        effective_value: Some(s4::api::ExtendedValue {
+                            number_value: Some(number),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    #[test]
+    fn jagged_grid_fills() {
+        // The first row is the shortest, its trailing empty cells were omitted
+        let grid = s4::api::GridData {
+            start_row: Some(0),
+            start_column: Some(1),
+            row_data: Some(vec![
+                row(&[1.0]),
+                row(&[2.0, 3.0, 4.0]),
+                s4::api::RowData { values: None },
+                row(&[5.0, 6.0]),
+            ]),
+            ..Default::default()
+        };
+
+        let mut sheet = LocalSheet::empty();
+        assert!(fill_grid(&mut sheet, "sheet", grid).is_ok());
+
+        assert_eq!((sheet.width(), sheet.height()), (4, 4));
+        assert_eq!(sheet.try_get(1, 0), Some(&Value::Number(1.0)));
+        assert_eq!(sheet.try_get(2, 0), Some(&Value::None));
+        assert_eq!(sheet.try_get(3, 1), Some(&Value::Number(4.0)));
+        assert_eq!(sheet.try_get(1, 2), Some(&Value::None));
+        assert_eq!(sheet.try_get(2, 3), Some(&Value::Number(6.0)));
+    }
+
+    #[test]
+    fn value_accessors() {
+        let number = Value::Number(1.5);
+        let string = Value::String("text".to_owned());
+        let bool = Value::Bool(true);
+
+        assert_eq!(number.as_number(), Some(1.5));
+        assert_eq!(string.as_str(), Some("text"));
+        assert_eq!(bool.as_bool(), Some(true));
+        assert!(Value::None.is_none());
+
+        // Mismatched types
+        assert_eq!(string.as_number(), None);
+        assert_eq!(bool.as_str(), None);
+        assert_eq!(number.as_bool(), None);
+        assert_eq!(Value::None.as_number(), None);
+        assert!(!number.is_none());
+    }
+
+    #[test]
+    fn value_try_into() {
+        let number: f64 = Value::Number(2.0).try_into().unwrap();
+        let string: String = Value::String("text".to_owned()).try_into().unwrap();
+        let bool: bool = Value::Bool(false).try_into().unwrap();
+
+        assert_eq!(number, 2.0);
+        assert_eq!(string, "text");
+        assert!(!bool);
+
+        let err = f64::try_from(Value::String("2".to_owned())).unwrap_err();
+        assert_eq!(
+            err,
+            ValueTypeError {
+                expected: "number",
+                found: "string"
+            }
+        );
+        assert!(String::try_from(Value::None).is_err());
+        assert!(bool::try_from(Value::Number(1.0)).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use std::error::Error as StdError;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::time::Instant;
+
+use crate::utils::text::truncate_chars;
+use crate::utils::verify::{
+    item::VerifyItemError, order::VerifyOrderError, payload::VerifyPayloadError,
+    product::VerifyProductError, user::VerifyUserError,
+    user_meta::VerifyUserMetaError,
+};
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Severity {
+    /// Failures caused by users, they have been notified already.
+    Low,
+    /// Failures of the bot itself, like failed writes.
+    High,
+}
+
+impl Severity {
+    pub fn of(error: &(dyn StdError + 'static)) -> Self {
+        // Verification errors are reported to the user, unless the warehouse failed
+        let user_caused = if error.is::<VerifyPayloadError>() {
+            true
+        } else if let Some(e) = error.downcast_ref::<VerifyOrderError>() {
+            !matches!(
+                e,
+                VerifyOrderError::WarehouseRefreshError(_)
+                    | VerifyOrderError::WarehouseUpdateError(_)
+            )
+        } else if let Some(e) = error.downcast_ref::<VerifyProductError>() {
+            !matches!(
+                e,
+                VerifyProductError::WarehouseRefreshError(_)
+                    | VerifyProductError::WarehouseUpdateError(_)
+            )
+        } else if let Some(e) = error.downcast_ref::<VerifyUserMetaError>() {
+            !matches!(
+                e,
+                VerifyUserMetaError::WarehouseRefreshError(_)
+                    | VerifyUserMetaError::WarehouseUpdateError(_)
+            )
+        } else if let Some(e) = error.downcast_ref::<VerifyUserError>() {
+            !matches!(e, VerifyUserError::WarehouseRefreshError(_))
+        } else if let Some(e) = error.downcast_ref::<VerifyItemError>() {
+            !matches!(e, VerifyItemError::WarehouseRefreshError(_))
+        } else {
+            false
+        };
+
+        if user_caused {
+            Self::Low
+        } else {
+            Self::High
+        }
+    }
+}
+
+/// Lets the first alert of a burst through and counts the rest until the interval passes.
+pub struct RateLimiter {
+    interval: Duration,
+    last_sent: Option<Instant>,
+    suppressed: usize,
+}
+
+impl RateLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_sent: None,
+            suppressed: 0,
+        }
+    }
+
+    /// Returns the number of alerts suppressed since the last one, if sending is allowed.
+    pub fn check(&mut self, now: Instant) -> Option<usize> {
+        match self.last_sent {
+            Some(last_sent) if now.duration_since(last_sent) < self.interval => {
+                self.suppressed += 1;
+                None
+            }
+            _ => {
+                self.last_sent = Some(now);
+                Some(std::mem::take(&mut self.suppressed))
+            }
+        }
+    }
+}
+
+pub struct Alerter {
+    min_severity: Severity,
+    limiter: RateLimiter,
+}
+
+impl Alerter {
+    pub fn new(min_severity: Severity, interval: Duration) -> Self {
+        Self {
+            min_severity,
+            limiter: RateLimiter::new(interval),
+        }
+    }
+
+    /// Builds the alert text for the operator chat, if the error should be forwarded.
+    pub fn prepare(&mut self, error: &(dyn StdError + 'static), now: Instant) -> Option<String> {
+        let severity = Severity::of(error);
+        if severity < self.min_severity {
+            return None;
+        }
+
+        let suppressed = self.limiter.check(now)?;
+
+        let mut text = format!("⚠️ {:?} severity error\n{}", severity, error);
+        // Keep the alert within the Telegram message limit
+        text = truncate_chars(&text, 3500);
+        if suppressed > 0 {
+            text += &format!("\n\n{} similar alerts were suppressed.", suppressed);
+        }
+
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UnkError;
+
+    fn high() -> UnkError {
+        UnkError::tables("write failed")
+    }
+
+    fn low() -> VerifyPayloadError {
+        VerifyPayloadError::PayloadEmpty
+    }
+
+    #[test]
+    fn severity_of_errors() {
+        assert_eq!(Severity::of(&high()), Severity::High);
+        assert_eq!(Severity::of(&low()), Severity::Low);
+        assert_eq!(
+            Severity::of(&VerifyItemError::NotFound("item".to_owned())),
+            Severity::Low
+        );
+        assert_eq!(
+            Severity::of(&VerifyItemError::WarehouseRefreshError(Box::new(high()))),
+            Severity::High
+        );
+    }
+
+    #[test]
+    fn forwards_configured_severity() {
+        let now = Instant::now();
+
+        let mut alerter = Alerter::new(Severity::High, Duration::from_secs(60));
+        assert!(alerter.prepare(&low(), now).is_none());
+        let text = alerter.prepare(&high(), now).unwrap();
+        assert!(text.contains("write failed"));
+
+        let mut alerter = Alerter::new(Severity::Low, Duration::from_secs(60));
+        assert!(alerter.prepare(&low(), now).is_some());
+    }
+
+    #[test]
+    fn coalesces_bursts() {
+        let now = Instant::now();
+        let mut alerter = Alerter::new(Severity::High, Duration::from_secs(60));
+
+        assert!(alerter.prepare(&high(), now).is_some());
+        for i in 1..=3 {
+            assert!(alerter.prepare(&high(), now + Duration::from_secs(i)).is_none());
+        }
+
+        let text = alerter
+            .prepare(&high(), now + Duration::from_secs(61))
+            .unwrap();
+        assert!(text.contains("3 similar alerts were suppressed."));
+
+        let text = alerter
+            .prepare(&high(), now + Duration::from_secs(122))
+            .unwrap();
+        assert!(!text.contains("suppressed"));
+    }
+}
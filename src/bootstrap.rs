@@ -0,0 +1,258 @@
+use std::{
+    error::Error as StdError,
+    fmt::Display,
+    fs::File,
+    io::{self, Read},
+    str::FromStr,
+};
+
+use age::x25519::Identity;
+use google_sheets4::oauth2::ServiceAccountKey;
+use tables::google_sheets::{sheet_ids::ResolveError, Error as SheetError};
+
+use crate::{config::Config, entries::currency::CurrencyError, BoxedError};
+
+const KEY_VAR: &str = "AGE_PRIVATE_KEY";
+const CONFIG_PATH: &str = "config.toml.enc";
+const CREDENTIALS_PATH: &str = "credentials.json.enc";
+
+#[derive(Debug)]
+pub enum BootstrapError {
+    MissingEnv(&'static str),
+    InvalidKey(&'static str),
+    Open(&'static str, io::Error),
+    PassphraseEncrypted(&'static str),
+    WrongKey(&'static str),
+    Corrupt(&'static str, String),
+    Config(toml::de::Error),
+    Credentials(serde_json::Error),
+    NotShared {
+        service_account: String,
+        spreadsheet_id: String,
+    },
+    Spreadsheet(SheetError),
+    SheetIds(ResolveError),
+    Wal(io::Error),
+    Replay(BoxedError),
+    DefaultShare(f32),
+    ExchangeRates(CurrencyError),
+    ProviderCurrencies(CurrencyError),
+}
+
+impl Display for BootstrapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BootstrapError::MissingEnv(var) => {
+                write!(f, "environment variable {} is not set", var)
+            }
+            BootstrapError::InvalidKey(e) => write!(f, "the age private key is invalid: {}", e),
+            BootstrapError::Open(path, e) => write!(f, "can't open {}: {}", path, e),
+            BootstrapError::PassphraseEncrypted(path) => write!(
+                f,
+                "{} is encrypted with a passphrase, expected an age recipient",
+                path
+            ),
+            BootstrapError::WrongKey(path) => {
+                write!(f, "{} is not encrypted for the given private key", path)
+            }
+            BootstrapError::Corrupt(path, e) => write!(f, "{} is corrupt: {}", path, e),
+            BootstrapError::Config(e) => write!(f, "can't parse the config: {}", e),
+            BootstrapError::Credentials(e) => write!(f, "can't parse the credentials: {}", e),
+            BootstrapError::NotShared {
+                service_account,
+                spreadsheet_id,
+            } => write!(
+                f,
+                concat!(
+                    "the spreadsheet {} is not shared with the service account {}, ",
+                    "open it in Google Sheets, press Share and add the account as an editor"
+                ),
+                spreadsheet_id, service_account
+            ),
+            BootstrapError::Spreadsheet(e) => write!(f, "can't open the spreadsheet: {}", e),
+            BootstrapError::SheetIds(e) => {
+                write!(f, "the sheet ids don't match the spreadsheet: {}", e)
+            }
+            BootstrapError::Wal(e) => write!(f, "can't open the write-ahead log: {}", e),
+            BootstrapError::Replay(e) => write!(f, "can't replay the write-ahead log: {}", e),
+            BootstrapError::DefaultShare(share) => {
+                write!(f, "the default share {} must be between 0 and 1", share)
+            }
+            BootstrapError::ExchangeRates(e) => write!(f, "invalid exchange rates: {}", e),
+            BootstrapError::ProviderCurrencies(e) => {
+                write!(f, "invalid provider currencies: {}", e)
+            }
+        }
+    }
+}
+
+impl StdError for BootstrapError {}
+
+/// Decrypts and parses the config and the service account credentials.
+pub fn load() -> Result<(Config, ServiceAccountKey), BootstrapError> {
+    let key = read_key(KEY_VAR)?;
+    let config = read_config(&key)?;
+    let creds = read_service_account_key(&key)?;
+
+    Ok((config, creds))
+}
+
+/// Explains the first failed request to the spreadsheet, a missing share is the usual cause.
+pub fn spreadsheet_error(
+    e: SheetError,
+    service_account: &str,
+    spreadsheet_id: &str,
+) -> BootstrapError {
+    if e.is_permission_denied() {
+        BootstrapError::NotShared {
+            service_account: service_account.to_owned(),
+            spreadsheet_id: spreadsheet_id.to_owned(),
+        }
+    } else {
+        BootstrapError::Spreadsheet(e)
+    }
+}
+
+fn read_key(var: &'static str) -> Result<Identity, BootstrapError> {
+    let key = std::env::var(var).map_err(|_| BootstrapError::MissingEnv(var))?;
+    parse_key(&key)
+}
+
+fn parse_key(key: &str) -> Result<Identity, BootstrapError> {
+    Identity::from_str(key.trim()).map_err(BootstrapError::InvalidKey)
+}
+
+fn read_config(key: &Identity) -> Result<Config, BootstrapError> {
+    let encrypted = File::open(CONFIG_PATH).map_err(|e| BootstrapError::Open(CONFIG_PATH, e))?;
+    parse_config(&decrypt(CONFIG_PATH, encrypted, key)?)
+}
+
+fn parse_config(config: &str) -> Result<Config, BootstrapError> {
+    toml::from_str(config).map_err(BootstrapError::Config)
+}
+
+fn read_service_account_key(key: &Identity) -> Result<ServiceAccountKey, BootstrapError> {
+    let encrypted =
+        File::open(CREDENTIALS_PATH).map_err(|e| BootstrapError::Open(CREDENTIALS_PATH, e))?;
+    let creds = decrypt(CREDENTIALS_PATH, encrypted, key)?;
+
+    serde_json::from_str(&creds).map_err(BootstrapError::Credentials)
+}
+
+fn decrypt(
+    path: &'static str,
+    encrypted: impl Read,
+    key: &Identity,
+) -> Result<String, BootstrapError> {
+    let corrupt = |e: &dyn Display| BootstrapError::Corrupt(path, e.to_string());
+
+    let decryptor = match age::Decryptor::new(encrypted).map_err(|e| corrupt(&e))? {
+        age::Decryptor::Recipients(d) => d,
+        age::Decryptor::Passphrase(_) => return Err(BootstrapError::PassphraseEncrypted(path)),
+    };
+
+    let mut reader = decryptor
+        .decrypt(std::iter::once(key as &dyn age::Identity))
+        .map_err(|e| match e {
+            age::DecryptError::NoMatchingKeys => BootstrapError::WrongKey(path),
+            e => corrupt(&e),
+        })?;
+
+    let mut data = String::new();
+    reader.read_to_string(&mut data).map_err(|e| corrupt(&e))?;
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn encrypt(data: &str, key: &Identity) -> Vec<u8> {
+        let encryptor = age::Encryptor::with_recipients(vec![Box::new(key.to_public())]).unwrap();
+
+        let mut encrypted = vec![];
+        let mut writer = encryptor.wrap_output(&mut encrypted).unwrap();
+        writer.write_all(data.as_bytes()).unwrap();
+        writer.finish().unwrap();
+
+        encrypted
+    }
+
+    #[test]
+    fn decrypt_round_trip() {
+        let key = Identity::generate();
+        let encrypted = encrypt("bot_token = \"token\"", &key);
+
+        let data = decrypt(CONFIG_PATH, encrypted.as_slice(), &key).unwrap();
+        assert_eq!(data, "bot_token = \"token\"");
+    }
+
+    #[test]
+    fn missing_key_env() {
+        assert!(matches!(
+            read_key("TELESTALL_TEST_MISSING_KEY"),
+            Err(BootstrapError::MissingEnv(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_key() {
+        assert!(matches!(
+            parse_key("not a key"),
+            Err(BootstrapError::InvalidKey(_))
+        ));
+    }
+
+    #[test]
+    fn wrong_key() {
+        let encrypted = encrypt("data", &Identity::generate());
+
+        let err = decrypt(CONFIG_PATH, encrypted.as_slice(), &Identity::generate()).unwrap_err();
+        assert!(matches!(err, BootstrapError::WrongKey(CONFIG_PATH)));
+    }
+
+    #[test]
+    fn corrupt_ciphertext() {
+        let key = Identity::generate();
+        let err = decrypt(CONFIG_PATH, "garbage".as_bytes(), &key).unwrap_err();
+        assert!(matches!(err, BootstrapError::Corrupt(CONFIG_PATH, _)));
+
+        // Intact header, but the payload is cut off
+        let mut encrypted = encrypt(&"data".repeat(1000), &key);
+        encrypted.truncate(encrypted.len() - 100);
+        let err = decrypt(CONFIG_PATH, encrypted.as_slice(), &key).unwrap_err();
+        assert!(matches!(err, BootstrapError::Corrupt(CONFIG_PATH, _)));
+    }
+
+    #[test]
+    fn forbidden_spreadsheet_is_not_shared() {
+        let forbidden = SheetError::Sheets(google_sheets4::Error::BadRequest(serde_json::json!({
+            "error": {
+                "code": 403,
+                "message": "The caller does not have permission",
+                "status": "PERMISSION_DENIED"
+            }
+        })));
+
+        let err = spreadsheet_error(forbidden, "bot@project.iam.gserviceaccount.com", "sheet-id");
+        assert!(matches!(err, BootstrapError::NotShared { .. }));
+
+        let text = err.to_string();
+        assert!(text.contains("bot@project.iam.gserviceaccount.com"));
+        assert!(text.contains("sheet-id"));
+
+        let err = spreadsheet_error(SheetError::InvalidResponse, "bot", "sheet-id");
+        assert!(matches!(err, BootstrapError::Spreadsheet(_)));
+    }
+
+    #[test]
+    fn malformed_config() {
+        assert!(matches!(
+            parse_config("[telegram\nbot_token = "),
+            Err(BootstrapError::Config(_))
+        ));
+    }
+}
@@ -0,0 +1,138 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::warn;
+use teloxide::{
+    prelude::*,
+    types::{Update, UpdateKind},
+};
+use tokio::{
+    sync::{OwnedRwLockWriteGuard, RwLock},
+    time,
+};
+
+use crate::inline::info_article;
+use crate::prelude::*;
+
+/// The localizations are kept in the warehouse which can't be locked at that point.
+const BUSY_TEXT: &str = "The bot is busy, please try again in a moment.";
+
+/// How long an update waits for the warehouse before the user is told the bot is busy.
+#[derive(Clone, Copy)]
+pub struct BusyTimeout(pub Duration);
+
+/// Locks the warehouse for the update and hands the guard on to the handlers
+/// as [`LockedWarehouse`]. When a long write holds it for more than the timeout,
+/// the user is told to retry instead of waiting for the bot in silence.
+pub fn gate() -> HandlerResult {
+    dptree::filter_map_async(
+        |bot: Bot, upd: Update, warehouse: SharedWarehouse, timeout: BusyTimeout| async move {
+            let reply = reply_busy(&bot, &upd);
+
+            match write_or_else(warehouse, timeout.0, reply).await {
+                Ok(guard) => guard.map(LockedWarehouse::new),
+                Err(e) => {
+                    warn!("Failed to tell the user the bot is busy: {}", e);
+                    None
+                }
+            }
+        },
+    )
+}
+
+/// Takes the write lock, waiting up to the timeout. Runs `busy` when the lock
+/// is held for longer.
+pub async fn write_or_else<T, F>(
+    lock: Arc<RwLock<T>>,
+    timeout: Duration,
+    busy: F,
+) -> Result<Option<OwnedRwLockWriteGuard<T>>>
+where
+    F: Future<Output = Result<()>>,
+{
+    if let Ok(guard) = time::timeout(timeout, lock.write_owned()).await {
+        return Ok(Some(guard));
+    }
+
+    busy.await?;
+    Ok(None)
+}
+
+async fn reply_busy(bot: &Bot, upd: &Update) -> Result<()> {
+    match &upd.kind {
+        UpdateKind::Message(msg) => {
+            bot.send_message(msg.chat.id, BUSY_TEXT).await?;
+        }
+        UpdateKind::CallbackQuery(q) => {
+            bot.answer_callback_query(&q.id)
+                .text(BUSY_TEXT)
+                .show_alert(true)
+                .await?;
+        }
+        // Telegram drops the checkout unless it's answered within seconds
+        UpdateKind::PreCheckoutQuery(q) => {
+            bot.answer_pre_checkout_query(&q.id, false)
+                .error_message(BUSY_TEXT)
+                .await?;
+        }
+        UpdateKind::InlineQuery(q) => {
+            let article = info_article("busy", BUSY_TEXT.to_owned(), String::new());
+            bot.answer_inline_query(&q.id, vec![article])
+                .cache_time(0)
+                .await?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn held_lock_replies_busy() {
+        let lock = Arc::new(RwLock::new(0));
+        let _held = lock.write().await;
+        let replied = AtomicBool::new(false);
+
+        let guard = time::timeout(
+            Duration::from_secs(1),
+            write_or_else(lock.clone(), Duration::from_millis(50), async {
+                replied.store(true, Ordering::SeqCst);
+                Ok(())
+            }),
+        )
+        .await
+        .expect("The held lock blocked the update")
+        .unwrap();
+
+        assert!(guard.is_none());
+        assert!(replied.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn free_lock_is_handed_on() {
+        let lock = Arc::new(RwLock::new(0));
+        let replied = AtomicBool::new(false);
+
+        let mut guard = write_or_else(lock.clone(), Duration::from_millis(50), async {
+            replied.store(true, Ordering::SeqCst);
+            Ok(())
+        })
+        .await
+        .unwrap()
+        .expect("The free lock wasn't taken");
+
+        assert!(!replied.load(Ordering::SeqCst));
+        // The handler holds the lock it was given until it's done
+        assert!(lock.try_write().is_err());
+        *guard += 1;
+        drop(guard);
+        assert_eq!(*lock.try_read().unwrap(), 1);
+    }
+}
@@ -1,7 +1,15 @@
+use log::warn;
+
 use crate::{
     localize_callq,
     prelude::*,
-    utils::{payload::PayloadOp, verify::verify_with_callback},
+    utils::{
+        outcome::{Flow, Outcome, Party},
+        payload::PayloadOp,
+        pending::paid_order_ids,
+        row::Row,
+        verify::{send_or_forget, verify_silently, verify_with_callback, verify_with_chat},
+    },
 };
 use teloxide::{
     prelude::*,
@@ -15,12 +23,24 @@ pub fn handler() -> HandlerResult {
             .branch(
                 dptree::filter(callback_prefix(PayloadOp::CompleteOrder)).endpoint(order_complete),
             )
-            .branch(dptree::filter(callback_prefix(PayloadOp::PayOrder)).endpoint(order_pay)),
+            .branch(dptree::filter(callback_prefix(PayloadOp::PayOrder)).endpoint(order_pay))
+            .branch(
+                dptree::filter(callback_prefix(PayloadOp::CompleteAllPaid))
+                    .endpoint(orders_complete_paid),
+            )
+            .branch(
+                dptree::filter(callback_prefix(PayloadOp::PublishProduct))
+                    .endpoint(product_publish),
+            )
+            .branch(
+                dptree::filter(callback_prefix(PayloadOp::ToggleProduct))
+                    .endpoint(product_toggle_active),
+            ),
     )
 }
 
-pub async fn order_cancel(bot: Bot, q: CallbackQuery, warehouse: SharedWarehouse) -> Result<()> {
-    let mut warehouse = warehouse.write().await;
+pub async fn order_cancel(bot: Bot, q: CallbackQuery, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
 
     let Some(username) = q.from.username.clone() else {
         bot.answer_callback_query(q.id.clone())
@@ -48,70 +68,21 @@ pub async fn order_cancel(bot: Bot, q: CallbackQuery, warehouse: SharedWarehouse
         .branch(|v| async move {
             let amount = v.result().amount;
             // Rollback changes
-            v.verify_product()
+            v.verify_product_any()
                 .await?
-                .update(|p| p.amount_left += amount)
+                .update(|p| p.put_back(amount))
                 .await
         })
         .await?
-        .branch(|v| async move {
-            let order_id = v.result().id.clone();
-            v.verify_customer()
-                .await?
-                .verify_meta()
-                .await?
-                .complete_order_by_id(&order_id)
-                .await
-        })
-        .await?
-        .branch(|v| async move {
-            let order_id = v.result().id.clone();
-            v.verify_merchant()
-                .await?
-                .verify_meta()
-                .await?
-                .complete_order_by_id(&order_id)
-                .await
-        })
-        .await?
-        .into_result();
-
-    let other_participant_name = if username != order.merchant {
-        order.merchant.clone()
-    } else {
-        order.customer.clone()
-    };
-
-    let other_participant_chat_id = verify_with_callback(&bot, &q, &mut warehouse)
-        .user_meta_by_name(&other_participant_name)
-        .await?
-        .has_chat_id()
-        .await?
-        .into_result()
-        .chat_id
-        .unwrap();
-
-    let item = verify_with_callback(&bot, &q, &mut warehouse)
-        .item_by_id(&order.item_id)
+        .close_in_metas()
         .await?
         .into_result();
 
-    bot.send_message(
-        other_participant_chat_id,
-        localize_callq!(
-            warehouse,
-            &q,
-            "We are sad to report, but your order for {name} has been canceled.",
-            "name" => item.name
-        ),
-    )
-    .reply_markup(ReplyMarkup::inline_kb(vec![vec![
-        InlineKeyboardButton::switch_inline_query_current_chat(
-            localize_callq!(warehouse, &q, "Details"),
-            format!(".o {}", order.id),
-        ),
-    ]]))
-    .await?;
+    let flow = Flow::Cancel(Party::of(&order, &username));
+    let outcome = Outcome::after(
+        flow,
+        tell_cancelled(&bot, &q, &mut warehouse, &username, &order).await,
+    );
 
     if let Some(msg) = q.message.clone() {
         bot.edit_message_reply_markup(msg.chat.id, msg.id)
@@ -120,18 +91,15 @@ pub async fn order_cancel(bot: Bot, q: CallbackQuery, warehouse: SharedWarehouse
     }
 
     bot.answer_callback_query(&q.id)
-        .text(localize_callq!(
-            warehouse,
-            &q,
-            "Order successfully cancelled."
-        ))
+        .text(localize_callq!(warehouse, &q, outcome.message(flow)))
+        .show_alert(outcome == Outcome::Unnotified)
         .await?;
 
     Ok(())
 }
 
-pub async fn order_complete(bot: Bot, q: CallbackQuery, warehouse: SharedWarehouse) -> Result<()> {
-    let mut warehouse = warehouse.write().await;
+pub async fn order_complete(bot: Bot, q: CallbackQuery, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
 
     let Some(username) = q.from.username.clone() else {
         bot.answer_callback_query(&q.id)
@@ -146,85 +114,17 @@ pub async fn order_complete(bot: Bot, q: CallbackQuery, warehouse: SharedWarehou
         .await?
         .verify_order()
         .await?
-        .stage_is_not(OrderStage::Cancelled)
-        .await?
-        .stage_is_not(OrderStage::Completed)
-        .await?
         .merchant_is(&username)
         .await?
-        .update(|order| {
-            order.stage = OrderStage::Completed;
-        })
-        .await?
-        .branch(|v| async move {
-            let amount = v.result().amount;
-            v.verify_product()
-                .await?
-                .update(|p| p.amount_sold += amount)
-                .await
-        })
-        .await?
-        .branch(|v| async move {
-            let order_id = v.result().id.clone();
-            v.verify_customer()
-                .await?
-                .verify_meta()
-                .await?
-                .complete_order_by_id(&order_id)
-                .await
-        })
-        .await?
-        .branch(|v| async move {
-            let order_id = v.result().id.clone();
-            v.verify_merchant()
-                .await?
-                .verify_meta()
-                .await?
-                .complete_order_by_id(&order_id)
-                .await
-        })
-        .await?
-        // Publish sale
-        .branch(|v| async move { v.verfy_sale().await?.publish().await })
+        .finish()
         .await?
         .into_result();
 
-    let other_participant_name = if username != order.merchant {
-        order.merchant.clone()
-    } else {
-        order.customer.clone()
-    };
-
-    let other_participant_chat_id = verify_with_callback(&bot, &q, &mut warehouse)
-        .user_meta_by_name(&other_participant_name)
-        .await?
-        .has_chat_id()
-        .await?
-        .into_result()
-        .chat_id
-        .unwrap();
-
-    let item = verify_with_callback(&bot, &q, &mut warehouse)
-        .item_by_id(&order.item_id)
-        .await?
-        .into_result();
-
-    bot.send_message(
-        other_participant_chat_id,
-        localize_callq!(
-            warehouse,
-            &q,
-            "Your order for {name} has been successfully completed. Thank you for your purchase!",
-            "name" => item.name
-        ),
-    )
-    .reply_markup(ReplyMarkup::inline_kb(vec![vec![
-        InlineKeyboardButton::switch_inline_query_current_chat(
-            localize_callq!(warehouse, &q, "Details"),
-            format!(".o {}", order.id),
-        ),
-    ]]))
-    .await?;
+    let flow = Flow::Complete(Party::of(&order, &username));
+    let outcome = Outcome::after(
+        flow,
+        tell_completed(&bot, &q, &mut warehouse, &username, &order).await,
+    );
 
     if let Some(msg) = q.message.clone() {
         bot.edit_message_reply_markup(msg.chat.id, msg.id)
@@ -232,19 +132,179 @@ pub async fn order_complete(bot: Bot, q: CallbackQuery, warehouse: SharedWarehou
             .await?;
     }
 
+    bot.answer_callback_query(&q.id)
+        .text(localize_callq!(warehouse, &q, outcome.message(flow)))
+        .show_alert(outcome == Outcome::Unnotified)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn orders_complete_paid(
+    bot: Bot,
+    q: CallbackQuery,
+    warehouse: LockedWarehouse,
+) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+
+    let Some(username) = q.from.username.clone() else {
+        bot.answer_callback_query(&q.id)
+            .text(localize_callq!(warehouse, &q, "No username"))
+            .show_alert(true)
+            .await?;
+        return Ok(());
+    };
+
+    warehouse.orders.refresh().await?;
+    let paid = paid_order_ids(warehouse.orders.inner.read()?, &username);
+    let lang_code = q.from.language_code.clone().unwrap_or("en".to_owned());
+
+    // The query can be answered once, so failures are reported to the chat
+    let mut completed = 0;
+    for order_id in paid.iter().cloned() {
+        let finished = complete_paid(
+            &bot,
+            q.from.id.into(),
+            lang_code.clone(),
+            &mut warehouse,
+            &username,
+            order_id.clone(),
+        )
+        .await;
+
+        match finished {
+            Ok(order) => {
+                completed += 1;
+                if let Err(e) = tell_completed(&bot, &q, &mut warehouse, &username, &order).await {
+                    warn!("Failed to tell about the completion of {}: {}", order.id, e);
+                }
+            }
+            Err(e) => warn!("Failed to complete the paid order {}: {}", order_id, e),
+        }
+    }
+
     bot.answer_callback_query(&q.id)
         .text(localize_callq!(
             warehouse,
             &q,
-            "Order successfully completed."
+            "Completed {completed} of {total} paid orders.",
+            "completed" => completed,
+            "total" => paid.len()
         ))
+        .show_alert(true)
         .await?;
 
     Ok(())
 }
 
-pub async fn order_pay(bot: Bot, q: CallbackQuery, warehouse: SharedWarehouse) -> Result<()> {
-    let mut warehouse = warehouse.write().await;
+async fn complete_paid(
+    bot: &Bot,
+    chat_id: ChatId,
+    lang_code: String,
+    warehouse: &mut Warehouse,
+    username: &str,
+    order_id: OrderId,
+) -> Result<Row<Order>> {
+    Ok(verify_with_chat(bot, chat_id, lang_code, warehouse)
+        .order_by_id(order_id)
+        .await?
+        .merchant_is(username)
+        .await?
+        .stage_is(OrderStage::Paid)
+        .await?
+        .finish()
+        .await?
+        .into_result())
+}
+
+/// Tells the other participant that the order is completed.
+async fn tell_completed(
+    bot: &Bot,
+    q: &CallbackQuery,
+    warehouse: &mut Warehouse,
+    username: &str,
+    order: &Order,
+) -> Result<()> {
+    let item = verify_silently(warehouse)
+        .item_by_id(&order.item_id)
+        .await?
+        .into_result();
+
+    let text = localize_callq!(
+        warehouse,
+        q,
+        "Your order for {name} has been successfully completed. Thank you for your purchase!",
+        "name" => item.name
+    );
+
+    tell_other_participant(bot, q, warehouse, username, order, text).await
+}
+
+/// Tells the other participant that the order is cancelled.
+async fn tell_cancelled(
+    bot: &Bot,
+    q: &CallbackQuery,
+    warehouse: &mut Warehouse,
+    username: &str,
+    order: &Order,
+) -> Result<()> {
+    let item = verify_silently(warehouse)
+        .item_by_id(&order.item_id)
+        .await?
+        .into_result();
+
+    let text = localize_callq!(
+        warehouse,
+        q,
+        "We are sad to report, but your order for {name} has been canceled.",
+        "name" => item.name
+    );
+
+    tell_other_participant(bot, q, warehouse, username, order, text).await
+}
+
+/// Sends the text to the other participant along with the order details. A participant
+/// without a chat finds the order among their ones later. Runs after the change is
+/// committed, so the errors are left to the caller instead of being shown to the user.
+async fn tell_other_participant(
+    bot: &Bot,
+    q: &CallbackQuery,
+    warehouse: &mut Warehouse,
+    username: &str,
+    order: &Order,
+    text: String,
+) -> Result<()> {
+    let other_participant_name = if username != order.merchant {
+        order.merchant.clone()
+    } else {
+        order.customer.clone()
+    };
+
+    warehouse.users_meta.refresh().await?;
+    let Some(other_participant_chat_id) = warehouse
+        .users_meta
+        .by_name
+        .get(&other_participant_name)
+        .and_then(|meta| meta.chat_id)
+    else {
+        return Ok(());
+    };
+
+    let request = bot
+        .send_message(other_participant_chat_id, text)
+        .reply_markup(ReplyMarkup::inline_kb(vec![vec![
+            InlineKeyboardButton::switch_inline_query_current_chat(
+                localize_callq!(warehouse, q, "Details"),
+                format!(".o {}", order.id),
+            ),
+        ]]));
+    send_or_forget(warehouse, &other_participant_name, request).await?;
+
+    Ok(())
+}
+
+pub async fn order_pay(bot: Bot, q: CallbackQuery, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
 
     let Some(username) = q.from.username.clone() else {
         bot.answer_callback_query(&q.id)
@@ -302,3 +362,85 @@ pub async fn order_pay(bot: Bot, q: CallbackQuery, warehouse: SharedWarehouse) -
 
     Ok(())
 }
+
+pub async fn product_publish(bot: Bot, q: CallbackQuery, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+
+    let Some(username) = q.from.username.clone() else {
+        bot.answer_callback_query(&q.id)
+            .text(localize_callq!(warehouse, &q, "No username"))
+            .show_alert(true)
+            .await?;
+        return Ok(());
+    };
+
+    let mut published = false;
+    verify_with_callback(&bot, &q, &mut warehouse)
+        .payload_str_opt(&q.data)
+        .await?
+        .verify_product()
+        .await?
+        .merchant_is(&username)
+        .await?
+        .update(|product| published = product.publish())
+        .await?;
+
+    let text = if published {
+        localize_callq!(
+            warehouse,
+            &q,
+            "The product is published, customers can see it now."
+        )
+    } else {
+        localize_callq!(warehouse, &q, "The product is already published.")
+    };
+
+    bot.answer_callback_query(&q.id).text(text).await?;
+
+    Ok(())
+}
+
+pub async fn product_toggle_active(
+    bot: Bot,
+    q: CallbackQuery,
+    warehouse: LockedWarehouse,
+) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+
+    let Some(username) = q.from.username.clone() else {
+        bot.answer_callback_query(&q.id)
+            .text(localize_callq!(warehouse, &q, "No username"))
+            .show_alert(true)
+            .await?;
+        return Ok(());
+    };
+
+    let mut active = false;
+    verify_with_callback(&bot, &q, &mut warehouse)
+        .payload_str_opt(&q.data)
+        .await?
+        .verify_product()
+        .await?
+        .merchant_is(&username)
+        .await?
+        .update(|product| active = product.toggle_active())
+        .await?;
+
+    let text = if active {
+        localize_callq!(
+            warehouse,
+            &q,
+            "The product is resumed, customers can see it again."
+        )
+    } else {
+        localize_callq!(
+            warehouse,
+            &q,
+            "The product is paused, customers can't see it until you resume it."
+        )
+    };
+
+    bot.answer_callback_query(&q.id).text(text).await?;
+
+    Ok(())
+}
@@ -0,0 +1,778 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, error, warn};
+use teloxide::prelude::*;
+
+use crate::prelude::*;
+use crate::refresh::{refresh, Ticker};
+use crate::utils::text::{fill_placeholders, truncate_utf16};
+
+/// Field of a listing a merchant may edit in the sheet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListingField {
+    Price,
+    Currency,
+    PaymentMethod,
+    Deleted,
+    Name,
+    Description,
+    Image,
+}
+
+impl ListingField {
+    /// Changes the customers holding an order of the product have to know about.
+    pub fn is_notable(&self) -> bool {
+        matches!(
+            self,
+            ListingField::Price | ListingField::Currency | ListingField::Deleted
+        )
+    }
+}
+
+pub fn product_changes(before: &Product, after: &Product) -> Vec<ListingField> {
+    [
+        (before.price != after.price, ListingField::Price),
+        (before.currency != after.currency, ListingField::Currency),
+        (
+            before.payment_method != after.payment_method,
+            ListingField::PaymentMethod,
+        ),
+        (before.deleted != after.deleted, ListingField::Deleted),
+    ]
+    .into_iter()
+    .filter_map(|(changed, field)| changed.then_some(field))
+    .collect()
+}
+
+pub fn item_changes(before: &Item, after: &Item) -> Vec<ListingField> {
+    [
+        (before.name != after.name, ListingField::Name),
+        (
+            before.inline_desc != after.inline_desc || before.full_desc != after.full_desc,
+            ListingField::Description,
+        ),
+        (before.image_url != after.image_url, ListingField::Image),
+        (before.deleted != after.deleted, ListingField::Deleted),
+    ]
+    .into_iter()
+    .filter_map(|(changed, field)| changed.then_some(field))
+    .collect()
+}
+
+/// A customer to tell about the edit of a product they have an open order of.
+#[derive(Debug, Clone)]
+pub struct Notice {
+    pub customer: String,
+    pub order_id: OrderId,
+    pub before: Product,
+    pub after: Product,
+    pub fields: Vec<ListingField>,
+}
+
+/// Products and items as they were seen on a refresh.
+#[derive(Default, Clone)]
+pub struct Catalog {
+    products: HashMap<ProductId, Product>,
+    items: HashMap<String, Item>,
+}
+
+impl Catalog {
+    pub fn new<'a>(
+        products: impl IntoIterator<Item = &'a Product>,
+        items: impl IntoIterator<Item = &'a Item>,
+    ) -> Self {
+        Self {
+            products: products.into_iter().map(|p| (p.id(), p.clone())).collect(),
+            items: items
+                .into_iter()
+                .map(|i| (i.id.clone(), i.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.products.is_empty() && self.items.is_empty()
+    }
+
+    /// Products listed for everyone, drafts, hidden products and products of
+    /// deleted items aren't announced.
+    fn listed(&self, id: &ProductId) -> Option<&Product> {
+        self.products.get(id).filter(|product| {
+            let item_deleted =
+                matches!(self.items.get(&product.item_id), Some(item) if item.deleted);
+            !product.deleted
+                && !item_deleted
+                && matches!(product.visibility, ProductVisibility::All)
+        })
+    }
+
+    fn name_of(&self, product: &Product) -> String {
+        match self.items.get(&product.item_id) {
+            Some(item) => item.name.clone(),
+            None => product.item_id.clone(),
+        }
+    }
+
+    /// Listed products added, removed or repriced since the snapshot.
+    pub fn diff(&self, after: &Catalog) -> CatalogDiff {
+        let mut diff = CatalogDiff::default();
+
+        for id in after.products.keys() {
+            let Some(product) = after.listed(id) else {
+                continue;
+            };
+
+            match self.listed(id) {
+                None => diff.added.push((after.name_of(product), product.clone())),
+                Some(before) if is_repriced(before, product) => {
+                    diff.repriced
+                        .push((after.name_of(product), before.clone(), product.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+
+        for id in self.products.keys() {
+            if let (Some(product), None) = (self.listed(id), after.listed(id)) {
+                diff.removed.push((self.name_of(product), product.clone()));
+            }
+        }
+
+        diff.sort();
+        diff
+    }
+}
+
+fn is_repriced(before: &Product, after: &Product) -> bool {
+    before.negotiated_price != after.negotiated_price
+        || (!after.negotiated_price
+            && (before.price != after.price || before.currency != after.currency))
+}
+
+/// Key phrases of the summary, the placeholders are filled after the translation.
+const ADDED: &str = "➕ {name} of {merchant} for {price}";
+const REPRICED: &str = "💱 {name} of {merchant}: {before} → {after}";
+const REMOVED: &str = "➖ {name} of {merchant}";
+const NEGOTIATED: &str = "negotiated";
+const SUMMARY_PHRASES: [&str; 4] = [ADDED, REPRICED, REMOVED, NEGOTIATED];
+
+/// Key phrases of the notices to the customers.
+const LISTING_CHANGED: &str =
+    "The seller has changed the listing of {name} you ordered, it may no longer be available.";
+const PRICE_CHANGED: &str =
+    "The seller has changed the price of {name} you ordered from {before} to {after}.";
+
+/// Changes of the catalog between two refreshes, each product with its name.
+#[derive(Default, Debug)]
+pub struct CatalogDiff {
+    pub added: Vec<(String, Product)>,
+    pub removed: Vec<(String, Product)>,
+    pub repriced: Vec<(String, Product, Product)>,
+}
+
+impl CatalogDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.repriced.is_empty()
+    }
+
+    fn sort(&mut self) {
+        self.added
+            .sort_by(|a, b| (&a.0, a.1.id()).cmp(&(&b.0, b.1.id())));
+        self.removed
+            .sort_by(|a, b| (&a.0, a.1.id()).cmp(&(&b.0, b.1.id())));
+        self.repriced
+            .sort_by(|a, b| (&a.0, a.2.id()).cmp(&(&b.0, b.2.id())));
+    }
+
+    /// One line per change, negotiated prices are shown as such. `translate`
+    /// gives the template of a key phrase in the language of the chat.
+    pub fn summary(&self, translate: impl Fn(&str) -> String) -> String {
+        let price = |product: &Product| {
+            if product.negotiated_price {
+                translate(NEGOTIATED)
+            } else {
+                product.currency.format_amount(product.price)
+            }
+        };
+        let line = |phrase: &str, args: &[(&str, String)]| {
+            let args = args
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect();
+            fill_placeholders(&translate(phrase), &args)
+        };
+
+        self.added
+            .iter()
+            .map(|(name, product)| {
+                line(
+                    ADDED,
+                    &[
+                        ("name", name.clone()),
+                        ("merchant", product.merchant.clone()),
+                        ("price", price(product)),
+                    ],
+                )
+            })
+            .chain(self.repriced.iter().map(|(name, before, after)| {
+                line(
+                    REPRICED,
+                    &[
+                        ("name", name.clone()),
+                        ("merchant", after.merchant.clone()),
+                        ("before", price(before)),
+                        ("after", price(after)),
+                    ],
+                )
+            }))
+            .chain(self.removed.iter().map(|(name, product)| {
+                line(
+                    REMOVED,
+                    &[
+                        ("name", name.clone()),
+                        ("merchant", product.merchant.clone()),
+                    ],
+                )
+            }))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Last seen listings, when customers were last told about them and the
+/// changes they weren't told about yet.
+pub struct ListingWatch {
+    catalog: Catalog,
+    notified: HashMap<(ProductId, String), DateTime<Utc>>,
+    pending: HashMap<(ProductId, String), Pending>,
+    debounce: Duration,
+}
+
+/// Changes of a product merged until the customer may be told again.
+struct Pending {
+    order_id: OrderId,
+    before: Product,
+    after: Product,
+    /// Item changes can't be recomputed from the products.
+    item_fields: Vec<ListingField>,
+}
+
+impl Pending {
+    /// The net change, the fields edited back and forth within the window are left out.
+    fn fields(&self) -> Vec<ListingField> {
+        let mut fields = product_changes(&self.before, &self.after);
+        for field in &self.item_fields {
+            if !fields.contains(field) {
+                fields.push(*field);
+            }
+        }
+        fields
+    }
+}
+
+impl ListingWatch {
+    pub fn new(debounce: Duration) -> Self {
+        Self {
+            catalog: Catalog::default(),
+            notified: HashMap::new(),
+            pending: HashMap::new(),
+            debounce,
+        }
+    }
+
+    /// Compares the listings with the last seen ones and returns notices for
+    /// the open orders of products with a notable change. Changes made within
+    /// the debounce window of the last notice are merged and told once the
+    /// window is over, so a sheet edited in several steps doesn't flood the
+    /// customer yet they end up with the latest listing. The first call only
+    /// takes a snapshot.
+    pub fn observe<'a>(
+        &mut self,
+        products: impl IntoIterator<Item = &'a Product>,
+        items: impl IntoIterator<Item = &'a Item>,
+        orders: impl IntoIterator<Item = &'a Order>,
+        now: DateTime<Utc>,
+    ) -> Vec<Notice> {
+        let previous = std::mem::replace(&mut self.catalog, Catalog::new(products, items));
+        if previous.is_empty() {
+            return vec![];
+        }
+
+        let mut changed = HashMap::new();
+        for (id, after) in &self.catalog.products {
+            let Some(before) = previous.products.get(id) else {
+                continue;
+            };
+
+            let mut item_fields = vec![];
+            if let (Some(before), Some(after)) = (
+                previous.items.get(&after.item_id),
+                self.catalog.items.get(&after.item_id),
+            ) {
+                item_fields = item_changes(before, after);
+            }
+
+            let notable = product_changes(before, after)
+                .iter()
+                .chain(&item_fields)
+                .any(ListingField::is_notable);
+            if notable {
+                changed.insert(*id, (before.clone(), after.clone(), item_fields));
+            }
+        }
+
+        let open: Vec<_> = orders.into_iter().filter(|order| order.is_open()).collect();
+        for order in &open {
+            let id = order.product_id();
+            let Some((before, after, item_fields)) = changed.get(&id) else {
+                continue;
+            };
+
+            let pending = self
+                .pending
+                .entry((id, order.customer.clone()))
+                .or_insert_with(|| Pending {
+                    order_id: order.id.clone(),
+                    before: before.clone(),
+                    after: after.clone(),
+                    item_fields: vec![],
+                });
+            pending.after = after.clone();
+            for field in item_fields {
+                if !pending.item_fields.contains(field) {
+                    pending.item_fields.push(*field);
+                }
+            }
+        }
+
+        // Orders closed in the meantime are nobody's business anymore
+        self.pending.retain(|(id, customer), _| {
+            open.iter()
+                .any(|order| order.product_id() == *id && order.customer == *customer)
+        });
+
+        let debounce = self.debounce;
+        self.notified.retain(|_, at| now - *at < debounce);
+        let due: Vec<_> = self
+            .pending
+            .keys()
+            .filter(|key| !self.notified.contains_key(*key))
+            .cloned()
+            .collect();
+
+        let mut notices = vec![];
+        for key in due {
+            let pending = self.pending.remove(&key).unwrap();
+            let fields = pending.fields();
+            if !fields.iter().any(ListingField::is_notable) {
+                continue;
+            }
+
+            self.notified.insert(key.clone(), now);
+            notices.push(Notice {
+                customer: key.1,
+                order_id: pending.order_id,
+                before: pending.before,
+                after: pending.after,
+                fields,
+            });
+        }
+        notices.sort_by(|a, b| a.order_id.cmp(&b.order_id));
+
+        notices
+    }
+}
+
+/// Tells the customers about notable edits of the products they ordered on every tick.
+/// The tables are fetched like the background refresh does, see [`refresh`], so the
+/// warehouse is locked only to install them and to compare the cached listings.
+pub async fn run<T: Ticker>(
+    bot: Bot,
+    warehouse: SharedWarehouse,
+    debounce: Duration,
+    mut ticker: T,
+) {
+    let mut watch = ListingWatch::new(debounce);
+
+    while ticker.tick().await {
+        if let Err(e) = refresh(&warehouse).await {
+            error!("Failed to fetch the listings: {}", e);
+            continue;
+        }
+        let mut warehouse = warehouse.write().await;
+
+        let notices = match observe(&mut warehouse, &mut watch) {
+            Ok(notices) => notices,
+            Err(e) => {
+                error!("Failed to check the listings for changes: {}", e);
+                continue;
+            }
+        };
+
+        let mut messages = vec![];
+        for notice in notices {
+            if let Some(message) = render(&mut warehouse, &notice).await {
+                messages.push((notice.customer, message));
+            }
+        }
+        drop(warehouse);
+
+        for (customer, (chat_id, text)) in messages {
+            if let Err(e) = bot.send_message(chat_id, text).await {
+                warn!("Failed to notify {} about a change: {}", customer, e);
+            }
+        }
+    }
+}
+
+/// Posts a summary of the catalog changes to the chat on every tick, quiet ticks post nothing.
+/// The tables are fetched apart from the warehouse like in [`run`].
+pub async fn run_changelog<T: Ticker>(
+    bot: Bot,
+    warehouse: SharedWarehouse,
+    chat_id: ChatId,
+    lang_code: String,
+    mut ticker: T,
+) {
+    let mut catalog = Catalog::default();
+
+    while ticker.tick().await {
+        if let Err(e) = refresh(&warehouse).await {
+            error!("Failed to fetch the catalog: {}", e);
+            continue;
+        }
+        let mut warehouse = warehouse.write().await;
+
+        let current = match snapshot(&mut warehouse) {
+            Ok(current) => current,
+            Err(e) => {
+                error!("Failed to take a snapshot of the catalog: {}", e);
+                continue;
+            }
+        };
+        let texts = translations(&mut warehouse, &lang_code, &SUMMARY_PHRASES).await;
+        drop(warehouse);
+
+        let previous = std::mem::replace(&mut catalog, current);
+        if previous.is_empty() {
+            continue;
+        }
+
+        let diff = previous.diff(&catalog);
+        if diff.is_empty() {
+            continue;
+        }
+
+        debug!(
+            "Catalog changed: {} added, {} repriced, {} removed",
+            diff.added.len(),
+            diff.repriced.len(),
+            diff.removed.len()
+        );
+        let summary = diff.summary(|phrase| texts[phrase].clone());
+        let text = truncate_utf16(&summary, 4096);
+        if let Err(e) = bot.send_message(chat_id, text).await {
+            warn!("Failed to post the catalog changes: {}", e);
+        }
+    }
+}
+
+fn snapshot(warehouse: &mut Warehouse) -> Result<Catalog> {
+    let Warehouse {
+        products, items, ..
+    } = warehouse;
+
+    Ok(Catalog::new(products.inner.read()?, items.inner.read()?))
+}
+
+/// Templates of the key phrases in the language, the missing ones are added to
+/// the localization sheet like `localize!` does. The cached translations are
+/// used, the tick has just fetched them.
+async fn translations(
+    warehouse: &mut Warehouse,
+    lang_code: &str,
+    phrases: &[&str],
+) -> HashMap<String, String> {
+    let mut texts = HashMap::new();
+    for phrase in phrases {
+        let text = match warehouse
+            .localization
+            .by_key_phrase
+            .get(&phrase.to_string())
+        {
+            Some(loc) => loc.get(lang_code),
+            None => {
+                let _ = warehouse
+                    .localization
+                    .extend_one(&Localization {
+                        key_phrase: phrase.to_string(),
+                        en: "-".to_owned(),
+                        ru: "-".to_owned(),
+                    })
+                    .await;
+                phrase.to_string()
+            }
+        };
+        texts.insert(phrase.to_string(), text);
+    }
+
+    texts
+}
+
+fn observe(warehouse: &mut Warehouse, watch: &mut ListingWatch) -> Result<Vec<Notice>> {
+    let Warehouse {
+        products,
+        items,
+        orders,
+        ..
+    } = warehouse;
+
+    let notices = watch.observe(
+        products.inner.read()?,
+        items.inner.read()?,
+        orders.inner.read()?,
+        Utc::now(),
+    );
+    debug!("Found {} listing change notices", notices.len());
+
+    Ok(notices)
+}
+
+/// The chat and the text to tell the customer about the notice, if they have a chat.
+async fn render(warehouse: &mut Warehouse, notice: &Notice) -> Option<(ChatId, String)> {
+    let chat_id = warehouse
+        .users_meta
+        .by_name
+        .get(&notice.customer)
+        .and_then(|meta| meta.chat_id)?;
+
+    let lang_code = warehouse
+        .users
+        .by_name
+        .get(&notice.customer)
+        .map(|user| user.lang_code.clone())
+        .unwrap_or("en".to_owned());
+
+    let name = match warehouse.items.by_id.get(&notice.after.item_id) {
+        Some(item) => item.name.clone(),
+        None => notice.after.item_id.clone(),
+    };
+
+    let mut args = HashMap::from([("name".to_owned(), name)]);
+    let phrase = if notice.fields.contains(&ListingField::Deleted) {
+        LISTING_CHANGED
+    } else {
+        let before = notice.before.currency.format_amount(notice.before.price);
+        let after = notice.after.currency.format_amount(notice.after.price);
+        args.insert("before".to_owned(), before);
+        args.insert("after".to_owned(), after);
+        PRICE_CHANGED
+    };
+
+    let texts = translations(warehouse, &lang_code, &[phrase]).await;
+    Some((chat_id, fill_placeholders(&texts[phrase], &args)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::fixtures;
+
+    fn product(price: f64) -> Product {
+        Product {
+            price,
+            amount_granted: 10.0,
+            amount_left: 10.0,
+            ..fixtures::product()
+        }
+    }
+
+    fn listing(item_id: &str, price: f64) -> Product {
+        Product {
+            item_id: item_id.to_owned(),
+            ..product(price)
+        }
+    }
+
+    fn named(id: &str, name: &str) -> Item {
+        Item {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            ..item("")
+        }
+    }
+
+    fn item(full_desc: &str) -> Item {
+        Item {
+            id: "item".to_owned(),
+            name: "Tea".to_owned(),
+            inline_desc: "".to_owned(),
+            full_desc: full_desc.to_owned(),
+            image_url: "".to_owned(),
+            deleted: false,
+        }
+    }
+
+    fn order(customer: &str, stage: OrderStage) -> Order {
+        Order {
+            id: format!("{}-order", customer),
+            customer: customer.to_owned(),
+            stage,
+            cost: 2.0,
+            ..fixtures::order()
+        }
+    }
+
+    #[test]
+    fn price_change_notifies_open_orders_only() {
+        let now = Utc::now();
+        let orders = [
+            order("waiting", OrderStage::WaitForPayment),
+            order("done", OrderStage::Completed),
+        ];
+        let mut watch = ListingWatch::new(Duration::minutes(10));
+        assert!(watch
+            .observe(&[product(2.0)], &[item("Green")], &orders, now)
+            .is_empty());
+
+        let notices = watch.observe(&[product(3.0)], &[item("Green")], &orders, now);
+
+        assert_eq!(notices.len(), 1);
+        assert_eq!(notices[0].customer, "waiting");
+        assert_eq!(notices[0].fields, vec![ListingField::Price]);
+        assert_eq!(
+            (notices[0].before.price, notices[0].after.price),
+            (2.0, 3.0)
+        );
+    }
+
+    #[test]
+    fn description_change_is_not_notable() {
+        let now = Utc::now();
+        let orders = [order("waiting", OrderStage::WaitForPayment)];
+        let mut watch = ListingWatch::new(Duration::minutes(10));
+        watch.observe(&[product(2.0)], &[item("Green")], &orders, now);
+
+        let notices = watch.observe(&[product(2.0)], &[item("Green, loose")], &orders, now);
+
+        assert!(notices.is_empty());
+    }
+
+    #[test]
+    fn repeated_edits_are_debounced() {
+        let now = Utc::now();
+        let orders = [order("waiting", OrderStage::WaitForPayment)];
+        let mut watch = ListingWatch::new(Duration::minutes(10));
+        watch.observe(&[product(2.0)], &[item("Green")], &orders, now);
+
+        // The last tick has no edit of its own, it delivers the one held back
+        let edits = [
+            (3.0, now),
+            (3.5, now + Duration::minutes(5)),
+            (4.0, now + Duration::minutes(7)),
+            (4.0, now + Duration::minutes(10)),
+        ];
+        let notices: Vec<_> = edits
+            .into_iter()
+            .map(|(price, at)| watch.observe(&[product(price)], &[item("Green")], &orders, at))
+            .collect();
+
+        let notified: Vec<_> = notices.iter().map(|n| !n.is_empty()).collect();
+        assert_eq!(notified, vec![true, false, false, true]);
+        let last = &notices[3][0];
+        assert_eq!((last.before.price, last.after.price), (3.0, 4.0));
+    }
+
+    #[test]
+    fn edits_undone_within_the_window_are_not_told() {
+        let now = Utc::now();
+        let orders = [order("waiting", OrderStage::WaitForPayment)];
+        let mut watch = ListingWatch::new(Duration::minutes(10));
+        watch.observe(&[product(2.0)], &[item("Green")], &orders, now);
+        watch.observe(&[product(3.0)], &[item("Green")], &orders, now);
+
+        watch.observe(
+            &[product(3.5)],
+            &[item("Green")],
+            &orders,
+            now + Duration::minutes(5),
+        );
+        let notices = watch.observe(
+            &[product(3.0)],
+            &[item("Green")],
+            &orders,
+            now + Duration::minutes(10),
+        );
+
+        assert!(notices.is_empty());
+    }
+
+    #[test]
+    fn catalog_diff_summarizes_changes() {
+        let items = [
+            named("tea", "Tea"),
+            named("coffee", "Coffee"),
+            named("cocoa", "Cocoa"),
+            named("mate", "Mate"),
+            named("rooibos", "Rooibos"),
+        ];
+        let mut draft = listing("mate", 4.0);
+        draft.visibility = ProductVisibility::Draft;
+        let rooibos = listing("rooibos", 1.5);
+
+        let before = Catalog::new(
+            &[
+                listing("tea", 2.0),
+                listing("coffee", 3.0),
+                draft.clone(),
+                rooibos.clone(),
+            ],
+            &items,
+        );
+        // The item is deleted while its product stays in the sheet
+        let mut after_items = items.to_vec();
+        after_items[4].deleted = true;
+        let after = Catalog::new(
+            &[listing("tea", 2.5), listing("cocoa", 1.0), draft, rooibos],
+            &after_items,
+        );
+
+        let diff = before.diff(&after);
+
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.repriced.len(), 1);
+        assert_eq!(diff.removed.len(), 2);
+        assert_eq!(
+            diff.summary(str::to_owned),
+            [
+                "➕ Cocoa of merchant for €1.00",
+                "💱 Tea of merchant: €2.00 → €2.50",
+                "➖ Coffee of merchant",
+                "➖ Rooibos of merchant",
+            ]
+            .join("\n")
+        );
+        assert!(after.diff(&after).is_empty());
+    }
+
+    #[test]
+    fn catalog_summary_is_translated() {
+        let items = [named("tea", "Чай")];
+        let mut negotiated = listing("tea", 0.0);
+        negotiated.negotiated_price = true;
+
+        let before = Catalog::new(&[listing("tea", 2.0)], &items);
+        let after = Catalog::new(&[negotiated], &items);
+
+        let ru = HashMap::from([
+            (REPRICED, "💱 {name} от {merchant}: {before} → {after}"),
+            (NEGOTIATED, "договорная"),
+        ]);
+        let summary = before
+            .diff(&after)
+            .summary(|phrase| ru.get(phrase).map_or(phrase, |text| *text).to_owned());
+
+        assert_eq!(summary, "💱 Чай от merchant: €2.00 → договорная");
+    }
+}
@@ -1,8 +1,30 @@
-use crate::{localize_msg, prelude::*};
+use std::sync::Arc;
+
+use chrono::Utc;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    localize_msg,
+    prelude::*,
+    usage::{self, Usage},
+    utils::{
+        archive, deadstock, dump, duplicate,
+        labels::display_stage,
+        lang_stats,
+        loc_transfer::{self, ImportPlan},
+        payload::Payload,
+        pending::{pending_orders, PendingAction},
+        preview, raw_row, restock, revenue,
+        verify::verify_with_msg,
+    },
+    warehouse::Table,
+};
 
 use teloxide::{
+    net::Download,
     prelude::*,
-    types::{InlineKeyboardButton, ReplyMarkup},
+    types::{InlineKeyboardButton, InputFile, ParseMode, ReplyMarkup},
+    utils::html,
 };
 
 pub fn handler() -> HandlerResult {
@@ -27,10 +49,100 @@ pub fn handler() -> HandlerResult {
                 .chain(filter_msg_prefix("🔄 Refresh"))
                 .endpoint(refresh),
         )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/archive"))
+                .endpoint(archive),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/restock"))
+                .endpoint(restock),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/deadstock"))
+                .endpoint(deadstock),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/usage"))
+                .endpoint(usage),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/lang_stats"))
+                .endpoint(language_stats),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/preview"))
+                .endpoint(preview),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/loc_export"))
+                .endpoint(localization_export),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/loc_import"))
+                .endpoint(localization_import),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/revenue"))
+                .endpoint(revenue),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/clone"))
+                .endpoint(clone),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/vacation"))
+                .endpoint(vacation),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/pending"))
+                .endpoint(pending),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/row"))
+                .endpoint(row),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/dump"))
+                .endpoint(dump),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/status"))
+                .endpoint(status),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/profile"))
+                .endpoint(profile),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/delete"))
+                .endpoint(delete),
+        )
+        .branch(
+            dptree::entry()
+                .chain(filter_msg_prefix("/restore"))
+                .endpoint(restore),
+        )
 }
 
-pub async fn start(bot: Bot, msg: Message, warehouse: SharedWarehouse) -> Result<()> {
-    let mut warehouse = warehouse.write().await;
+pub async fn start(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
     let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
 
     let lang_code = msg
@@ -53,8 +165,8 @@ pub async fn start(bot: Bot, msg: Message, warehouse: SharedWarehouse) -> Result
     Ok(())
 }
 
-pub async fn orders(bot: Bot, msg: Message, warehouse: SharedWarehouse) -> Result<()> {
-    let mut warehouse = warehouse.write().await;
+pub async fn orders(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
     let (user, meta) = handle_user_from_msg(&mut warehouse, &msg).await?;
 
     if !user.role.is_at_least(Role::User) || user.blocked {
@@ -86,8 +198,8 @@ pub async fn orders(bot: Bot, msg: Message, warehouse: SharedWarehouse) -> Resul
     Ok(())
 }
 
-pub async fn search(bot: Bot, msg: Message, warehouse: SharedWarehouse) -> Result<()> {
-    let mut warehouse = warehouse.write().await;
+pub async fn search(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
     let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
 
     if !user.role.is_at_least(Role::User) || user.blocked {
@@ -117,35 +229,1125 @@ pub async fn search(bot: Bot, msg: Message, warehouse: SharedWarehouse) -> Resul
     Ok(())
 }
 
-pub async fn refresh(bot: Bot, msg: Message, warehouse: SharedWarehouse) -> Result<()> {
-    let mut warehouse = warehouse.write().await;
+pub async fn refresh(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
     let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
 
     if !user.role.is_at_least(Role::Moderator) {
         return Ok(());
     }
 
-    warehouse.products.inner.mark_as_dirty();
+    warehouse.refresh_all().await?;
+
+    bot.send_message(msg.chat.id, localize_msg!(warehouse, msg, "Done."))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn archive(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::Moderator) {
+        return Ok(());
+    }
+
+    let warehouse = &mut **warehouse;
+    let threshold = Utc::now() - warehouse.archive_after;
+
+    let (Some(orders_archive), Some(sales_archive)) = (
+        warehouse.orders_archive.as_mut(),
+        warehouse.sales_archive.as_mut(),
+    ) else {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(warehouse, msg, "Archive sheets are not configured."),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    warehouse.orders.refresh().await?;
+    let orders = archive::expired_rows(warehouse.orders.inner.read()?, |order: &Order| {
+        order.date < threshold
+            && matches!(order.stage, OrderStage::Completed | OrderStage::Cancelled)
+    });
+    let archived: Vec<_> = orders.iter().map(|(_, order)| order.id.clone()).collect();
+    let orders = archive::move_rows(&mut warehouse.orders, orders_archive, orders).await?;
+    for id in &archived {
+        warehouse.completed_orders.remove(id);
+    }
+
+    warehouse.sales.refresh().await?;
+    let sales = archive::expired_rows(warehouse.sales.read()?, |sale: &Sale| {
+        sale.date < threshold
+    });
+    let sales = archive::move_rows(&mut warehouse.sales, sales_archive, sales).await?;
+
+    bot.send_message(
+        msg.chat.id,
+        localize_msg!(warehouse, msg,
+            "Archived {orders} orders and {sales} sales.",
+            "orders" => orders,
+            "sales" => sales
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn restock(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::Merchant) || user.blocked {
+        return Ok(());
+    }
+
     warehouse.products.refresh().await?;
-    warehouse.items.inner.mark_as_dirty();
     warehouse.items.refresh().await?;
-    warehouse.users.inner.mark_as_dirty();
+    warehouse.sales.refresh().await?;
+
+    // Moderators see every merchant, merchants only their own products
+    let products: Vec<_> = warehouse
+        .products
+        .inner
+        .read()?
+        .filter(|product| user.role == Role::Moderator || product.merchant == user.name)
+        .cloned()
+        .collect();
+
+    let now = Utc::now();
+    let (window, horizon) = (warehouse.restock_window, warehouse.restock_horizon);
+    let sales: Vec<_> = warehouse.sales.read()?.cloned().collect();
+
+    let report: Vec<_> = restock::restock_report(&products, &sales, now, window, horizon)
+        .into_iter()
+        .filter(|entry| entry.needs_restock)
+        .collect();
+
+    if report.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(warehouse, msg, "Nothing needs a restock right now."),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let mut lines = vec![localize_msg!(warehouse, msg, "<b>Restock suggestions</b>")];
+    for entry in report {
+        let name = match warehouse.items.by_id.get(&entry.item_id) {
+            Some(item) => item.name.clone(),
+            None => entry.item_id.clone(),
+        };
+
+        lines.push(localize_msg!(warehouse, msg,
+            "• {name} ({merchant}): {left} left, {velocity}/day, ~{days} days",
+            "name" => html::escape(&name),
+            "merchant" => html::escape(&entry.merchant),
+            "left" => entry.amount_left,
+            "velocity" => format!("{:.1}", entry.velocity),
+            "days" => format!("{:.0}", entry.days_left.unwrap_or(0.0))
+        ));
+    }
+
+    bot.send_message(msg.chat.id, lines.join("\n"))
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    Ok(())
+}
+
+/// Shows how often every command was used since the start.
+pub async fn usage(
+    bot: Bot,
+    msg: Message,
+    warehouse: LockedWarehouse,
+    usage: Arc<Usage>,
+) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::Moderator) {
+        return Ok(());
+    }
+
+    let text = format!(
+        "{}\n{}",
+        localize_msg!(warehouse, msg, "<b>Usage since the start</b>"),
+        usage::render(&usage.snapshot())
+    );
+
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    Ok(())
+}
+
+/// Shows how many users use every language, to prioritize translations.
+pub async fn language_stats(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::Moderator) {
+        return Ok(());
+    }
+
     warehouse.users.refresh().await?;
-    warehouse.users_meta.inner.mark_as_dirty();
-    warehouse.users_meta.refresh().await?;
-    // warehouse.sales.mark_as_dirty();
-    // warehouse.sales.refresh();
-    warehouse.merchants.inner.mark_as_dirty();
-    warehouse.merchants.refresh().await?;
-    warehouse.orders.inner.mark_as_dirty();
-    warehouse.orders.refresh().await?;
+    let distribution = lang_stats::language_distribution(warehouse.users.inner.read()?);
+
+    let text = format!(
+        "{}\n{}",
+        localize_msg!(warehouse, msg, "<b>Users by language</b>"),
+        lang_stats::render(&distribution)
+    );
+
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    Ok(())
+}
+
+/// Renders a localization key with sample values for its placeholders.
+/// Usage: `/preview <lang> <key>`, the key may contain spaces.
+pub async fn preview(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::Moderator) {
+        return Ok(());
+    }
+
+    let text = msg.text().unwrap_or_default();
+    let mut args = text.splitn(3, ' ').skip(1);
+    let (Some(lang), Some(key)) = (args.next(), args.next()) else {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(warehouse, msg, "Usage: /preview <lang> <key>"),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    warehouse.localization.refresh().await?;
+    let Some(loc) = warehouse
+        .localization
+        .by_key_phrase
+        .get(&key.to_owned())
+        .cloned()
+    else {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(warehouse, msg, "Unknown key {key}.", "key" => key),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let preview = preview::preview(&loc, lang);
+    let mut lines = vec![preview.rendered];
+    if !preview.missing.is_empty() {
+        lines.push(localize_msg!(warehouse, msg,
+            "Missing placeholders: {keys}",
+            "keys" => preview.missing.join(", ")
+        ));
+    }
+    if !preview.unfilled.is_empty() {
+        lines.push(localize_msg!(warehouse, msg,
+            "Unfilled placeholders: {keys}",
+            "keys" => preview.unfilled.join(", ")
+        ));
+    }
+
+    bot.send_message(msg.chat.id, lines.join("\n\n")).await?;
+
+    Ok(())
+}
+
+/// Sends every localization as a JSON file for translators, see `/loc_import`.
+pub async fn localization_export(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::Moderator) {
+        return Ok(());
+    }
+
     warehouse.localization.inner.mark_as_dirty();
     warehouse.localization.refresh().await?;
-    // warehouse.writeoffs.mark_as_dirty();
-    // warehouse.replenishments.mark_as_dirty();
+    let json = loc_transfer::export(warehouse.localization.inner.read()?)?;
 
-    bot.send_message(msg.chat.id, localize_msg!(warehouse, msg, "Done."))
+    bot.send_document(
+        msg.chat.id,
+        InputFile::memory(json.into_bytes()).file_name("localization.json"),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// The largest file `/loc_import` downloads, in kilobytes.
+const IMPORT_LIMIT_KB: u32 = 512;
+
+/// Upserts localizations by their key phrase from a JSON document made by `/loc_export`.
+/// Usage: reply `/loc_import` to the file, or paste the JSON after the command.
+pub async fn localization_import(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    {
+        let mut warehouse = warehouse.lock().await;
+        let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+        if !user.role.is_at_least(Role::Moderator) {
+            return Ok(());
+        }
+    }
+
+    // Downloaded without the lock, so a slow upload doesn't stall everyone else
+    let json = match msg.reply_to_message().and_then(|reply| reply.document()) {
+        Some(document) if document.file.size > IMPORT_LIMIT_KB * 1024 => None,
+        Some(document) => {
+            let file = bot.get_file(&document.file.id).await?;
+            let mut content = vec![];
+            bot.download_file(&file.path, &mut content).await?;
+            String::from_utf8(content).ok()
+        }
+        None => {
+            let text = msg.text().unwrap_or_default();
+            Some(
+                text.split_once(char::is_whitespace)
+                    .map(|(_, json)| json.trim().to_owned())
+                    .unwrap_or_default(),
+            )
+        }
+    };
+
+    let mut warehouse = warehouse.lock().await;
+
+    let Some(json) = json else {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(warehouse, msg,
+                "Nothing is imported, the file must be UTF-8 JSON of at most {max} KB.",
+                "max" => IMPORT_LIMIT_KB
+            ),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    if json.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(
+                warehouse,
+                msg,
+                "Usage: reply /loc_import to a JSON file, or paste the JSON after the command."
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let imported = match loc_transfer::parse(&json) {
+        Ok(imported) => imported,
+        Err(e) => {
+            bot.send_message(
+                msg.chat.id,
+                localize_msg!(warehouse, msg,
+                    "Nothing is imported, the document is invalid: {error}",
+                    "error" => e
+                ),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    warehouse.localization.inner.mark_as_dirty();
+    warehouse.localization.refresh().await?;
+    let plan = ImportPlan::new(imported, |key| {
+        warehouse.localization.by_key_phrase.get_with_row(key)
+    });
+
+    for (from_row, run) in plan.update_runs() {
+        warehouse.localization.update(from_row, run).await?;
+    }
+    if !plan.additions.is_empty() {
+        warehouse.localization.extend(&plan.additions).await?;
+    }
+
+    bot.send_message(
+        msg.chat.id,
+        localize_msg!(warehouse, msg,
+            "Imported: {added} added, {updated} updated, {unchanged} unchanged.",
+            "added" => plan.additions.len(),
+            "updated" => plan.updates.len(),
+            "unchanged" => plan.unchanged
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Sums the revenue of all sales in the base currency.
+pub async fn revenue(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::Moderator) {
+        return Ok(());
+    }
+
+    let Some(rates) = warehouse.exchange_rates.clone() else {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(warehouse, msg, "No base currency is configured."),
+        )
         .await?;
+        return Ok(());
+    };
+
+    let sales = warehouse.all_sales().await?;
+    let (total, unconverted) = revenue::base_revenue(&sales, &rates);
+
+    let mut text = localize_msg!(warehouse, msg,
+        "Revenue of all sales: {total}",
+        "total" => rates.base.format_amount(total)
+    );
+    if unconverted > 0 {
+        text.push('\n');
+        text.push_str(&localize_msg!(warehouse, msg,
+            "{count} sales in currencies without a rate are left out.",
+            "count" => unconverted
+        ));
+    }
+
+    bot.send_message(msg.chat.id, text).await?;
 
     Ok(())
 }
+
+/// Lists products in stock that didn't sell lately, `/deadstock <days>` overrides the window.
+pub async fn deadstock(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::Merchant) || user.blocked {
+        return Ok(());
+    }
+
+    let window = match msg.text().and_then(|text| text.split_whitespace().nth(1)) {
+        Some(days) => match deadstock::parse_window(days) {
+            Some(window) => window,
+            None => {
+                let usage = localize_msg!(warehouse, msg,
+                    "Usage: /deadstock [days], from 1 to {max} days.",
+                    "max" => deadstock::MAX_WINDOW_DAYS
+                );
+                bot.send_message(msg.chat.id, usage).await?;
+                return Ok(());
+            }
+        },
+        None => warehouse.deadstock_window,
+    };
+
+    warehouse.products.refresh().await?;
+    warehouse.items.refresh().await?;
+
+    // Moderators see every merchant, merchants only their own products
+    let products: Vec<_> = warehouse
+        .products
+        .inner
+        .read()?
+        .filter(|product| user.role == Role::Moderator || product.merchant == user.name)
+        .filter(|product| !product.deleted)
+        .cloned()
+        .collect();
+
+    // The archived sales still tell when a product sold last
+    let sales = warehouse.all_sales().await?;
+
+    let report = deadstock::dead_stock_report(&products, &sales, Utc::now(), window);
+
+    if report.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(warehouse, msg, "Everything in stock sells, no dead stock."),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let mut lines = vec![localize_msg!(warehouse, msg,
+        "<b>No sales in {days} days, consider a markdown</b>",
+        "days" => window.num_days()
+    )];
+    for entry in report {
+        let name = match warehouse.items.by_id.get(&entry.item_id) {
+            Some(item) => item.name.clone(),
+            None => entry.item_id.clone(),
+        };
+        let last_sale = match entry.last_sale {
+            Some(date) => date.format("%Y-%m-%d").to_string(),
+            None => localize_msg!(warehouse, msg, "never"),
+        };
+
+        lines.push(localize_msg!(warehouse, msg,
+            "• {name} ({merchant}): {left} left, last sold {last_sale}",
+            "name" => html::escape(&name),
+            "merchant" => html::escape(&entry.merchant),
+            "left" => entry.amount_left,
+            "last_sale" => last_sale
+        ));
+    }
+
+    bot.send_message(msg.chat.id, lines.join("\n"))
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    Ok(())
+}
+
+/// Shows the delivery note saved to the profile, `/profile <note>` replaces it and `/profile -` clears it.
+pub async fn profile(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::User) || user.blocked {
+        return Ok(());
+    }
+
+    let mut meta = verify_with_msg(&bot, &msg, &mut warehouse)
+        .user_meta_by_name(&user.name)
+        .await?
+        .into_result();
+
+    let note = command_args(msg.text().unwrap_or_default()).to_owned();
+
+    let text = if note.trim().is_empty() {
+        match &meta.note {
+            Some(note) => localize_msg!(warehouse, msg,
+                "Your delivery note: {note}\nSend \"/profile <note>\" to change it or \"/profile -\" to remove it.",
+                "note" => note
+            ),
+            None => localize_msg!(
+                warehouse,
+                msg,
+                "You have no delivery note yet. Send \"/profile <note>\" to save one, e.g. your address."
+            ),
+        }
+    } else {
+        meta.set_note(&note);
+        warehouse.users_meta.update_one(meta.row, &meta).await?;
+
+        match meta.note {
+            Some(_) => localize_msg!(
+                warehouse,
+                msg,
+                "Saved! You can attach the note when confirming an order."
+            ),
+            None => localize_msg!(warehouse, msg, "The delivery note was removed."),
+        }
+    };
+
+    bot.send_message(msg.chat.id, text).await?;
+
+    Ok(())
+}
+
+/// Soft-deletes an item or a product: `/delete item <id>` or `/delete product <merchant> <item id>`.
+pub async fn delete(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    set_deleted(bot, msg, warehouse, true).await
+}
+
+/// Brings back a soft-deleted item or product, takes the same arguments as `/delete`.
+pub async fn restore(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    set_deleted(bot, msg, warehouse, false).await
+}
+
+async fn set_deleted(
+    bot: Bot,
+    msg: Message,
+    warehouse: LockedWarehouse,
+    deleted: bool,
+) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::Moderator) {
+        return Ok(());
+    }
+
+    let text = msg.text().unwrap_or_default();
+    let args: Vec<_> = text.split_whitespace().skip(1).collect();
+
+    let found = match args.as_slice() {
+        ["item", id] => {
+            warehouse.items.refresh().await?;
+            let item = warehouse
+                .items
+                .by_id
+                .get_with_row(&id.to_string())
+                .map(|(row, item)| (*row, item.clone()));
+
+            match item {
+                Some((row, mut item)) => {
+                    item.deleted = deleted;
+                    warehouse.items.update_one(row, &item).await?;
+                    true
+                }
+                None => false,
+            }
+        }
+        ["product", merchant, item_id] => {
+            warehouse.products.refresh().await?;
+            let product = warehouse
+                .products
+                .by_id
+                .get_with_row(&Product::id_from(merchant, item_id))
+                .map(|(row, product)| (*row, product.clone()));
+
+            match product {
+                Some((row, mut product)) => {
+                    product.deleted = deleted;
+                    warehouse.products.update_one(row, &product).await?;
+                    true
+                }
+                None => false,
+            }
+        }
+        _ => {
+            bot.send_message(
+                msg.chat.id,
+                localize_msg!(
+                    warehouse,
+                    msg,
+                    "Usage: /delete (or /restore) item <id> or product <merchant> <item id>"
+                ),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let text = match (found, deleted) {
+        (false, _) => localize_msg!(warehouse, msg, "Nothing was found."),
+        (true, true) => localize_msg!(warehouse, msg, "Deleted, use /restore to bring it back."),
+        (true, false) => localize_msg!(warehouse, msg, "Restored."),
+    };
+
+    bot.send_message(msg.chat.id, text).await?;
+
+    Ok(())
+}
+
+/// Lists a copy of a product for another merchant:
+/// `/clone <merchant> <item id> <target merchant> [amount]`.
+/// Merchants can only copy their own products to themselves, see [`duplicate::may_clone`].
+pub async fn clone(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::Merchant) || user.blocked {
+        return Ok(());
+    }
+
+    let text = msg.text().unwrap_or_default();
+    let args: Vec<_> = text.split_whitespace().skip(1).collect();
+
+    let parsed = match args.as_slice() {
+        [merchant, item_id, target] => Some((*merchant, *item_id, *target, 0)),
+        [merchant, item_id, target, amount] => amount
+            .parse::<u32>()
+            .ok()
+            .map(|amount| (*merchant, *item_id, *target, amount)),
+        _ => None,
+    };
+
+    let Some((merchant, item_id, target, amount)) = parsed else {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(
+                warehouse,
+                msg,
+                "Usage: /clone <merchant> <item id> <target merchant> [amount]"
+            ),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    if !duplicate::may_clone(&user, merchant, target) {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(
+                warehouse,
+                msg,
+                "You can only clone your own products, and only for yourself."
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    warehouse.merchants.refresh().await?;
+    warehouse.products.refresh().await?;
+
+    if warehouse
+        .merchants
+        .by_name
+        .get(&target.to_owned())
+        .is_none()
+    {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(warehouse, msg,
+                "There is no merchant named {target}.",
+                "target" => target
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    if warehouse
+        .products
+        .by_id
+        .get(&Product::id_from(target, item_id))
+        .is_some()
+    {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(warehouse, msg,
+                "{target} already sells this item.",
+                "target" => target
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let source = warehouse
+        .products
+        .by_id
+        .get(&Product::id_from(merchant, item_id))
+        .filter(|product| !product.deleted)
+        .cloned();
+
+    let Some(source) = source else {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(warehouse, msg, "Nothing was found."),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let copy = duplicate::duplicate_product(&source, target, amount);
+    if Product::new_share(Some(copy.share), warehouse.default_share).is_none() {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(warehouse, msg,
+                "The share {share} of the product is out of range, fix it before cloning.",
+                "share" => copy.share
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    warehouse.products.extend_one(&copy).await?;
+
+    bot.send_message(
+        msg.chat.id,
+        localize_msg!(warehouse, msg,
+            "The product is now listed by {target} too.",
+            "target" => target
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Orders listed by `/pending`, the rest is left for the next call.
+const PENDING_LIMIT: usize = 20;
+
+/// Lists the orders waiting for the merchant with a button to act on each.
+pub async fn pending(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::Merchant) || user.blocked {
+        return Ok(());
+    }
+
+    let lang_code = msg
+        .from()
+        .map(|u| u.language_code.clone())
+        .flatten()
+        .unwrap_or("en".to_owned());
+
+    warehouse.orders.refresh().await?;
+    warehouse.items.refresh().await?;
+    let pending: Vec<_> = pending_orders(warehouse.orders.inner.read()?, &user.name)
+        .into_iter()
+        .map(|(order, action)| (order.clone(), action))
+        .collect();
+
+    if pending.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(warehouse, msg, "No orders are waiting for you."),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let mut lines = vec![];
+    let mut keyboard = vec![];
+    for (idx, (order, action)) in pending.iter().enumerate().take(PENDING_LIMIT) {
+        let name = match warehouse.items.by_id.get(&order.item_id) {
+            Some(item) => item.name.clone(),
+            None => order.item_id.clone(),
+        };
+        let stage = display_stage(&mut warehouse, &lang_code, &order.stage).await;
+        lines.push(format!(
+            "{}. {}x {} • {} • {}",
+            idx + 1,
+            order.amount,
+            name,
+            order.customer,
+            stage
+        ));
+
+        let button = match action {
+            PendingAction::SpecifyPrice => InlineKeyboardButton::callback(
+                localize_msg!(warehouse, msg, "💬 Specify price #{idx}", "idx" => idx + 1),
+                Payload::specify_order_price(order.id.clone()).to_string(),
+            ),
+            PendingAction::Complete => InlineKeyboardButton::callback(
+                localize_msg!(warehouse, msg, "✅ Complete #{idx}", "idx" => idx + 1),
+                Payload::complete_order(order.id.clone()).to_string(),
+            ),
+        };
+        keyboard.push(vec![button]);
+    }
+
+    if pending.len() > PENDING_LIMIT {
+        lines.push(localize_msg!(warehouse, msg,
+            "...and {count} more.",
+            "count" => pending.len() - PENDING_LIMIT
+        ));
+    }
+
+    let paid = pending
+        .iter()
+        .filter(|(order, _)| order.stage == OrderStage::Paid)
+        .count();
+    if paid > 0 {
+        keyboard.push(vec![InlineKeyboardButton::callback(
+            localize_msg!(warehouse, msg, "✅ Complete all paid ({count})", "count" => paid),
+            Payload::complete_all_paid().to_string(),
+        )]);
+    }
+
+    let text = format!(
+        "{}\n\n{}",
+        localize_msg!(warehouse, msg, "Orders waiting for you:"),
+        lines.join("\n")
+    );
+    bot.send_message(msg.chat.id, text)
+        .reply_markup(ReplyMarkup::inline_kb(keyboard))
+        .await?;
+
+    Ok(())
+}
+
+/// Pauses or resumes the sales of a merchant: `/vacation on|off`.
+pub async fn vacation(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::Merchant) || user.blocked {
+        return Ok(());
+    }
+
+    let text = msg.text().unwrap_or_default();
+    let vacation = match text.split_whitespace().nth(1) {
+        Some("on") => true,
+        Some("off") => false,
+        _ => {
+            bot.send_message(
+                msg.chat.id,
+                localize_msg!(warehouse, msg, "Usage: /vacation on|off"),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    warehouse.merchants.refresh().await?;
+    let rows: Vec<_> = warehouse
+        .merchants
+        .by_name
+        .group(&user.name)
+        .into_iter()
+        .flatten()
+        .map(|(row, merchant)| (*row, merchant.clone()))
+        .collect();
+
+    if rows.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(warehouse, msg, "You don't have a merchant profile."),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    for (row, mut merchant) in rows {
+        merchant.vacation = vacation;
+        warehouse.merchants.update_one(row, &merchant).await?;
+    }
+
+    let text = if vacation {
+        localize_msg!(
+            warehouse,
+            msg,
+            "Your products are hidden until you're back, use /vacation off to resume sales."
+        )
+    } else {
+        localize_msg!(
+            warehouse,
+            msg,
+            "Welcome back, your products are visible again."
+        )
+    };
+
+    bot.send_message(msg.chat.id, text).await?;
+
+    Ok(())
+}
+
+/// Shows a raw sheet row, and overwrites one of its cells if a column and a value are given.
+/// Usage: `/row <table> <row> [<column> <value>]`, the row is counted from the first data row.
+pub async fn row(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::Moderator) {
+        return Ok(());
+    }
+
+    let text = msg.text().unwrap_or_default();
+    let mut args = text.splitn(5, ' ').skip(1);
+    let (Some(table), Some(Ok(row))) = (args.next(), args.next().map(str::parse::<usize>)) else {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(
+                warehouse,
+                msg,
+                "Usage: /row <table> <row> [<column> <value>]"
+            ),
+        )
+        .await?;
+        return Ok(());
+    };
+    let edit = args.next().zip(args.next());
+
+    let warehouse = &mut **warehouse;
+    let rendered = match table {
+        "items" => raw_row(&mut warehouse.items.inner, row, edit).await?,
+        "products" => raw_row(&mut warehouse.products.inner, row, edit).await?,
+        "users" => raw_row(&mut warehouse.users.inner, row, edit).await?,
+        "users_meta" => raw_row(&mut warehouse.users_meta.inner, row, edit).await?,
+        "merchants" => raw_row(&mut warehouse.merchants.inner, row, edit).await?,
+        "orders" => raw_row(&mut warehouse.orders.inner, row, edit).await?,
+        "localization" => raw_row(&mut warehouse.localization.inner, row, edit).await?,
+        "sales" => raw_row(&mut warehouse.sales, row, edit).await?,
+        "replenishments" => raw_row(&mut warehouse.replenishments, row, edit).await?,
+        "writeoffs" => raw_row(&mut warehouse.writeoffs, row, edit).await?,
+        _ => {
+            bot.send_message(
+                msg.chat.id,
+                localize_msg!(warehouse, msg, "Unknown table {table}.", "table" => table),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let text = match rendered {
+        Some(rendered) => rendered,
+        None => localize_msg!(warehouse, msg, "Unknown column."),
+    };
+
+    bot.send_message(msg.chat.id, text).await?;
+
+    Ok(())
+}
+
+/// Refetches a table and shows its first rows as the bot has them cached.
+/// Usage: `/dump <table> [<rows>]`, 10 rows by default.
+pub async fn dump(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::Moderator) {
+        return Ok(());
+    }
+
+    let text = msg.text().unwrap_or_default();
+    let mut args = text.split_whitespace().skip(1);
+    let table = args.next();
+    let limit = args.next().map_or(Ok(10), str::parse::<usize>);
+    let (Some(table), Ok(limit)) = (table, limit) else {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(warehouse, msg, "Usage: /dump <table> [<rows>]"),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    // Forks are fetched as a whole, so their indices match the dumped rows
+    macro_rules! dump_fork {
+        ($fork:expr) => {{
+            $fork.inner.mark_as_dirty();
+            dump::dump(&mut $fork, limit).await?
+        }};
+    }
+
+    let warehouse = &mut **warehouse;
+    let (rows, total) = match table {
+        "items" => dump_fork!(warehouse.items),
+        "products" => dump_fork!(warehouse.products),
+        "users" => dump_fork!(warehouse.users),
+        "users_meta" => dump_fork!(warehouse.users_meta),
+        "merchants" => dump_fork!(warehouse.merchants),
+        "orders" => dump_fork!(warehouse.orders),
+        "localization" => dump_fork!(warehouse.localization),
+        "sales" => {
+            warehouse.sales.mark_as_dirty();
+            dump::dump(&mut warehouse.sales, limit).await?
+        }
+        "replenishments" => {
+            warehouse.replenishments.mark_as_dirty();
+            dump::dump(&mut warehouse.replenishments, limit).await?
+        }
+        "writeoffs" => {
+            warehouse.writeoffs.mark_as_dirty();
+            dump::dump(&mut warehouse.writeoffs, limit).await?
+        }
+        _ => {
+            bot.send_message(
+                msg.chat.id,
+                localize_msg!(warehouse, msg, "Unknown table {table}.", "table" => table),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let header = localize_msg!(warehouse, msg,
+        "{table}: {shown} of {total} rows",
+        "table" => table,
+        "shown" => limit.min(total),
+        "total" => total
+    );
+    let text = format!("{}\n\n{}", header, rows);
+
+    // Longer dumps don't fit into a message
+    if text.chars().count() > 4096 {
+        bot.send_document(
+            msg.chat.id,
+            InputFile::memory(text.into_bytes()).file_name(format!("{}.txt", table)),
+        )
+        .await?;
+    } else {
+        bot.send_message(msg.chat.id, text).await?;
+    }
+
+    Ok(())
+}
+
+/// Shows the meta cells of every table as of its last version check, and when
+/// the version is checked again.
+pub async fn status(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
+
+    if !user.role.is_at_least(Role::Moderator) {
+        return Ok(());
+    }
+
+    let header = localize_msg!(warehouse, msg, "Tables as of their last version check:");
+
+    let now = Utc::now();
+    let warehouse = &**warehouse;
+    let lines = [
+        table_status("items", &warehouse.items.inner, now),
+        table_status("products", &warehouse.products.inner, now),
+        table_status("users", &warehouse.users.inner, now),
+        table_status("users_meta", &warehouse.users_meta.inner, now),
+        table_status("merchants", &warehouse.merchants.inner, now),
+        table_status("orders", &warehouse.orders.inner, now),
+        table_status("localization", &warehouse.localization.inner, now),
+        table_status("sales", &warehouse.sales, now),
+        table_status("replenishments", &warehouse.replenishments, now),
+        table_status("writeoffs", &warehouse.writeoffs, now),
+    ];
+
+    bot.send_message(msg.chat.id, format!("{}\n\n{}", header, lines.join("\n")))
+        .await?;
+
+    Ok(())
+}
+
+/// A `/status` line, "?" stands for a meta cell the sheet doesn't have.
+fn table_status<E>(name: &str, table: &Table<E>, now: chrono::DateTime<Utc>) -> String {
+    let clock = table.origin();
+    let sheet = clock.inner();
+    let meta = sheet.meta();
+    let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "?".to_owned());
+
+    format!(
+        "{}: hash {}, {} rows ({} in the sheet), schema {}, updated {}, checked again in {}s",
+        name,
+        or_unknown(Some(meta.hash.clone()).filter(|hash| !hash.is_empty())),
+        or_unknown(meta.row_count.map(|count| count.to_string())),
+        sheet.row_count(),
+        or_unknown(meta.schema_version.map(|version| version.to_string())),
+        or_unknown(meta.updated_at.clone()),
+        clock.freshness(now).num_seconds()
+    )
+}
+
+/// Renders the row after applying the edit, `None` if the edited column doesn't exist.
+async fn raw_row<E: Serialize + DeserializeOwned + Send + Sync + Clone + 'static>(
+    table: &mut Table<E>,
+    row: usize,
+    edit: Option<(&str, &str)>,
+) -> Result<Option<String>> {
+    let fields = raw_row::field_names::<E>();
+    let sheet = table.origin_mut().inner_mut();
+
+    if let Some((column, value)) = edit {
+        let Some(column) = raw_row::resolve_column(fields, column) else {
+            return Ok(None);
+        };
+
+        sheet.write_cell(row, column, value).await?;
+        // The edit bypassed the cache, so it has to be fetched again
+        table.mark_as_dirty();
+    }
+
+    let cells = table.origin_mut().inner_mut().fetch_raw_row(row).await?;
+    Ok(Some(raw_row::render_row(fields, &cells)))
+}
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use chrono::Utc;
 use regex::{Regex, RegexBuilder};
 use tables::prelude::*;
@@ -11,8 +13,11 @@ use teloxide::{
 };
 
 use lazy_static::lazy_static;
+use serde::Deserialize;
 
 use crate::prelude::*;
+use crate::usage::Usage;
+use crate::utils::payload::PayloadOp;
 
 pub async fn handle_user_from_inline(
     warehouse: &mut Warehouse,
@@ -98,6 +103,7 @@ pub async fn handle_user_info(
                 chat_id,
                 pending_orders: vec![],
                 completed_orders: vec![],
+                note: None,
             };
 
             warehouse.users.extend_one(&user).await?;
@@ -137,56 +143,109 @@ pub fn make_product_answer(product: &Product, item: &Item) -> String {
     )
 }
 
+/// Matches messages starting with the localized prefix, counting the hits.
 pub fn filter_msg_prefix(prefix: &'static str) -> HandlerResult {
-    dptree::entry().filter_async(move |msg: Message, warehouse: SharedWarehouse| async move {
-        let mut warehouse = warehouse.write().await;
+    dptree::entry().filter_async(
+        move |msg: Message, warehouse: LockedWarehouse, usage: Arc<Usage>| async move {
+            let mut warehouse = warehouse.lock().await;
+
+            let matches = if let Some(text) = msg.text() {
+                text.starts_with(&crate::localize_msg!(warehouse, msg, prefix))
+            } else {
+                false
+            };
+
+            if matches {
+                usage.hit(prefix);
+            }
+            matches
+        },
+    )
+}
+
+/// Text after the command word, which may carry the bot's name as in `/profile@bot`.
+pub fn command_args(text: &str) -> &str {
+    text.split_once(char::is_whitespace)
+        .map_or("", |(_, args)| args)
+}
+
+/// Matches callback queries of the payload operation, counting the hits.
+pub fn callback_prefix(op: PayloadOp) -> impl Fn(CallbackQuery, Arc<Usage>) -> bool {
+    move |m: CallbackQuery, usage: Arc<Usage>| {
+        let matches = m
+            .data
+            .map(|t| op.is_in_payload(&t))
+            .unwrap_or(false);
 
-        if let Some(text) = msg.text() {
-            text.starts_with(&crate::localize_msg!(warehouse, msg, prefix))
-        } else {
-            false
+        if matches {
+            usage.hit(&format!("{:?}", op));
         }
-    })
+        matches
+    }
+}
+
+/// Keyboard button shown to users with at least the given role.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ButtonSpec {
+    /// Localization key of the button text.
+    pub key: String,
+    #[serde(default = "default_button_role")]
+    pub role: Role,
 }
 
-pub fn callback_prefix(text: impl ToString) -> impl Fn(CallbackQuery) -> bool {
-    move |m: CallbackQuery| {
-        m.data
-            .map(|t| t.starts_with(&text.to_string()))
-            .unwrap_or(false)
+fn default_button_role() -> Role {
+    Role::User
+}
+
+impl ButtonSpec {
+    fn new(key: &str, role: Role) -> Self {
+        Self {
+            key: key.to_owned(),
+            role,
+        }
     }
 }
 
+pub fn default_keyboard_layout() -> Vec<Vec<ButtonSpec>> {
+    vec![
+        vec![
+            ButtonSpec::new("🔍 Search", Role::User),
+            ButtonSpec::new("📦 Orders", Role::User),
+        ],
+        vec![
+            ButtonSpec::new("💸 Sell", Role::Merchant),
+            ButtonSpec::new("✍️ Writeoff", Role::Merchant),
+        ],
+        vec![
+            ButtonSpec::new("🪫 Replenish", Role::Moderator),
+            ButtonSpec::new("🔄 Refresh", Role::Moderator),
+        ],
+    ]
+}
+
+/// Localization keys of the buttons available to the role, rows left empty are dropped.
+pub fn keyboard_keys(layout: &[Vec<ButtonSpec>], role: &Role) -> Vec<Vec<String>> {
+    layout
+        .iter()
+        .map(|row| {
+            row.iter()
+                .filter(|button| role.is_at_least(button.role.clone()))
+                .map(|button| button.key.clone())
+                .collect::<Vec<_>>()
+        })
+        .filter(|row| !row.is_empty())
+        .collect()
+}
+
 pub async fn user_keyboard(warehouse: &mut Warehouse, lang_code: &str, user: &User) -> ReplyMarkup {
-    let mut keyboard = vec![vec![
-        KeyboardButton::new(crate::localize!(warehouse, lang_code, "🔍 Search").to_string()),
-        KeyboardButton::new(crate::localize!(warehouse, lang_code, "📦 Orders").to_string()),
-    ]];
-
-    if user.role.is_at_least(Role::Merchant) {
-        keyboard.push(vec![
-            KeyboardButton::new(crate::localize!(warehouse, lang_code, "💸 Sell").to_string()),
-            KeyboardButton::new(crate::localize!(
-                warehouse,
-                lang_code,
-                "✍️ Writeoff".to_string()
-            )),
-        ]);
-    }
+    let mut keyboard = vec![];
 
-    if user.role.is_at_least(Role::Moderator) {
-        keyboard.push(vec![
-            KeyboardButton::new(crate::localize!(
-                warehouse,
-                lang_code,
-                "🪫 Replenish".to_string()
-            )),
-            KeyboardButton::new(crate::localize!(
-                warehouse,
-                lang_code,
-                "🔄 Refresh".to_string()
-            )),
-        ]);
+    for row in keyboard_keys(&warehouse.keyboard_layout, &user.role) {
+        let mut buttons = vec![];
+        for key in row {
+            buttons.push(KeyboardButton::new(crate::localize!(warehouse, lang_code, key)));
+        }
+        keyboard.push(buttons);
     }
 
     ReplyMarkup::Keyboard(KeyboardMarkup {
@@ -272,7 +331,7 @@ macro_rules! localize {
             };
 
             let map = std::collections::HashMap::<String, String>::new();
-            strfmt::strfmt(&loc_text, &map).unwrap_or(loc_text)
+            $crate::utils::text::fill_placeholders(&loc_text, &map)
         }
     };
     ($warehouse:expr, $lang_code:expr, $text:expr $(,$key:expr => $value:expr)*) => {
@@ -295,13 +354,70 @@ macro_rules! localize {
 
             let mut map = std::collections::HashMap::<String, String>::new();
             $(map.insert($key.to_owned(), $value.to_string());)*
-            strfmt::strfmt(&loc_text, &map).unwrap_or(loc_text)
+            $crate::utils::text::fill_placeholders(&loc_text, &map)
         }
     };
 }
 
-pub async fn default_handler(bot: Bot, upd: Update, warehouse: SharedWarehouse) -> Result<()> {
-    let mut warehouse = warehouse.write().await;
+/// Same as `localize!`, but only looks the text up: a missing key is used as is and
+/// never added to the localization sheet. Texts built at runtime, e.g. item names,
+/// go through it, so each of them doesn't end up in the sheet as a new key.
+#[macro_export]
+macro_rules! localize_static {
+    ($warehouse:expr, $lang_code:expr, $text:expr $(,$key:expr => $value:expr)*) => {
+        {
+            let _ = $warehouse.localization.refresh().await;
+            let loc_text = match $warehouse
+                .localization
+                .by_key_phrase
+                .get(&$text.to_string())
+            {
+                Some(loc) => loc.get($lang_code),
+                None => $text.to_string(),
+            };
+
+            #[allow(unused_mut)]
+            let mut map = std::collections::HashMap::<String, String>::new();
+            $(map.insert($key.to_owned(), $value.to_string());)*
+            $crate::utils::text::fill_placeholders(&loc_text, &map)
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! localize_static_msg {
+    ($warehouse:expr, $msg:expr, $text:expr $(,$key:expr => $value:expr)*) => {
+        $crate::localize_static!(
+            $warehouse,
+            match $msg.from() {
+                Some(teloxide::types::User {
+                    language_code: Some(lang_code),
+                    ..
+                }) => lang_code,
+                _ => "en",
+            },
+            $text $(,$key => $value)*)
+    };
+}
+
+#[macro_export]
+macro_rules! localize_static_upd {
+    ($warehouse:expr, $upd:expr, $text:expr $(,$key:expr => $value:expr)*) => {
+        $crate::localize_static!(
+            $warehouse,
+            match $upd.user() {
+                Some(teloxide::types::User {
+                    language_code: Some(lang_code),
+                    ..
+                }) => lang_code,
+                _ => "en",
+            },
+            $text $(,$key => $value)*)
+    };
+}
+
+pub async fn default_handler(bot: Bot, upd: Update, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
 
     match upd.kind {
         teloxide::types::UpdateKind::Message(ref msg) => {
@@ -327,3 +443,107 @@ pub async fn default_handler(bot: Bot, upd: Update, warehouse: SharedWarehouse)
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn layout() -> Vec<Vec<ButtonSpec>> {
+        toml::from_str::<HashMap<String, Vec<Vec<ButtonSpec>>>>(
+            r#"
+            keyboard = [
+                [{ key = "Search" }, { key = "Sell", role = "Merchant" }],
+                [{ key = "Refresh", role = "Moderator" }],
+            ]
+            "#,
+        )
+        .unwrap()
+        .remove("keyboard")
+        .unwrap()
+    }
+
+    #[test]
+    fn keyboard_gated_by_role() {
+        let layout = layout();
+
+        assert_eq!(keyboard_keys(&layout, &Role::User), vec![vec!["Search"]]);
+        assert_eq!(
+            keyboard_keys(&layout, &Role::Merchant),
+            vec![vec!["Search", "Sell"]]
+        );
+        assert_eq!(
+            keyboard_keys(&layout, &Role::Moderator),
+            vec![vec!["Search", "Sell"], vec!["Refresh"]]
+        );
+    }
+
+    #[test]
+    fn command_args_after_the_mention() {
+        assert_eq!(command_args("/profile Baker Street"), "Baker Street");
+        assert_eq!(
+            command_args("/profile@stall_bot Baker Street"),
+            "Baker Street"
+        );
+        assert_eq!(command_args("/profile@stall_bot"), "");
+        assert_eq!(command_args("/profile"), "");
+    }
+
+    #[derive(Default)]
+    struct MockLocalization {
+        by_key_phrase: HashMap<String, Localization>,
+        inserted: Vec<String>,
+    }
+
+    impl MockLocalization {
+        async fn refresh(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn extend_one(&mut self, loc: &Localization) -> Result<()> {
+            self.inserted.push(loc.key_phrase.clone());
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct MockWarehouse {
+        localization: MockLocalization,
+    }
+
+    #[tokio::test]
+    async fn static_localization_never_inserts() {
+        let mut warehouse = MockWarehouse::default();
+        warehouse.localization.by_key_phrase.insert(
+            "Hat".to_owned(),
+            Localization {
+                key_phrase: "Hat".to_owned(),
+                en: "-".to_owned(),
+                ru: "Шапка".to_owned(),
+            },
+        );
+
+        assert_eq!(localize_static!(warehouse, "ru", "Hat"), "Шапка");
+        assert_eq!(localize_static!(warehouse, "en", "Hat"), "Hat");
+        assert_eq!(
+            localize_static!(warehouse, "en", "Red hat #{n}", "n" => 2),
+            "Red hat #2"
+        );
+        assert!(warehouse.localization.inserted.is_empty());
+
+        // The regular variant still collects missing keys
+        assert_eq!(localize!(warehouse, "en", "Red hat"), "Red hat");
+        assert_eq!(warehouse.localization.inserted, vec!["Red hat"]);
+    }
+
+    #[test]
+    fn default_keyboard() {
+        let keys = keyboard_keys(&default_keyboard_layout(), &Role::Merchant);
+
+        assert_eq!(
+            keys,
+            vec![vec!["🔍 Search", "📦 Orders"], vec!["💸 Sell", "✍️ Writeoff"]]
+        );
+    }
+}
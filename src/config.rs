@@ -1,16 +1,55 @@
+use std::collections::HashMap;
+
 use serde::Deserialize;
 
+use crate::alert::Severity;
+use crate::common::ButtonSpec;
 use crate::tables::google_sheets::SheetArgs;
 
 #[derive(Deserialize)]
 pub struct TelegramConfig {
     pub bot_token: String,
+    /// Chat which receives alerts about failures.
+    pub operator_chat_id: Option<i64>,
+    /// Lowest severity forwarded to the operator chat.
+    pub alert_severity: Option<Severity>,
+    /// Minimal interval between alerts in seconds.
+    pub alert_interval: Option<u64>,
+    /// Rows of the reply keyboard, the built-in layout is used when absent.
+    pub keyboard: Option<Vec<Vec<ButtonSpec>>>,
+    /// Seconds an identical inline query of the same user is answered from memory, 3 by default.
+    pub inline_cache_ttl: Option<u64>,
+    /// Number of recent inline answers kept in memory, 512 by default.
+    pub inline_cache_size: Option<usize>,
+    /// Seconds Telegram may cache product listings, 60 by default.
+    pub listing_cache_time: Option<u32>,
+    /// Characters of the item description shown in inline results, 120 by default.
+    pub inline_description_limit: Option<usize>,
+    /// Thumbnail shown instead of an item image with a broken URL.
+    pub fallback_image_url: Option<String>,
+    /// Seconds a customer isn't told again about edits of a product they ordered, 600 by default.
+    pub change_notice_debounce: Option<u64>,
+    /// Seconds an update waits for the warehouse before the user is told the bot is busy, 5 by default.
+    pub busy_timeout: Option<u64>,
+    /// Chat the catalog changes are posted to on every change check, not posted when absent.
+    pub changelog_chat_id: Option<i64>,
+    /// Language the catalog changes are posted in, en by default.
+    pub changelog_lang_code: Option<String>,
+    /// Currency codes the global provider token takes, any currency is tried when absent.
+    pub provider_currencies: Option<Vec<String>>,
 }
 
 #[derive(Deserialize)]
 pub struct SheetsConfig {
     pub spreadsheet_id: String,
     pub clock_ttl: usize,
+    /// Background refresh interval in seconds, disabled when absent or zero.
+    pub refresh_interval: Option<u64>,
+    /// Seconds between the checks for listing changes, which fetch the tables on their
+    /// own. The refresh interval when absent, disabled when zero.
+    pub change_check_interval: Option<u64>,
+    /// File logging sheet writes until they land, replayed on startup. Disabled when absent.
+    pub wal_path: Option<String>,
     pub meta: SheetArgs,
     pub items: SheetArgs,
     pub products: SheetArgs,
@@ -22,6 +61,51 @@ pub struct SheetsConfig {
     pub replenishments: SheetArgs,
     pub writeoffs: SheetArgs,
     pub localization: SheetArgs,
+    pub orders_archive: Option<SheetArgs>,
+    pub sales_archive: Option<SheetArgs>,
+    /// Sheet collecting command usage counters, not collected when absent.
+    pub usage: Option<SheetArgs>,
+    /// Interval in seconds the usage counters are flushed to the sheet with.
+    pub usage_flush_interval: Option<u64>,
+    /// Sheet collecting product reports of customers, reporting is disabled when absent.
+    pub reports: Option<SheetArgs>,
+    /// Age in days after which closed orders and sales are archived.
+    pub archive_after: Option<usize>,
+    /// Days of sales used to estimate how fast products sell, 30 by default.
+    pub restock_window: Option<usize>,
+    /// Products running out within that many days are flagged for restock, 14 by default.
+    pub restock_horizon: Option<usize>,
+    /// Products in stock without sales over that many days are reported as dead stock, 60 by default.
+    pub deadstock_window: Option<usize>,
+    /// Share of products created without an explicit one, 0 by default.
+    pub default_share: Option<f32>,
+    /// Currency revenue reports are made in, sales aren't converted when absent.
+    pub base_currency: Option<String>,
+    /// Units of the base currency per unit of another currency by its code.
+    pub exchange_rates: Option<HashMap<String, f64>>,
+}
+
+impl SheetsConfig {
+    pub fn sheet_args_mut(&mut self) -> impl Iterator<Item = &mut SheetArgs> {
+        [
+            &mut self.meta,
+            &mut self.items,
+            &mut self.products,
+            &mut self.users,
+            &mut self.users_meta,
+            &mut self.merchants,
+            &mut self.sales,
+            &mut self.orders,
+            &mut self.replenishments,
+            &mut self.writeoffs,
+            &mut self.localization,
+        ]
+        .into_iter()
+        .chain(self.orders_archive.as_mut())
+        .chain(self.sales_archive.as_mut())
+        .chain(self.usage.as_mut())
+        .chain(self.reports.as_mut())
+    }
 }
 
 #[derive(Deserialize)]
@@ -2,18 +2,22 @@ pub mod particular;
 pub mod stages;
 
 use crate::prelude::*;
+use crate::utils::payload::{Payload, PayloadOp};
 use async_trait::async_trait;
 use log::error;
 use std::sync::Arc;
 use teloxide::dispatching::dialogue::{Dialogue, Storage};
 use teloxide::dispatching::DpHandlerDescription;
 use teloxide::prelude::*;
-use teloxide::types::UpdateKind;
+use teloxide::types::{
+    InlineKeyboardButton, InlineKeyboardMarkup, ParseMode, ReplyMarkup, UpdateKind,
+};
 
 pub mod prelude {
     pub use super::{
-        cancel, filter_dialogue_started, stages::prelude::*, ConversationEnd, ConversationStage,
-        ConversationStart,
+        cancel, cancel_by_callback, filter_dialogue_started, inline_cancel_button,
+        send_cancel_hint, stages::prelude::*, ConversationEnd, ConversationStage,
+        ConversationStart, Prompt,
     };
 }
 
@@ -33,6 +37,16 @@ pub trait ConversationStart {
         Role::User
     }
 
+    /// Unit the amounts of the current stage are parsed in.
+    fn amount_unit(&self) -> Unit {
+        Unit::Each
+    }
+
+    /// Question of the current stage, asked again when the answer isn't usable.
+    fn prompt(&self) -> Option<&Prompt> {
+        None
+    }
+
     async fn start(
         self,
         bot: Bot,
@@ -78,20 +92,120 @@ where
     S::Error: std::error::Error + Send + Sync,
 {
     dptree::filter_async(|dialogue: Dialogue<D, S>| async move {
-        match dialogue.get().await {
-            Ok(stage) => match stage {
-                Some(stage) => stage.is_started(),
-                _ => false,
-            },
-            Err(_) => false,
-        }
+        is_dialogue_started(&dialogue).await
     })
 }
 
+async fn is_dialogue_started<D, S>(dialogue: &Dialogue<D, S>) -> bool
+where
+    D: ConversationStart + Send + Sync + 'static,
+    S: Storage<D> + Send + Sync + 'static,
+    S::Error: std::error::Error + Send + Sync,
+{
+    match dialogue.get().await {
+        Ok(stage) => match stage {
+            Some(stage) => stage.is_started(),
+            _ => false,
+        },
+        Err(_) => false,
+    }
+}
+
+/// Message a stage asks its question with. The stage keeps it, so a sticker or
+/// a photo in place of the answer gets the question and its keyboard again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prompt {
+    pub text: String,
+    pub keyboard: Option<ReplyMarkup>,
+    pub parse_mode: Option<ParseMode>,
+}
+
+impl Prompt {
+    pub fn new(text: String, keyboard: impl Into<ReplyMarkup>) -> Self {
+        Self {
+            text,
+            keyboard: Some(keyboard.into()),
+            parse_mode: None,
+        }
+    }
+
+    /// Same as [`Prompt::new`], the text is HTML.
+    pub fn html(text: String, keyboard: impl Into<ReplyMarkup>) -> Self {
+        Self {
+            parse_mode: Some(ParseMode::Html),
+            ..Self::new(text, keyboard)
+        }
+    }
+
+    pub async fn send(&self, bot: &Bot, chat_id: ChatId) -> Result<()> {
+        let mut request = bot.send_message(chat_id, &self.text);
+
+        if let Some(parse_mode) = self.parse_mode {
+            request = request.parse_mode(parse_mode);
+        }
+        if let Some(keyboard) = &self.keyboard {
+            request = request.reply_markup(keyboard.clone());
+        }
+
+        request.await?;
+        Ok(())
+    }
+}
+
+/// Button that cancels the current dialogue regardless of the message text.
+pub fn inline_cancel_button(text: String) -> InlineKeyboardButton {
+    InlineKeyboardButton::callback(text, Payload::cancel_dialogue().to_string())
+}
+
+/// Tells the user the dialogue can be left at any time, with an inline Cancel button.
+pub async fn send_cancel_hint(
+    bot: &Bot,
+    chat_id: ChatId,
+    upd: &Update,
+    warehouse: &mut Warehouse,
+) -> Result<()> {
+    bot.send_message(
+        chat_id,
+        localize_upd!(
+            warehouse,
+            upd,
+            "You can terminate dialogue at any time by pressing Cancel."
+        ),
+    )
+    .reply_markup(ReplyMarkup::inline_kb(vec![vec![inline_cancel_button(
+        localize_upd!(warehouse, upd, "Cancel"),
+    )]]))
+    .await?;
+
+    Ok(())
+}
+
+/// Handles the inline "Cancel" button of a started dialogue.
+pub fn cancel_by_callback<D, S>() -> HandlerResult
+where
+    D: ConversationStart + Default + Clone + Send + Sync + 'static,
+    S: Storage<D> + Send + Sync + 'static,
+    S::Error: std::error::Error + Send + Sync,
+{
+    filter_cancel_callback::<D, S>().endpoint(cancel_callback::<D, S>)
+}
+
+fn filter_cancel_callback<D, S>() -> HandlerResult
+where
+    D: ConversationStart + Default + Clone + Send + Sync + 'static,
+    S: Storage<D> + Send + Sync + 'static,
+    S::Error: std::error::Error + Send + Sync,
+{
+    Update::filter_callback_query()
+        .filter(callback_prefix(PayloadOp::CancelDialogue))
+        .enter_dialogue::<CallbackQuery, S, D>()
+        .chain(filter_dialogue_started::<D, S>())
+}
+
 pub async fn cancel<D, S>(
     bot: Bot,
     msg: Message,
-    warehouse: SharedWarehouse,
+    warehouse: LockedWarehouse,
     dialogue: Dialogue<D, S>,
 ) -> Result<()>
 where
@@ -101,7 +215,7 @@ where
 {
     dialogue.exit().await?;
 
-    let mut warehouse = warehouse.write().await;
+    let mut warehouse = warehouse.lock().await;
     let (user, _) = handle_user_from_msg(&mut warehouse, &msg).await?;
 
     let lang_code = msg
@@ -116,6 +230,38 @@ where
     Ok(())
 }
 
+async fn cancel_callback<D, S>(
+    bot: Bot,
+    q: CallbackQuery,
+    warehouse: LockedWarehouse,
+    dialogue: Dialogue<D, S>,
+) -> Result<()>
+where
+    D: Send + Sync + 'static,
+    S: Storage<D> + Send + Sync + 'static,
+    S::Error: std::error::Error + Send + Sync,
+{
+    dialogue.exit().await?;
+
+    let mut warehouse = warehouse.lock().await;
+    let (user, _) = handle_user(&mut warehouse, None, &q.from).await?;
+
+    let lang_code = q.from.language_code.clone().unwrap_or("en".to_owned());
+
+    bot.answer_callback_query(&q.id).await?;
+
+    if let Some(msg) = &q.message {
+        bot.edit_message_reply_markup(msg.chat.id, msg.id)
+            .reply_markup(InlineKeyboardMarkup::default())
+            .await?;
+    }
+
+    bot.send_message(dialogue.chat_id(), "Dialogue cancelled.")
+        .reply_markup(user_keyboard(&mut warehouse, &lang_code, &user).await)
+        .await?;
+    Ok(())
+}
+
 pub fn enter_user_dialogue<S, D>(
     err_msg: &'static str,
 ) -> Handler<'static, DependencyMap, Result<()>, DpHandlerDescription>
@@ -126,8 +272,8 @@ where
 {
     dptree::entry()
         .filter_map_async(
-            move |bot: Bot, upd: Update, warehouse: SharedWarehouse, storage: Arc<S>| async move {
-                let mut warehouse = warehouse.write().await;
+            move |bot: Bot, upd: Update, warehouse: LockedWarehouse, storage: Arc<S>| async move {
+                let mut warehouse = warehouse.lock().await;
                 let _ = warehouse.users.refresh().await;
                 let _ = warehouse.users_meta.refresh().await;
 
@@ -198,3 +344,98 @@ async fn answer_err(bot: Bot, upd: Update, text: String) {
         _ => (),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use teloxide::{
+        dispatching::dialogue::InMemStorage,
+        types::{ChatId, InlineKeyboardButtonKind},
+    };
+
+    use super::*;
+    use crate::usage::Usage;
+
+    #[derive(Default, Clone)]
+    enum Stage {
+        #[default]
+        Start,
+        WaitAmount,
+    }
+
+    #[async_trait]
+    impl ConversationStart for Stage {
+        fn is_started(&self) -> bool {
+            !matches!(self, Stage::Start)
+        }
+
+        async fn start(
+            self,
+            _: Bot,
+            _: Update,
+            _: (User, UserMeta),
+            _: &mut Warehouse,
+        ) -> Result<Self> {
+            Ok(Stage::WaitAmount)
+        }
+    }
+
+    fn callback_update(data: &str) -> Update {
+        // Update reads borrowed keys, which a `Value` can't lend
+        let json = serde_json::json!({
+            "update_id": 1,
+            "callback_query": {
+                "id": "1",
+                "from": { "id": 1, "is_bot": false, "first_name": "User" },
+                "message": {
+                    "message_id": 1,
+                    "date": 0,
+                    "chat": { "id": 1, "type": "private", "first_name": "User" },
+                    "text": "Select"
+                },
+                "chat_instance": "1",
+                "data": data
+            }
+        });
+        serde_json::from_str(&json.to_string()).unwrap()
+    }
+
+    async fn press(storage: Arc<InMemStorage<Stage>>, data: &str) -> bool {
+        let handler = filter_cancel_callback::<Stage, InMemStorage<Stage>>().endpoint(
+            |dialogue: Dialogue<Stage, InMemStorage<Stage>>| async move {
+                dialogue.exit().await?;
+                Result::<()>::Ok(())
+            },
+        );
+
+        handler
+            .dispatch(dptree::deps![
+                callback_update(data),
+                storage,
+                Arc::new(Usage::default())
+            ])
+            .await
+            .is_break()
+    }
+
+    #[tokio::test]
+    async fn inline_cancel_exits_dialogue() {
+        let storage = InMemStorage::<Stage>::new();
+        let dialogue = Dialogue::new(storage.clone(), ChatId(1));
+        dialogue.update(Stage::WaitAmount).await.unwrap();
+
+        let button = inline_cancel_button("Cancel".to_owned());
+        let InlineKeyboardButtonKind::CallbackData(data) = button.kind else {
+            panic!("expected a callback button");
+        };
+
+        // Other callbacks leave the dialogue alone
+        assert!(!press(storage.clone(), &PayloadOp::Purchase.to_string()).await);
+        assert!(is_dialogue_started(&dialogue).await);
+
+        assert!(press(storage.clone(), &data).await);
+        assert!(dialogue.get().await.unwrap().is_none());
+
+        // Nothing to cancel anymore
+        assert!(!press(storage, &data).await);
+    }
+}
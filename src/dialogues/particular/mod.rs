@@ -1,7 +1,10 @@
+pub mod onboard;
+pub mod order_message;
 pub mod order_specify_price;
 pub mod purchase;
 pub mod redeem;
 pub mod replenish;
+pub mod report;
 pub mod sell;
 pub mod writeoff;
 
@@ -16,6 +19,9 @@ pub fn handler() -> HandlerResult {
         .branch(purchase::handler())
         .branch(redeem::handler())
         .branch(order_specify_price::handler())
+        .branch(order_message::handler())
+        .branch(report::handler())
+        .branch(onboard::handler())
 }
 
 pub fn write_deps(deps: &mut DependencyMap) {
@@ -25,4 +31,7 @@ pub fn write_deps(deps: &mut DependencyMap) {
     purchase::write_deps(deps);
     redeem::write_deps(deps);
     order_specify_price::write_deps(deps);
+    order_message::write_deps(deps);
+    report::write_deps(deps);
+    onboard::write_deps(deps);
 }
@@ -0,0 +1,646 @@
+use async_trait::async_trait;
+use teloxide::{
+    dispatching::dialogue::{GetChatId, InMemStorage},
+    prelude::*,
+    types::{KeyboardButton, KeyboardMarkup, ParseMode, Update, UpdateKind},
+};
+
+use crate::prelude::*;
+
+type Storage = InMemStorage<Stage>;
+
+#[derive(Default, Clone)]
+struct StageData {
+    pub user: String,
+    pub location: Option<String>,
+    pub address: Option<String>,
+    pub item_id: Option<String>,
+    pub price: Option<(f64, Currency)>,
+    pub amount: Option<f64>,
+    pub prompt: Option<Prompt>,
+}
+
+#[derive(Default, Clone)]
+enum Stage {
+    #[default]
+    Start,
+    WaitLocation(StageData),
+    WaitAddress(StageData),
+    WaitItem(StageData),
+    WaitPrice(StageData),
+    WaitAmount(StageData),
+    WaitConfirmation(StageData),
+}
+
+pub fn handler() -> HandlerResult {
+    dptree::entry()
+        .branch(cancel_by_callback::<Stage, Storage>())
+        .branch(
+            Update::filter_message()
+                .enter_dialogue::<Message, InMemStorage<Stage>, Stage>()
+                .branch(
+                    filter_dialogue_started::<Stage, Storage>()
+                        .chain(filter_msg_prefix("Cancel"))
+                        .endpoint(cancel::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::Start]
+                        .chain(filter_msg_prefix("/onboard"))
+                        .endpoint(start::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitLocation(data)]
+                        .endpoint(receive_text_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitAddress(data)]
+                        .endpoint(receive_text_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitItem(data)]
+                        .endpoint(receive_text_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitPrice(data)]
+                        .endpoint(receive_money_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitAmount(data)]
+                        .endpoint(receive_amount_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitConfirmation(data)]
+                        .endpoint(receive_text_stage::<Stage, Storage>),
+                ),
+        )
+}
+
+pub fn write_deps(deps: &mut DependencyMap) {
+    deps.insert(InMemStorage::<Stage>::new());
+}
+
+/// Everything the wizard creates, applied at once when confirmed.
+#[derive(Clone, Debug)]
+pub struct OnboardPlan {
+    pub user: String,
+    pub merchant: Merchant,
+    pub product: Option<Product>,
+}
+
+/// Tables touched by the onboarding, so the orchestration can be checked without sheets.
+/// Every step returns once its write has landed, else an undo could land before it.
+#[async_trait]
+pub trait OnboardTarget {
+    /// Sets the role and returns the previous one.
+    async fn set_role(&mut self, user: &str, role: Role) -> Result<Role>;
+    async fn add_merchant(&mut self, merchant: &Merchant) -> Result<()>;
+    async fn remove_merchant(&mut self, name: &str) -> Result<()>;
+    async fn add_product(&mut self, product: &Product) -> Result<()>;
+}
+
+/// Promotes the user, creates the merchant and the first product. A failed step
+/// undoes the ones before it, so the user is never left half onboarded.
+pub async fn apply_onboarding<T: OnboardTarget + Send>(
+    target: &mut T,
+    plan: &OnboardPlan,
+) -> Result<()> {
+    let previous_role = target.set_role(&plan.user, Role::Merchant).await?;
+
+    if let Err(e) = target.add_merchant(&plan.merchant).await {
+        target.set_role(&plan.user, previous_role).await?;
+        return Err(e);
+    }
+
+    if let Some(product) = &plan.product {
+        if let Err(e) = target.add_product(product).await {
+            target.remove_merchant(&plan.merchant.name).await?;
+            target.set_role(&plan.user, previous_role).await?;
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl OnboardTarget for Warehouse {
+    async fn set_role(&mut self, user: &str, role: Role) -> Result<Role> {
+        self.users.refresh().await?;
+        let (row, mut entry) = self
+            .users
+            .by_name
+            .get_with_row(&user.to_owned())
+            .map(|(row, user)| (*row, user.clone()))
+            .ok_or(UnkError::tables("user not found"))?;
+
+        let previous = std::mem::replace(&mut entry.role, role);
+        self.users.update_one(row, &entry).await?;
+        self.users.flush().await?;
+        Ok(previous)
+    }
+
+    async fn add_merchant(&mut self, merchant: &Merchant) -> Result<()> {
+        self.merchants.extend_one(merchant).await?;
+        self.merchants.flush().await?;
+        Ok(())
+    }
+
+    async fn remove_merchant(&mut self, name: &str) -> Result<()> {
+        self.merchants.refresh().await?;
+        if let Some((row, _)) = self.merchants.by_name.get_with_row(&name.to_owned()) {
+            let row = *row;
+            self.merchants.delete(vec![row]).await?;
+            self.merchants.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn add_product(&mut self, product: &Product) -> Result<()> {
+        self.products.extend_one(product).await?;
+        self.products.flush().await?;
+        Ok(())
+    }
+}
+
+fn make_plan(data: &StageData, default_share: f32) -> OnboardPlan {
+    let product = match (&data.item_id, data.price, data.amount) {
+        (Some(item_id), Some((price, currency)), Some(amount)) => Some(Product {
+            merchant: data.user.clone(),
+            item_id: item_id.clone(),
+            price,
+            currency,
+            payment_method: PaymentMethod::Both,
+            negotiated_price: false,
+            share: default_share,
+            visibility: ProductVisibility::All,
+            amount_granted: amount,
+            amount_sold: 0.0,
+            amount_left: amount,
+            deleted: false,
+            unit: Unit::Each,
+            oversell_allowance: 0.0,
+            backordered: 0.0,
+            active: true,
+        }),
+        _ => None,
+    };
+
+    OnboardPlan {
+        user: data.user.clone(),
+        merchant: Merchant {
+            name: data.user.clone(),
+            location: data.location.clone().unwrap_or_default(),
+            address: data.address.clone().unwrap_or_default(),
+            provider_token: None,
+            provider_currencies: None,
+            vacation: false,
+            min_order_value: None,
+            min_order_currency: None,
+            display_name: None,
+        },
+        product,
+    }
+}
+
+fn cancel_keyboard(cancel: String) -> KeyboardMarkup {
+    KeyboardMarkup {
+        resize_keyboard: Some(true),
+        is_persistent: true,
+        keyboard: vec![vec![KeyboardButton::new(cancel)]],
+        ..Default::default()
+    }
+}
+
+#[async_trait]
+impl ConversationStart for Stage {
+    fn is_started(&self) -> bool {
+        match self {
+            Self::Start => false,
+            _ => true,
+        }
+    }
+
+    fn required_role(&self) -> Role {
+        Role::Moderator
+    }
+
+    fn prompt(&self) -> Option<&Prompt> {
+        match self {
+            Self::Start => None,
+            Self::WaitLocation(data)
+            | Self::WaitAddress(data)
+            | Self::WaitItem(data)
+            | Self::WaitPrice(data)
+            | Self::WaitAmount(data)
+            | Self::WaitConfirmation(data) => data.prompt.as_ref(),
+        }
+    }
+
+    async fn start(
+        self,
+        bot: Bot,
+        upd: Update,
+        _: (User, UserMeta),
+        warehouse: &mut Warehouse,
+    ) -> Result<Self> {
+        let chat_id = upd.chat_id().ok_or(UnkError::unknown("upd.chat_id"))?;
+
+        let text = match &upd.kind {
+            UpdateKind::Message(msg) => msg.text().unwrap_or_default().to_owned(),
+            _ => return Ok(Self::Start),
+        };
+
+        let username = text
+            .trim_start_matches("/onboard")
+            .trim()
+            .trim_start_matches('@')
+            .to_owned();
+
+        if username.is_empty() {
+            bot.send_message(
+                chat_id,
+                localize_upd!(warehouse, upd, "Usage: /onboard @username"),
+            )
+            .await?;
+            return Ok(Self::Start);
+        }
+
+        warehouse.users.refresh().await?;
+        warehouse.merchants.refresh().await?;
+
+        if warehouse.users.by_name.get(&username).is_none() {
+            bot.send_message(
+                chat_id,
+                localize_upd!(warehouse, upd,
+                    "User @{user} has never talked to the bot.",
+                    "user" => username
+                ),
+            )
+            .await?;
+            return Ok(Self::Start);
+        }
+
+        if warehouse.merchants.by_name.get(&username).is_some() {
+            bot.send_message(
+                chat_id,
+                localize_upd!(warehouse, upd,
+                    "@{user} is already a merchant.",
+                    "user" => username
+                ),
+            )
+            .await?;
+            return Ok(Self::Start);
+        }
+
+        let prompt = Prompt::html(
+            localize_upd!(warehouse, upd,
+                "<b>Onboarding @{user}</b>\nWhere is the merchant located? (for example, a city)",
+                "user" => username
+            ),
+            cancel_keyboard(localize_upd!(warehouse, upd, "Cancel")),
+        );
+        prompt.send(&bot, chat_id).await?;
+
+        Ok(Self::WaitLocation(StageData {
+            user: username,
+            prompt: Some(prompt),
+            ..Default::default()
+        }))
+    }
+}
+
+#[async_trait]
+impl ConversationStage<String> for Stage {
+    async fn next(
+        self,
+        bot: Bot,
+        msg: Message,
+        user: (User, UserMeta),
+        warehouse: &mut Warehouse,
+        text: String,
+    ) -> Result<Self> {
+        let text = text.trim().to_owned();
+
+        match self {
+            Stage::WaitLocation(_) | Stage::WaitAddress(_) if text.is_empty() => {
+                bot.send_message(
+                    msg.chat.id,
+                    localize_msg!(warehouse, msg, "It can't be empty, please try again."),
+                )
+                .await?;
+
+                Ok(self)
+            }
+            Stage::WaitLocation(mut data) => {
+                data.location = Some(text);
+
+                let prompt = Prompt::new(
+                    localize_msg!(warehouse, msg, "What's the merchant's address?"),
+                    cancel_keyboard(localize_msg!(warehouse, msg, "Cancel")),
+                );
+                prompt.send(&bot, msg.chat.id).await?;
+                data.prompt = Some(prompt);
+
+                Ok(Self::WaitAddress(data))
+            }
+            Stage::WaitAddress(mut data) => {
+                data.address = Some(text);
+
+                let prompt = Prompt::new(
+                    localize_msg!(
+                        warehouse,
+                        msg,
+                        "Send the id of an item to add the first product, or \"Skip\"."
+                    ),
+                    KeyboardMarkup {
+                        resize_keyboard: Some(true),
+                        is_persistent: true,
+                        keyboard: vec![
+                            vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Skip"))],
+                            vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Cancel"))],
+                        ],
+                        ..Default::default()
+                    },
+                );
+                prompt.send(&bot, msg.chat.id).await?;
+                data.prompt = Some(prompt);
+
+                Ok(Self::WaitItem(data))
+            }
+            Stage::WaitItem(mut data) => {
+                let skip_text = localize_msg!(warehouse, msg, "Skip").to_lowercase();
+                if text.to_lowercase() == skip_text {
+                    return confirm(bot, msg, warehouse, data).await;
+                }
+
+                warehouse.items.refresh().await?;
+                let Some(item) = warehouse.items.by_id.get(&text).cloned() else {
+                    bot.send_message(
+                        msg.chat.id,
+                        localize_msg!(warehouse, msg, "Item not found, please try again."),
+                    )
+                    .await?;
+                    return Ok(Self::WaitItem(data));
+                };
+
+                data.item_id = Some(item.id.clone());
+
+                let prompt = Prompt::new(
+                    localize_msg!(warehouse, msg,
+                        "What's the price of the {name}? Write as a real number with a currency (for example, \"100.50 eur\").",
+                        "name" => localize_static_msg!(warehouse, msg, item.name)
+                    ),
+                    cancel_keyboard(localize_msg!(warehouse, msg, "Cancel")),
+                );
+                prompt.send(&bot, msg.chat.id).await?;
+                data.prompt = Some(prompt);
+
+                Ok(Self::WaitPrice(data))
+            }
+            Stage::WaitConfirmation(data) => {
+                let lang_code = msg
+                    .from()
+                    .map(|u| u.language_code.clone())
+                    .flatten()
+                    .unwrap_or("en".to_owned());
+
+                let yes_text = localize_msg!(warehouse, msg, "Yes").to_lowercase();
+                if text.to_lowercase() != yes_text {
+                    bot.send_message(
+                        msg.chat.id,
+                        localize_msg!(warehouse, msg, "Dialogue cancelled."),
+                    )
+                    .reply_markup(user_keyboard(warehouse, &lang_code, &user.0).await)
+                    .await?;
+                    return Ok(Self::Start);
+                }
+
+                let plan = make_plan(&data, warehouse.default_share);
+                match apply_onboarding(warehouse, &plan).await {
+                    Ok(_) => {
+                        bot.send_message(
+                            msg.chat.id,
+                            localize_msg!(warehouse, msg,
+                                "@{user} is a merchant now!",
+                                "user" => plan.user
+                            ),
+                        )
+                        .reply_markup(user_keyboard(warehouse, &lang_code, &user.0).await)
+                        .await?;
+                    }
+                    Err(e) => {
+                        bot.send_message(
+                            msg.chat.id,
+                            localize_msg!(
+                                warehouse,
+                                msg,
+                                "Failed to onboard the merchant, nothing was changed."
+                            ),
+                        )
+                        .reply_markup(user_keyboard(warehouse, &lang_code, &user.0).await)
+                        .await?;
+                        return Err(e);
+                    }
+                }
+
+                update_user_activity(warehouse, &user.0.name).await?;
+
+                Ok(Self::Start)
+            }
+            _ => Ok(self),
+        }
+    }
+}
+
+#[async_trait]
+impl ConversationStage<(f64, Currency)> for Stage {
+    async fn next(
+        self,
+        bot: Bot,
+        msg: Message,
+        _: (User, UserMeta),
+        warehouse: &mut Warehouse,
+        price: (f64, Currency),
+    ) -> Result<Self> {
+        match self {
+            Stage::WaitPrice(mut data) => {
+                if price.0 < 0.0 {
+                    bot.send_message(
+                        msg.chat.id,
+                        localize_msg!(warehouse, msg, "The price can't be negative."),
+                    )
+                    .await?;
+                    return Ok(Self::WaitPrice(data));
+                }
+
+                data.price = Some(price);
+
+                let prompt = Prompt::new(
+                    localize_msg!(warehouse, msg, "How many items does the merchant have?"),
+                    cancel_keyboard(localize_msg!(warehouse, msg, "Cancel")),
+                );
+                prompt.send(&bot, msg.chat.id).await?;
+                data.prompt = Some(prompt);
+
+                Ok(Self::WaitAmount(data))
+            }
+            _ => Ok(self),
+        }
+    }
+}
+
+#[async_trait]
+impl ConversationStage<f64> for Stage {
+    async fn next(
+        self,
+        bot: Bot,
+        msg: Message,
+        _: (User, UserMeta),
+        warehouse: &mut Warehouse,
+        amount: f64,
+    ) -> Result<Self> {
+        match self {
+            Stage::WaitAmount(mut data) => {
+                data.amount = Some(amount);
+                confirm(bot, msg, warehouse, data).await
+            }
+            _ => Ok(self),
+        }
+    }
+}
+
+async fn confirm(
+    bot: Bot,
+    msg: Message,
+    warehouse: &mut Warehouse,
+    mut data: StageData,
+) -> Result<Stage> {
+    let plan = make_plan(&data, warehouse.default_share);
+
+    let mut lines = vec![
+        localize_msg!(warehouse, msg, "<b>Confirm the onboarding</b>"),
+        localize_msg!(warehouse, msg, "• User: @{user}", "user" => plan.user),
+        localize_msg!(warehouse, msg, "• Location: {location}", "location" => plan.merchant.location),
+        localize_msg!(warehouse, msg, "• Address: {address}", "address" => plan.merchant.address),
+    ];
+
+    if let Some(product) = &plan.product {
+        lines.push(localize_msg!(warehouse, msg,
+            "• First product: {amount}x {item} for {price}",
+            "amount" => product.amount_left,
+            "item" => product.item_id,
+            "price" => product.currency.format_amount(product.price)
+        ));
+    }
+
+    bot.send_message(msg.chat.id, lines.join("\n"))
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    let prompt = Prompt::new(
+        localize_msg!(warehouse, msg, "Is everything correct?"),
+        KeyboardMarkup {
+            resize_keyboard: Some(true),
+            one_time_keyboard: Some(true),
+            keyboard: vec![vec![
+                KeyboardButton::new(localize_msg!(warehouse, msg, "Yes")),
+                KeyboardButton::new(localize_msg!(warehouse, msg, "No")),
+            ]],
+            ..Default::default()
+        },
+    );
+    prompt.send(&bot, msg.chat.id).await?;
+    data.prompt = Some(prompt);
+
+    Ok(Stage::WaitConfirmation(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Tables {
+        roles: HashMap<String, Role>,
+        merchants: Vec<Merchant>,
+        products: Vec<Product>,
+        fail_merchant: bool,
+    }
+
+    #[async_trait]
+    impl OnboardTarget for Tables {
+        async fn set_role(&mut self, user: &str, role: Role) -> Result<Role> {
+            let entry = self.roles.get_mut(user).ok_or("user not found")?;
+            Ok(std::mem::replace(entry, role))
+        }
+
+        async fn add_merchant(&mut self, merchant: &Merchant) -> Result<()> {
+            if self.fail_merchant {
+                return Err(UnkError::tables("merchant write failed").into());
+            }
+            self.merchants.push(merchant.clone());
+            Ok(())
+        }
+
+        async fn remove_merchant(&mut self, name: &str) -> Result<()> {
+            self.merchants.retain(|merchant| merchant.name != name);
+            Ok(())
+        }
+
+        async fn add_product(&mut self, product: &Product) -> Result<()> {
+            self.products.push(product.clone());
+            Ok(())
+        }
+    }
+
+    fn plan() -> OnboardPlan {
+        make_plan(
+            &StageData {
+                user: "bob".to_owned(),
+                location: Some("Prague".to_owned()),
+                address: Some("Main st. 1".to_owned()),
+                item_id: Some("hat".to_owned()),
+                price: Some((10.0, Currency::EUR)),
+                amount: Some(3.0),
+                prompt: None,
+            },
+            0.15,
+        )
+    }
+
+    fn tables() -> Tables {
+        Tables {
+            roles: [("bob".to_owned(), Role::User)].into(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn onboarding_creates_all_records() {
+        let mut tables = tables();
+        apply_onboarding(&mut tables, &plan()).await.unwrap();
+
+        assert_eq!(tables.roles["bob"], Role::Merchant);
+        assert_eq!(tables.merchants.len(), 1);
+        assert_eq!(tables.merchants[0].location, "Prague");
+        assert_eq!(tables.products.len(), 1);
+        assert_eq!(tables.products[0].merchant, "bob");
+        assert_eq!(tables.products[0].amount_left, 3.0);
+        assert_eq!(tables.products[0].share, 0.15);
+    }
+
+    #[tokio::test]
+    async fn failed_merchant_creation_rolls_back() {
+        let mut tables = Tables {
+            fail_merchant: true,
+            ..tables()
+        };
+
+        assert!(apply_onboarding(&mut tables, &plan()).await.is_err());
+        assert_eq!(tables.roles["bob"], Role::User);
+        assert!(tables.merchants.is_empty());
+        assert!(tables.products.is_empty());
+    }
+}
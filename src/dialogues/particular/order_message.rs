@@ -0,0 +1,305 @@
+use async_trait::async_trait;
+use teloxide::{
+    dispatching::dialogue::InMemStorage,
+    prelude::*,
+    types::{
+        InlineKeyboardButton, KeyboardButton, KeyboardMarkup, ReplyMarkup, Update, UpdateKind,
+    },
+};
+
+use crate::{
+    dialogues::enter_user_dialogue,
+    prelude::*,
+    utils::{
+        payload::PayloadOp,
+        row::Row,
+        verify::{send_or_forget, verify_with_callback, verify_with_msg},
+    },
+};
+
+type Storage = InMemStorage<Stage>;
+
+#[derive(Default, Clone)]
+struct StageData {
+    pub order: Option<Row<Order>>,
+    pub prompt: Option<Prompt>,
+}
+
+#[derive(Default, Clone)]
+enum Stage {
+    #[default]
+    Start,
+    WaitMessage(StageData),
+}
+
+pub fn handler() -> HandlerResult {
+    dptree::entry()
+        .branch(cancel_by_callback::<Stage, Storage>())
+        .branch(
+            Update::filter_callback_query()
+                .chain(enter_user_dialogue::<Storage, Stage>(
+                    "To message about an order you first need to start a dialog with the bot.",
+                ))
+                .filter(callback_prefix(PayloadOp::MessageOrder))
+                .endpoint(start::<Stage, Storage>),
+        )
+        .branch(
+            Update::filter_message()
+                .enter_dialogue::<Message, Storage, Stage>()
+                .branch(
+                    filter_dialogue_started::<Stage, Storage>()
+                        .chain(filter_msg_prefix("Cancel"))
+                        .endpoint(cancel::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitMessage(data)]
+                        .endpoint(receive_text_stage::<Stage, Storage>),
+                ),
+        )
+}
+
+pub fn write_deps(deps: &mut DependencyMap) {
+    deps.insert(InMemStorage::<Stage>::new());
+}
+
+#[async_trait]
+impl ConversationStart for Stage {
+    fn is_started(&self) -> bool {
+        match self {
+            Stage::Start => false,
+            _ => true,
+        }
+    }
+
+    fn prompt(&self) -> Option<&Prompt> {
+        match self {
+            Stage::Start => None,
+            Stage::WaitMessage(data) => data.prompt.as_ref(),
+        }
+    }
+
+    async fn start(
+        self,
+        bot: Bot,
+        upd: Update,
+        user: (User, UserMeta),
+        warehouse: &mut Warehouse,
+    ) -> Result<Self> {
+        let chat_id = user.1.chat_id.ok_or(UnkError::unknown("No chat id"))?;
+
+        let UpdateKind::CallbackQuery(q) = upd.kind.clone() else {
+            Err(UnkError::unknown("Invalid update kind"))?
+        };
+
+        let order = verify_with_callback(&bot, &q, warehouse)
+            .payload_str_opt(&q.data)
+            .await?
+            .verify_order()
+            .await?
+            .stage_is_not(OrderStage::Cancelled)
+            .await?
+            .stage_is_not(OrderStage::Completed)
+            .await?
+            .participant_is(&user.0.name)
+            .await?
+            .into_result();
+
+        bot.answer_callback_query(q.id).await?;
+        let name = recipient(&order, &user.0.name)
+            .map(|name| shown_name(warehouse, &order, name))
+            .unwrap_or_default();
+        let prompt = Prompt::new(
+            localize_upd!(warehouse, upd,
+                "Please write your message to {name}, it will be forwarded along with the order.",
+                "name" => name
+            ),
+            KeyboardMarkup {
+                resize_keyboard: Some(true),
+                one_time_keyboard: Some(true),
+                keyboard: vec![vec![KeyboardButton::new(localize_upd!(
+                    warehouse, upd, "Cancel"
+                ))]],
+                ..Default::default()
+            },
+        );
+        prompt.send(&bot, chat_id).await?;
+
+        send_cancel_hint(&bot, chat_id, &upd, warehouse).await?;
+
+        Ok(Self::WaitMessage(StageData {
+            order: Some(order),
+            prompt: Some(prompt),
+        }))
+    }
+}
+
+#[async_trait]
+impl ConversationStage<String> for Stage {
+    async fn next(
+        self,
+        bot: Bot,
+        msg: Message,
+        user: (User, UserMeta),
+        warehouse: &mut Warehouse,
+        text: String,
+    ) -> Result<Self> {
+        match self {
+            Stage::WaitMessage(StageData {
+                order: Some(order), ..
+            }) => {
+                let lang_code = msg
+                    .from()
+                    .map(|u| u.language_code.clone())
+                    .flatten()
+                    .unwrap_or("en".to_owned());
+
+                // The order may have been closed while the message was written
+                let order = verify_with_msg(&bot, &msg, warehouse)
+                    .order_by_id(order.id.clone())
+                    .await?
+                    .stage_is_not(OrderStage::Cancelled)
+                    .await?
+                    .stage_is_not(OrderStage::Completed)
+                    .await?
+                    .into_result();
+
+                let Some(recipient_name) = recipient(&order, &user.0.name).map(str::to_owned)
+                else {
+                    return Ok(Self::Start);
+                };
+
+                warehouse.users.refresh().await?;
+                let recipient_lang = lang_code_of(warehouse.users.by_name.get(&recipient_name));
+
+                warehouse.users_meta.refresh().await?;
+
+                let recipient_chat_id = verify_with_msg(&bot, &msg, warehouse)
+                    .user_meta_by_name(&recipient_name)
+                    .await?
+                    .has_chat_id()
+                    .await?
+                    .into_result()
+                    .chat_id
+                    .unwrap();
+
+                let item = verify_with_msg(&bot, &msg, warehouse)
+                    .item_by_id(&order.item_id)
+                    .await?
+                    .into_result();
+
+                let sender = shown_name(warehouse, &order, &user.0.name);
+                let recipient_shown = shown_name(warehouse, &order, &recipient_name);
+
+                let request = bot
+                    .send_message(
+                        recipient_chat_id,
+                        localize!(
+                            warehouse,
+                            &recipient_lang,
+                            "Message about your order for {name} from {sender}:\n\n{text}",
+                            "name" => item.name,
+                            "sender" => sender,
+                            "text" => text
+                        ),
+                    )
+                    .reply_markup(ReplyMarkup::inline_kb(vec![vec![
+                        InlineKeyboardButton::switch_inline_query_current_chat(
+                            localize!(warehouse, &recipient_lang, "Details"),
+                            format!(".o {}", order.id),
+                        ),
+                    ]]));
+                send_or_forget(warehouse, &recipient_name, request).await?;
+
+                bot.send_message(
+                    msg.chat.id,
+                    localize_msg!(warehouse, msg,
+                        "Your message has been sent to {name}.",
+                        "name" => recipient_shown
+                    ),
+                )
+                .reply_markup(user_keyboard(warehouse, &lang_code, &user.0).await)
+                .await?;
+
+                Ok(Self::Start)
+            }
+            _ => Ok(self),
+        }
+    }
+}
+
+/// The other participant of the order, none if the sender isn't one
+/// or the order is already completed or cancelled.
+fn recipient<'o>(order: &'o Order, sender: &str) -> Option<&'o str> {
+    if matches!(order.stage, OrderStage::Completed | OrderStage::Cancelled) {
+        None
+    } else if sender == order.customer {
+        Some(&order.merchant)
+    } else if sender == order.merchant {
+        Some(&order.customer)
+    } else {
+        None
+    }
+}
+
+/// Participant as the other one sees them, the merchant by the shop name.
+fn shown_name(warehouse: &Warehouse, order: &Order, name: &str) -> String {
+    if name == order.merchant {
+        warehouse.merchant_contact(name)
+    } else {
+        name.to_owned()
+    }
+}
+
+/// The relayed message is written in the language of the recipient, not the sender.
+fn lang_code_of(user: Option<&User>) -> String {
+    user.map_or("en", |user| user.lang_code.as_str()).to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::fixtures;
+
+    fn order() -> Order {
+        Order {
+            stage: OrderStage::Negotiated,
+            cost: 0.0,
+            ..fixtures::order()
+        }
+    }
+
+    #[test]
+    fn relays_to_other_participant() {
+        let order = order();
+
+        assert_eq!(recipient(&order, "customer"), Some("merchant"));
+        assert_eq!(recipient(&order, "merchant"), Some("customer"));
+        assert_eq!(recipient(&order, "stranger"), None);
+    }
+
+    #[test]
+    fn relays_only_ongoing_orders() {
+        for (stage, relayed) in [
+            (OrderStage::WaitForPayment, true),
+            (OrderStage::Paid, true),
+            (OrderStage::Completed, false),
+            (OrderStage::Cancelled, false),
+        ] {
+            let order = Order { stage, ..order() };
+            assert_eq!(recipient(&order, "customer").is_some(), relayed);
+        }
+    }
+
+    #[test]
+    fn written_in_recipient_language() {
+        let merchant = User {
+            name: "merchant".to_owned(),
+            role: Role::Merchant,
+            lang_code: "ru".to_owned(),
+            ..fixtures::user()
+        };
+
+        assert_eq!(lang_code_of(Some(&merchant)), "ru");
+        assert_eq!(lang_code_of(None), "en");
+    }
+}
@@ -11,9 +11,10 @@ use crate::{
     dialogues::enter_user_dialogue,
     prelude::*,
     utils::{
+        labels::display_stage,
         payload::PayloadOp,
         row::Row,
-        verify::{verify_with_callback, verify_with_msg},
+        verify::{send_or_forget, verify_with_callback, verify_with_msg},
     },
 };
 
@@ -22,6 +23,7 @@ type Storage = InMemStorage<Stage>;
 #[derive(Default, Clone)]
 struct StageData {
     pub order: Option<Row<Order>>,
+    pub prompt: Option<Prompt>,
 }
 
 #[derive(Default, Clone)]
@@ -33,6 +35,7 @@ enum Stage {
 
 pub fn handler() -> HandlerResult {
     dptree::entry()
+        .branch(cancel_by_callback::<Stage, Storage>())
         .branch(
             Update::filter_callback_query()
                 .chain(enter_user_dialogue::<Storage, Stage>(
@@ -73,6 +76,13 @@ impl ConversationStart for Stage {
         Role::Merchant
     }
 
+    fn prompt(&self) -> Option<&Prompt> {
+        match self {
+            Stage::WaitPrice(data) => data.prompt.as_ref(),
+            _ => None,
+        }
+    }
+
     async fn start(
         self,
         bot: Bot,
@@ -96,23 +106,22 @@ impl ConversationStart for Stage {
             .into_result();
 
         bot.answer_callback_query(q.id).await?;
-        bot.send_message(
-            chat_id,
+        let prompt = price_prompt(
             localize_upd!(warehouse, upd,
                 concat!(
                     "Please enter the price for all items in total. ",
                     "Write as a real number with a currency (for example, \"100.50 eur\" or \"30 CZK\").")
                 ),
-        )
-        .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
-            resize_keyboard: Some(true),
-            one_time_keyboard: Some(true),
-            keyboard: vec![vec![KeyboardButton::new(localize_upd!(warehouse, upd, "Cancel"))]],
-            ..Default::default()
-        }))
-        .await?;
+            localize_upd!(warehouse, upd, "Cancel"),
+        );
+        prompt.send(&bot, chat_id).await?;
 
-        Ok(Self::WaitPrice(StageData { order: Some(order) }))
+        send_cancel_hint(&bot, chat_id, &upd, warehouse).await?;
+
+        Ok(Self::WaitPrice(StageData {
+            order: Some(order),
+            prompt: Some(prompt),
+        }))
     }
 }
 
@@ -128,7 +137,8 @@ impl ConversationStage<(f64, Currency)> for Stage {
     ) -> Result<Self> {
         match self {
             Stage::WaitPrice(StageData {
-                order: Some(order), ..
+                order: Some(order),
+                prompt,
             }) => {
                 let lang_code = msg
                     .from()
@@ -136,10 +146,50 @@ impl ConversationStage<(f64, Currency)> for Stage {
                     .flatten()
                     .unwrap_or("en".to_owned());
 
+                let (cost, currency) = money;
+                if let Some(min) = warehouse
+                    .min_order_shortfall(&order.merchant, cost, currency)
+                    .await?
+                {
+                    bot.send_message(
+                        msg.chat.id,
+                        localize_msg!(warehouse, msg,
+                            "The minimum order is {amount}, please enter a larger price.",
+                            "amount" => currency.format_amount(min)
+                        ),
+                    )
+                    .await?;
+                    return Ok(Self::WaitPrice(StageData {
+                        order: Some(order),
+                        prompt,
+                    }));
+                }
+
                 bot.send_message(msg.chat.id, localize_msg!(warehouse, msg, "Processing..."))
                     .reply_markup(user_keyboard(warehouse, &lang_code, &user.0).await)
                     .await?;
 
+                // The order may have been cancelled while the price was being typed
+                let current = verify_with_msg(&bot, &msg, warehouse)
+                    .order_by_id(order.id.clone())
+                    .await?
+                    .into_result();
+
+                let mut priced = current.entry.clone();
+                if let Err(stage) = price_order(&mut priced, money) {
+                    let stage = display_stage(warehouse, &lang_code, &stage).await;
+                    bot.send_message(
+                        msg.chat.id,
+                        localize_msg!(warehouse, msg,
+                            "The order is {stage} now, so it can't be priced anymore.",
+                            "stage" => stage
+                        ),
+                    )
+                    .await?;
+                    return Ok(Self::Start);
+                }
+                priced.pin_base_amount(warehouse.exchange_rates.as_ref());
+
                 warehouse.users_meta.refresh().await?;
 
                 let customer_chat_id = verify_with_msg(&bot, &msg, warehouse)
@@ -157,12 +207,8 @@ impl ConversationStage<(f64, Currency)> for Stage {
                     .into_result();
 
                 let order = verify_with_msg(&bot, &msg, warehouse)
-                    .with(order)
-                    .update(|o| {
-                        o.cost = money.0;
-                        o.currency = money.1;
-                        o.stage = OrderStage::WaitForPayment;
-                    })
+                    .with(current)
+                    .update(|o| o.entry = priced)
                     .await?
                     .into_result();
 
@@ -178,22 +224,23 @@ impl ConversationStage<(f64, Currency)> for Stage {
                 ]]))
                 .await?;
 
-                bot.send_message(
-                    customer_chat_id,
-                    localize_msg!(
-                        warehouse,
-                        msg,
-                        "The seller has priced the {name}, you can now pay for it!",
-                        "name" => item.name
-                    ),
-                )
-                .reply_markup(ReplyMarkup::inline_kb(vec![vec![
-                    InlineKeyboardButton::switch_inline_query_current_chat(
-                        localize_msg!(warehouse, msg, "Details"),
-                        format!(".o {}", order.id),
-                    ),
-                ]]))
-                .await?;
+                let request = bot
+                    .send_message(
+                        customer_chat_id,
+                        localize_msg!(
+                            warehouse,
+                            msg,
+                            "The seller has priced the {name}, you can now pay for it!",
+                            "name" => item.name
+                        ),
+                    )
+                    .reply_markup(ReplyMarkup::inline_kb(vec![vec![
+                        InlineKeyboardButton::switch_inline_query_current_chat(
+                            localize_msg!(warehouse, msg, "Details"),
+                            format!(".o {}", order.id),
+                        ),
+                    ]]));
+                send_or_forget(warehouse, &order.customer, request).await?;
 
                 Ok(Self::Start)
             }
@@ -201,3 +248,95 @@ impl ConversationStage<(f64, Currency)> for Stage {
         }
     }
 }
+
+/// Asks for the total price, the keyboard only has the Cancel button.
+fn price_prompt(text: String, cancel: String) -> Prompt {
+    Prompt::new(
+        text,
+        KeyboardMarkup {
+            resize_keyboard: Some(true),
+            one_time_keyboard: Some(true),
+            keyboard: vec![vec![KeyboardButton::new(cancel)]],
+            ..Default::default()
+        },
+    )
+}
+
+/// Sets the price of a negotiated order, returns the stage the order is in otherwise.
+fn price_order(
+    order: &mut Order,
+    (cost, currency): (f64, Currency),
+) -> std::result::Result<(), OrderStage> {
+    if order.stage != OrderStage::Negotiated {
+        return Err(order.stage.clone());
+    }
+
+    order.cost = cost;
+    order.currency = currency;
+    order.stage = OrderStage::WaitForPayment;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialogues::stages::reask_message;
+    use crate::entries::fixtures;
+
+    fn order(stage: OrderStage) -> Order {
+        Order {
+            stage,
+            cost: 0.0,
+            ..fixtures::order()
+        }
+    }
+
+    #[test]
+    fn prices_negotiated_order() {
+        let mut order = order(OrderStage::Negotiated);
+
+        assert_eq!(price_order(&mut order, (12.5, Currency::CZK)), Ok(()));
+        assert_eq!(order.cost, 12.5);
+        assert_eq!(order.currency, Currency::CZK);
+        assert_eq!(order.stage, OrderStage::WaitForPayment);
+    }
+
+    #[test]
+    fn aborts_when_order_left_negotiation() {
+        for stage in [OrderStage::Cancelled, OrderStage::WaitForPayment] {
+            let mut order = order(stage.clone());
+
+            assert_eq!(
+                price_order(&mut order, (12.5, Currency::CZK)),
+                Err(stage.clone())
+            );
+            assert_eq!(order.cost, 0.0);
+            assert_eq!(order.stage, stage);
+        }
+    }
+
+    #[test]
+    fn non_text_price_reasks_with_keyboard() {
+        let prompt = price_prompt("Please enter the price.".to_owned(), "Cancel".to_owned());
+        let stage = Stage::WaitPrice(StageData {
+            order: None,
+            prompt: Some(prompt.clone()),
+        });
+
+        let reply = reask_message(stage.prompt(), "Please send a number with a currency.");
+        assert_eq!(reply, prompt);
+        assert!(matches!(
+            reply.keyboard,
+            Some(ReplyMarkup::Keyboard(keyboard))
+                if keyboard.keyboard == vec![vec![KeyboardButton::new("Cancel")]]
+        ));
+
+        // Stages keeping no prompt still get the plain reminder
+        let reply = reask_message(
+            Stage::Start.prompt(),
+            "Please send a number with a currency.",
+        );
+        assert_eq!(reply.text, "Please send a number with a currency.");
+        assert_eq!(reply.keyboard, None);
+    }
+}
@@ -22,6 +22,77 @@ pub fn handler() -> HandlerResult {
         )
 }
 
+/// Picks the merchant's own provider token, falling back to the global one.
+fn select_provider_token(merchant: Option<&Merchant>, global: String) -> String {
+    merchant
+        .and_then(|merchant| merchant.provider_token.as_ref())
+        .map(Secret::expose)
+        .filter(|token| !token.trim().is_empty())
+        .map_or(global, str::to_owned)
+}
+
+/// Whether the provider the invoice goes through takes the currency. The merchant's
+/// currencies go with their own token, the configured ones with the global token.
+fn provider_accepts(
+    merchant: Option<&Merchant>,
+    global_currencies: Option<&[Currency]>,
+    currency: &Currency,
+) -> bool {
+    let own_token = merchant
+        .and_then(|merchant| merchant.provider_token.as_ref())
+        .map(Secret::expose)
+        .filter(|token| !token.trim().is_empty())
+        .is_some();
+
+    let currencies = if own_token {
+        merchant.and_then(Merchant::provider_currencies)
+    } else {
+        global_currencies.map(|currencies| currencies.to_vec())
+    };
+
+    currencies.map_or(true, |currencies| currencies.contains(currency))
+}
+
+/// Whether the merchant's products can be paid by card in the currency. If they
+/// can't, the customer is told to contact the merchant, since the provider would
+/// reject the invoice without telling why.
+pub async fn card_accepted(
+    bot: &Bot,
+    chat_id: ChatId,
+    lang_code: &str,
+    warehouse: &mut Warehouse,
+    merchant: &str,
+    currency: &Currency,
+) -> Result<bool> {
+    warehouse.merchants.refresh().await?;
+
+    if provider_accepts(
+        warehouse.merchants.by_name.get(&merchant.to_owned()),
+        warehouse.provider_currencies.as_deref(),
+        currency,
+    ) {
+        return Ok(true);
+    }
+
+    let contact = warehouse.merchant_contact(merchant);
+    bot.send_message(
+        chat_id,
+        localize!(
+            warehouse,
+            lang_code,
+            concat!(
+                "Sorry, card payments in {currency} aren't available for this seller. ",
+                "Please contact {merchant} to pay another way."
+            ),
+            "currency" => currency.to_string(),
+            "merchant" => contact
+        ),
+    )
+    .await?;
+
+    Ok(false)
+}
+
 pub async fn send_invoice(
     bot: Bot,
     chat_id: ChatId,
@@ -33,8 +104,29 @@ pub async fn send_invoice(
     let item = verify_with_chat_user(&bot, chat_id, user, lang_code, warehouse)
         .item_by_id(&order.item_id)
         .await?
+        .is_renderable()
+        .await?
         .into_result();
 
+    if !card_accepted(
+        &bot,
+        chat_id,
+        lang_code,
+        warehouse,
+        &order.merchant,
+        &order.currency,
+    )
+    .await?
+    {
+        warn!(
+            "Invoice for order {} isn't sent, the provider doesn't take {}",
+            order.id,
+            order.currency.to_string()
+        );
+        return Ok(());
+    }
+    let merchant = warehouse.merchants.by_name.get(&order.merchant).cloned();
+
     bot.send_message(
         chat_id,
         localize!(
@@ -44,25 +136,30 @@ pub async fn send_invoice(
                 "Here's your invoice for the purchase of <b>{name}</b> in quantit{end} of <b>{quantity}</b>. ",
                 "You can get an invoice at any time through the order menu."
             ),
-            "name" => localize!(warehouse, lang_code, item.name),
-            "end" => if order.amount == 1 { "y" } else { "ies" },
+            "name" => localize_static!(warehouse, lang_code, item.name),
+            "end" => if order.amount == 1.0 { "y" } else { "ies" },
             "quantity" => order.amount
         ),
     )
     .parse_mode(ParseMode::Html)
     .await?;
 
+    let provider_token = select_provider_token(
+        merchant.as_ref(),
+        localize!(warehouse, lang_code, "PROVIDER_TOKEN"),
+    );
+
     let result = bot
         .send_invoice(
             chat_id,
-            localize!(warehouse, lang_code, item.name),
-            localize!(warehouse, lang_code, item.full_desc),
+            localize_static!(warehouse, lang_code, item.name),
+            localize_static!(warehouse, lang_code, item.full_desc),
             Payload::checkout(order.id).to_string(),
-            localize!(warehouse, lang_code, "PROVIDER_TOKEN"),
+            provider_token,
             order.currency.to_string(),
             vec![LabeledPrice::new(
                 format!("{}x {}", order.amount, item.name.clone()),
-                (order.cost * 100.0) as i32,
+                order.currency.to_minor(order.cost),
             )],
         )
         .photo_url(item.image_url.clone().parse()?)
@@ -90,47 +187,48 @@ pub async fn send_invoice(
     Ok(())
 }
 
-async fn pre_checkout(bot: Bot, q: PreCheckoutQuery, warehouse: SharedWarehouse) -> Result<()> {
-    let mut warehouse = warehouse.write().await;
+async fn pre_checkout(bot: Bot, q: PreCheckoutQuery, warehouse: LockedWarehouse) -> Result<()> {
+    let mut warehouse = warehouse.lock().await;
+    let username = q.from.username.clone();
 
-    let _ = verify_with_pre_checkout(&bot, &q, &mut warehouse)
+    // On failure the notifier has already declined the query
+    verify_with_pre_checkout(&bot, &q, &mut warehouse)
         .payload_str(&q.invoice_payload)
         .await?
+        .verify_order()
+        .await?
+        .stage_is(OrderStage::WaitForPayment)
+        .await?
         .branch(|v| async move {
-            v.verify_order()
+            v.verify_customer()
                 .await?
-                .stage_is(OrderStage::WaitForPayment)
+                .username_is_opt(username)
                 .await?
-                .branch(|v| async move {
-                    v.verify_customer()
-                        .await?
-                        .username_is_opt(q.from.username)
-                        .await?
-                        .verify_meta()
-                        .await
-                })
-                .await?
-                .customer_has_order()
-                .await?
-                .merchant_has_order()
-                .await?
-                .currency_is(q.currency)
-                .await?
-                .cost_is(q.total_amount as f64 / 100.0)
+                .verify_meta()
                 .await
-        });
+        })
+        .await?
+        .customer_has_order()
+        .await?
+        .merchant_has_order()
+        .await?
+        .invoice_matches(q.currency, q.total_amount as i32)
+        .await?
+        // The stock was reserved with the order, so the product only has to exist
+        .branch(|v| async move { v.verify_product().await })
+        .await?;
 
     bot.answer_pre_checkout_query(q.id, true).await?;
 
     Ok(())
 }
 
-async fn successful_payment(bot: Bot, msg: Message, warehouse: SharedWarehouse) -> Result<()> {
+async fn successful_payment(bot: Bot, msg: Message, warehouse: LockedWarehouse) -> Result<()> {
     if msg.successful_payment().is_none() {
         return Ok(());
     }
 
-    let mut warehouse = warehouse.write().await;
+    let mut warehouse = warehouse.lock().await;
     let payment = msg.successful_payment().unwrap();
 
     let order = verify_with_msg(&bot, &msg, &mut warehouse)
@@ -138,9 +236,7 @@ async fn successful_payment(bot: Bot, msg: Message, warehouse: SharedWarehouse)
         .await?
         .verify_order()
         .await?
-        .update(|o| {
-            o.stage = OrderStage::Paid;
-        })
+        .mark_paid()
         .await?
         .into_result();
 
@@ -179,17 +275,77 @@ async fn successful_payment(bot: Bot, msg: Message, warehouse: SharedWarehouse)
         .await?
         .into_result();
 
-    bot.send_message(
-        merchant_chat_id,
-        format!("The order for {} has been paid!", item.name),
-    )
-    .reply_markup(ReplyMarkup::inline_kb(vec![vec![
-        InlineKeyboardButton::switch_inline_query_current_chat(
-            "Details",
-            format!(".o {}", order.id),
-        ),
-    ]]))
-    .await?;
+    let request = bot
+        .send_message(
+            merchant_chat_id,
+            format!("The order for {} has been paid!", item.name),
+        )
+        .reply_markup(ReplyMarkup::inline_kb(vec![vec![
+            InlineKeyboardButton::switch_inline_query_current_chat(
+                "Details",
+                format!(".o {}", order.id),
+            ),
+        ]]));
+    send_or_forget(&mut warehouse, &order.merchant, request).await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::fixtures;
+
+    fn merchant(provider_token: Option<&str>) -> Merchant {
+        Merchant {
+            location: "-".to_owned(),
+            address: "-".to_owned(),
+            provider_token: provider_token.map(|token| Secret(token.to_owned())),
+            ..fixtures::merchant()
+        }
+    }
+
+    #[test]
+    fn unsupported_currency_blocked_before_invoice() {
+        let mut own = merchant(Some("own"));
+        own.provider_currencies = Some("rub, kzt".to_owned());
+        let global = [Currency::EUR];
+
+        // The merchant's provider goes by the merchant's currencies
+        assert!(!provider_accepts(Some(&own), Some(&global), &Currency::EUR));
+        assert!(provider_accepts(Some(&own), Some(&global), &Currency::RUB));
+
+        // The global provider goes by the configured ones
+        let plain = merchant(None);
+        assert!(!provider_accepts(
+            Some(&plain),
+            Some(&global),
+            &Currency::RUB
+        ));
+        assert!(provider_accepts(None, Some(&global), &Currency::EUR));
+
+        // Unknown currencies don't block anything
+        own.provider_currencies = Some(" ".to_owned());
+        assert!(provider_accepts(Some(&own), Some(&global), &Currency::USD));
+        assert!(provider_accepts(None, None, &Currency::USD));
+    }
+
+    #[test]
+    fn provider_token_per_merchant() {
+        let global = || "global".to_owned();
+
+        assert_eq!(
+            select_provider_token(Some(&merchant(Some("own"))), global()),
+            "own"
+        );
+        assert_eq!(
+            select_provider_token(Some(&merchant(None)), global()),
+            "global"
+        );
+        assert_eq!(
+            select_provider_token(Some(&merchant(Some(" "))), global()),
+            "global"
+        );
+        assert_eq!(select_provider_token(None, global()), "global");
+    }
+}
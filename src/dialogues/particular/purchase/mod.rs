@@ -1,8 +1,10 @@
 pub mod invoice;
 
+use std::{collections::HashMap, fmt::Display};
+
 use async_trait::async_trait;
 use chrono::Utc;
-use log::error;
+use log::{error, warn};
 use teloxide::{
     dispatching::dialogue::InMemStorage,
     prelude::*,
@@ -10,10 +12,20 @@ use teloxide::{
         InlineKeyboardButton, KeyboardButton, KeyboardMarkup, LabeledPrice, ParseMode, ReplyMarkup,
         Update, UpdateKind,
     },
+    utils::html,
 };
 
 use crate::utils::verify::{prelude::*, verify_with_msg};
-use crate::{dialogues::enter_user_dialogue, prelude::*, utils::row::Row};
+use crate::{
+    dialogues::enter_user_dialogue,
+    prelude::*,
+    utils::{
+        lifecycle::{new_order, open_order, update_metas},
+        outcome::{Flow, Outcome},
+        row::Row,
+    },
+    warehouse::available_of,
+};
 use crate::{
     localize_upd,
     utils::payload::{Payload, PayloadOp},
@@ -25,8 +37,10 @@ type Storage = InMemStorage<Stage>;
 struct StageData {
     pub product: Option<Row<Product>>,
     pub item: Option<Row<Item>>,
-    pub amount: Option<u32>,
+    pub amount: Option<f64>,
     pub payment_method: Option<PurchaseWith>,
+    pub note: Option<String>,
+    pub prompt: Option<Prompt>,
 }
 
 #[derive(Default, Clone)]
@@ -47,15 +61,25 @@ enum PurchaseWith {
 
 pub fn handler() -> HandlerResult {
     dptree::entry()
+        .branch(cancel_by_callback::<Stage, Storage>())
         // Handle purcahse
         .branch(
             Update::filter_callback_query()
                 .chain(enter_user_dialogue::<Storage, Stage>(
-                    "To purchase a product you first need to start a dialog with the bot."
+                    "To purchase a product you first need to start a dialog with the bot.",
                 ))
                 .filter(callback_prefix(PayloadOp::Purchase))
                 .endpoint(start::<Stage, Storage>),
         )
+        // Handle reorder of a completed order
+        .branch(
+            Update::filter_callback_query()
+                .chain(enter_user_dialogue::<Storage, Stage>(
+                    "To purchase a product you first need to start a dialog with the bot.",
+                ))
+                .filter(callback_prefix(PayloadOp::Reorder))
+                .endpoint(start::<Stage, Storage>),
+        )
         // Handle dialogue stages
         .branch(
             Update::filter_message()
@@ -78,7 +102,6 @@ pub fn handler() -> HandlerResult {
                         .endpoint(receive_text_stage::<Stage, Storage>),
                 ),
         )
-        .chain(invoice::handler())
 }
 
 pub fn write_deps(deps: &mut DependencyMap) {
@@ -98,6 +121,25 @@ impl ConversationStart for Stage {
         Role::User
     }
 
+    fn amount_unit(&self) -> Unit {
+        match self {
+            Self::WaitAmount(StageData {
+                product: Some(product),
+                ..
+            }) => product.unit,
+            _ => Unit::Each,
+        }
+    }
+
+    fn prompt(&self) -> Option<&Prompt> {
+        match self {
+            Self::Start => None,
+            Self::WaitAmount(data) | Self::WaitPaymentMethod(data) | Self::WaitConfirm(data) => {
+                data.prompt.as_ref()
+            }
+        }
+    }
+
     async fn start(
         self,
         bot: Bot,
@@ -116,46 +158,86 @@ impl ConversationStart for Stage {
                 let payload_content = q.data.clone().unwrap_or("".to_owned());
 
                 let chat_id = user.1.chat_id.unwrap();
-                let product = verify_with_callback(&bot, &q, warehouse)
-                    .payload_str(&payload_content)
-                    .await?
-                    .verify_product()
-                    .await?
-                    .visible_to_user(&user.0)
-                    .await?
-                    .left_at_least(1)
-                    .await?
-                    .into_result();
+                let (product, last_amount) = if PayloadOp::Reorder.is_in_payload(&payload_content) {
+                    let (product, order, offer) =
+                        verify_reorder(&bot, &q, warehouse, &user.0, &payload_content).await?;
+                    (product, Some((order.amount, offer)))
+                } else {
+                    let product = verify_with_callback(&bot, &q, warehouse)
+                        .payload_str(&payload_content)
+                        .await?
+                        .verify_product()
+                        .await?
+                        .visible_to_user(&user.0)
+                        .await?
+                        .in_stock()
+                        .await?
+                        .into_result();
+                    (product, None)
+                };
 
                 let item = verify_with_callback(&bot, &q, warehouse)
                     .item_by_id(&product.item_id)
                     .await?
+                    .is_renderable()
+                    .await?
                     .into_result();
 
-                bot.send_message(chat_id, localize_upd!(
-                        warehouse, upd, 
+                let name = html::escape(&localize_static_upd!(warehouse, upd, item.name));
+                let text = match last_amount {
+                    Some((amount, offer)) if offer < amount => localize_upd!(
+                        warehouse, upd,
+                        "Last time you bought {amount}x <b>{name}</b>, but only {left} are left now. How much do you want?",
+                        "amount" => amount,
+                        "name" => name,
+                        "left" => offer
+                    ),
+                    Some((amount, _)) => localize_upd!(
+                        warehouse, upd,
+                        "Last time you bought {amount}x <b>{name}</b>, how much do you want now?",
+                        "amount" => amount,
+                        "name" => name
+                    ),
+                    None => localize_upd!(
+                        warehouse, upd,
                         "Hey, you wanted to buy the <b>{name}</b>, I'm very pleased! Just need to clarify how much you want to buy?",
-                        "name" => localize_upd!(warehouse, upd, item.name) 
-                    ))
-                    .parse_mode(ParseMode::Html)
-                    .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
+                        "name" => name
+                    ),
+                };
+
+                // The amount to reorder goes first, one tap repeats the order
+                let mut keyboard: Vec<Vec<_>> = last_amount
+                    .map(|(_, offer)| vec![KeyboardButton::new(offer.to_string())])
+                    .into_iter()
+                    .collect();
+                keyboard.push(
+                    (1..=5)
+                        .map(|i| KeyboardButton::new(i.to_string()))
+                        .collect(),
+                );
+                keyboard.push(vec![KeyboardButton::new(localize_upd!(
+                    warehouse, upd, "Cancel"
+                ))]);
+
+                let prompt = Prompt::html(
+                    text,
+                    KeyboardMarkup {
                         resize_keyboard: Some(true),
                         is_persistent: true,
-                        keyboard: vec![
-                            (1..=5)
-                                .map(|i| KeyboardButton::new(i.to_string()))
-                                .collect(),
-                            vec![KeyboardButton::new(localize_upd!(warehouse, upd, "Cancel"))],
-                        ],
+                        keyboard,
                         ..Default::default()
-                    }))
-                    .await?;
+                    },
+                );
+                prompt.send(&bot, chat_id).await?;
+
+                send_cancel_hint(&bot, chat_id, &upd, warehouse).await?;
 
                 bot.answer_callback_query(q.id.clone()).await?;
 
                 Ok(Self::WaitAmount(StageData {
                     product: Some(product),
                     item: Some(item),
+                    prompt: Some(prompt),
                     ..Default::default()
                 }))
             }
@@ -165,48 +247,67 @@ impl ConversationStart for Stage {
 }
 
 #[async_trait]
-impl ConversationStage<u32> for Stage {
+impl ConversationStage<f64> for Stage {
     async fn next(
         self,
         bot: Bot,
         msg: Message,
         user: (User, UserMeta),
         warehouse: &mut Warehouse,
-        amount: u32,
+        amount: f64,
     ) -> Result<Self> {
         match self {
             Stage::WaitAmount(mut data) => {
-                data.amount = Some(amount as u32);
+                data.amount = Some(amount);
 
                 let product = data.product.as_ref().unwrap();
                 
                 verify_with_msg(&bot, &msg, warehouse)
                     .with(product.clone())
-                    .left_at_least(amount)
+                    .available_at_least(amount)
                     .await?
                     .merchant_is_not(user.0.name.clone())
                     .await?;
 
+                // Negotiated orders are checked once the merchant names the price
+                if !product.negotiated_price {
+                    let (merchant, currency) = (product.merchant.clone(), product.currency);
+                    let total = product.price * amount;
+
+                    if let Some(min) = warehouse
+                        .min_order_shortfall(&merchant, total, currency)
+                        .await?
+                    {
+                        bot.send_message(
+                            msg.chat.id,
+                            localize_msg!(warehouse, msg,
+                                "The minimum order is {amount}, please enter a larger amount.",
+                                "amount" => currency.format_amount(min)
+                            ),
+                        )
+                        .await?;
+                        return Ok(Self::WaitAmount(data));
+                    }
+                }
+
+                let product = data.product.as_ref().unwrap();
                 if product.negotiated_price {
                     data.payment_method = Some(PurchaseWith::Negotiated);
-                    bot.send_message(
-                        msg.chat.id,
+                    let prompt = Prompt::html(
                         localize_msg!(warehouse, msg,
                             "Do you really want to buy {amount}x {name} at a negotiated price?",
                             "amount" => amount, 
-                            "name" => localize_msg!(warehouse, msg, data.item.as_ref().unwrap().name)
+                            "name" => html::escape(&localize_static_msg!(warehouse, msg, data.item.as_ref().unwrap().name))
                         ),
-                    )
-                    .parse_mode(ParseMode::Html)
-                    .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
-                        resize_keyboard: Some(true),
-                        one_time_keyboard: Some(true),
-                        keyboard: vec![vec![
-                            KeyboardButton::new(localize_msg!(warehouse, msg, "Yes")), 
-                            KeyboardButton::new(localize_msg!(warehouse, msg, "No"))]],
-                        ..Default::default()
-                    }))
-                    .await?;
+                        KeyboardMarkup {
+                            resize_keyboard: Some(true),
+                            one_time_keyboard: Some(true),
+                            keyboard: confirm_keyboard(warehouse, &msg, &user.1).await,
+                            ..Default::default()
+                        },
+                    );
+                    prompt.send(&bot, msg.chat.id).await?;
+                    data.prompt = Some(prompt);
                     return Ok(Self::WaitConfirm(data));
                 }
 
@@ -220,12 +321,12 @@ impl ConversationStage<u32> for Stage {
                     PaymentMethod::Both => {
                         let text = localize_msg!(warehouse, msg, 
                             "How do you want to pay for the <b>{name}</b>?",
-                            "name" => localize_msg!(warehouse, msg, data.item.as_ref().unwrap().name)
+                            "name" => html::escape(&localize_static_msg!(warehouse, msg, data.item.as_ref().unwrap().name))
                         );
 
-                        bot.send_message(msg.chat.id, text)
-                            .parse_mode(ParseMode::Html)
-                            .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
+                        let prompt = Prompt::html(
+                            text,
+                            KeyboardMarkup {
                                 resize_keyboard: Some(true),
                                 one_time_keyboard: Some(true),
                                 keyboard: vec![
@@ -236,36 +337,18 @@ impl ConversationStage<u32> for Stage {
                                         KeyboardButton::new(localize_msg!(warehouse, msg, "Cancel"))],
                                 ],
                                 ..Default::default()
-                            }))
-                            .await?;
+                            },
+                        );
+                        prompt.send(&bot, msg.chat.id).await?;
+                        data.prompt = Some(prompt);
 
                         return Ok(Self::WaitPaymentMethod(data));
                     }
                 };
 
-                let product = data.product.as_ref().unwrap();
-                let item = data.item.as_ref().unwrap();
-                let amount = data.amount.unwrap();
-
-                bot.send_message(
-                    msg.chat.id,
-                    localize_msg!(warehouse, msg,
-                        "Do you really want to buy {amount}x {name} for <b>{price}</b>?",
-                        "amount" => amount, 
-                        "name" => localize_msg!(warehouse, msg, item.name),
-                        "price" => product.currency.format(&(product.price * amount as f64).to_string())
-                    ),
-                )
-                .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
-                    resize_keyboard: Some(true),
-                    one_time_keyboard: Some(true),
-                    keyboard: vec![vec![
-                        KeyboardButton::new(localize_msg!(warehouse, msg, "Yes")), 
-                        KeyboardButton::new(localize_msg!(warehouse, msg, "No"))]],
-                    ..Default::default()
-                }))
-                .parse_mode(ParseMode::Html)
-                .await?;
+                let prompt = confirm_prompt(warehouse, &msg, &user.1, &data).await;
+                prompt.send(&bot, msg.chat.id).await?;
+                data.prompt = Some(prompt);
 
                 Ok(Self::WaitConfirm(data))
             }
@@ -310,34 +393,14 @@ impl ConversationStage<String> for Stage {
                     }
                 }
 
-                let product = data.product.as_ref().unwrap();
-                let item = data.item.as_ref().unwrap();
-                let amount = data.amount.unwrap();
-
-                bot.send_message(
-                    msg.chat.id,
-                    localize_msg!(warehouse, msg,
-                        "Do you really want to buy {amount}x {name} for <b>{price}</b>?",
-                        "amount" => amount, 
-                        "name" => localize_msg!(warehouse, msg, item.name),
-                        "price" => product.currency.format(&(product.price * amount as f64).to_string())
-                    ),
-                )
-                .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
-                    resize_keyboard: Some(true),
-                    one_time_keyboard: Some(true),
-                    keyboard: vec![vec![
-                        KeyboardButton::new(localize_msg!(warehouse, msg, "Yes")), 
-                        KeyboardButton::new(localize_msg!(warehouse, msg, "No"))]],
-                    ..Default::default()
-                }))
-                .parse_mode(ParseMode::Html)
-                .await?;
+                let prompt = confirm_prompt(warehouse, &msg, &user.1, &data).await;
+                prompt.send(&bot, msg.chat.id).await?;
+                data.prompt = Some(prompt);
 
                 Ok(Self::WaitConfirm(data))
             }
 
-            Stage::WaitConfirm(data) => {
+            Stage::WaitConfirm(mut data) => {
                 let lang_code = msg
                     .from()
                     .map(|u| u.language_code.clone())
@@ -345,8 +408,13 @@ impl ConversationStage<String> for Stage {
                     .unwrap_or("en".to_owned());
 
                 let yes_text = localize_msg!(warehouse, msg, "Yes").to_lowercase();
+                let note_text = localize_msg!(warehouse, msg, "Yes, attach my note").to_lowercase();
+
+                if user.1.note.is_some() && text.to_lowercase() == note_text {
+                    data.note = user.1.note.clone();
+                }
 
-                if text.to_lowercase() == yes_text {
+                if text.to_lowercase() == yes_text || data.note.is_some() {
                     bot.send_message(msg.chat.id, localize_msg!(warehouse, msg, "Preparing order..."))
                         .reply_markup(user_keyboard(warehouse, &lang_code, &user.0).await)
                         .parse_mode(ParseMode::Html)
@@ -378,6 +446,96 @@ impl ConversationStage<String> for Stage {
     }
 }
 
+/// Why a completed order can't be placed again.
+#[derive(Debug, PartialEq)]
+enum ReorderError {
+    /// The listing was deleted since.
+    Gone,
+    /// Nothing is available anymore.
+    OutOfStock,
+}
+
+impl Display for ReorderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReorderError::Gone => write!(f, "the product is gone"),
+            ReorderError::OutOfStock => write!(f, "not enough of the product is available"),
+        }
+    }
+}
+
+impl std::error::Error for ReorderError {}
+
+/// Reorders go by the current listing, so an old order never repeats a stale price or stock.
+/// The amount to offer is the one of the order, or what's left when less is available.
+fn check_reorder(
+    order: &Order,
+    listing: Option<&Product>,
+    reserved: &HashMap<ProductId, f64>,
+) -> std::result::Result<f64, ReorderError> {
+    let listing = listing
+        .filter(|product| !product.deleted && product.active)
+        .ok_or(ReorderError::Gone)?;
+
+    let available = available_of(listing, reserved);
+    if available < listing.unit.smallest() {
+        return Err(ReorderError::OutOfStock);
+    }
+
+    Ok(order.amount.min(available))
+}
+
+/// The current listing of a completed order of the customer, with the order itself
+/// and the amount to offer, see [`check_reorder`].
+async fn verify_reorder(
+    bot: &Bot,
+    q: &CallbackQuery,
+    warehouse: &mut Warehouse,
+    user: &User,
+    payload: &str,
+) -> Result<(Row<Product>, Row<Order>, f64)> {
+    let order = verify_with_callback(bot, q, warehouse)
+        .payload_str(payload)
+        .await?
+        .verify_order()
+        .await?
+        .stage_is(OrderStage::Completed)
+        .await?
+        .customer_is(&user.name)
+        .await?
+        .into_result();
+
+    warehouse.products.refresh().await?;
+    warehouse.orders.refresh().await?;
+
+    let reserved = warehouse.reserved_stock()?;
+    let listing = warehouse
+        .products
+        .by_id
+        .get_with_row(&order.product_id())
+        .cloned();
+
+    let offer = match check_reorder(&order, listing.as_ref().map(|(_, p)| p), &reserved) {
+        Ok(offer) => offer,
+        Err(e) => {
+            let text = match e {
+                ReorderError::Gone => "Sorry, we can't find your product.",
+                ReorderError::OutOfStock => "Sorry, we don't have enough of this product.",
+            };
+            verify_with_callback(bot, q, warehouse).notify(text).await?;
+            return Err(Box::new(e));
+        }
+    };
+
+    let product = verify_with_callback(bot, q, warehouse)
+        .with(Row::from(listing.unwrap()))
+        .visible_to_user(user)
+        .await?
+        .into_result();
+
+    Ok((product, order, offer))
+}
+
 async fn purchase_negitiated(
     bot: Bot,
     msg: Message,
@@ -387,6 +545,7 @@ async fn purchase_negitiated(
 ) -> Result<()> {
     let product = data.product.unwrap();
     let amount = data.amount.unwrap();
+    let note = data.note;
 
     let order = submit_order(
         &bot,
@@ -395,11 +554,15 @@ async fn purchase_negitiated(
         user,
         product,
         amount,
+        note,
         OrderStage::Negotiated,
     )
     .await?;
-    notify_merchant_about_new_order(&bot, &msg, warehouse, user, &order).await?;
-    notify_customer_about_order(&bot, &msg, warehouse, user, &order).await?;
+    let outcome = Outcome::after(
+        Flow::Purchase,
+        notify_merchant_about_new_order(&bot, &msg, warehouse, user, &order).await,
+    );
+    notify_customer_about_order(&bot, &msg, warehouse, user, &order, outcome).await?;
 
     Ok(())
 }
@@ -413,6 +576,7 @@ async fn purchase_by_cash(
 ) -> Result<()> {
     let product = data.product.unwrap();
     let amount = data.amount.unwrap();
+    let note = data.note;
 
     let order = submit_order(
         &bot,
@@ -421,11 +585,15 @@ async fn purchase_by_cash(
         user,
         product,
         amount,
+        note,
         OrderStage::WaitForPayment,
     )
     .await?;
-    notify_merchant_about_new_order(&bot, &msg, warehouse, user, &order).await?;
-    notify_customer_about_order(&bot, &msg, warehouse, user, &order).await?;
+    let outcome = Outcome::after(
+        Flow::Purchase,
+        notify_merchant_about_new_order(&bot, &msg, warehouse, user, &order).await,
+    );
+    notify_customer_about_order(&bot, &msg, warehouse, user, &order, outcome).await?;
 
     Ok(())
 }
@@ -439,6 +607,29 @@ async fn purchase_by_card(
 ) -> Result<()> {
     let product = data.product.unwrap();
     let amount = data.amount.unwrap();
+    let note = data.note;
+
+    let chat_lang_code = msg.from()
+        .map(|u| u.language_code
+            .as_ref()
+            .map(|c| c
+                .as_str()))
+        .flatten()
+        .unwrap_or("en");
+
+    // No stock is reserved for an order that can't be paid
+    if !invoice::card_accepted(
+        &bot,
+        msg.chat.id,
+        chat_lang_code,
+        warehouse,
+        &product.merchant,
+        &product.currency,
+    )
+    .await?
+    {
+        return Ok(());
+    }
 
     let order = submit_order(
         &bot,
@@ -447,20 +638,16 @@ async fn purchase_by_card(
         user,
         product,
         amount,
+        note,
         OrderStage::WaitForPayment,
     )
     .await?;
 
-    notify_merchant_about_new_order(&bot, &msg, warehouse, user, &order).await?;
-    notify_customer_about_order(&bot, &msg, warehouse, user, &order).await?;
-
-    let chat_lang_code = msg.from()
-        .map(|u| u.language_code
-            .as_ref()
-            .map(|c| c
-                .as_str()))
-        .flatten()
-        .unwrap_or("en");
+    let outcome = Outcome::after(
+        Flow::Purchase,
+        notify_merchant_about_new_order(&bot, &msg, warehouse, user, &order).await,
+    );
+    notify_customer_about_order(&bot, &msg, warehouse, user, &order, outcome).await?;
 
     invoice::send_invoice(bot, msg.chat.id, &chat_lang_code, warehouse, user, order).await?;
 
@@ -473,15 +660,20 @@ async fn submit_order(
     warehouse: &mut Warehouse,
     _: &User,
     mut product: Row<Product>,
-    amount: u32,
+    amount: f64,
+    note: Option<String>,
     stage: OrderStage,
 ) -> Result<Order> {
-    let order = make_order(
+    let mut order = new_order(
+        minimal_id::Generator::new_id().to_string(),
         msg.from().unwrap().username.clone().unwrap(),
         stage,
         &product,
         amount,
+        note,
+        Utc::now(),
     );
+    order.pin_base_amount(warehouse.exchange_rates.as_ref());
 
     let mut customer = verify_with_msg(bot, msg, warehouse)
         .user_by_name(&order.customer)
@@ -498,24 +690,26 @@ async fn submit_order(
         .await?
         .into_result();
 
+    // Nothing is written unless the product still covers the order
+    let metas_before = (customer_meta.entry.clone(), merchant_meta.entry.clone());
+    if !open_order(&order, &mut product, &mut customer_meta, &mut merchant_meta) {
+        verify_with_msg(bot, msg, warehouse)
+            .notify("Sorry, we don't have enough of this product.")
+            .await?;
+        return Err(Box::new(VerifyProductError::NotEnough(product, amount)));
+    }
+
     customer.last_activity_date = Utc::now();
     warehouse.users.update_one(customer.row, &customer).await?;
 
     warehouse.orders.extend_one(&order).await?;
 
-    customer_meta.pending_orders.push(order.id.clone());
-    warehouse
-        .users_meta
-        .update_one(customer_meta.row, &customer_meta)
-        .await?;
-
-    merchant_meta.pending_orders.push(order.id.clone());
-    warehouse
-        .users_meta
-        .update_one(merchant_meta.row, &merchant_meta)
-        .await?;
-
-    product.amount_left -= amount;
+    update_metas(
+        &mut warehouse.users_meta,
+        (customer_meta.row, &metas_before.0, &customer_meta.entry),
+        (merchant_meta.row, &metas_before.1, &merchant_meta.entry),
+    )
+    .await?;
     warehouse.products.update_one(product.row, &product).await?;
 
     update_user_activity(warehouse, &order.customer).await?;
@@ -523,24 +717,62 @@ async fn submit_order(
     Ok(order)
 }
 
-fn make_order(customer: String, stage: OrderStage, product: &Product, amount: u32) -> Order {
-    Order {
-        id: minimal_id::Generator::new_id().to_string(),
-        customer: customer,
-        merchant: product.merchant.clone(),
-        stage,
-        item_id: product.item_id.clone(),
-        amount,
-        cost: if product.negotiated_price {
-            0f64
-        } else {
-            product.price * amount as f64
+/// Asks to confirm the order at the price of the product.
+async fn confirm_prompt(
+    warehouse: &mut Warehouse,
+    msg: &Message,
+    meta: &UserMeta,
+    data: &StageData,
+) -> Prompt {
+    let product = data.product.as_ref().unwrap();
+    let item = data.item.as_ref().unwrap();
+    let amount = data.amount.unwrap();
+
+    let text = localize_msg!(warehouse, msg,
+        "Do you really want to buy {amount}x {name} for <b>{price}</b>?",
+        "amount" => amount,
+        "name" => html::escape(&localize_static_msg!(warehouse, msg, item.name)),
+        "price" => product.currency.format_amount(product.price * amount)
+    );
+
+    Prompt::html(
+        text,
+        KeyboardMarkup {
+            resize_keyboard: Some(true),
+            one_time_keyboard: Some(true),
+            keyboard: confirm_keyboard(warehouse, msg, meta).await,
+            ..Default::default()
         },
-        currency: product.currency,
-        date: Utc::now(),
+    )
+}
+
+/// Yes/No answers, with an extra one attaching the profile note when the customer has it.
+async fn confirm_keyboard(
+    warehouse: &mut Warehouse,
+    msg: &Message,
+    meta: &UserMeta,
+) -> Vec<Vec<KeyboardButton>> {
+    let mut keyboard = vec![vec![
+        KeyboardButton::new(localize_msg!(warehouse, msg, "Yes")),
+        KeyboardButton::new(localize_msg!(warehouse, msg, "No")),
+    ]];
+
+    if meta.note.is_some() {
+        keyboard.insert(
+            0,
+            vec![KeyboardButton::new(localize_msg!(
+                warehouse,
+                msg,
+                "Yes, attach my note"
+            ))],
+        );
     }
+
+    keyboard
 }
 
+/// Runs after the order is placed, so the errors are left to the caller
+/// instead of being shown to the customer.
 async fn notify_merchant_about_new_order(
     bot: &Bot,
     msg: &Message,
@@ -548,7 +780,7 @@ async fn notify_merchant_about_new_order(
     _: &User,
     order: &Order,
 ) -> Result<()> {
-    let merchant_chat_id = verify_with_msg(&bot, msg, warehouse)
+    let merchant_chat_id = verify_silently(warehouse)
         .user_meta_by_name(&order.merchant)
         .await?
         .has_chat_id()
@@ -557,26 +789,29 @@ async fn notify_merchant_about_new_order(
         .chat_id
         .unwrap();
 
-    let item = verify_with_msg(&bot, msg, warehouse)
+    let item = verify_silently(warehouse)
         .item_by_id(&order.item_id)
         .await?
         .into_result();
 
-    bot.send_message(
-        merchant_chat_id,
-        localize_msg!(warehouse, msg,
-            "You have a new order for a {name} item from @{customer}!",
-            "name" => localize_msg!(warehouse, msg, item.name), 
-            "customer" => order.customer
-        ),
-    )
-    .reply_markup(ReplyMarkup::inline_kb(vec![vec![
-        InlineKeyboardButton::switch_inline_query_current_chat(
-            localize_msg!(warehouse, msg, "Details"),
-            format!(".o {}", order.id),
-        ),
-    ]]))
-    .await?;
+    let mut text = localize_msg!(warehouse, msg,
+        "You have a new order for a {name} item from @{customer}!",
+        "name" => localize_static_msg!(warehouse, msg, item.name),
+        "customer" => order.customer
+    );
+    if let Some(note) = &order.note {
+        text += &localize_msg!(warehouse, msg, "\nNote: {note}", "note" => note);
+    }
+
+    let request = bot
+        .send_message(merchant_chat_id, text)
+        .reply_markup(ReplyMarkup::inline_kb(vec![vec![
+            InlineKeyboardButton::switch_inline_query_current_chat(
+                localize_msg!(warehouse, msg, "Details"),
+                format!(".o {}", order.id),
+            ),
+        ]]));
+    send_or_forget(warehouse, &order.merchant, request).await?;
 
     Ok(())
 }
@@ -587,14 +822,17 @@ async fn notify_customer_about_order(
     warehouse: &mut Warehouse,
     _: &User,
     order: &Order,
+    outcome: Outcome,
 ) -> Result<()> {
+    let contact = warehouse.merchant_contact(&order.merchant);
+
     bot.send_message(
         msg.chat.id,
         localize_msg!(warehouse, msg,
             concat!(
-                "Thank you for your order! The seller @{merchant} will be in touch ",
+                "Thank you for your order! The seller {merchant} will be in touch ",
                 "with you soon, but if you have any questions, you can ask him yourself."),
-            "merchant" => order.merchant
+            "merchant" => html::escape(&contact)
         )
         .as_str(),
     )
@@ -607,5 +845,121 @@ async fn notify_customer_about_order(
     ]]))
     .await?;
 
+    if outcome == Outcome::Unnotified {
+        bot.send_message(
+            msg.chat.id,
+            localize_msg!(warehouse, msg, outcome.message(Flow::Purchase),
+                "merchant" => contact
+            ),
+        )
+        .await?;
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::fixtures;
+
+    fn product(amount_left: f64) -> Product {
+        Product {
+            price: 2.0,
+            amount_granted: amount_left,
+            amount_left,
+            ..fixtures::product()
+        }
+    }
+
+    fn completed_order(amount: f64) -> Order {
+        Order {
+            completed_at: Some(Utc::now()),
+            ..new_order(
+                "order".to_owned(),
+                "customer".to_owned(),
+                OrderStage::Completed,
+                &product(amount),
+                amount,
+                None,
+                Utc::now(),
+            )
+        }
+    }
+
+    #[test]
+    fn reorder_checks_current_listing() {
+        let order = completed_order(3.0);
+        let nothing_reserved = HashMap::new();
+
+        assert_eq!(
+            check_reorder(&order, Some(&product(5.0)), &nothing_reserved),
+            Ok(3.0)
+        );
+        assert_eq!(
+            check_reorder(&order, None, &nothing_reserved),
+            Err(ReorderError::Gone)
+        );
+
+        let deleted = Product {
+            deleted: true,
+            ..product(5.0)
+        };
+        assert_eq!(
+            check_reorder(&order, Some(&deleted), &nothing_reserved),
+            Err(ReorderError::Gone)
+        );
+    }
+
+    #[test]
+    fn weight_orders_keep_fractions() {
+        let cheese = Product {
+            unit: Unit::Weight,
+            price: 20.0,
+            ..product(1.0)
+        };
+        let order = new_order(
+            "order".to_owned(),
+            "customer".to_owned(),
+            OrderStage::WaitForPayment,
+            &cheese,
+            0.25,
+            None,
+            Utc::now(),
+        );
+
+        assert_eq!((order.amount, order.cost), (0.25, 5.0));
+        assert_eq!(
+            Stage::WaitAmount(StageData {
+                product: Some(Row::new(0, cheese)),
+                ..Default::default()
+            })
+            .amount_unit(),
+            Unit::Weight
+        );
+    }
+
+    #[test]
+    fn reorder_offers_what_is_left() {
+        let order = completed_order(3.0);
+        let nothing_reserved = HashMap::new();
+
+        assert_eq!(
+            check_reorder(&order, Some(&product(2.0)), &nothing_reserved),
+            Ok(2.0)
+        );
+        assert_eq!(
+            check_reorder(&order, Some(&product(3.0)), &nothing_reserved),
+            Ok(3.0)
+        );
+        assert_eq!(
+            check_reorder(&order, Some(&product(0.0)), &nothing_reserved),
+            Err(ReorderError::OutOfStock)
+        );
+
+        // Open orders of others hold some of the stock
+        let listing = product(4.0);
+        let reserved = [(listing.id(), 2.0)].into();
+        assert_eq!(check_reorder(&order, Some(&listing), &reserved), Ok(2.0));
+    }
+}
@@ -3,7 +3,7 @@ use chrono::Utc;
 use teloxide::{
     dispatching::dialogue::InMemStorage,
     prelude::*,
-    types::{KeyboardButton, KeyboardMarkup, ParseMode, ReplyMarkup, Update, UpdateKind},
+    types::{KeyboardButton, KeyboardMarkup, Update, UpdateKind},
 };
 
 use crate::utils::verify::prelude::*;
@@ -15,7 +15,8 @@ type Storage = InMemStorage<Stage>;
 #[derive(Default, Clone)]
 struct StageData {
     pub product: Option<Row<Product>>,
-    pub amount: Option<u32>,
+    pub amount: Option<f64>,
+    pub prompt: Option<Prompt>,
 }
 
 #[derive(Default, Clone)]
@@ -28,6 +29,7 @@ enum Stage {
 
 pub fn handler() -> HandlerResult {
     dptree::entry()
+        .branch(cancel_by_callback::<Stage, Storage>())
         // Handle purcahse
         .branch(
             Update::filter_callback_query()
@@ -74,6 +76,23 @@ impl ConversationStart for Stage {
         Role::Merchant
     }
 
+    fn amount_unit(&self) -> Unit {
+        match self {
+            Self::WaitAmount(StageData {
+                product: Some(product),
+                ..
+            }) => product.unit,
+            _ => Unit::Each,
+        }
+    }
+
+    fn prompt(&self) -> Option<&Prompt> {
+        match self {
+            Self::Start => None,
+            Self::WaitAmount(data) | Self::WaitConfirm(data) => data.prompt.as_ref(),
+        }
+    }
+
     async fn start(
         self,
         bot: Bot,
@@ -99,7 +118,7 @@ impl ConversationStart for Stage {
                     .await?
                     .visible_to_user(&user.0)
                     .await?
-                    .left_at_least(1)
+                    .left_at_least(1.0)
                     .await?
                     .price_is_not_negotiated()
                     .await?
@@ -112,29 +131,33 @@ impl ConversationStart for Stage {
                     .await?
                     .into_result();
 
-                bot.send_message(chat_id,
+                let prompt = Prompt::html(
                     localize_upd!(warehouse, upd,
                         "You wanted to redeem the <b>{item}</b>, I'm very pleased! Just need to clarify how much you want to redeem?",
                         "item" => item.name
-                    ))
-                    .parse_mode(ParseMode::Html)
-                    .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
+                    ),
+                    KeyboardMarkup {
                         resize_keyboard: Some(true),
                         one_time_keyboard: Some(true),
                         keyboard: vec![
-                            (1..=5).map(|x| KeyboardButton::new(x.to_string())).collect(),
-                            vec![KeyboardButton::new(
-                                localize_upd!(warehouse, upd, "Cancel"))],
+                            (1..=5)
+                                .map(|x| KeyboardButton::new(x.to_string()))
+                                .collect(),
+                            vec![KeyboardButton::new(localize_upd!(warehouse, upd, "Cancel"))],
                         ],
                         ..Default::default()
-                    }))
-                    .await?;
+                    },
+                );
+                prompt.send(&bot, chat_id).await?;
+
+                send_cancel_hint(&bot, chat_id, &upd, warehouse).await?;
 
                 bot.answer_callback_query(q.id.clone()).await?;
 
                 Ok(Self::WaitAmount(StageData {
                     product: Some(product),
                     amount: None,
+                    prompt: Some(prompt),
                 }))
             }
             _ => Ok(Self::Start),
@@ -143,22 +166,22 @@ impl ConversationStart for Stage {
 }
 
 #[async_trait]
-impl ConversationStage<u32> for Stage {
+impl ConversationStage<f64> for Stage {
     async fn next(
         self,
         bot: Bot,
         msg: Message,
         _: (User, UserMeta),
         warehouse: &mut Warehouse,
-        amount: u32,
+        amount: f64,
     ) -> Result<Self> {
         match self {
             Stage::WaitAmount(mut data) => {
-                data.amount = Some(amount as u32);
+                data.amount = Some(amount);
 
                 let product = data.product.as_ref().unwrap();
 
-                if product.amount_left < amount {
+                if !product.can_sell(amount) {
                     bot.send_message(
                         msg.chat.id,
                         localize_msg!(warehouse, msg, "You can't redeem more than you have."),
@@ -172,12 +195,9 @@ impl ConversationStage<u32> for Stage {
                     .await?
                     .into_result();
 
-                let price = product
-                    .currency
-                    .format(&(product.price * amount as f64).to_string());
+                let price = product.currency.format_amount(product.price * amount);
 
-                bot.send_message(
-                    msg.chat.id,
+                let prompt = Prompt::new(
                     localize_msg!(
                         warehouse,
                         msg,
@@ -186,17 +206,18 @@ impl ConversationStage<u32> for Stage {
                         "name" => item.name,
                         "price" => price
                     ),
-                )
-                .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
-                    resize_keyboard: Some(true),
-                    one_time_keyboard: Some(true),
-                    keyboard: vec![vec![
-                        KeyboardButton::new(localize_msg!(warehouse, msg, "Yes")),
-                        KeyboardButton::new(localize_msg!(warehouse, msg, "No")),
-                    ]],
-                    ..Default::default()
-                }))
-                .await?;
+                    KeyboardMarkup {
+                        resize_keyboard: Some(true),
+                        one_time_keyboard: Some(true),
+                        keyboard: vec![vec![
+                            KeyboardButton::new(localize_msg!(warehouse, msg, "Yes")),
+                            KeyboardButton::new(localize_msg!(warehouse, msg, "No")),
+                        ]],
+                        ..Default::default()
+                    },
+                );
+                prompt.send(&bot, msg.chat.id).await?;
+                data.prompt = Some(prompt);
 
                 Ok(Self::WaitConfirm(data))
             }
@@ -256,24 +277,27 @@ impl ConversationStage<String> for Stage {
                     .merchant_is(&user.0.name)
                     .await?
                     .update(|p| {
-                        p.amount_left -= amount;
-                        p.amount_sold += amount;
+                        p.take_for_sale(amount);
+                        p.count_sold(amount);
                     })
                     .await?
                     .into_result();
 
-                let sale = Sale {
+                let mut sale = Sale {
                     merchant: user.0.name.clone(),
                     sale_type: SaleType::Redeem,
                     customer: user.0.name.clone(),
                     item_id: product.item_id.clone(),
                     comment: "Redeemed".to_string(),
                     amount,
-                    revenue: product.price * amount as f64,
+                    revenue: product.price * amount,
                     currency: product.currency.clone(),
                     share: 0f32,
                     date: Utc::now(),
+                    base_amount: None,
+                    base_currency: None,
                 };
+                sale.pin_base_amount(warehouse.exchange_rates.as_ref());
 
                 verify_with_msg(&bot, &msg, warehouse)
                     .with(Row::new(0, sale))
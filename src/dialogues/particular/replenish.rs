@@ -4,8 +4,8 @@ use teloxide::{
     dispatching::dialogue::{GetChatId, InMemStorage},
     prelude::*,
     types::{
-        Currency, InlineKeyboardButton, InlineKeyboardMarkup, KeyboardButton, KeyboardMarkup,
-        ParseMode, ReplyMarkup, Update,
+        InlineKeyboardButton, InlineKeyboardMarkup, KeyboardButton, KeyboardMarkup, ParseMode,
+        ReplyMarkup, Update,
     },
 };
 
@@ -18,45 +18,54 @@ type Storage = InMemStorage<Stage>;
 struct StageData {
     pub product: Option<Product>,
     pub item: Option<Item>,
-    pub amount: Option<u32>,
+    pub amount: Option<f64>,
     pub cost_price: Option<f64>,
     pub currency: Option<Currency>,
+    pub prompt: Option<Prompt>,
 }
 
 #[derive(Default, Clone)]
 enum Stage {
     #[default]
     Start,
-    WaitProduct,
+    WaitProduct(StageData),
     WaitAmount(StageData),
     WaitCostPrice(StageData),
     WaitConfirmation(StageData),
 }
 
 pub fn handler() -> HandlerResult {
-    Update::filter_message()
-        .enter_dialogue::<Message, InMemStorage<Stage>, Stage>()
+    dptree::entry()
+        .branch(cancel_by_callback::<Stage, Storage>())
         .branch(
-            filter_dialogue_started::<Stage, Storage>()
-                .chain(filter_msg_prefix("Cancel"))
-                .endpoint(cancel::<Stage, Storage>),
-        )
-        .branch(
-            dptree::case![Stage::Start]
-                .chain(filter_msg_prefix("🪫 Replenish"))
-                .endpoint(start::<Stage, Storage>),
-        )
-        .branch(dptree::case![Stage::WaitProduct].endpoint(receive_product_stage::<Stage, Storage>))
-        .branch(
-            dptree::case![Stage::WaitAmount(data)].endpoint(receive_amount_stage::<Stage, Storage>),
-        )
-        .branch(
-            dptree::case![Stage::WaitCostPrice(data)]
-                .endpoint(receive_money_stage::<Stage, Storage>),
-        )
-        .branch(
-            dptree::case![Stage::WaitConfirmation(data)]
-                .endpoint(receive_text_stage::<Stage, Storage>),
+            Update::filter_message()
+                .enter_dialogue::<Message, InMemStorage<Stage>, Stage>()
+                .branch(
+                    filter_dialogue_started::<Stage, Storage>()
+                        .chain(filter_msg_prefix("Cancel"))
+                        .endpoint(cancel::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::Start]
+                        .chain(filter_msg_prefix("🪫 Replenish"))
+                        .endpoint(start::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitProduct(data)]
+                        .endpoint(receive_product_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitAmount(data)]
+                        .endpoint(receive_amount_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitCostPrice(data)]
+                        .endpoint(receive_money_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitConfirmation(data)]
+                        .endpoint(receive_text_stage::<Stage, Storage>),
+                ),
         )
 }
 
@@ -77,6 +86,26 @@ impl ConversationStart for Stage {
         Role::Moderator
     }
 
+    fn amount_unit(&self) -> Unit {
+        match self {
+            Self::WaitAmount(StageData {
+                product: Some(product),
+                ..
+            }) => product.unit,
+            _ => Unit::Each,
+        }
+    }
+
+    fn prompt(&self) -> Option<&Prompt> {
+        match self {
+            Self::Start => None,
+            Self::WaitProduct(data)
+            | Self::WaitAmount(data)
+            | Self::WaitCostPrice(data)
+            | Self::WaitConfirmation(data) => data.prompt.as_ref(),
+        }
+    }
+
     async fn start(
         self,
         bot: Bot,
@@ -97,16 +126,17 @@ impl ConversationStart for Stage {
 
                 let chat_id = upd.chat_id().ok_or(UnkError::unknown("upd.chat_id"))?;
 
-                bot.send_message(chat_id, text)
-                    .parse_mode(ParseMode::Html)
-                    .reply_markup(InlineKeyboardMarkup::default().append_row(vec![
-                        InlineKeyboardButton::switch_inline_query_current_chat(
+                let prompt = Prompt::html(
+                    text,
+                    InlineKeyboardMarkup::new(vec![
+                        vec![InlineKeyboardButton::switch_inline_query_current_chat(
                             localize_upd!(warehouse, upd, "Select"),
                             "~repl ",
-                        ),
-                    ]))
-                    .send()
-                    .await?;
+                        )],
+                        vec![inline_cancel_button(localize_upd!(warehouse, upd, "Cancel"))],
+                    ]),
+                );
+                prompt.send(&bot, chat_id).await?;
 
                 bot.send_message(
                     chat_id,
@@ -125,7 +155,10 @@ impl ConversationStart for Stage {
                     ..Default::default()
                 }))
                 .await?;
-                Ok(Self::WaitProduct)
+                Ok(Self::WaitProduct(StageData {
+                    prompt: Some(prompt),
+                    ..Default::default()
+                }))
             }
             _ => Ok(self),
         }
@@ -143,31 +176,31 @@ impl ConversationStage<(Product, Item)> for Stage {
         pair: (Product, Item),
     ) -> Result<Self> {
         match self {
-            Stage::WaitProduct => {
-                bot.send_message(
-                    msg.chat.id,
+            Stage::WaitProduct(_) => {
+                let prompt = Prompt::new(
                     localize_msg!(
                         warehouse,
                         msg,
                         "Great! Please tell me how much you want to replenish."
                     ),
-                )
-                .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
-                    resize_keyboard: Some(true),
-                    is_persistent: true,
-                    keyboard: vec![
-                        (1..=5)
-                            .map(|i| KeyboardButton::new(i.to_string()))
-                            .collect(),
-                        vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Cancel"))],
-                    ],
-                    ..Default::default()
-                }))
-                .await?;
+                    KeyboardMarkup {
+                        resize_keyboard: Some(true),
+                        is_persistent: true,
+                        keyboard: vec![
+                            (1..=5)
+                                .map(|i| KeyboardButton::new(i.to_string()))
+                                .collect(),
+                            vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Cancel"))],
+                        ],
+                        ..Default::default()
+                    },
+                );
+                prompt.send(&bot, msg.chat.id).await?;
 
                 Ok(Self::WaitAmount(StageData {
                     product: Some(pair.0),
                     item: Some(pair.1),
+                    prompt: Some(prompt),
                     ..Default::default()
                 }))
             }
@@ -177,30 +210,33 @@ impl ConversationStage<(Product, Item)> for Stage {
 }
 
 #[async_trait]
-impl ConversationStage<u32> for Stage {
+impl ConversationStage<f64> for Stage {
     async fn next(
         self,
         bot: Bot,
         msg: Message,
         _: (User, UserMeta),
         warehouse: &mut Warehouse,
-        amount: u32,
+        amount: f64,
     ) -> Result<Self> {
         match self {
             Stage::WaitAmount(mut data) => {
-                data.amount = Some(amount as u32);
-                bot.send_message(msg.chat.id, localize_msg!(warehouse, msg, concat!(
-                    "Please tell me the total cost of all items. ",
-                    "Write as a real number with a currency (for example, \"100.50 eur\" or \"30 CZK\").")))
-                .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
-                    resize_keyboard: Some(true),
-                    is_persistent: true,
-                    keyboard: vec![
-                        vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Cancel"))],
-                    ],
-                    ..Default::default()
-                }))
-                .await?;
+                data.amount = Some(amount);
+                let prompt = Prompt::new(
+                    localize_msg!(warehouse, msg, concat!(
+                        "Please tell me the total cost of all items. ",
+                        "Write as a real number with a currency (for example, \"100.50 eur\" or \"30 CZK\").")),
+                    KeyboardMarkup {
+                        resize_keyboard: Some(true),
+                        is_persistent: true,
+                        keyboard: vec![
+                            vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Cancel"))],
+                        ],
+                        ..Default::default()
+                    },
+                );
+                prompt.send(&bot, msg.chat.id).await?;
+                data.prompt = Some(prompt);
                 Ok(Self::WaitCostPrice(data))
             }
             _ => Ok(self),
@@ -220,7 +256,7 @@ impl ConversationStage<(f64, Currency)> for Stage {
     ) -> Result<Self> {
         match self {
             Stage::WaitCostPrice(mut data) => {
-                let price = money.1.format(&money.0.to_string());
+                let price = money.1.format_amount(money.0);
 
                 let text = [
                     "<b>Confirm the sell</b>".to_owned(),
@@ -236,21 +272,21 @@ impl ConversationStage<(f64, Currency)> for Stage {
                     .parse_mode(ParseMode::Html)
                     .await?;
 
-                bot.send_message(
-                    msg.chat.id,
+                let prompt = Prompt::new(
                     localize_msg!(warehouse, msg, "Is everything correct?"),
-                )
-                .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
-                    resize_keyboard: Some(true),
-                    one_time_keyboard: Some(true),
-                    keyboard: vec![vec![
-                        KeyboardButton::new(localize_msg!(warehouse, msg, "Yes")),
-                        KeyboardButton::new(localize_msg!(warehouse, msg, "No")),
-                    ]],
-                    ..Default::default()
-                }))
-                .await?;
+                    KeyboardMarkup {
+                        resize_keyboard: Some(true),
+                        one_time_keyboard: Some(true),
+                        keyboard: vec![vec![
+                            KeyboardButton::new(localize_msg!(warehouse, msg, "Yes")),
+                            KeyboardButton::new(localize_msg!(warehouse, msg, "No")),
+                        ]],
+                        ..Default::default()
+                    },
+                );
+                prompt.send(&bot, msg.chat.id).await?;
 
+                data.prompt = Some(prompt);
                 data.cost_price = Some(money.0);
                 data.currency = Some(money.1);
 
@@ -317,8 +353,7 @@ impl ConversationStage<String> for Stage {
                     }
                 };
 
-                product.amount_granted += data.amount.unwrap();
-                product.amount_left += data.amount.unwrap();
+                product.grant(data.amount.unwrap());
 
                 match warehouse.products.update_one(row, &product).await {
                     Ok(_) => (),
@@ -0,0 +1,316 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::warn;
+use teloxide::{
+    dispatching::dialogue::InMemStorage,
+    prelude::*,
+    types::{KeyboardButton, KeyboardMarkup, Update, UpdateKind},
+};
+
+use crate::{
+    dialogues::enter_user_dialogue,
+    prelude::*,
+    utils::{
+        payload::PayloadOp,
+        row::Row,
+        verify::{send_or_forget, verify_with_callback, verify_with_msg},
+    },
+};
+
+type Storage = InMemStorage<Stage>;
+
+#[derive(Default, Clone)]
+struct StageData {
+    pub product: Option<Row<Product>>,
+    pub prompt: Option<Prompt>,
+}
+
+#[derive(Default, Clone)]
+enum Stage {
+    #[default]
+    Start,
+    WaitReason(StageData),
+}
+
+pub fn handler() -> HandlerResult {
+    dptree::entry()
+        .branch(cancel_by_callback::<Stage, Storage>())
+        .branch(
+            Update::filter_callback_query()
+                .chain(enter_user_dialogue::<Storage, Stage>(
+                    "To report a product you first need to start a dialog with the bot.",
+                ))
+                .filter(callback_prefix(PayloadOp::ReportProduct))
+                .endpoint(start::<Stage, Storage>),
+        )
+        .branch(
+            Update::filter_message()
+                .enter_dialogue::<Message, Storage, Stage>()
+                .branch(
+                    filter_dialogue_started::<Stage, Storage>()
+                        .chain(filter_msg_prefix("Cancel"))
+                        .endpoint(cancel::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitReason(data)]
+                        .endpoint(receive_text_stage::<Stage, Storage>),
+                ),
+        )
+}
+
+pub fn write_deps(deps: &mut DependencyMap) {
+    deps.insert(InMemStorage::<Stage>::new());
+}
+
+#[async_trait]
+impl ConversationStart for Stage {
+    fn is_started(&self) -> bool {
+        match self {
+            Stage::Start => false,
+            _ => true,
+        }
+    }
+
+    fn prompt(&self) -> Option<&Prompt> {
+        match self {
+            Stage::Start => None,
+            Stage::WaitReason(data) => data.prompt.as_ref(),
+        }
+    }
+
+    async fn start(
+        self,
+        bot: Bot,
+        upd: Update,
+        user: (User, UserMeta),
+        warehouse: &mut Warehouse,
+    ) -> Result<Self> {
+        let chat_id = user.1.chat_id.ok_or(UnkError::unknown("No chat id"))?;
+
+        let UpdateKind::CallbackQuery(q) = upd.kind.clone() else {
+            Err(UnkError::unknown("Invalid update kind"))?
+        };
+
+        let product = verify_with_callback(&bot, &q, warehouse)
+            .payload_str_opt(&q.data)
+            .await?
+            .verify_product()
+            .await?
+            .visible_to_user(&user.0)
+            .await?
+            .into_result();
+
+        bot.answer_callback_query(q.id).await?;
+        let prompt = Prompt::new(
+            localize_upd!(
+                warehouse,
+                upd,
+                "Please describe the problem with the product, e.g. a wrong price or description."
+            ),
+            KeyboardMarkup {
+                resize_keyboard: Some(true),
+                one_time_keyboard: Some(true),
+                keyboard: vec![vec![KeyboardButton::new(localize_upd!(
+                    warehouse, upd, "Cancel"
+                ))]],
+                ..Default::default()
+            },
+        );
+        prompt.send(&bot, chat_id).await?;
+
+        send_cancel_hint(&bot, chat_id, &upd, warehouse).await?;
+
+        Ok(Self::WaitReason(StageData {
+            product: Some(product),
+            prompt: Some(prompt),
+        }))
+    }
+}
+
+#[async_trait]
+impl ConversationStage<String> for Stage {
+    async fn next(
+        self,
+        bot: Bot,
+        msg: Message,
+        user: (User, UserMeta),
+        warehouse: &mut Warehouse,
+        text: String,
+    ) -> Result<Self> {
+        match self {
+            Stage::WaitReason(StageData {
+                product: Some(product),
+                ..
+            }) => {
+                let lang_code = msg
+                    .from()
+                    .map(|u| u.language_code.clone())
+                    .flatten()
+                    .unwrap_or("en".to_owned());
+
+                let report = new_report(&user.0.name, &product, &text, Utc::now());
+
+                if let Some(reports) = warehouse.reports.as_mut() {
+                    reports.extend(&[report.clone()]).await?;
+                }
+
+                let item = verify_with_msg(&bot, &msg, warehouse)
+                    .item_by_id(&product.item_id)
+                    .await?
+                    .into_result();
+
+                warehouse.users.refresh().await?;
+                warehouse.users_meta.refresh().await?;
+
+                let moderators = moderator_chats(
+                    warehouse
+                        .users
+                        .by_name
+                        .all()
+                        .flat_map(|(_, users)| users)
+                        .map(|(_, user)| user),
+                    warehouse
+                        .users_meta
+                        .by_name
+                        .all()
+                        .flat_map(|(_, metas)| metas)
+                        .map(|(_, meta)| meta),
+                    &report.reporter,
+                );
+
+                for (name, chat_id) in moderators {
+                    let request = bot.send_message(
+                        chat_id,
+                        localize_msg!(
+                            warehouse,
+                            msg,
+                            "{reporter} reported a problem with {name} of {merchant}:\n\n{reason}",
+                            "reporter" => report.reporter,
+                            "name" => item.name,
+                            "merchant" => product.merchant,
+                            "reason" => report.reason
+                        ),
+                    );
+
+                    // One unreachable moderator shouldn't keep the report from the rest
+                    if let Err(e) = send_or_forget(warehouse, &name, request).await {
+                        warn!("Failed to notify {} about a product report: {}", name, e);
+                    }
+                }
+
+                bot.send_message(
+                    msg.chat.id,
+                    localize_msg!(
+                        warehouse,
+                        msg,
+                        "Thank you, your report has been passed to the moderators."
+                    ),
+                )
+                .reply_markup(user_keyboard(warehouse, &lang_code, &user.0).await)
+                .await?;
+
+                Ok(Self::Start)
+            }
+            _ => Ok(self),
+        }
+    }
+}
+
+fn new_report(
+    reporter: &str,
+    product: &Product,
+    reason: &str,
+    now: DateTime<Utc>,
+) -> ProductReport {
+    ProductReport {
+        date: now,
+        reporter: reporter.to_owned(),
+        merchant: product.merchant.clone(),
+        item_id: product.item_id.clone(),
+        reason: reason.trim().to_owned(),
+    }
+}
+
+/// Chats of the moderators to notify about a report, the reporter is left out.
+fn moderator_chats<'u>(
+    users: impl IntoIterator<Item = &'u User>,
+    metas: impl IntoIterator<Item = &'u UserMeta>,
+    reporter: &str,
+) -> Vec<(String, ChatId)> {
+    let moderators: Vec<_> = users
+        .into_iter()
+        .filter(|user| user.role == Role::Moderator && !user.blocked && user.name != reporter)
+        .map(|user| user.name.as_str())
+        .collect();
+
+    metas
+        .into_iter()
+        .filter(|meta| moderators.contains(&meta.name.as_str()))
+        .filter_map(|meta| Some((meta.name.clone(), meta.chat_id?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::fixtures;
+
+    fn user(name: &str, role: Role, blocked: bool) -> User {
+        User {
+            name: name.to_owned(),
+            role,
+            blocked,
+            ..fixtures::user()
+        }
+    }
+
+    fn meta(name: &str, chat_id: Option<i64>) -> UserMeta {
+        UserMeta {
+            name: name.to_owned(),
+            chat_id: chat_id.map(ChatId),
+            ..fixtures::user_meta()
+        }
+    }
+
+    #[test]
+    fn report_records_product_and_reason() {
+        let product = fixtures::product();
+        let now = Utc::now();
+
+        assert_eq!(
+            new_report("customer", &product, "  Wrong price \n", now),
+            ProductReport {
+                date: now,
+                reporter: "customer".to_owned(),
+                merchant: "merchant".to_owned(),
+                item_id: "item".to_owned(),
+                reason: "Wrong price".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn notifies_reachable_moderators_only() {
+        let users = [
+            user("customer", Role::User, false),
+            user("merchant", Role::Merchant, false),
+            user("moderator", Role::Moderator, false),
+            user("blocked", Role::Moderator, true),
+            user("silent", Role::Moderator, false),
+            user("reporter", Role::Moderator, false),
+        ];
+        let metas = [
+            meta("customer", Some(1)),
+            meta("merchant", Some(2)),
+            meta("moderator", Some(3)),
+            meta("blocked", Some(4)),
+            meta("silent", None),
+            meta("reporter", Some(5)),
+        ];
+
+        assert_eq!(
+            moderator_chats(&users, &metas, "reporter"),
+            vec![("moderator".to_owned(), ChatId(3))]
+        );
+    }
+}
@@ -1,15 +1,16 @@
 use async_trait::async_trait;
 use chrono::Utc;
+use log::error;
 use teloxide::{
     dispatching::dialogue::{GetChatId, InMemStorage},
     prelude::*,
     types::{
-        Currency, InlineKeyboardButton, InlineKeyboardMarkup, KeyboardButton, KeyboardMarkup,
-        ParseMode, ReplyMarkup, Update,
+        InlineKeyboardButton, InlineKeyboardMarkup, KeyboardButton, KeyboardMarkup, ParseMode,
+        ReplyMarkup, Update,
     },
 };
 
-use crate::{dialogues::stages::verify_product, prelude::*};
+use crate::{dialogues::stages::verify_product, prelude::*, BoxedError};
 
 type Storage = InMemStorage<Stage>;
 
@@ -17,18 +18,19 @@ type Storage = InMemStorage<Stage>;
 struct StageData {
     pub product: Option<Product>,
     pub item: Option<Item>,
-    pub amount: Option<u32>,
+    pub amount: Option<f64>,
     pub revenue: Option<f64>,
     pub currency: Option<Currency>,
     pub customer: Option<String>,
     pub comment: Option<String>,
+    pub prompt: Option<Prompt>,
 }
 
 #[derive(Default, Clone)]
 enum Stage {
     #[default]
     Start,
-    WaitProduct,
+    WaitProduct(StageData),
     WaitAmount(StageData),
     WaitRevenue(StageData),
     WaitCustomer(StageData),
@@ -37,34 +39,45 @@ enum Stage {
 }
 
 pub fn handler() -> HandlerResult {
-    Update::filter_message()
-        .enter_dialogue::<Message, InMemStorage<Stage>, Stage>()
+    dptree::entry()
+        .branch(cancel_by_callback::<Stage, Storage>())
         .branch(
-            filter_dialogue_started::<Stage, Storage>()
-                .chain(filter_msg_prefix("Cancel"))
-                .endpoint(cancel::<Stage, Storage>),
-        )
-        .branch(
-            dptree::case![Stage::Start]
-                .chain(filter_msg_prefix("💸 Sell"))
-                .endpoint(start::<Stage, Storage>),
-        )
-        .branch(dptree::case![Stage::WaitProduct].endpoint(receive_product_stage::<Stage, Storage>))
-        .branch(
-            dptree::case![Stage::WaitAmount(data)].endpoint(receive_amount_stage::<Stage, Storage>),
-        )
-        .branch(
-            dptree::case![Stage::WaitRevenue(data)].endpoint(receive_money_stage::<Stage, Storage>),
-        )
-        .branch(
-            dptree::case![Stage::WaitCustomer(data)].endpoint(receive_text_stage::<Stage, Storage>),
-        )
-        .branch(
-            dptree::case![Stage::WaitComment(data)].endpoint(receive_text_stage::<Stage, Storage>),
-        )
-        .branch(
-            dptree::case![Stage::WaitConfirmation(data)]
-                .endpoint(receive_text_stage::<Stage, Storage>),
+            Update::filter_message()
+                .enter_dialogue::<Message, InMemStorage<Stage>, Stage>()
+                .branch(
+                    filter_dialogue_started::<Stage, Storage>()
+                        .chain(filter_msg_prefix("Cancel"))
+                        .endpoint(cancel::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::Start]
+                        .chain(filter_msg_prefix("💸 Sell"))
+                        .endpoint(start::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitProduct(data)]
+                        .endpoint(receive_product_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitAmount(data)]
+                        .endpoint(receive_amount_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitRevenue(data)]
+                        .endpoint(receive_money_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitCustomer(data)]
+                        .endpoint(receive_text_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitComment(data)]
+                        .endpoint(receive_text_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitConfirmation(data)]
+                        .endpoint(receive_text_stage::<Stage, Storage>),
+                ),
         )
 }
 
@@ -85,6 +98,28 @@ impl ConversationStart for Stage {
         Role::Merchant
     }
 
+    fn amount_unit(&self) -> Unit {
+        match self {
+            Self::WaitAmount(StageData {
+                product: Some(product),
+                ..
+            }) => product.unit,
+            _ => Unit::Each,
+        }
+    }
+
+    fn prompt(&self) -> Option<&Prompt> {
+        match self {
+            Self::Start => None,
+            Self::WaitProduct(data)
+            | Self::WaitAmount(data)
+            | Self::WaitRevenue(data)
+            | Self::WaitCustomer(data)
+            | Self::WaitComment(data)
+            | Self::WaitConfirmation(data) => data.prompt.as_ref(),
+        }
+    }
+
     async fn start(
         self,
         bot: Bot,
@@ -104,16 +139,17 @@ impl ConversationStart for Stage {
                 );
 
                 let chat_id = upd.chat_id().ok_or(UnkError::unknown("upd.chat_id"))?;
-                bot.send_message(chat_id, text)
-                    .parse_mode(ParseMode::Html)
-                    .reply_markup(InlineKeyboardMarkup::default().append_row(vec![
-                        InlineKeyboardButton::switch_inline_query_current_chat(
+                let prompt = Prompt::html(
+                    text,
+                    InlineKeyboardMarkup::new(vec![
+                        vec![InlineKeyboardButton::switch_inline_query_current_chat(
                             localize_upd!(warehouse, upd, "Select").to_string(),
                             "~sell ",
-                        ),
-                    ]))
-                    .send()
-                    .await?;
+                        )],
+                        vec![inline_cancel_button(localize_upd!(warehouse, upd, "Cancel"))],
+                    ]),
+                );
+                prompt.send(&bot, chat_id).await?;
 
                 bot.send_message(
                     chat_id,
@@ -132,7 +168,10 @@ impl ConversationStart for Stage {
                     ..Default::default()
                 }))
                 .await?;
-                Ok(Self::WaitProduct)
+                Ok(Self::WaitProduct(StageData {
+                    prompt: Some(prompt),
+                    ..Default::default()
+                }))
             }
             _ => Ok(self),
         }
@@ -150,8 +189,8 @@ impl ConversationStage<(Product, Item)> for Stage {
         pair: (Product, Item),
     ) -> Result<Self> {
         match self {
-            Stage::WaitProduct => {
-                if pair.0.amount_left <= 0 {
+            Stage::WaitProduct(_) => {
+                if !pair.0.can_sell(pair.0.unit.smallest()) {
                     bot.send_message(
                         msg.chat.id,
                         localize_msg!(warehouse, msg,
@@ -161,26 +200,26 @@ impl ConversationStage<(Product, Item)> for Stage {
                     return Ok(self);
                 }
 
-                bot.send_message(
-                    msg.chat.id,
+                let prompt = Prompt::new(
                     localize_msg!(warehouse, msg, "Great! Please tell me how much you sold."),
-                )
-                .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
-                    resize_keyboard: Some(true),
-                    is_persistent: true,
-                    keyboard: vec![
-                        (1..=5)
-                            .map(|i| KeyboardButton::new(i.to_string()))
-                            .collect(),
-                        vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Cancel"))],
-                    ],
-                    ..Default::default()
-                }))
-                .await?;
+                    KeyboardMarkup {
+                        resize_keyboard: Some(true),
+                        is_persistent: true,
+                        keyboard: vec![
+                            (1..=5)
+                                .map(|i| KeyboardButton::new(i.to_string()))
+                                .collect(),
+                            vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Cancel"))],
+                        ],
+                        ..Default::default()
+                    },
+                );
+                prompt.send(&bot, msg.chat.id).await?;
 
                 Ok(Self::WaitAmount(StageData {
                     product: Some(pair.0),
                     item: Some(pair.1),
+                    prompt: Some(prompt),
                     ..Default::default()
                 }))
             }
@@ -190,21 +229,21 @@ impl ConversationStage<(Product, Item)> for Stage {
 }
 
 #[async_trait]
-impl ConversationStage<u32> for Stage {
+impl ConversationStage<f64> for Stage {
     async fn next(
         self,
         bot: Bot,
         msg: Message,
         _: (User, UserMeta),
         warehouse: &mut Warehouse,
-        amount: u32,
+        amount: f64,
     ) -> Result<Self> {
         match self {
             Stage::WaitAmount(mut data) => {
-                data.amount = Some(amount as u32);
+                data.amount = Some(amount);
                 let product = data.product.as_ref().unwrap();
 
-                if product.amount_left < amount {
+                if !product.can_sell(amount) {
                     bot.send_message(
                         msg.chat.id,
                         localize_msg!(warehouse, msg, "You can't sell more than you have."),
@@ -214,40 +253,34 @@ impl ConversationStage<u32> for Stage {
                 }
 
                 if product.negotiated_price {
-                    bot.send_message(msg.chat.id, localize_msg!(warehouse, msg, concat!(
-                        "Fine, how much money did you get for all this in total? ",
-                        "Write as a real number with a currency (for example, \"100.50 eur\" or \"30 CZK\").")))
-                        .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
+                    let prompt = Prompt::new(
+                        localize_msg!(warehouse, msg, concat!(
+                            "Fine, how much money did you get for all this in total? ",
+                            "Write as a real number with a currency (for example, \"100.50 eur\" or \"30 CZK\").")),
+                        KeyboardMarkup {
                             resize_keyboard: Some(true),
                             is_persistent: true,
                             keyboard: vec![
                                 vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Cancel"))],
                             ],
                             ..Default::default()
-                        }))
-                        .await?;
+                        },
+                    );
+                    prompt.send(&bot, msg.chat.id).await?;
+                    data.prompt = Some(prompt);
                     Ok(Self::WaitRevenue(data))
                 } else {
-                    data.revenue = Some(product.price * amount as f64);
+                    data.revenue = Some(product.price * amount);
                     data.currency = Some(product.currency);
 
-                    bot.send_message(
-                        msg.chat.id,
-                        localize_msg!(
-                            warehouse,
-                            msg,
-                            "Fine. Optional: write the buyer's username (like pavel_durov)."
-                        ),
-                    )
-                    .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
-                        resize_keyboard: Some(true),
-                        keyboard: vec![
-                            vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Skip"))],
-                            vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Cancel"))],
-                        ],
-                        ..Default::default()
-                    }))
-                    .await?;
+                    let text = localize_msg!(
+                        warehouse,
+                        msg,
+                        "Fine. Optional: write the buyer's username (like pavel_durov)."
+                    );
+                    let prompt = skip_prompt(warehouse, &msg, text).await;
+                    prompt.send(&bot, msg.chat.id).await?;
+                    data.prompt = Some(prompt);
                     Ok(Self::WaitCustomer(data))
                 }
             }
@@ -271,23 +304,14 @@ impl ConversationStage<(f64, Currency)> for Stage {
                 data.revenue = Some(money.0);
                 data.currency = Some(money.1);
 
-                bot.send_message(
-                    msg.chat.id,
-                    localize_msg!(
-                        warehouse,
-                        msg,
-                        "Okay. Optional: write the buyer's username (like pavel_durov)."
-                    ),
-                )
-                .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
-                    resize_keyboard: Some(true),
-                    keyboard: vec![
-                        vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Skip"))],
-                        vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Cancel"))],
-                    ],
-                    ..Default::default()
-                }))
-                .await?;
+                let text = localize_msg!(
+                    warehouse,
+                    msg,
+                    "Okay. Optional: write the buyer's username (like pavel_durov)."
+                );
+                let prompt = skip_prompt(warehouse, &msg, text).await;
+                prompt.send(&bot, msg.chat.id).await?;
+                data.prompt = Some(prompt);
 
                 Ok(Self::WaitCustomer(data))
             }
@@ -325,7 +349,10 @@ impl ConversationStage<String> for Stage {
                         "was a little scratched, but the buyer agreed to a discount.\").\n"
                     )
                 );
-                bot.send_message(msg.chat.id, text).await?;
+                // The Skip button of the customer stage is still shown
+                let prompt = skip_prompt(warehouse, &msg, text).await;
+                prompt.send(&bot, msg.chat.id).await?;
+                data.prompt = Some(prompt);
 
                 Ok(Self::WaitComment(data))
             }
@@ -345,7 +372,7 @@ impl ConversationStage<String> for Stage {
                     .currency
                     .as_ref()
                     .unwrap()
-                    .format(&data.revenue.as_ref().unwrap().to_string());
+                    .format_amount(*data.revenue.as_ref().unwrap());
 
                 let text = [
                     "<b>Confirm the sell</b>".to_owned(),
@@ -361,20 +388,20 @@ impl ConversationStage<String> for Stage {
                     .parse_mode(ParseMode::Html)
                     .await?;
 
-                bot.send_message(
-                    msg.chat.id,
+                let prompt = Prompt::new(
                     localize_msg!(warehouse, msg, "Is everything correct?"),
-                )
-                .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
-                    resize_keyboard: Some(true),
-                    one_time_keyboard: Some(true),
-                    keyboard: vec![vec![
-                        KeyboardButton::new(localize_msg!(warehouse, msg, "Yes")),
-                        KeyboardButton::new(localize_msg!(warehouse, msg, "No")),
-                    ]],
-                    ..Default::default()
-                }))
-                .await?;
+                    KeyboardMarkup {
+                        resize_keyboard: Some(true),
+                        one_time_keyboard: Some(true),
+                        keyboard: vec![vec![
+                            KeyboardButton::new(localize_msg!(warehouse, msg, "Yes")),
+                            KeyboardButton::new(localize_msg!(warehouse, msg, "No")),
+                        ]],
+                        ..Default::default()
+                    },
+                );
+                prompt.send(&bot, msg.chat.id).await?;
+                data.prompt = Some(prompt);
 
                 Ok(Self::WaitConfirmation(data))
             }
@@ -425,7 +452,8 @@ impl ConversationStage<String> for Stage {
                     }
                 };
 
-                if product.amount_left < data.amount.unwrap() {
+                let before = product.clone();
+                if !product.take_for_sale(data.amount.unwrap()) {
                     bot.send_message(
                         msg.chat.id,
                         localize_msg!(
@@ -438,22 +466,9 @@ impl ConversationStage<String> for Stage {
                     return Ok(Self::Start);
                 }
 
-                product.amount_left -= data.amount.unwrap();
-                product.amount_sold += data.amount.unwrap();
+                product.count_sold(data.amount.unwrap());
 
-                match warehouse.products.update_one(row, &product).await {
-                    Ok(_) => (),
-                    Err(e) => {
-                        bot.send_message(
-                            msg.chat.id,
-                            localize_msg!(warehouse, msg, "Failed to update the product state."),
-                        )
-                        .await?;
-                        return Err(Box::new(e));
-                    }
-                }
-
-                let sale = Sale {
+                let mut sale = Sale {
                     merchant: user.0.name.clone(),
                     sale_type: SaleType::HandToHand,
                     customer: data.customer.unwrap(),
@@ -464,25 +479,46 @@ impl ConversationStage<String> for Stage {
                     currency: data.currency.unwrap(),
                     share: product.share.clone(),
                     date: Utc::now(),
+                    base_amount: None,
+                    base_currency: None,
                 };
+                sale.pin_base_amount(warehouse.exchange_rates.as_ref());
+
+                let registered = register_sale(
+                    &mut warehouse.products,
+                    &mut warehouse.sales,
+                    row,
+                    (&before, &product),
+                    &sale,
+                )
+                .await;
 
-                match warehouse.sales.extend_one(&sale).await {
-                    Ok(_) => {
+                match registered {
+                    Ok(()) => {
                         bot.send_message(
                             msg.chat.id,
                             localize_msg!(warehouse, msg, "The sale was successfully registered."),
                         )
                         .await?;
                     }
-                    Err(e) => {
+                    Err(SaleFailure::Product(e)) => {
                         bot.send_message(
                             msg.chat.id,
-                            localize_msg!(warehouse, msg, "Failed to register the sale."),
+                            localize_msg!(warehouse, msg, "Failed to update the product state."),
                         )
                         .await?;
+                        return Err(e);
+                    }
+                    Err(SaleFailure::Sale(e)) => {
+                        // The cache may hold the sale which never landed
+                        warehouse.sales.mark_as_dirty();
 
-                        // Rust analyzer goes wild when I try to use return here
-                        Err(Box::new(e))?;
+                        bot.send_message(
+                            msg.chat.id,
+                            localize_msg!(warehouse, msg, "Failed to register the sale."),
+                        )
+                        .await?;
+                        return Err(e);
                     }
                 }
 
@@ -494,3 +530,176 @@ impl ConversationStage<String> for Stage {
         }
     }
 }
+
+/// Asks an optional question, the keyboard skips it or cancels the dialogue.
+async fn skip_prompt(warehouse: &mut Warehouse, msg: &Message, text: String) -> Prompt {
+    Prompt::new(
+        text,
+        KeyboardMarkup {
+            resize_keyboard: Some(true),
+            keyboard: vec![
+                vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Skip"))],
+                vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Cancel"))],
+            ],
+            ..Default::default()
+        },
+    )
+}
+
+enum SaleFailure {
+    /// The product wasn't updated, nothing has changed.
+    Product(BoxedError),
+    /// The sale didn't land in the sheet, the product was rolled back.
+    Sale(BoxedError),
+}
+
+/// Takes the sold amount from the product and appends the sale, waiting until
+/// the sale has landed, so a lost sale isn't reported as registered.
+async fn register_sale<P, S>(
+    products: &mut P,
+    sales: &mut S,
+    row: usize,
+    (before, after): (&Product, &Product),
+    sale: &Sale,
+) -> std::result::Result<(), SaleFailure>
+where
+    P: TableUpdate<Product> + TableFlush + Send,
+    S: TableExtend<Sale> + TableFlush + Send,
+    <P as TableUpdate<Product>>::Error: Sync + 'static,
+    <S as TableExtend<Sale>>::Error: Sync + 'static,
+    <S as TableFlush>::Error: Sync + 'static,
+{
+    if let Err(e) = products.update_one(row, after).await {
+        return Err(SaleFailure::Product(Box::new(e)));
+    }
+
+    let landed = match sales.extend_one(sale).await {
+        Ok(_) => sales.flush().await.map_err(|e| Box::new(e) as BoxedError),
+        Err(e) => Err(Box::new(e) as BoxedError),
+    };
+
+    if let Err(e) = landed {
+        // The rollback must not land before the update it reverts
+        let _ = products.flush().await;
+        if let Err(rollback) = products.update_one(row, before).await {
+            error!("Failed to roll back the product at {}: {}", row, rollback);
+        }
+
+        return Err(SaleFailure::Sale(e));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use async_trait::async_trait;
+    use tables::in_mem::InMemTable;
+
+    use super::*;
+    use crate::entries::fixtures;
+
+    /// Sales sheet which accepts writes, but never gets them through.
+    struct Unreachable;
+
+    #[async_trait]
+    impl TableExtend<Sale> for Unreachable {
+        type Ok = ();
+        type Error = io::Error;
+
+        async fn extend<'a, T>(&'a mut self, _: T) -> std::result::Result<(), io::Error>
+        where
+            T: IntoIterator<Item = &'a Sale> + Clone + Send + Sync,
+            Sale: 'a,
+        {
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl TableFlush for Unreachable {
+        type Error = io::Error;
+
+        async fn flush(&mut self) -> std::result::Result<(), io::Error> {
+            Err(io::Error::new(io::ErrorKind::TimedOut, "unreachable"))
+        }
+    }
+
+    fn product() -> Product {
+        Product {
+            item_id: "hat".to_owned(),
+            price: 10.0,
+            amount_granted: 5.0,
+            amount_left: 5.0,
+            ..fixtures::product()
+        }
+    }
+
+    fn sale() -> Sale {
+        Sale {
+            merchant: "merchant".to_owned(),
+            sale_type: SaleType::HandToHand,
+            customer: "customer".to_owned(),
+            item_id: "hat".to_owned(),
+            comment: "".to_owned(),
+            amount: 2.0,
+            revenue: 20.0,
+            currency: Currency::EUR,
+            share: 0.0,
+            date: Utc::now(),
+            base_amount: None,
+            base_currency: None,
+        }
+    }
+
+    fn amounts(products: &InMemTable<Product>) -> Vec<(f64, f64)> {
+        products
+            .rows
+            .iter()
+            .flatten()
+            .map(|product| (product.amount_left, product.amount_sold))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn landed_sale_keeps_the_product_update() {
+        let before = product();
+        let mut after = before.clone();
+        assert!(after.take(2.0));
+        after.count_sold(2.0);
+
+        let mut products: InMemTable<Product> = [before.clone()].into();
+        let mut sales: InMemTable<Sale> = InMemTable::new(vec![]);
+
+        let registered =
+            register_sale(&mut products, &mut sales, 0, (&before, &after), &sale()).await;
+
+        assert!(registered.is_ok());
+        assert_eq!(amounts(&products), vec![(3.0, 2.0)]);
+        assert_eq!(sales.rows.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_sale_rolls_the_product_back() {
+        let before = product();
+        let mut after = before.clone();
+        assert!(after.take(2.0));
+        after.count_sold(2.0);
+
+        let mut products: InMemTable<Product> = [before.clone()].into();
+
+        let registered = register_sale(
+            &mut products,
+            &mut Unreachable,
+            0,
+            (&before, &after),
+            &sale(),
+        )
+        .await;
+
+        assert!(matches!(registered, Err(SaleFailure::Sale(_))));
+        assert_eq!(amounts(&products), vec![(5.0, 0.0)]);
+    }
+}
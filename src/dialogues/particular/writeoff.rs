@@ -17,17 +17,18 @@ type Storage = InMemStorage<Stage>;
 struct StageData {
     pub product: Option<Product>,
     pub item: Option<Item>,
-    pub amount: Option<u32>,
+    pub amount: Option<f64>,
     pub price: Option<f64>,
     pub currency: Option<Currency>,
     pub reason: Option<String>,
+    pub prompt: Option<Prompt>,
 }
 
 #[derive(Default, Clone)]
 enum Stage {
     #[default]
     Start,
-    WaitProduct,
+    WaitProduct(StageData),
     WaitAmount(StageData),
     WaitPrice(StageData),
     WaitReason(StageData),
@@ -35,31 +36,41 @@ enum Stage {
 }
 
 pub fn handler() -> HandlerResult {
-    Update::filter_message()
-        .enter_dialogue::<Message, InMemStorage<Stage>, Stage>()
+    dptree::entry()
+        .branch(cancel_by_callback::<Stage, Storage>())
         .branch(
-            filter_dialogue_started::<Stage, Storage>()
-                .chain(filter_msg_prefix("Cancel"))
-                .endpoint(cancel::<Stage, Storage>),
-        )
-        .branch(
-            dptree::case![Stage::Start]
-                .chain(filter_msg_prefix("✍️ Writeoff"))
-                .endpoint(start::<Stage, Storage>),
-        )
-        .branch(dptree::case![Stage::WaitProduct].endpoint(receive_product_stage::<Stage, Storage>))
-        .branch(
-            dptree::case![Stage::WaitAmount(data)].endpoint(receive_amount_stage::<Stage, Storage>),
-        )
-        .branch(
-            dptree::case![Stage::WaitPrice(data)].endpoint(receive_money_stage::<Stage, Storage>),
-        )
-        .branch(
-            dptree::case![Stage::WaitReason(data)].endpoint(receive_text_stage::<Stage, Storage>),
-        )
-        .branch(
-            dptree::case![Stage::WaitConfirmation(data)]
-                .endpoint(receive_text_stage::<Stage, Storage>),
+            Update::filter_message()
+                .enter_dialogue::<Message, InMemStorage<Stage>, Stage>()
+                .branch(
+                    filter_dialogue_started::<Stage, Storage>()
+                        .chain(filter_msg_prefix("Cancel"))
+                        .endpoint(cancel::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::Start]
+                        .chain(filter_msg_prefix("✍️ Writeoff"))
+                        .endpoint(start::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitProduct(data)]
+                        .endpoint(receive_product_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitAmount(data)]
+                        .endpoint(receive_amount_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitPrice(data)]
+                        .endpoint(receive_money_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitReason(data)]
+                        .endpoint(receive_text_stage::<Stage, Storage>),
+                )
+                .branch(
+                    dptree::case![Stage::WaitConfirmation(data)]
+                        .endpoint(receive_text_stage::<Stage, Storage>),
+                ),
         )
 }
 
@@ -80,6 +91,27 @@ impl ConversationStart for Stage {
         Role::Merchant
     }
 
+    fn amount_unit(&self) -> Unit {
+        match self {
+            Self::WaitAmount(StageData {
+                product: Some(product),
+                ..
+            }) => product.unit,
+            _ => Unit::Each,
+        }
+    }
+
+    fn prompt(&self) -> Option<&Prompt> {
+        match self {
+            Self::Start => None,
+            Self::WaitProduct(data)
+            | Self::WaitAmount(data)
+            | Self::WaitPrice(data)
+            | Self::WaitReason(data)
+            | Self::WaitConfirmation(data) => data.prompt.as_ref(),
+        }
+    }
+
     async fn start(
         self,
         bot: Bot,
@@ -96,16 +128,17 @@ impl ConversationStart for Stage {
 
                 let chat_id = upd.chat_id().ok_or(UnkError::unknown("upd.chat_id"))?;
 
-                bot.send_message(chat_id, localize_upd!(warehouse, upd, text))
-                    .parse_mode(ParseMode::Html)
-                    .reply_markup(InlineKeyboardMarkup::default().append_row(vec![
-                        InlineKeyboardButton::switch_inline_query_current_chat(
+                let prompt = Prompt::html(
+                    localize_upd!(warehouse, upd, text),
+                    InlineKeyboardMarkup::new(vec![
+                        vec![InlineKeyboardButton::switch_inline_query_current_chat(
                             localize_upd!(warehouse, upd, "Select").to_string(),
                             "~woff ",
-                        ),
-                    ]))
-                    .send()
-                    .await?;
+                        )],
+                        vec![inline_cancel_button(localize_upd!(warehouse, upd, "Cancel"))],
+                    ]),
+                );
+                prompt.send(&bot, chat_id).await?;
 
                 bot.send_message(
                     chat_id,
@@ -125,7 +158,10 @@ impl ConversationStart for Stage {
                 }))
                 .await?;
 
-                Ok(Self::WaitProduct)
+                Ok(Self::WaitProduct(StageData {
+                    prompt: Some(prompt),
+                    ..Default::default()
+                }))
             }
             _ => Ok(self),
         }
@@ -143,8 +179,8 @@ impl ConversationStage<(Product, Item)> for Stage {
         pair: (Product, Item),
     ) -> Result<Self> {
         match self {
-            Stage::WaitProduct => {
-                if pair.0.amount_left <= 0 {
+            Stage::WaitProduct(_) => {
+                if pair.0.amount_left <= 0.0 {
                     bot.send_message(
                         msg.chat.id,
                         localize_msg!(warehouse, msg, "This product is out of stock, please choose another one or terminate the dialogue."),
@@ -153,29 +189,29 @@ impl ConversationStage<(Product, Item)> for Stage {
                     return Ok(self);
                 }
 
-                bot.send_message(
-                    msg.chat.id,
+                let prompt = Prompt::new(
                     localize_msg!(
                         warehouse,
                         msg,
                         "Great! Please tell me how much you want to write-off."
                     ),
-                )
-                .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
-                    resize_keyboard: Some(true),
-                    is_persistent: true,
-                    keyboard: vec![
-                        (1..=5)
-                            .map(|i| KeyboardButton::new(i.to_string()))
-                            .collect(),
-                        vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Cancel"))],
-                    ],
-                    ..Default::default()
-                }))
-                .await?;
+                    KeyboardMarkup {
+                        resize_keyboard: Some(true),
+                        is_persistent: true,
+                        keyboard: vec![
+                            (1..=5)
+                                .map(|i| KeyboardButton::new(i.to_string()))
+                                .collect(),
+                            vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Cancel"))],
+                        ],
+                        ..Default::default()
+                    },
+                );
+                prompt.send(&bot, msg.chat.id).await?;
                 Ok(Self::WaitAmount(StageData {
                     product: Some(pair.0),
                     item: Some(pair.1),
+                    prompt: Some(prompt),
                     ..Default::default()
                 }))
             }
@@ -185,19 +221,19 @@ impl ConversationStage<(Product, Item)> for Stage {
 }
 
 #[async_trait]
-impl ConversationStage<u32> for Stage {
+impl ConversationStage<f64> for Stage {
     async fn next(
         self,
         bot: Bot,
         msg: Message,
         _: (User, UserMeta),
         warehouse: &mut Warehouse,
-        amount: u32,
+        amount: f64,
     ) -> Result<Self> {
         match self {
             Stage::WaitAmount(mut data) => {
                 let product = data.product.as_ref().unwrap();
-                if product.amount_left < amount {
+                if !product.has_left(amount) {
                     bot.send_message(
                         msg.chat.id,
                         localize_msg!(warehouse, msg, "You can't write-off more than you have."),
@@ -206,11 +242,12 @@ impl ConversationStage<u32> for Stage {
                     return Ok(Self::WaitAmount(data));
                 }
 
-                data.amount = Some(amount as u32);
-                bot.send_message(msg.chat.id, localize_msg!(warehouse, msg, concat!(
+                data.amount = Some(amount);
+                let prompt = Prompt::new(
+                    localize_msg!(warehouse, msg, concat!(
                         "Fine, how much would it all cost in total? ",
-                        "Write as a real number with a currency (for example, \"100.50 eur\" or \"30 CZK\").")))
-                    .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
+                        "Write as a real number with a currency (for example, \"100.50 eur\" or \"30 CZK\").")),
+                    KeyboardMarkup {
                         resize_keyboard: Some(true),
                         is_persistent: true,
                         input_field_placeholder: Some("9.99 eur".to_string()),
@@ -218,8 +255,10 @@ impl ConversationStage<u32> for Stage {
                             vec![KeyboardButton::new(localize_msg!(warehouse, msg, "Cancel"))],
                         ],
                         ..Default::default()
-                    }))
-                    .await?;
+                    },
+                );
+                prompt.send(&bot, msg.chat.id).await?;
+                data.prompt = Some(prompt);
                 Ok(Self::WaitPrice(data))
             }
             _ => Ok(self),
@@ -242,22 +281,22 @@ impl ConversationStage<(f64, Currency)> for Stage {
                 data.price = Some(money.0);
                 data.currency = Some(money.1);
 
-                bot.send_message(
-                    msg.chat.id,
+                let prompt = Prompt::new(
                     localize_msg!(
                         warehouse,
                         msg,
                         "Okay. Write the reason for the write-off (like \"Defect\")"
                     ),
-                )
-                .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
-                    resize_keyboard: Some(true),
-                    keyboard: vec![vec![KeyboardButton::new(localize_msg!(
-                        warehouse, msg, "Cancel"
-                    ))]],
-                    ..Default::default()
-                }))
-                .await?;
+                    KeyboardMarkup {
+                        resize_keyboard: Some(true),
+                        keyboard: vec![vec![KeyboardButton::new(localize_msg!(
+                            warehouse, msg, "Cancel"
+                        ))]],
+                        ..Default::default()
+                    },
+                );
+                prompt.send(&bot, msg.chat.id).await?;
+                data.prompt = Some(prompt);
 
                 Ok(Self::WaitReason(data))
             }
@@ -284,7 +323,7 @@ impl ConversationStage<String> for Stage {
                     .currency
                     .as_ref()
                     .unwrap()
-                    .format(&data.price.as_ref().unwrap().to_string());
+                    .format_amount(*data.price.as_ref().unwrap());
 
                 let text = [
                     "<b>Confirm the sell</b>".to_owned(),
@@ -300,20 +339,20 @@ impl ConversationStage<String> for Stage {
                     .parse_mode(ParseMode::Html)
                     .await?;
 
-                bot.send_message(
-                    msg.chat.id,
+                let prompt = Prompt::new(
                     localize_msg!(warehouse, msg, "Is everything correct?"),
-                )
-                .reply_markup(ReplyMarkup::Keyboard(KeyboardMarkup {
-                    resize_keyboard: Some(true),
-                    one_time_keyboard: Some(true),
-                    keyboard: vec![vec![
-                        KeyboardButton::new(localize_msg!(warehouse, msg, "Yes")),
-                        KeyboardButton::new(localize_msg!(warehouse, msg, "No")),
-                    ]],
-                    ..Default::default()
-                }))
-                .await?;
+                    KeyboardMarkup {
+                        resize_keyboard: Some(true),
+                        one_time_keyboard: Some(true),
+                        keyboard: vec![vec![
+                            KeyboardButton::new(localize_msg!(warehouse, msg, "Yes")),
+                            KeyboardButton::new(localize_msg!(warehouse, msg, "No")),
+                        ]],
+                        ..Default::default()
+                    },
+                );
+                prompt.send(&bot, msg.chat.id).await?;
+                data.prompt = Some(prompt);
 
                 Ok(Self::WaitConfirmation(data))
             }
@@ -363,7 +402,18 @@ impl ConversationStage<String> for Stage {
                     }
                 };
 
-                product.amount_left -= data.amount.unwrap();
+                if !product.take(data.amount.unwrap()) {
+                    bot.send_message(
+                        msg.chat.id,
+                        localize_msg!(
+                            warehouse,
+                            msg,
+                            "Product was edited during the dialogue, so you can't sell that much."
+                        ),
+                    )
+                    .await?;
+                    return Ok(Self::Start);
+                }
 
                 match warehouse.products.update_one(row, &product).await {
                     Ok(_) => (),
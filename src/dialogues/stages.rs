@@ -13,14 +13,14 @@ pub async fn start<D, S>(
     bot: Bot,
     upd: Update,
     dialogue: Dialogue<D, S>,
-    warehouse: SharedWarehouse,
+    warehouse: LockedWarehouse,
 ) -> Result<()>
 where
     D: ConversationStart + Send + Sync + 'static,
     S: Storage<D> + Send + Sync + 'static,
     S::Error: std::error::Error + Send + Sync,
 {
-    let mut warehouse = warehouse.write().await;
+    let mut warehouse = warehouse.lock().await;
     let (user, meta) = handle_user_from_upd(&mut warehouse, &upd).await?;
 
     let stage = dialogue
@@ -43,14 +43,14 @@ pub async fn receive_product_stage<D, S>(
     bot: Bot,
     msg: Message,
     dialogue: Dialogue<D, S>,
-    warehouse: SharedWarehouse,
+    warehouse: LockedWarehouse,
 ) -> Result<()>
 where
     D: ConversationStart + ConversationStage<(Product, Item)> + Send + Sync + 'static,
     S: Storage<D> + Send + Sync + 'static,
     S::Error: std::error::Error + Send + Sync,
 {
-    let mut warehouse = warehouse.write().await;
+    let mut warehouse = warehouse.lock().await;
     let (user, meta) = handle_user_from_msg(&mut warehouse, &msg).await?;
 
     let stage = dialogue
@@ -62,7 +62,8 @@ where
         return Ok(());
     }
 
-    let (_, product) = match receive_product(bot.clone(), &msg, &mut warehouse).await? {
+    let received = receive_product(bot.clone(), &msg, &mut warehouse, stage.prompt()).await?;
+    let (_, product) = match received {
         Some((row, product)) => (*row, product.clone()),
         None => return Ok(()),
     };
@@ -102,11 +103,12 @@ pub async fn receive_product<'a>(
     bot: Bot,
     msg: &Message,
     warehouse: &'a mut Warehouse,
+    prompt: Option<&Prompt>,
 ) -> Result<Option<(&'a usize, &'a Product)>> {
     let text = match msg.text() {
         Some(t) => t,
         None => {
-            bot.send_message(msg.chat.id, "Please send a text.").await?;
+            reask(&bot, msg.chat.id, prompt, "Please send a text.").await?;
             return Ok(None);
         }
     };
@@ -165,14 +167,14 @@ pub async fn receive_amount_stage<D, S>(
     bot: Bot,
     msg: Message,
     dialogue: Dialogue<D, S>,
-    warehouse: SharedWarehouse,
+    warehouse: LockedWarehouse,
 ) -> Result<()>
 where
-    D: ConversationStart + ConversationStage<u32> + Send + Sync + 'static,
+    D: ConversationStart + ConversationStage<f64> + Send + Sync + 'static,
     S: Storage<D> + Send + Sync + 'static,
     S::Error: std::error::Error + Send + Sync,
 {
-    let mut warehouse = warehouse.write().await;
+    let mut warehouse = warehouse.lock().await;
     let (user, meta) = handle_user_from_msg(&mut warehouse, &msg).await?;
 
     let stage = dialogue
@@ -184,7 +186,8 @@ where
         return Ok(());
     }
 
-    let amount = match receive_amount(bot.clone(), &msg).await? {
+    let received = receive_amount(bot.clone(), &msg, stage.amount_unit(), stage.prompt()).await?;
+    let amount = match received {
         Some(p) => p,
         None => return Ok(()),
     };
@@ -200,27 +203,30 @@ where
     Ok(())
 }
 
-pub async fn receive_amount(bot: Bot, msg: &Message) -> Result<Option<u32>> {
+/// Reads a positive amount, fractional only for products sold by weight.
+pub async fn receive_amount(
+    bot: Bot,
+    msg: &Message,
+    unit: Unit,
+    prompt: Option<&Prompt>,
+) -> Result<Option<f64>> {
     let text = match msg.text() {
         Some(t) => t,
         None => {
-            bot.send_message(msg.chat.id, "Please send a number.")
-                .await?;
+            reask(&bot, msg.chat.id, prompt, "Please send a number.").await?;
             return Ok(None);
         }
     };
 
-    Ok(match text.parse::<u32>() {
-        Ok(amount) => match amount {
-            0 => {
-                bot.send_message(msg.chat.id, "🧐").await?;
-                bot.send_message(msg.chat.id, "Please send a positive number.")
-                    .await?;
-                None
-            }
-            _ => Some(amount),
-        },
-        Err(_) => {
+    Ok(match unit.parse_amount(text) {
+        Some(amount) if amount == 0.0 => {
+            bot.send_message(msg.chat.id, "🧐").await?;
+            bot.send_message(msg.chat.id, "Please send a positive number.")
+                .await?;
+            None
+        }
+        Some(amount) => Some(amount),
+        None => {
             bot.send_message(msg.chat.id, "Invalid number format.")
                 .await?;
             None
@@ -232,14 +238,14 @@ pub async fn receive_money_stage<D, S>(
     bot: Bot,
     msg: Message,
     dialogue: Dialogue<D, S>,
-    warehouse: SharedWarehouse,
+    warehouse: LockedWarehouse,
 ) -> Result<()>
 where
     D: ConversationStart + ConversationStage<(f64, Currency)> + Send + Sync + 'static,
     S: Storage<D> + Send + Sync + 'static,
     S::Error: std::error::Error + Send + Sync,
 {
-    let mut warehouse = warehouse.write().await;
+    let mut warehouse = warehouse.lock().await;
     let (user, meta) = handle_user_from_msg(&mut warehouse, &msg).await?;
 
     let stage = dialogue
@@ -251,7 +257,7 @@ where
         return Ok(());
     }
 
-    let (money, currency) = match receive_money(bot.clone(), &msg).await? {
+    let (money, currency) = match receive_money(bot.clone(), &msg, stage.prompt()).await? {
         Some(p) => p,
         None => return Ok(()),
     };
@@ -273,13 +279,32 @@ where
     Ok(())
 }
 
-pub async fn receive_money(bot: Bot, msg: &Message) -> Result<Option<(f64, Currency)>> {
+pub async fn receive_money(
+    bot: Bot,
+    msg: &Message,
+    prompt: Option<&Prompt>,
+) -> Result<Option<(f64, Currency)>> {
+    match read_money(msg, prompt) {
+        Ok(money) => Ok(Some(money)),
+        Err(reply) => {
+            reply.send(&bot, msg.chat.id).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Price and currency of the answer, or the reply telling what's wrong with it.
+fn read_money(
+    msg: &Message,
+    prompt: Option<&Prompt>,
+) -> std::result::Result<(f64, Currency), Prompt> {
     let text = match msg.text() {
         Some(t) => t.trim().to_uppercase(),
         None => {
-            bot.send_message(msg.chat.id, "Please send a number with a currency.")
-                .await?;
-            return Ok(None);
+            return Err(reask_message(
+                prompt,
+                "Please send a number with a currency.",
+            ))
         }
     };
 
@@ -288,49 +313,34 @@ pub async fn receive_money(bot: Bot, msg: &Message) -> Result<Option<(f64, Curre
     let price = match parts.next() {
         Some(text) => match text.parse::<f64>() {
             Ok(price) => price,
-            Err(e) => {
-                bot.send_message(msg.chat.id, format!("Worng number format: {e}"))
-                    .await?;
-                return Ok(None);
-            }
+            Err(e) => return Err(notice(format!("Worng number format: {e}"))),
         },
-        None => {
-            bot.send_message(msg.chat.id, "Wrong format.").await?;
-            return Ok(None);
-        }
+        None => return Err(notice("Wrong format.")),
     };
 
     let currency = match parts.next() {
         Some(text) => match Currency::parse(text) {
             Ok(c) => c,
-            Err(e) => {
-                bot.send_message(msg.chat.id, format!("Wrong currency format: {e}"))
-                    .await?;
-                return Ok(None);
-            }
+            Err(e) => return Err(notice(format!("Wrong currency format: {e}"))),
         },
-        None => {
-            bot.send_message(msg.chat.id, "Wrong currency format.")
-                .await?;
-            return Ok(None);
-        }
+        None => return Err(notice("Wrong currency format.")),
     };
 
-    Ok(Some((price, currency)))
+    Ok((price, currency))
 }
 
 pub async fn receive_text_stage<D, S>(
     bot: Bot,
     msg: Message,
     dialogue: Dialogue<D, S>,
-    warehouse: SharedWarehouse,
+    warehouse: LockedWarehouse,
 ) -> Result<()>
 where
     D: ConversationStart + ConversationStage<String> + Send + Sync + 'static,
     S: Storage<D> + Send + Sync + 'static,
     S::Error: std::error::Error + Send + Sync,
 {
-    let mut warehouse = warehouse.write().await;
+    let mut warehouse = warehouse.lock().await;
     let (user, meta) = handle_user_from_msg(&mut warehouse, &msg).await?;
 
     let stage = dialogue
@@ -345,7 +355,7 @@ where
     let text = match msg.text() {
         Some(t) => t.to_owned(),
         None => {
-            bot.send_message(msg.chat.id, "Please send a text.").await?;
+            reask(&bot, msg.chat.id, stage.prompt(), "Please send a text.").await?;
             return Ok(());
         }
     };
@@ -371,3 +381,92 @@ where
 
     Ok(())
 }
+
+/// Answer to a message the stage can't read, e.g. a sticker. The stage's prompt
+/// when it keeps one, so the user has the question and its keyboard again.
+pub fn reask_message(prompt: Option<&Prompt>, fallback: &str) -> Prompt {
+    prompt.cloned().unwrap_or_else(|| notice(fallback))
+}
+
+async fn reask(bot: &Bot, chat_id: ChatId, prompt: Option<&Prompt>, fallback: &str) -> Result<()> {
+    reask_message(prompt, fallback).send(bot, chat_id).await
+}
+
+/// Plain text reply, leaves the keyboard the user has.
+fn notice(text: impl Into<String>) -> Prompt {
+    Prompt {
+        text: text.into(),
+        keyboard: None,
+        parse_mode: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::types::{KeyboardButton, KeyboardMarkup, ParseMode, ReplyMarkup};
+
+    use super::*;
+
+    /// Message from a private chat, `content` holds the text or the media of it.
+    fn message(content: serde_json::Value) -> Message {
+        let mut json = serde_json::json!({
+            "message_id": 1,
+            "date": 1700000000,
+            "chat": { "id": 1, "type": "private", "first_name": "Ann" },
+            "from": { "id": 1, "is_bot": false, "first_name": "Ann", "language_code": "en" }
+        });
+        json.as_object_mut()
+            .unwrap()
+            .extend(content.as_object().unwrap().clone());
+
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn price_prompt() -> Prompt {
+        Prompt::html(
+            "Please enter the price, e.g. <i>30 CZK</i>.".to_owned(),
+            KeyboardMarkup::new(vec![vec![KeyboardButton::new("Cancel")]]),
+        )
+    }
+
+    #[test]
+    fn non_text_money_reasks_with_the_prompt() {
+        let prompt = price_prompt();
+        let location = message(serde_json::json!({
+            "location": { "latitude": 50.08, "longitude": 14.42 }
+        }));
+
+        let reply = read_money(&location, Some(&prompt)).unwrap_err();
+        assert_eq!(reply, prompt);
+        assert_eq!(reply.parse_mode, Some(ParseMode::Html));
+        assert_eq!(
+            reply.keyboard,
+            Some(ReplyMarkup::Keyboard(KeyboardMarkup::new(vec![vec![
+                KeyboardButton::new("Cancel")
+            ]])))
+        );
+
+        // Stages keeping no prompt still get the plain reminder
+        let reply = read_money(&location, None).unwrap_err();
+        assert_eq!(reply.text, "Please send a number with a currency.");
+        assert_eq!(reply.keyboard, None);
+        assert_eq!(reply.parse_mode, None);
+    }
+
+    #[test]
+    fn money_is_read_from_text() {
+        let prompt = price_prompt();
+
+        let answer = message(serde_json::json!({ "text": " 30 czk " }));
+        assert_eq!(
+            read_money(&answer, Some(&prompt)),
+            Ok((30.0, Currency::CZK))
+        );
+
+        // A typo gets told, not the question again
+        let answer = message(serde_json::json!({ "text": "30" }));
+        let reply = read_money(&answer, Some(&prompt)).unwrap_err();
+        assert_eq!(reply.text, "Wrong currency format.");
+        assert_eq!(reply.keyboard, None);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::error::Error as StdError;
 use std::fmt::Display;
 
@@ -5,17 +6,24 @@ use serde::{
     de::{self, IntoDeserializer},
     Deserialize,
 };
+/// The only currency type of the crate. It is the one Telegram payments accept,
+/// so entries, money stages and invoices share it without any conversion.
 pub use teloxide::types::Currency;
 
 #[derive(Debug)]
 pub enum CurrencyError {
     Custom(String),
+    /// Not an ISO 4217 code Telegram payments support.
+    Unsupported(String),
 }
 
 impl Display for CurrencyError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             CurrencyError::Custom(msg) => f.write_str(msg),
+            CurrencyError::Unsupported(code) => {
+                write!(f, "currency \"{}\" is not supported", code)
+            }
         }
     }
 }
@@ -30,6 +38,13 @@ impl StdError for CurrencyError {}
 
 pub trait CurrencyExt {
     fn format(&self, price: &str) -> String;
+    /// Formats a numeric amount, rounded to the currency minor units
+    /// and grouped by thousands.
+    fn format_amount(&self, amount: f64) -> String;
+    fn minor_units(&self) -> usize;
+    /// Converts an amount into the smallest currency units used by invoices.
+    fn to_minor(&self, amount: f64) -> i32;
+    /// Parses a currency code regardless of its case, e.g. "eur" or "CZK".
     fn parse(currency: &str) -> Result<Self, CurrencyError>
     where
         Self: Sized;
@@ -49,12 +64,201 @@ impl CurrencyExt for Currency {
         }
     }
 
+    fn format_amount(&self, amount: f64) -> String {
+        let minor_units = self.minor_units();
+        let rounded = format!("{:.*}", minor_units, amount.abs());
+
+        let (int_part, frac_part) = match rounded.split_once('.') {
+            Some((int_part, frac_part)) => (int_part, Some(frac_part)),
+            None => (rounded.as_str(), None),
+        };
+
+        let mut grouped = String::with_capacity(int_part.len() + int_part.len() / 3);
+        for (i, ch) in int_part.chars().enumerate() {
+            if i > 0 && (int_part.len() - i) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(ch);
+        }
+
+        if let Some(frac_part) = frac_part {
+            grouped.push('.');
+            grouped.push_str(frac_part);
+        }
+
+        // The sign goes before the symbol, "-0.00" isn't printed for values rounding to zero
+        let formatted = self.format(&grouped);
+        if amount < 0.0 && grouped.chars().any(|ch| ch.is_ascii_digit() && ch != '0') {
+            format!("-{}", formatted)
+        } else {
+            formatted
+        }
+    }
+
+    fn minor_units(&self) -> usize {
+        match self {
+            Currency::CLP
+            | Currency::ISK
+            | Currency::JPY
+            | Currency::KRW
+            | Currency::PYG
+            | Currency::UGX
+            | Currency::VND => 0,
+            _ => 2,
+        }
+    }
+
+    fn to_minor(&self, amount: f64) -> i32 {
+        (amount * 10f64.powi(self.minor_units() as i32)).round() as i32
+    }
+
     fn parse(currency: &str) -> Result<Self, CurrencyError> {
-        let de: de::value::StrDeserializer<'_, CurrencyError> = currency.into_deserializer();
-        Self::deserialize(de)
+        let code = currency.trim().to_uppercase();
+        let de: de::value::StrDeserializer<'_, CurrencyError> = code.as_str().into_deserializer();
+        Self::deserialize(de).map_err(|_| CurrencyError::Unsupported(currency.trim().to_owned()))
     }
 
     fn to_string(&self) -> String {
         format!("{:x?}", self)
     }
 }
+
+/// Rates of the currencies to the base one, in base units per unit of the currency.
+#[derive(Clone, Debug)]
+pub struct ExchangeRates {
+    pub base: Currency,
+    rates: HashMap<String, f64>,
+}
+
+impl ExchangeRates {
+    pub fn new<'a>(
+        base: &str,
+        rates: impl IntoIterator<Item = (&'a String, &'a f64)>,
+    ) -> Result<Self, CurrencyError> {
+        let rates = rates
+            .into_iter()
+            .map(|(code, rate)| Ok((Currency::parse(code)?.to_string(), *rate)))
+            .collect::<Result<_, CurrencyError>>()?;
+
+        Ok(Self {
+            base: Currency::parse(base)?,
+            rates,
+        })
+    }
+
+    /// Converts the amount like [`Self::convert`], along with the base currency it's in.
+    pub fn pin(&self, amount: f64, currency: &Currency) -> Option<(f64, Currency)> {
+        Some((self.convert(amount, currency)?, self.base))
+    }
+
+    /// Converts the amount into the base currency, rounded to its minor units.
+    /// `None` when there is no rate for the currency.
+    pub fn convert(&self, amount: f64, currency: &Currency) -> Option<f64> {
+        self.exchange(amount, currency, &self.base)
+    }
+
+    /// Converts the amount between two currencies through the base one,
+    /// rounded to the minor units of the target currency.
+    pub fn exchange(&self, amount: f64, from: &Currency, to: &Currency) -> Option<f64> {
+        let rate = self.rate(from)? / self.rate(to)?;
+
+        let scale = 10f64.powi(to.minor_units() as i32);
+        Some((amount * rate * scale).round() / scale)
+    }
+
+    fn rate(&self, currency: &Currency) -> Option<f64> {
+        if *currency == self.base {
+            Some(1.0)
+        } else {
+            self.rates.get(&currency.to_string()).copied()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_amount_rounds_to_minor_units() {
+        assert_eq!(Currency::EUR.format_amount(10.0), "€10.00");
+        assert_eq!(Currency::EUR.format_amount(0.1 + 0.2), "€0.30");
+        assert_eq!(Currency::USD.format_amount(2.005), "$2.00");
+        assert_eq!(Currency::RUB.format_amount(99.999), "100.00₽");
+        assert_eq!(Currency::JPY.format_amount(1234.6), "1,235 JPY");
+    }
+
+    #[test]
+    fn format_amount_groups_thousands() {
+        assert_eq!(Currency::CZK.format_amount(1234.5), "Kč 1,234.50");
+        assert_eq!(Currency::UAH.format_amount(1234567.0), "1,234,567.00₴");
+        assert_eq!(Currency::KZT.format_amount(999.0), "999.00₸");
+        assert_eq!(Currency::EUR.format_amount(100000.0), "€100,000.00");
+    }
+
+    #[test]
+    fn format_amount_handles_negatives() {
+        assert_eq!(Currency::EUR.format_amount(-1500.25), "-€1,500.25");
+        assert_eq!(Currency::CZK.format_amount(-5.0), "-Kč 5.00");
+        assert_eq!(Currency::RUB.format_amount(-5.0), "-5.00₽");
+        assert_eq!(Currency::EUR.format_amount(-0.001), "€0.00");
+    }
+
+    #[test]
+    fn to_minor_rounds() {
+        assert_eq!(Currency::EUR.to_minor(0.29), 29);
+        assert_eq!(Currency::EUR.to_minor(10.0), 1000);
+        assert_eq!(Currency::JPY.to_minor(120.0), 120);
+    }
+
+    #[test]
+    fn format_keeps_string_prices() {
+        assert_eq!(Currency::EUR.format("10"), "€10");
+        assert_eq!(Currency::RUB.format("-"), "-₽");
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        for currency in [
+            Currency::EUR,
+            Currency::USD,
+            Currency::CZK,
+            Currency::UAH,
+            Currency::KZT,
+            Currency::RUB,
+            Currency::JPY,
+        ] {
+            let code = currency.to_string();
+            assert_eq!(Currency::parse(&code).unwrap(), currency);
+        }
+
+        assert_eq!(Currency::EUR.to_string(), "EUR");
+        assert_eq!(Currency::parse("eur").unwrap(), Currency::EUR);
+        assert_eq!(Currency::parse(" Czk ").unwrap(), Currency::CZK);
+    }
+
+    #[test]
+    fn converts_into_base_currency() {
+        let rates = HashMap::from([("czk".to_owned(), 0.04)]);
+        let rates = ExchangeRates::new("EUR", &rates).unwrap();
+
+        assert_eq!(rates.convert(250.0, &Currency::CZK), Some(10.0));
+        assert_eq!(rates.convert(2.5, &Currency::EUR), Some(2.5));
+        assert_eq!(rates.convert(1.0, &Currency::USD), None);
+        assert_eq!(
+            rates.exchange(10.0, &Currency::EUR, &Currency::CZK),
+            Some(250.0)
+        );
+        assert_eq!(rates.exchange(1.0, &Currency::CZK, &Currency::USD), None);
+    }
+
+    #[test]
+    fn unsupported_codes_rejected() {
+        for code in ["BTC", "EURO", "€", ""] {
+            assert!(matches!(
+                Currency::parse(code),
+                Err(CurrencyError::Unsupported(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Entries with plain values for tests, which spell out only the fields they
+//! are about: `Product { price: 2.0, ..fixtures::product() }`.
+
+use chrono::Utc;
+use teloxide::types::ChatId;
+
+use super::*;
+
+/// One hat of "merchant" for €1, in stock and listed for everyone.
+pub fn product() -> Product {
+    Product {
+        merchant: "merchant".to_owned(),
+        item_id: "item".to_owned(),
+        price: 1.0,
+        currency: Currency::EUR,
+        payment_method: PaymentMethod::Both,
+        negotiated_price: false,
+        share: 0.0,
+        visibility: ProductVisibility::All,
+        amount_granted: 1.0,
+        amount_sold: 0.0,
+        amount_left: 1.0,
+        deleted: false,
+        unit: Unit::Each,
+        oversell_allowance: 0.0,
+        backordered: 0.0,
+        active: true,
+    }
+}
+
+/// Merchant without a provider, a minimum order or a shop name.
+pub fn merchant() -> Merchant {
+    Merchant {
+        name: "merchant".to_owned(),
+        location: "Prague".to_owned(),
+        address: "Main st. 1".to_owned(),
+        provider_token: None,
+        provider_currencies: None,
+        vacation: false,
+        min_order_value: None,
+        min_order_currency: None,
+        display_name: None,
+    }
+}
+
+/// Order of one "item" by "customer" from "merchant", waiting for the payment.
+pub fn order() -> Order {
+    Order {
+        id: "order".to_owned(),
+        customer: "customer".to_owned(),
+        merchant: "merchant".to_owned(),
+        stage: OrderStage::WaitForPayment,
+        item_id: "item".to_owned(),
+        amount: 1.0,
+        cost: 1.0,
+        currency: Currency::EUR,
+        date: Utc::now(),
+        note: None,
+        completed_at: None,
+        base_amount: None,
+        base_currency: None,
+    }
+}
+
+pub fn user() -> User {
+    User {
+        name: "customer".to_owned(),
+        role: Role::User,
+        lang_code: "en".to_owned(),
+        created_date: Utc::now(),
+        last_activity_date: Utc::now(),
+        blocked: false,
+    }
+}
+
+/// Meta of [`user`], reachable and without orders.
+pub fn user_meta() -> UserMeta {
+    UserMeta {
+        name: "customer".to_owned(),
+        chat_id: Some(ChatId(1)),
+        pending_orders: vec![],
+        completed_orders: vec![],
+        note: None,
+    }
+}
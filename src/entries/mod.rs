@@ -1,24 +1,34 @@
 pub mod currency;
+#[cfg(test)]
+pub mod fixtures;
 pub mod serde_fn;
 
 use std::{
     collections::hash_map::DefaultHasher,
+    fmt::Display,
     hash::{Hash, Hasher},
+    ops::RangeInclusive,
 };
 
 use chrono::{DateTime, Utc};
 use log::info;
+use num_derive::FromPrimitive;
 use serde::{Deserialize, Serialize};
 
-pub use currency::{Currency, CurrencyExt};
-use tables::search::{Searchable, Searcher};
+pub use currency::{Currency, CurrencyExt, ExchangeRates};
+use tables::{
+    search::{Searchable, Searcher},
+    Validate,
+};
 use teloxide::types::ChatId;
 
+use crate::utils::labels::Label;
+
 pub mod prelude {
     pub use super::{
         Currency, CurrencyExt, Item, Localization, Merchant, Order, OrderId, OrderStage,
-        PaymentMethod, Product, ProductId, ProductVisibility, Replenishment, Role, Sale, SaleType,
-        User, UserMeta, Writeoff,
+        PaymentMethod, Product, ProductId, ProductReport, ProductVisibility, Replenishment, Role,
+        Sale, SaleType, Secret, Unit, User, UserMeta, Writeoff,
     };
 }
 
@@ -31,15 +41,71 @@ pub mod search_group {
 pub struct Product {
     pub merchant: String,
     pub item_id: String,
+    #[serde(with = "serde_fn::money")]
     pub price: f64,
     pub currency: Currency,
     pub payment_method: PaymentMethod,
     pub negotiated_price: bool,
     pub share: f32,
     pub visibility: ProductVisibility,
-    pub amount_granted: u32,
-    pub amount_sold: u32,
-    pub amount_left: u32,
+    pub amount_granted: f64,
+    pub amount_sold: f64,
+    pub amount_left: f64,
+    /// Soft-deleted products stay in the sheet for history, but are hidden everywhere.
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub unit: Unit,
+    /// Amount which may be sold beyond the stock, for merchants taking backorders.
+    #[serde(default)]
+    pub oversell_allowance: f64,
+    /// Amount sold beyond the stock and owed to the customers, nothing is left meanwhile.
+    #[serde(default)]
+    pub backordered: f64,
+    /// Paused products keep their stock, but only the merchant sees them.
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+/// Shares are fractions of the revenue, anything else is a typo.
+pub const SHARE_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+
+/// How the stock of a product is counted, only weight may be fractional.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Unit {
+    #[default]
+    Each,
+    Weight,
+}
+
+impl Unit {
+    /// Parses a positive amount, or zero, in this unit.
+    pub fn parse_amount(&self, text: &str) -> Option<f64> {
+        let text = text.trim();
+        let amount = match self {
+            Unit::Each => text.parse::<u32>().ok()? as f64,
+            Unit::Weight => text.replace(',', ".").parse::<f64>().ok()?,
+        };
+
+        (amount.is_finite() && amount >= 0.0).then(|| round_amount(amount))
+    }
+
+    /// The least amount that can be bought, weights go by grams.
+    pub fn smallest(&self) -> f64 {
+        match self {
+            Unit::Each => 1.0,
+            Unit::Weight => 0.001,
+        }
+    }
+}
+
+/// Keeps the float error of the stock math out of the sheet, weights are counted to grams.
+pub(crate) fn round_amount(amount: f64) -> f64 {
+    (amount * 1000.0).round() / 1000.0
 }
 
 impl Product {
@@ -47,24 +113,154 @@ impl Product {
         Self::id_from(&self.merchant, &self.item_id)
     }
 
-    fn id_from(merchant: &str, item_id: &str) -> ProductId {
+    pub fn id_from(merchant: &str, item_id: &str) -> ProductId {
         let mut s = DefaultHasher::new();
         merchant.hash(&mut s);
         item_id.hash(&mut s);
         s.finish()
     }
 
+    /// Share of a new product, the default one unless given explicitly.
+    /// None when the share is out of [`SHARE_RANGE`].
+    pub fn new_share(explicit: Option<f32>, default: f32) -> Option<f32> {
+        Some(explicit.unwrap_or(default)).filter(|share| SHARE_RANGE.contains(share))
+    }
+
     pub fn is_visible_to(&self, user: &User) -> bool {
+        if self.deleted {
+            return false;
+        }
+
+        if !self.active && self.merchant != user.name {
+            return false;
+        }
+
         match self.visibility {
             ProductVisibility::All => true,
-            ProductVisibility::Personal => self.merchant == user.name,
+            ProductVisibility::Personal | ProductVisibility::Draft => self.merchant == user.name,
             ProductVisibility::Merchants => user.role.is_at_least(Role::Merchant),
         }
     }
 
+    pub fn is_draft(&self) -> bool {
+        matches!(self.visibility, ProductVisibility::Draft)
+    }
+
+    /// Makes a draft visible to everyone, returns false if it isn't a draft.
+    pub fn publish(&mut self) -> bool {
+        if !self.is_draft() {
+            return false;
+        }
+
+        self.visibility = ProductVisibility::All;
+        true
+    }
+
+    /// Pauses an active product or resumes a paused one, returns whether it's active now.
+    pub fn toggle_active(&mut self) -> bool {
+        self.active = !self.active;
+        self.active
+    }
+
     pub fn supports_invoice(&self) -> bool {
         self.payment_method.supports_card()
     }
+
+    pub fn has_left(&self, amount: f64) -> bool {
+        self.amount_left >= round_amount(amount)
+    }
+
+    /// Takes the amount out of the stock, false and untouched if less is left.
+    pub fn take(&mut self, amount: f64) -> bool {
+        if !self.has_left(amount) {
+            return false;
+        }
+
+        self.amount_left = round_amount(self.amount_left - amount);
+        true
+    }
+
+    /// What can still be sold, the oversell allowance included.
+    pub fn sellable(&self) -> f64 {
+        round_amount(self.amount_left + self.oversell_allowance - self.backordered).max(0.0)
+    }
+
+    /// Whether the amount can be sold, the oversell allowance included.
+    pub fn can_sell(&self, amount: f64) -> bool {
+        self.sellable() >= round_amount(amount)
+    }
+
+    /// Takes a sold amount out of the stock, the part beyond it is backordered.
+    /// False and untouched if even the oversell allowance isn't enough.
+    pub fn take_for_sale(&mut self, amount: f64) -> bool {
+        if !self.can_sell(amount) {
+            return false;
+        }
+
+        let short = (amount - self.amount_left).max(0.0);
+        self.amount_left = round_amount((self.amount_left - amount).max(0.0));
+        self.backordered = round_amount(self.backordered + short);
+        true
+    }
+
+    pub fn is_backordered(&self) -> bool {
+        self.backordered > 0.0
+    }
+
+    /// Returns a taken amount to the stock, e.g. of a cancelled order.
+    /// Backorders are settled first.
+    pub fn put_back(&mut self, amount: f64) {
+        let settled = amount.min(self.backordered);
+        self.backordered = round_amount(self.backordered - settled);
+        self.amount_left = round_amount(self.amount_left + amount - settled);
+    }
+
+    /// Counts a taken amount as sold.
+    pub fn count_sold(&mut self, amount: f64) {
+        self.amount_sold = round_amount(self.amount_sold + amount);
+    }
+
+    /// Adds a delivered amount to the stock.
+    pub fn grant(&mut self, amount: f64) {
+        self.amount_granted = round_amount(self.amount_granted + amount);
+        self.put_back(amount);
+    }
+}
+
+impl Validate for Product {
+    /// Nothing may be sold or left beyond the granted amount and the backorders,
+    /// the share must be in range.
+    fn validate(&mut self, repair: bool) -> Vec<String> {
+        let mut issues = vec![];
+        let sellable = round_amount(self.amount_granted + self.backordered);
+
+        if !SHARE_RANGE.contains(&self.share) {
+            issues.push(format!("share {} is out of range", self.share));
+        }
+
+        if self.amount_sold > sellable {
+            issues.push(format!(
+                "{} sold of {} granted and {} backordered",
+                self.amount_sold, self.amount_granted, self.backordered
+            ));
+        }
+        if round_amount(self.amount_sold + self.amount_left) > sellable {
+            issues.push(format!(
+                "{} left with {} sold of {} granted and {} backordered",
+                self.amount_left, self.amount_sold, self.amount_granted, self.backordered
+            ));
+        }
+
+        if repair {
+            self.share = self.share.clamp(*SHARE_RANGE.start(), *SHARE_RANGE.end());
+            self.amount_sold = self.amount_sold.min(sellable);
+            self.amount_left = self
+                .amount_left
+                .min(round_amount(sellable - self.amount_sold));
+        }
+
+        issues
+    }
 }
 
 impl Searchable for Product {
@@ -80,7 +276,7 @@ impl Searchable for Product {
                 amount_granted,
                 ..
             } => {
-                let fmt_price = currency.format(&price.to_string());
+                let fmt_price = currency.format_amount(*price);
 
                 searcher.write(
                     search_group::USER.to_owned(),
@@ -105,7 +301,7 @@ impl Searchable for Product {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, FromPrimitive)]
 pub enum PaymentMethod {
     Cash,
     Card,
@@ -130,6 +326,36 @@ pub struct Item {
     pub inline_desc: String,
     pub full_desc: String,
     pub image_url: String,
+    /// Soft-deleted items stay in the sheet for history, but are hidden everywhere.
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MalformedItem {
+    EmptyName,
+}
+
+impl Display for MalformedItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MalformedItem::EmptyName => f.write_str("the name is empty"),
+        }
+    }
+}
+
+impl Item {
+    /// Checks the fields every article and invoice of the item relies on. A broken
+    /// image isn't one of them, the item is shown without it, see [`thumb_url`].
+    ///
+    /// [`thumb_url`]: crate::inline::thumb_url
+    pub fn check_renderable(&self) -> Result<(), MalformedItem> {
+        if self.name.trim().is_empty() {
+            return Err(MalformedItem::EmptyName);
+        }
+
+        Ok(())
+    }
 }
 
 impl Searchable for Item {
@@ -156,11 +382,13 @@ impl Searchable for Item {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, FromPrimitive)]
 pub enum ProductVisibility {
     All,
     Personal,
     Merchants,
+    /// Being prepared by the merchant, only they see it until it's published.
+    Draft,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -184,6 +412,31 @@ pub struct UserMeta {
     pub pending_orders: Vec<String>,
     #[serde(with = "serde_fn::list")]
     pub completed_orders: Vec<String>,
+    /// Default delivery note, e.g. an address, offered to attach to new orders.
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+impl UserMeta {
+    /// Saves the note, a blank one or `-` clears it.
+    pub fn set_note(&mut self, note: &str) {
+        self.note = match note.trim() {
+            "" | "-" => None,
+            note => Some(note.to_owned()),
+        };
+    }
+
+    /// Moves the order from pending to completed. Returns `false` when the
+    /// order isn't pending.
+    pub fn close_order(&mut self, order_id: &OrderId) -> bool {
+        let Some(pos) = self.pending_orders.iter().position(|id| id == order_id) else {
+            return false;
+        };
+
+        let order_id = self.pending_orders.swap_remove(pos);
+        self.completed_orders.push(order_id);
+        true
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
@@ -203,11 +456,111 @@ impl Role {
     }
 }
 
+/// Cell value which is never printed, e.g. by `/dump` or in the logs.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct Secret(pub String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Shown in place of a secret.
+pub const REDACTED: &str = "<redacted>";
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Merchant {
     pub name: String,
     pub location: String,
     pub address: String,
+    /// Payment provider token for the merchant's invoices, the global one is used if empty.
+    #[serde(default)]
+    pub provider_token: Option<Secret>,
+    /// Comma separated currency codes the merchant's provider takes, any when empty.
+    #[serde(default)]
+    pub provider_currencies: Option<String>,
+    /// Sales are paused, the products are hidden from everyone but the merchant.
+    #[serde(default)]
+    pub vacation: bool,
+    /// Smallest total the merchant takes orders for, in `min_order_currency`.
+    #[serde(default)]
+    pub min_order_value: Option<f64>,
+    /// Currency of the minimum order value, the currency of the order when empty.
+    #[serde(default)]
+    pub min_order_currency: Option<Currency>,
+    /// Shop name shown to customers, the username stays the key.
+    #[serde(default)]
+    pub display_name: Option<String>,
+}
+
+impl Merchant {
+    pub fn hides_products_from(&self, user: &User) -> bool {
+        self.vacation && self.name != user.name
+    }
+
+    /// Name the merchant is shown by, the username when no shop name is set.
+    pub fn display_name(&self) -> &str {
+        self.display_name
+            .as_deref()
+            .filter(|name| !name.trim().is_empty())
+            .unwrap_or(&self.name)
+    }
+
+    /// How customers are pointed to the merchant, the shop name along with the
+    /// username to write to.
+    pub fn contact(&self) -> String {
+        match self.display_name() {
+            name if name == self.name => format!("@{}", self.name),
+            name => format!("{} (@{})", name, self.name),
+        }
+    }
+
+    /// Currencies the merchant's own provider takes, None when any may be tried.
+    /// Unknown codes are skipped, Telegram wouldn't take them anyway.
+    pub fn provider_currencies(&self) -> Option<Vec<Currency>> {
+        let currencies: Vec<_> = self
+            .provider_currencies
+            .as_deref()?
+            .split(',')
+            .filter_map(|code| Currency::parse(code).ok())
+            .collect();
+
+        (!currencies.is_empty()).then_some(currencies)
+    }
+
+    /// Minimum order value converted into the currency of an order, None when
+    /// there is no minimum or no rate to convert it with.
+    pub fn min_order_value_in(
+        &self,
+        currency: &Currency,
+        rates: Option<&ExchangeRates>,
+    ) -> Option<f64> {
+        let min = self.min_order_value?;
+
+        match self.min_order_currency {
+            Some(from) if from != *currency => rates?.exchange(min, &from, currency),
+            _ => Some(min),
+        }
+    }
+
+    /// Whether an order of that total is worth processing for the merchant.
+    pub fn accepts_order_value(
+        &self,
+        total: f64,
+        currency: &Currency,
+        rates: Option<&ExchangeRates>,
+    ) -> bool {
+        self.min_order_value_in(currency, rates)
+            .map_or(true, |min| round_amount(total) >= round_amount(min))
+    }
 }
 
 impl Searchable for Merchant {
@@ -232,15 +585,32 @@ pub struct Sale {
     pub customer: String,
     pub item_id: String,
     pub comment: String,
-    pub amount: u32,
+    pub amount: f64,
+    #[serde(with = "serde_fn::money")]
     pub revenue: f64,
     pub currency: Currency,
     pub share: f32,
     #[serde(with = "serde_fn::datetime")]
     pub date: DateTime<Utc>,
+    /// Revenue in the base currency at the rate of the sale day, so later
+    /// reports don't depend on the current rates.
+    #[serde(default)]
+    pub base_amount: Option<f64>,
+    /// Base currency `base_amount` is in, it's stale once another one is configured.
+    #[serde(default)]
+    pub base_currency: Option<Currency>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+impl Sale {
+    /// Converts the revenue at the current rates, if there are any.
+    pub fn pin_base_amount(&mut self, rates: Option<&ExchangeRates>) {
+        (self.base_amount, self.base_currency) = rates
+            .and_then(|rates| rates.pin(self.revenue, &self.currency))
+            .unzip();
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, FromPrimitive)]
 pub enum SaleType {
     #[serde(rename = "Hand-to-hand")]
     HandToHand,
@@ -253,7 +623,8 @@ pub struct Replenishment {
     pub supplier: String,
     pub merchant: String,
     pub item_id: String,
-    pub amount: u32,
+    pub amount: f64,
+    #[serde(with = "serde_fn::money")]
     pub cost_price: f64,
     pub currency: Currency,
     #[serde(with = "serde_fn::datetime")]
@@ -264,7 +635,8 @@ pub struct Replenishment {
 pub struct Writeoff {
     pub merchant: String,
     pub item_id: String,
-    pub amount: u32,
+    pub amount: f64,
+    #[serde(with = "serde_fn::money")]
     pub price: f64,
     pub currency: Currency,
     pub reason: String,
@@ -272,6 +644,26 @@ pub struct Writeoff {
     pub date: DateTime<Utc>,
 }
 
+/// Hits of a command since the previous flush.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UsageRecord {
+    #[serde(with = "serde_fn::datetime")]
+    pub date: DateTime<Utc>,
+    pub command: String,
+    pub count: u64,
+}
+
+/// Problem with a listing flagged by a customer, e.g. a wrong price.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProductReport {
+    #[serde(with = "serde_fn::datetime")]
+    pub date: DateTime<Utc>,
+    pub reporter: String,
+    pub merchant: String,
+    pub item_id: String,
+    pub reason: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Order {
     pub id: OrderId,
@@ -279,11 +671,25 @@ pub struct Order {
     pub merchant: String,
     pub stage: OrderStage,
     pub item_id: String,
-    pub amount: u32,
+    #[serde(with = "serde_fn::qty")]
+    pub amount: f64,
+    #[serde(with = "serde_fn::money")]
     pub cost: f64,
     pub currency: Currency,
     #[serde(with = "serde_fn::datetime")]
     pub date: DateTime<Utc>,
+    /// Delivery note the customer attached to the order.
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Set once on completion, a redelivered completion finds it and does nothing.
+    #[serde(default, with = "serde_fn::datetime_opt")]
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Cost in the base currency at the rate of the day it was set.
+    #[serde(default)]
+    pub base_amount: Option<f64>,
+    /// Base currency `base_amount` is in.
+    #[serde(default)]
+    pub base_currency: Option<Currency>,
 }
 
 impl Order {
@@ -291,7 +697,55 @@ impl Order {
         Product::id_from(&self.merchant, &self.item_id)
     }
 
-    pub fn into_sale(self, share: f32) -> Sale {
+    /// Open orders still hold their amount of the product.
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self.stage,
+            OrderStage::WaitForPayment | OrderStage::Negotiated
+        )
+    }
+
+    /// Checks an invoice with the given currency and total (in minor units)
+    /// is the one issued for the order.
+    pub fn matches_invoice(&self, currency: &Currency, total_amount: i32) -> bool {
+        self.currency == *currency && self.currency.to_minor(self.cost) == total_amount
+    }
+
+    /// Moves the order into the paid stage. Returns `false` when the order
+    /// wasn't waiting for a payment, so it's left untouched.
+    pub fn mark_paid(&mut self) -> bool {
+        if self.stage != OrderStage::WaitForPayment {
+            return false;
+        }
+
+        self.stage = OrderStage::Paid;
+        true
+    }
+
+    /// Moves the order into the completed stage and records the time. Returns
+    /// `false` when the order is already completed or cancelled.
+    pub fn mark_completed(&mut self, now: DateTime<Utc>) -> bool {
+        if self.completed_at.is_some()
+            || matches!(self.stage, OrderStage::Completed | OrderStage::Cancelled)
+        {
+            return false;
+        }
+
+        self.stage = OrderStage::Completed;
+        self.completed_at = Some(now);
+        true
+    }
+
+    /// Converts the cost at the current rates, unless it is still to be negotiated.
+    pub fn pin_base_amount(&mut self, rates: Option<&ExchangeRates>) {
+        (self.base_amount, self.base_currency) = match self.stage {
+            OrderStage::Negotiated => None,
+            _ => rates.and_then(|rates| rates.pin(self.cost, &self.currency)),
+        }
+        .unzip();
+    }
+
+    pub fn into_sale(self, share: f32, now: DateTime<Utc>) -> Sale {
         Sale {
             merchant: self.merchant,
             sale_type: SaleType::Order,
@@ -302,7 +756,9 @@ impl Order {
             revenue: self.cost,
             currency: self.currency,
             share,
-            date: Utc::now(),
+            date: now,
+            base_amount: self.base_amount,
+            base_currency: self.base_currency,
         }
     }
 }
@@ -322,8 +778,9 @@ impl Searchable for Order {
                 ..
             } => {
                 let q = format!(
-                    "id {id} by {customer} for {merchant} in {stage:?} x{amount} paid {paid} at {date}",
-                    paid = currency.format(&paid.to_string()),
+                    "id {id} by {customer} for {merchant} in {stage} x{amount} paid {paid} at {date}",
+                    stage = stage.label(),
+                    paid = currency.format_amount(*paid),
                     date = date.format("%Y-%m-%d %H:%M:%S").to_string()
                 )
                 .to_lowercase();
@@ -335,7 +792,7 @@ impl Searchable for Order {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, FromPrimitive)]
 pub enum OrderStage {
     Paid,
     Negotiated,
@@ -347,7 +804,7 @@ pub enum OrderStage {
 
 pub type OrderId = String;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Localization {
     pub key_phrase: String,
     pub en: String,
@@ -355,6 +812,8 @@ pub struct Localization {
 }
 
 impl Localization {
+    /// Text in the language, "-" marks a missing translation. The key phrase is
+    /// the last resort, its placeholders are filled like the ones of a translation.
     pub fn get(&self, lang: &str) -> String {
         info!("get localization for {} in {}", self.key_phrase, lang);
         match lang {
@@ -364,3 +823,393 @@ impl Localization {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::fixtures;
+
+    pub fn order(stage: OrderStage) -> Order {
+        Order {
+            stage,
+            amount: 2.0,
+            cost: 10.29,
+            ..fixtures::order()
+        }
+    }
+
+    #[test]
+    fn soft_deleted_product_is_hidden_until_restored() {
+        let customer = fixtures::user();
+        let mut product = Product {
+            deleted: true,
+            ..fixtures::product()
+        };
+
+        assert!(!product.is_visible_to(&customer));
+
+        product.deleted = false;
+        assert!(product.is_visible_to(&customer));
+    }
+
+    #[test]
+    fn draft_visible_to_owner_until_published() {
+        let user = |name: &str, role: Role| User {
+            name: name.to_owned(),
+            role,
+            ..fixtures::user()
+        };
+        let owner = user("merchant", Role::Merchant);
+        let merchant = user("other", Role::Merchant);
+        let customer = user("customer", Role::User);
+
+        let mut product = Product {
+            visibility: ProductVisibility::Draft,
+            ..fixtures::product()
+        };
+
+        assert!(product.is_visible_to(&owner));
+        assert!(!product.is_visible_to(&merchant));
+        assert!(!product.is_visible_to(&customer));
+
+        assert!(product.publish());
+        assert!(product.is_visible_to(&merchant));
+        assert!(product.is_visible_to(&customer));
+
+        // Already live
+        assert!(!product.publish());
+    }
+
+    #[test]
+    fn paused_product_keeps_stock_and_owner_view() {
+        let user = |name: &str, role| User {
+            name: name.to_owned(),
+            role,
+            ..fixtures::user()
+        };
+        let owner = user("merchant", Role::Merchant);
+        let customer = user("customer", Role::User);
+
+        let mut product = Product {
+            amount_granted: 5.0,
+            amount_sold: 2.0,
+            amount_left: 3.0,
+            ..fixtures::product()
+        };
+
+        assert!(!product.toggle_active());
+        assert!(!product.is_visible_to(&customer));
+        assert!(product.is_visible_to(&owner));
+        assert_eq!(
+            (
+                product.amount_granted,
+                product.amount_sold,
+                product.amount_left
+            ),
+            (5.0, 2.0, 3.0)
+        );
+
+        assert!(product.toggle_active());
+        assert!(product.is_visible_to(&customer));
+    }
+
+    #[test]
+    fn unnamed_items_are_not_renderable() {
+        let item = Item {
+            id: "hat".to_owned(),
+            name: "Hat".to_owned(),
+            inline_desc: "".to_owned(),
+            full_desc: "".to_owned(),
+            image_url: "https://example.com/hat.png".to_owned(),
+            deleted: false,
+        };
+        assert_eq!(item.check_renderable(), Ok(()));
+
+        let unnamed = Item {
+            name: "  ".to_owned(),
+            ..item.clone()
+        };
+        assert_eq!(unnamed.check_renderable(), Err(MalformedItem::EmptyName));
+
+        // Shown without the image instead
+        let broken_image = Item {
+            image_url: "hat.png".to_owned(),
+            ..item
+        };
+        assert_eq!(broken_image.check_renderable(), Ok(()));
+    }
+
+    #[test]
+    fn vacation_hides_products_from_customers() {
+        let user = |name: &str, role| User {
+            name: name.to_owned(),
+            role,
+            ..fixtures::user()
+        };
+        let owner = user("merchant", Role::Merchant);
+        let customer = user("customer", Role::User);
+
+        let mut merchant = Merchant {
+            address: "-".to_owned(),
+            ..fixtures::merchant()
+        };
+        let product = Product {
+            item_id: "hat".to_owned(),
+            ..fixtures::product()
+        };
+        let listed = |merchant: &Merchant, user: &User| {
+            product.is_visible_to(user) && !merchant.hides_products_from(user)
+        };
+
+        assert!(listed(&merchant, &customer));
+
+        merchant.vacation = true;
+        assert!(!listed(&merchant, &customer));
+        assert!(listed(&merchant, &owner));
+
+        merchant.vacation = false;
+        assert!(listed(&merchant, &customer));
+    }
+
+    #[test]
+    fn profile_note_set_and_cleared() {
+        let mut meta = UserMeta {
+            chat_id: None,
+            ..fixtures::user_meta()
+        };
+
+        meta.set_note("  Baker Street 221b, ring twice ");
+        assert_eq!(meta.note.as_deref(), Some("Baker Street 221b, ring twice"));
+
+        meta.set_note("-");
+        assert_eq!(meta.note, None);
+        meta.set_note("Home");
+        meta.set_note("   ");
+        assert_eq!(meta.note, None);
+    }
+
+    #[test]
+    fn order_matches_invoice() {
+        let order = order(OrderStage::WaitForPayment);
+        assert!(order.matches_invoice(&Currency::EUR, 1029));
+        assert!(!order.matches_invoice(&Currency::EUR, 1028));
+        assert!(!order.matches_invoice(&Currency::USD, 1029));
+    }
+
+    #[test]
+    fn order_mark_paid() {
+        let mut waiting = order(OrderStage::WaitForPayment);
+        assert!(waiting.mark_paid());
+        assert_eq!(waiting.stage, OrderStage::Paid);
+
+        // A redelivered payment must not touch the order again
+        assert!(!waiting.mark_paid());
+        assert_eq!(waiting.stage, OrderStage::Paid);
+
+        for stage in [
+            OrderStage::Negotiated,
+            OrderStage::Completed,
+            OrderStage::Cancelled,
+        ] {
+            let mut order = order(stage.clone());
+            assert!(!order.mark_paid());
+            assert_eq!(order.stage, stage);
+        }
+    }
+
+    #[test]
+    fn inconsistent_amounts_are_flagged_or_clamped() {
+        let inconsistent = Product {
+            amount_granted: 10.0,
+            amount_sold: 4.0,
+            amount_left: 8.0,
+            ..fixtures::product()
+        };
+
+        let mut flagged = inconsistent.clone();
+        assert_eq!(flagged.validate(false).len(), 1);
+        assert_eq!((flagged.amount_sold, flagged.amount_left), (4.0, 8.0));
+
+        let mut clamped = inconsistent.clone();
+        assert_eq!(clamped.validate(true).len(), 1);
+        assert_eq!((clamped.amount_sold, clamped.amount_left), (4.0, 6.0));
+        assert!(clamped.validate(false).is_empty());
+
+        let mut oversold = Product {
+            amount_sold: 12.0,
+            amount_left: 1.0,
+            ..inconsistent
+        };
+        assert_eq!(oversold.validate(true).len(), 2);
+        assert_eq!((oversold.amount_sold, oversold.amount_left), (10.0, 0.0));
+    }
+
+    #[test]
+    fn new_products_get_default_share() {
+        assert_eq!(Product::new_share(None, 0.15), Some(0.15));
+        assert_eq!(Product::new_share(Some(0.3), 0.15), Some(0.3));
+        assert_eq!(Product::new_share(Some(0.0), 0.15), Some(0.0));
+
+        assert_eq!(Product::new_share(Some(1.5), 0.15), None);
+        assert_eq!(Product::new_share(Some(-0.1), 0.15), None);
+        assert_eq!(Product::new_share(None, 15.0), None);
+    }
+
+    #[test]
+    fn flags_and_clamps_share_out_of_range() {
+        let mut product = Product {
+            share: 15.0,
+            ..fixtures::product()
+        };
+
+        assert_eq!(product.validate(true).len(), 1);
+        assert_eq!(product.share, 1.0);
+        assert!(product.validate(false).is_empty());
+    }
+
+    #[test]
+    fn only_weight_amounts_are_fractional() {
+        assert_eq!(Unit::Weight.parse_amount("0.5"), Some(0.5));
+        assert_eq!(Unit::Weight.parse_amount(" 1,25 "), Some(1.25));
+        assert_eq!(Unit::Weight.parse_amount("2"), Some(2.0));
+        assert_eq!(Unit::Weight.parse_amount("-1"), None);
+        assert_eq!(Unit::Weight.parse_amount("NaN"), None);
+
+        assert_eq!(Unit::Each.parse_amount("3"), Some(3.0));
+        assert_eq!(Unit::Each.parse_amount("0.5"), None);
+    }
+
+    #[test]
+    fn weight_stock_decrements_without_float_error() {
+        let mut product = Product {
+            item_id: "cheese".to_owned(),
+            price: 20.0,
+            unit: Unit::Weight,
+            ..fixtures::product()
+        };
+
+        for amount in [0.3, 0.6] {
+            assert!(product.take(amount));
+            product.count_sold(amount);
+        }
+        assert_eq!((product.amount_left, product.amount_sold), (0.1, 0.9));
+
+        assert!(!product.take(0.25));
+        assert_eq!(product.amount_left, 0.1);
+        assert!(product.validate(false).is_empty());
+    }
+
+    fn backorderable(amount_left: f64, allowance: f64) -> Product {
+        Product {
+            item_id: "hat".to_owned(),
+            price: 10.0,
+            amount_granted: amount_left,
+            amount_left,
+            oversell_allowance: allowance,
+            ..fixtures::product()
+        }
+    }
+
+    #[test]
+    fn sells_within_oversell_allowance() {
+        let mut product = backorderable(2.0, 2.0);
+
+        assert!(product.can_sell(4.0));
+        assert!(product.take_for_sale(3.0));
+        product.count_sold(3.0);
+        assert_eq!((product.amount_left, product.backordered), (0.0, 1.0));
+        assert!(product.is_backordered());
+        assert!(product.validate(false).is_empty());
+
+        // The backorder uses up the allowance
+        assert!(product.take_for_sale(1.0));
+        product.count_sold(1.0);
+        assert_eq!((product.amount_left, product.backordered), (0.0, 2.0));
+        assert!(!product.can_sell(1.0));
+    }
+
+    #[test]
+    fn rejects_beyond_oversell_allowance() {
+        let mut product = backorderable(2.0, 1.0);
+
+        assert!(!product.take_for_sale(4.0));
+        assert_eq!((product.amount_left, product.backordered), (2.0, 0.0));
+        assert!(!product.is_backordered());
+
+        // No allowance, no backorders
+        let mut strict = backorderable(2.0, 0.0);
+        assert!(!strict.take_for_sale(3.0));
+        assert!(strict.take_for_sale(2.0));
+        assert!(!strict.is_backordered());
+    }
+
+    #[test]
+    fn delivery_settles_backorders_first() {
+        let mut product = backorderable(1.0, 3.0);
+        assert!(product.take_for_sale(3.0));
+        product.count_sold(3.0);
+
+        product.grant(5.0);
+        assert_eq!((product.amount_left, product.backordered), (3.0, 0.0));
+        assert_eq!(product.amount_granted, 6.0);
+        assert!(product.validate(false).is_empty());
+    }
+
+    #[test]
+    fn enforces_min_order_value() {
+        let mut merchant = Merchant {
+            address: "-".to_owned(),
+            ..fixtures::merchant()
+        };
+        assert!(merchant.accepts_order_value(0.0, &Currency::EUR, None));
+
+        merchant.min_order_value = Some(9.9);
+        assert!(merchant.accepts_order_value(9.9, &Currency::EUR, None));
+        assert!(merchant.accepts_order_value(3.3 * 3.0, &Currency::EUR, None));
+        assert!(merchant.accepts_order_value(10.0, &Currency::EUR, None));
+        assert!(!merchant.accepts_order_value(9.89, &Currency::EUR, None));
+    }
+
+    #[test]
+    fn min_order_value_is_converted() {
+        let rates = std::collections::HashMap::from([("CZK".to_owned(), 0.04)]);
+        let rates = ExchangeRates::new("EUR", &rates).unwrap();
+        let merchant = Merchant {
+            address: "-".to_owned(),
+            min_order_value: Some(10.0),
+            min_order_currency: Some(Currency::EUR),
+            ..fixtures::merchant()
+        };
+
+        assert_eq!(
+            merchant.min_order_value_in(&Currency::CZK, Some(&rates)),
+            Some(250.0)
+        );
+        assert!(merchant.accepts_order_value(250.0, &Currency::CZK, Some(&rates)));
+        assert!(!merchant.accepts_order_value(249.0, &Currency::CZK, Some(&rates)));
+        assert!(!merchant.accepts_order_value(9.0, &Currency::EUR, None));
+
+        // Without a rate the minimum can't be told
+        assert_eq!(
+            merchant.min_order_value_in(&Currency::USD, Some(&rates)),
+            None
+        );
+        assert!(merchant.accepts_order_value(1.0, &Currency::CZK, None));
+    }
+
+    #[test]
+    fn provider_token_is_not_printed() {
+        let merchant = Merchant {
+            address: "-".to_owned(),
+            provider_token: Some(Secret("284685063:TEST:token".to_owned())),
+            ..fixtures::merchant()
+        };
+
+        let printed = format!("{:?}", merchant);
+        assert!(!printed.contains("284685063"));
+        assert!(printed.contains("provider_token: Some(<redacted>)"));
+
+        let json = serde_json::to_value(&merchant).unwrap();
+        assert_eq!(json["provider_token"], "284685063:TEST:token");
+    }
+}
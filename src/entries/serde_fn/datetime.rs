@@ -1,6 +1,8 @@
 use chrono::{DateTime, TimeZone, Utc};
 use serde::{de, Deserialize, Deserializer, Serializer};
 
+pub const FORMAT: &str = "%d.%m.%Y %-H:%M:%S";
+
 macro_rules! de_error {
     ($($arg:tt)*) => {
         de::Error::custom(format!($($arg)*))
@@ -11,12 +13,12 @@ pub fn serialize<S: Serializer>(
     datetime: &DateTime<Utc>,
     serializer: S,
 ) -> Result<S::Ok, S::Error> {
-    serializer.serialize_str(datetime.format("%d.%m.%Y %-H:%M:%S").to_string().as_str())
+    serializer.serialize_str(datetime.format(FORMAT).to_string().as_str())
 }
 
 pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
     let string = String::deserialize(deserializer)?;
 
-    Utc.datetime_from_str(&string, "%d.%m.%Y %-H:%M:%S")
+    Utc.datetime_from_str(&string, FORMAT)
         .map_err(|_| de_error!("unable to parse the datetime \"{}\"", string))
 }
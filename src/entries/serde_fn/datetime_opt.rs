@@ -0,0 +1,29 @@
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{de, Deserialize, Deserializer, Serializer};
+
+use super::datetime::{self, FORMAT};
+
+/// Same format as [`datetime`], an empty cell is `None`.
+pub fn serialize<S: Serializer>(
+    value: &Option<DateTime<Utc>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => datetime::serialize(value, serializer),
+        None => serializer.serialize_str(""),
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DateTime<Utc>>, D::Error> {
+    let string = String::deserialize(deserializer)?;
+
+    match string.trim() {
+        "" | "-" => Ok(None),
+        trimmed => Utc
+            .datetime_from_str(trimmed, FORMAT)
+            .map(Some)
+            .map_err(|_| de::Error::custom(format!("unable to parse the datetime \"{}\"", string))),
+    }
+}
@@ -1,3 +1,6 @@
 pub mod chat_id;
 pub mod datetime;
+pub mod datetime_opt;
 pub mod list;
+pub mod money;
+pub mod qty;
@@ -0,0 +1,10 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tables::google_sheets::serde_impl::Qty;
+
+pub fn serialize<S: Serializer>(amount: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    Qty(*amount).serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    f64::deserialize(deserializer)
+}
@@ -0,0 +1,113 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+/// Small LRU of recent inline answers, so an identical query repeated
+/// within the TTL is answered without touching the tables.
+pub struct InlineCache<K, V> {
+    ttl: Duration,
+    capacity: usize,
+    entries: HashMap<K, (Instant, V)>,
+    // Least recently used key goes first
+    order: VecDeque<K>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> InlineCache<K, V> {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub fn get(&mut self, key: &K, now: Instant) -> Option<V> {
+        let (inserted, value) = self.entries.get(key)?;
+
+        if now.duration_since(*inserted) > self.ttl {
+            self.remove(key);
+            return None;
+        }
+
+        let value = value.clone();
+        self.touch(key);
+        Some(value)
+    }
+
+    pub fn insert(&mut self, key: K, value: V, now: Instant) {
+        if self.capacity == 0 || self.ttl.is_zero() {
+            return;
+        }
+
+        if self.entries.insert(key.clone(), (now, value)).is_some() {
+            self.touch(&key);
+            return;
+        }
+
+        self.order.push_back(key);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    fn touch(&mut self, key: &K) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            let key = self.order.remove(pos).unwrap();
+            self.order.push_back(key);
+        }
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.entries.remove(key);
+        self.order.retain(|k| k != key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(user: &str, query: &str) -> (String, String) {
+        (user.to_owned(), query.to_owned())
+    }
+
+    #[test]
+    fn identical_repeat_within_ttl_is_cached() {
+        let now = Instant::now();
+        let mut cache = InlineCache::new(Duration::from_secs(5), 8);
+
+        cache.insert(key("alice", "hat"), vec!["Red hat"], now);
+
+        let later = now + Duration::from_secs(3);
+        assert_eq!(
+            cache.get(&key("alice", "hat"), later),
+            Some(vec!["Red hat"])
+        );
+        // Different query or another user bypass the cache
+        assert_eq!(cache.get(&key("alice", "ha"), later), None);
+        assert_eq!(cache.get(&key("bob", "hat"), later), None);
+
+        let expired = now + Duration::from_secs(6);
+        assert_eq!(cache.get(&key("alice", "hat"), expired), None);
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted() {
+        let now = Instant::now();
+        let mut cache = InlineCache::new(Duration::from_secs(5), 2);
+
+        cache.insert(key("alice", "a"), 1, now);
+        cache.insert(key("alice", "b"), 2, now);
+        assert_eq!(cache.get(&key("alice", "a"), now), Some(1));
+
+        cache.insert(key("alice", "c"), 3, now);
+        assert_eq!(cache.get(&key("alice", "b"), now), None);
+        assert_eq!(cache.get(&key("alice", "a"), now), Some(1));
+        assert_eq!(cache.get(&key("alice", "c"), now), Some(3));
+    }
+}
@@ -6,12 +6,15 @@ use teloxide::types::{
 };
 
 use crate::entries::{CurrencyExt, Item, Product};
+use crate::utils::text::truncate_words;
 use crate::Result;
 
 use super::InlineRequest;
 
 impl<'a> InlineRequest<'a> {
     pub async fn make_items(&mut self) -> Result<()> {
+        let paused = self.warehouse.paused_merchants(self.user);
+
         let mut pairs: Vec<_> = self
             .warehouse
             .products
@@ -19,9 +22,11 @@ impl<'a> InlineRequest<'a> {
             .all()
             // Filter out invisible & out of stock items
             .filter(|(_, products)| {
-                products
-                    .iter()
-                    .any(|(_, p)| p.is_visible_to(self.user) && p.amount_left > 0)
+                products.iter().any(|(_, p)| {
+                    p.is_visible_to(self.user)
+                        && !paused.contains(&p.merchant)
+                        && p.amount_left > 0.0
+                })
             })
             // Map item to iterator
             .filter_map(|(item_id, products)| {
@@ -29,6 +34,7 @@ impl<'a> InlineRequest<'a> {
                     .items
                     .by_id
                     .get(item_id)
+                    .filter(|item| !item.deleted && item.check_renderable().is_ok())
                     .map(|item| (item.clone(), products.clone()))
             })
             .skip(self.page * 49)
@@ -47,10 +53,8 @@ impl<'a> InlineRequest<'a> {
 
         self.process_results(&mut results).await;
 
-        self.bot
-            .answer_inline_query(&self.q.id, results)
-            .cache_time(60)
-            .await?;
+        let cache_time = self.warehouse.listing_cache_time();
+        self.answer(results, cache_time).await?;
 
         Ok(())
     }
@@ -73,15 +77,16 @@ impl<'a> InlineRequest<'a> {
             return self.make_product_article(&merchant, product, item).await;
         }
 
-        Ok(InlineQueryResultArticle::new(
+        let article = InlineQueryResultArticle::new(
             format!("i{}", item.id),
-            localize!(self.warehouse, &self.lang_code, item.name),
+            localize_static!(self.warehouse, &self.lang_code, item.name),
             self.make_item_content(item, products).await,
         )
         .description(self.make_item_description(item, products).await)
         .reply_markup(self.make_item_markup(item).await)
-        .hide_url(true)
-        .thumb_url(item.image_url.clone().parse()?))
+        .hide_url(true);
+
+        Ok(self.with_thumb(article, &item.image_url))
     }
 
     async fn make_item_content(
@@ -107,9 +112,9 @@ impl<'a> InlineRequest<'a> {
         let description = [
             format!(
                 "<b>{}</b>",
-                localize!(self.warehouse, &self.lang_code, item.name)
+                localize_static!(self.warehouse, &self.lang_code, item.name)
             ),
-            localize!(self.warehouse, &self.lang_code, item.full_desc.clone()),
+            localize_static!(self.warehouse, &self.lang_code, item.full_desc),
         ]
         .join("\n");
 
@@ -131,20 +136,26 @@ impl<'a> InlineRequest<'a> {
         item: &Item,
         products: &Vec<(usize, Product)>,
     ) -> String {
-        // Assign with an inline description
-        let description = localize!(self.warehouse, &self.lang_code, item.inline_desc.clone());
+        // Assign with an inline description, Telegram cuts long ones unpredictably
+        let description = truncate_words(
+            &localize_static!(self.warehouse, &self.lang_code, item.inline_desc),
+            self.warehouse.inline_description_limit,
+        );
         let mut info = vec![];
 
         // Add price
         if let Some(product) = Self::choose_best_product(products) {
-            let price = product.currency.format(&product.price.to_string());
+            let price = product.currency.format_amount(product.price);
             info.push(format!("{}", price));
         }
 
         // Add seller count
+        let paused = self.warehouse.paused_merchants(self.user);
         let product_count = products
             .iter()
-            .filter(|(_, p)| p.is_visible_to(self.user) && p.amount_left > 0)
+            .filter(|(_, p)| {
+                p.is_visible_to(self.user) && !paused.contains(&p.merchant) && p.amount_left > 0.0
+            })
             .count();
 
         let ending = if product_count == 1 { "" } else { "s" };
@@ -177,8 +188,8 @@ impl<'a> InlineRequest<'a> {
             return None;
         }
 
-        let min_price = products[min_idx].1.currency.format(&min.to_string());
-        let max_price = products[max_idx].1.currency.format(&max.to_string());
+        let min_price = products[min_idx].1.currency.format_amount(min);
+        let max_price = products[max_idx].1.currency.format_amount(max);
 
         Some((min_price, max_price))
     }
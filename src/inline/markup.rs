@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::prelude::*;
+use crate::utils::payload::Payload;
+
+use super::InlineRequest;
+
+/// Callback button which is only shown when its condition holds.
+pub struct Action {
+    pub label_key: &'static str,
+    pub payload: Payload,
+    pub condition: bool,
+}
+
+impl Action {
+    pub fn new(label_key: &'static str, payload: Payload, condition: bool) -> Self {
+        Self {
+            label_key,
+            payload,
+            condition,
+        }
+    }
+}
+
+/// Rows of conditional buttons, rows without a single shown button are dropped.
+#[derive(Default)]
+pub struct Actions {
+    rows: Vec<Vec<Action>>,
+}
+
+impl Actions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a row of a single button.
+    pub fn button(self, label_key: &'static str, payload: Payload, condition: bool) -> Self {
+        self.row(vec![Action::new(label_key, payload, condition)])
+    }
+
+    pub fn row(mut self, actions: Vec<Action>) -> Self {
+        let actions: Vec<_> = actions.into_iter().filter(|a| a.condition).collect();
+        if !actions.is_empty() {
+            self.rows.push(actions);
+        }
+        self
+    }
+
+    /// Label keys of the shown buttons.
+    pub fn label_keys(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.rows.iter().flatten().map(|action| action.label_key)
+    }
+
+    pub fn build(self, localize: impl Fn(&str) -> String) -> InlineKeyboardMarkup {
+        self.rows
+            .into_iter()
+            .fold(InlineKeyboardMarkup::default(), |markup, row| {
+                markup.append_row(
+                    row.into_iter()
+                        .map(|action| {
+                            InlineKeyboardButton::callback(
+                                localize(action.label_key),
+                                action.payload.to_string(),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+    }
+}
+
+impl<'a> InlineRequest<'a> {
+    /// Builds the markup with the labels in the language of the request.
+    pub async fn localize_actions(&mut self, actions: Actions) -> InlineKeyboardMarkup {
+        let mut labels = HashMap::new();
+        for key in actions.label_keys() {
+            labels.insert(key, localize!(self.warehouse, &self.lang_code, key));
+        }
+
+        actions.build(|key| labels.get(key).cloned().unwrap_or_else(|| key.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::types::InlineKeyboardButtonKind;
+
+    use super::*;
+
+    fn rows(markup: &InlineKeyboardMarkup) -> Vec<Vec<(String, String)>> {
+        markup
+            .inline_keyboard
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|button| match &button.kind {
+                        InlineKeyboardButtonKind::CallbackData(data) => {
+                            (button.text.clone(), data.clone())
+                        }
+                        _ => unreachable!(),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn emits_enabled_buttons_in_order() {
+        let order_id = "order".to_owned();
+        let markup = Actions::new()
+            .row(vec![
+                Action::new(
+                    "Pay with card",
+                    Payload::pay_for_order(order_id.clone()),
+                    false,
+                ),
+                Action::new("Cancel", Payload::cancel_order(order_id.clone()), true),
+                Action::new("Complete", Payload::complete_order(order_id.clone()), true),
+            ])
+            .button("Publish", Payload::publish(1), true)
+            .build(|key| key.to_uppercase());
+
+        assert_eq!(
+            rows(&markup),
+            vec![
+                vec![
+                    (
+                        "CANCEL".to_owned(),
+                        Payload::cancel_order(order_id.clone()).to_string()
+                    ),
+                    (
+                        "COMPLETE".to_owned(),
+                        Payload::complete_order(order_id).to_string()
+                    ),
+                ],
+                vec![("PUBLISH".to_owned(), Payload::publish(1).to_string())],
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_empty_rows() {
+        let actions = Actions::new()
+            .row(vec![Action::new(
+                "Cancel",
+                Payload::cancel_order("order".to_owned()),
+                false,
+            )])
+            .button("Publish", Payload::publish(1), false);
+
+        assert_eq!(actions.label_keys().count(), 0);
+        assert!(actions
+            .build(|key| key.to_owned())
+            .inline_keyboard
+            .is_empty());
+    }
+}
@@ -1,15 +1,21 @@
+pub mod cache;
 mod items;
+mod markup;
 mod orders;
 mod products;
 mod replenish_products;
 mod sell_products;
 
+use std::{fmt::Display, sync::Arc, time::Instant};
+
 use lazy_static::lazy_static;
 use regex::Regex;
 use teloxide::types::{InlineQueryResultArticle, InlineQueryResult, InputMessageContentText, InputMessageContent, InlineKeyboardMarkup, InlineKeyboardButton};
 use teloxide::{prelude::*, types::InlineQuery};
+use url::Url;
 
 use crate::prelude::*;
+use crate::usage::Usage;
 
 pub fn handler() -> HandlerResult {
     Update::filter_inline_query().endpoint(handle_inline_query)
@@ -18,9 +24,16 @@ pub fn handler() -> HandlerResult {
 pub async fn handle_inline_query(
     bot: Bot,
     q: InlineQuery,
-    warehouse: SharedWarehouse,
+    warehouse: LockedWarehouse,
+    usage: Arc<Usage>,
 ) -> Result<()> {
-    let mut warehouse = warehouse.write().await;
+    let mut warehouse = warehouse.lock().await;
+    let lang_code = q.from.language_code.clone().unwrap_or("en".to_string());
+
+    // Users are identified by their username, inline errors can't show alerts
+    if q.from.username.is_none() {
+        return answer_no_username(&bot, &q, &mut warehouse, &lang_code).await;
+    }
 
     let (user, _) = handle_user_from_inline(&mut warehouse, &q).await?;
 
@@ -28,42 +41,254 @@ pub async fn handle_inline_query(
         return Ok(());
     }
 
-    let lang_code = q.from.language_code.clone().unwrap_or("en".to_string());
+    let parsed = match parse_query(&q.query) {
+        Ok(parsed) => parsed,
+        Err(e) => return answer_query_error(&bot, &q, &mut warehouse, &lang_code, e).await,
+    };
 
-    let mut request = InlineRequest::new(bot.clone(), &q, &mut warehouse, &user, lang_code)?;
+    let cmd = InlineCommand::resolve(&parsed.cmd, &user);
+    usage.hit(&format!("inline {:?}", cmd));
 
-    match request.cmd.as_str() {
-        "" if request.query.is_empty() => request.make_items().await?,
-        ".o" => request.make_orders().await?,
-        "~sell" | "~woff" if user.role.is_at_least(Role::Merchant) => {
-            request.make_sells().await?
+    let key = (user.name.clone(), q.query.trim().to_owned());
+    if cmd.is_cached() {
+        if let Some((results, cache_time)) = warehouse.inline_cache.get(&key, Instant::now()) {
+            bot.answer_inline_query(&q.id, results)
+                .cache_time(cache_time)
+                .await?;
+            return Ok(());
         }
-        "~repl" if user.role.is_at_least(Role::Moderator) => {
-            request.make_replenish().await?
+    }
+
+    let mut request =
+        InlineRequest::new(bot.clone(), &q, &mut warehouse, &user, lang_code, parsed);
+
+    match request.cmd {
+        InlineCommand::Products if request.query.is_empty() => request.make_items().await?,
+        InlineCommand::Products => request.make_products().await?,
+        InlineCommand::Orders => request.make_orders().await?,
+        InlineCommand::Sell | InlineCommand::Writeoff => request.make_sells().await?,
+        InlineCommand::Replenish => request.make_replenish().await?,
+    };
+
+    Ok(())
+}
+
+async fn answer_query_error(
+    bot: &Bot,
+    q: &InlineQuery,
+    warehouse: &mut Warehouse,
+    lang_code: &str,
+    error: QueryError,
+) -> Result<()> {
+    let text = match &error {
+        QueryError::Malformed => localize!(warehouse, lang_code, "The query is malformed."),
+        QueryError::UnknownCommand(cmd) => {
+            localize!(warehouse, lang_code, "Unknown command \"{cmd}\".", "cmd" => cmd)
         }
-        _ => request.make_products().await?,
+        QueryError::PageOutOfRange(page) => localize!(warehouse, lang_code,
+            "There is no page #{page}, pages are numbered from #1 to #{max}.",
+            "page" => page,
+            "max" => MAX_PAGE
+        ),
     };
 
+    let article = info_article(
+        "query_error",
+        text,
+        localize!(warehouse, lang_code, "Check the query and try again."),
+    );
+
+    bot.answer_inline_query(&q.id, vec![article])
+        .cache_time(0)
+        .await?;
+
+    Ok(())
+}
+
+async fn answer_no_username(
+    bot: &Bot,
+    q: &InlineQuery,
+    warehouse: &mut Warehouse,
+    lang_code: &str,
+) -> Result<()> {
+    let article = info_article(
+        "no_username",
+        localize!(warehouse, lang_code, "Set a username to use the bot."),
+        localize!(
+            warehouse,
+            lang_code,
+            "Open Telegram settings, choose a username and try again."
+        ),
+    );
+
+    bot.answer_inline_query(&q.id, vec![article])
+        .cache_time(0)
+        .is_personal(true)
+        .await?;
+
     Ok(())
 }
 
+/// A single article explaining why the query has no regular results.
+pub fn info_article(id: &str, text: String, description: String) -> InlineQueryResult {
+    InlineQueryResult::Article(
+        InlineQueryResultArticle::new(
+            id,
+            text.clone(),
+            InputMessageContent::Text(InputMessageContentText::new(text)),
+        )
+        .description(description),
+    )
+}
+
 lazy_static! {
     static ref QUERY_RE: Regex =
-        Regex::new(r"^\s*((?<cmd>[.~]\w*))?(\s*#(?<page>[1-9][0-9]*))?(\s*(?<query>.*?))?\s*$")
+        Regex::new(r"^\s*((?<cmd>[.~]\w*))?(\s*#(?<page>[0-9]+))?(\s*(?<query>.*?))?\s*$")
             .unwrap();
 }
 
+/// Highest page accepted in a query, keeps the page offsets far from overflowing.
+pub const MAX_PAGE: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    Malformed,
+    UnknownCommand(String),
+    PageOutOfRange(String),
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Malformed => f.write_str("malformed query"),
+            Self::UnknownCommand(cmd) => write!(f, "unknown command {}", cmd),
+            Self::PageOutOfRange(page) => write!(f, "page #{} is out of range", page),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedQuery {
+    pub cmd: String,
+    /// Zero-based page index.
+    pub page: usize,
+    pub query: Vec<String>,
+}
+
+pub fn parse_query(query: &str) -> std::result::Result<ParsedQuery, QueryError> {
+    let captures = QUERY_RE.captures(query).ok_or(QueryError::Malformed)?;
+
+    let cmd = captures.name("cmd").map(|cmd| cmd.as_str()).unwrap_or("");
+    if InlineCommand::parse(cmd).is_none() {
+        return Err(QueryError::UnknownCommand(cmd.to_owned()));
+    }
+
+    let page = match captures.name("page") {
+        Some(page) => match page.as_str().parse::<usize>() {
+            Ok(number @ 1..=MAX_PAGE) => number - 1,
+            _ => return Err(QueryError::PageOutOfRange(page.as_str().to_owned())),
+        },
+        None => 0,
+    };
+
+    let query = captures
+        .name("query")
+        .map(|query| {
+            tables::search::normalize(query.as_str())
+                .replace("+", " ")
+                .split(' ')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_owned())
+                .collect()
+        })
+        .unwrap_or(vec![]);
+
+    Ok(ParsedQuery {
+        cmd: cmd.to_owned(),
+        page,
+        query,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InlineCommand {
+    Products,
+    Orders,
+    Sell,
+    Writeoff,
+    Replenish,
+}
+
+impl InlineCommand {
+    pub fn parse(cmd: &str) -> Option<Self> {
+        match cmd {
+            "" => Some(Self::Products),
+            ".o" => Some(Self::Orders),
+            "~sell" => Some(Self::Sell),
+            "~woff" => Some(Self::Writeoff),
+            "~repl" => Some(Self::Replenish),
+            _ => None,
+        }
+    }
+
+    /// Parses the command, falling back to the products search when
+    /// it is unknown or the user lacks the role for it.
+    pub fn resolve(cmd: &str, user: &User) -> Self {
+        match Self::parse(cmd) {
+            Some(cmd) if user.role.is_at_least(cmd.required_role()) => cmd,
+            _ => Self::Products,
+        }
+    }
+
+    pub fn required_role(&self) -> Role {
+        match self {
+            Self::Products | Self::Orders => Role::User,
+            Self::Sell | Self::Writeoff => Role::Merchant,
+            Self::Replenish => Role::Moderator,
+        }
+    }
+
+    /// Only the catalog is cached, the other commands list orders and stock
+    /// which change with every write and must show it right away.
+    pub fn is_cached(&self) -> bool {
+        matches!(self, Self::Products)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Products => "",
+            Self::Orders => ".o",
+            Self::Sell => "~sell",
+            Self::Writeoff => "~woff",
+            Self::Replenish => "~repl",
+        }
+    }
+}
+
+impl Display for InlineCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 pub struct InlineRequest<'a> {
     bot: Bot,
     q: &'a InlineQuery,
     page: usize,
-    cmd: String,
+    cmd: InlineCommand,
     query: Vec<String>,
     warehouse: &'a mut Warehouse,
     user: &'a User,
     lang_code: String,
 }
 
+/// Parses the thumbnail of an article, a broken URL is replaced with the placeholder.
+pub fn thumb_url(image_url: &str, fallback: Option<&Url>) -> Option<Url> {
+    image_url.parse().ok().or_else(|| fallback.cloned())
+}
+
 impl<'a> InlineRequest<'a> {
     pub fn new(
         bot: Bot,
@@ -71,45 +296,52 @@ impl<'a> InlineRequest<'a> {
         warehouse: &'a mut Warehouse,
         user: &'a User,
         lang_code: String,
-    ) -> Result<Self> {
-        let captures = QUERY_RE.captures(&q.query).ok_or("Invalid query.")?;
-
-        let cmd = captures
-            .name("cmd")
-            .map(|cmd| cmd.as_str().to_owned())
-            .unwrap_or("".to_owned());
-
-        let page = captures
-            .name("page")
-            .map(|page| page.as_str().parse::<usize>().ok())
-            .flatten()
-            .unwrap_or(1)
-            - 1;
-
-        let query = captures
-            .name("query")
-            .map(|query| {
-                query
-                    .as_str()
-                    .to_lowercase()
-                    .replace("+", " ")
-                    .split(' ')
-                    .filter(|s| !s.is_empty())
-                    .map(|s| s.to_owned())
-                    .collect()
-            })
-            .unwrap_or(vec![]);
-
-        Ok(Self {
+        parsed: ParsedQuery,
+    ) -> Self {
+        Self {
             bot,
             q,
-            page,
-            cmd,
-            query,
+            page: parsed.page,
+            cmd: InlineCommand::resolve(&parsed.cmd, user),
+            query: parsed.query,
             warehouse,
             user,
             lang_code,
-        })
+        }
+    }
+
+    /// Sets the thumbnail of the article, leaving it out when there's no valid one.
+    pub fn with_thumb(
+        &self,
+        article: InlineQueryResultArticle,
+        image_url: &str,
+    ) -> InlineQueryResultArticle {
+        match thumb_url(image_url, self.warehouse.fallback_image.as_ref()) {
+            Some(url) => article.thumb_url(url),
+            None => article,
+        }
+    }
+
+    /// Answers the query and remembers the answer for identical repeats.
+    pub async fn answer(
+        &mut self,
+        results: Vec<InlineQueryResult>,
+        cache_time: u32,
+    ) -> Result<()> {
+        if self.cmd.is_cached() {
+            self.warehouse.inline_cache.insert(
+                (self.user.name.clone(), self.q.query.trim().to_owned()),
+                (results.clone(), cache_time),
+                Instant::now(),
+            );
+        }
+
+        self.bot
+            .answer_inline_query(&self.q.id, results)
+            .cache_time(cache_time)
+            .await?;
+
+        Ok(())
     }
 
     pub async fn process_results(&mut self, results: &mut Vec<InlineQueryResult>)
@@ -120,16 +352,16 @@ impl<'a> InlineRequest<'a> {
                 results.push(InlineQueryResult::Article(
                     InlineQueryResultArticle::new(
                         format!("p?np?{}", self.page),
-                        localize!(self.warehouse, &self.lang_code, hint.name),
+                        localize_static!(self.warehouse, &self.lang_code, hint.name),
                         InputMessageContent::Text(InputMessageContentText::new(
-                            localize!(self.warehouse, &self.lang_code, 
+                            localize_static!(self.warehouse, &self.lang_code, 
                                 hint.full_desc, 
                                 "page" => self.page + 2, 
                                 "query" => self.query.join(" "),
                                 "cmd" => self.cmd))),
                     )
                     .description(
-                        localize!(self.warehouse, &self.lang_code, 
+                        localize_static!(self.warehouse, &self.lang_code, 
                             hint.inline_desc, 
                             "page" => self.page + 2, 
                             "query" => "",
@@ -147,3 +379,131 @@ impl<'a> InlineRequest<'a> {
 
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::fixtures;
+
+    fn user(role: Role) -> User {
+        User {
+            name: "user".to_owned(),
+            role,
+            ..fixtures::user()
+        }
+    }
+
+    #[test]
+    fn no_username_gets_info_article() {
+        let from: teloxide::types::User = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "is_bot": false,
+            "first_name": "Anna",
+        }))
+        .unwrap();
+        assert!(from.username.is_none());
+
+        let InlineQueryResult::Article(article) = info_article(
+            "no_username",
+            "Set a username to use the bot.".to_owned(),
+            "Open Telegram settings.".to_owned(),
+        ) else {
+            panic!("expected an article");
+        };
+
+        assert_eq!(article.id, "no_username");
+        assert_eq!(article.title, "Set a username to use the bot.");
+        assert_eq!(article.description.as_deref(), Some("Open Telegram settings."));
+        assert!(matches!(
+            article.input_message_content,
+            InputMessageContent::Text(InputMessageContentText { message_text, .. })
+                if message_text == "Set a username to use the bot."
+        ));
+    }
+
+    #[test]
+    fn parse_known_commands() {
+        assert_eq!(InlineCommand::parse(""), Some(InlineCommand::Products));
+        assert_eq!(InlineCommand::parse(".o"), Some(InlineCommand::Orders));
+        assert_eq!(InlineCommand::parse("~sell"), Some(InlineCommand::Sell));
+        assert_eq!(InlineCommand::parse("~woff"), Some(InlineCommand::Writeoff));
+        assert_eq!(InlineCommand::parse("~repl"), Some(InlineCommand::Replenish));
+        assert_eq!(InlineCommand::parse(".x"), None);
+    }
+
+    #[test]
+    fn round_trip_through_str() {
+        for cmd in [
+            InlineCommand::Products,
+            InlineCommand::Orders,
+            InlineCommand::Sell,
+            InlineCommand::Writeoff,
+            InlineCommand::Replenish,
+        ] {
+            assert_eq!(InlineCommand::parse(cmd.as_str()), Some(cmd));
+        }
+    }
+
+    #[test]
+    fn resolve_respects_roles() {
+        let customer = user(Role::User);
+        let merchant = user(Role::Merchant);
+        let moderator = user(Role::Moderator);
+
+        assert_eq!(InlineCommand::resolve(".o", &customer), InlineCommand::Orders);
+        assert_eq!(InlineCommand::resolve("~sell", &customer), InlineCommand::Products);
+        assert_eq!(InlineCommand::resolve("~sell", &merchant), InlineCommand::Sell);
+        assert_eq!(InlineCommand::resolve("~woff", &merchant), InlineCommand::Writeoff);
+        assert_eq!(InlineCommand::resolve("~repl", &merchant), InlineCommand::Products);
+        assert_eq!(InlineCommand::resolve("~repl", &moderator), InlineCommand::Replenish);
+        assert_eq!(InlineCommand::resolve(".unknown", &moderator), InlineCommand::Products);
+    }
+
+    #[test]
+    fn only_catalog_is_cached() {
+        let moderator = user(Role::Moderator);
+        let cached: Vec<_> = ["", ".o", "~sell", "~woff", "~repl"]
+            .into_iter()
+            .map(|cmd| InlineCommand::resolve(cmd, &moderator).is_cached())
+            .collect();
+
+        assert_eq!(cached, vec![true, false, false, false, false]);
+    }
+
+    #[test]
+    fn parse_regular_query() {
+        assert_eq!(
+            parse_query("~sell #3 Red+Hat"),
+            Ok(ParsedQuery {
+                cmd: "~sell".to_owned(),
+                page: 2,
+                query: vec!["red".to_owned(), "hat".to_owned()],
+            })
+        );
+        assert_eq!(parse_query("").unwrap().page, 0);
+        assert_eq!(parse_query("#hat").unwrap().query, vec!["#hat".to_owned()]);
+    }
+
+    #[test]
+    fn reject_page_zero() {
+        assert_eq!(parse_query("#0"), Err(QueryError::PageOutOfRange("0".to_owned())));
+        assert_eq!(
+            parse_query(".o #00 hat"),
+            Err(QueryError::PageOutOfRange("00".to_owned()))
+        );
+    }
+
+    #[test]
+    fn reject_bare_command_prefix() {
+        assert_eq!(parse_query("~"), Err(QueryError::UnknownCommand("~".to_owned())));
+        assert_eq!(parse_query(". hat"), Err(QueryError::UnknownCommand(".".to_owned())));
+    }
+
+    #[test]
+    fn reject_extreme_pages() {
+        assert_eq!(parse_query(&format!("#{}", MAX_PAGE)).unwrap().page, MAX_PAGE - 1);
+        assert!(parse_query(&format!("#{}", MAX_PAGE + 1)).is_err());
+        assert!(parse_query(&format!("#{}", usize::MAX)).is_err());
+        assert!(parse_query("#99999999999999999999999999").is_err());
+    }
+}
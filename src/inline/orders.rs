@@ -1,14 +1,19 @@
 use tables::prelude::*;
+use tables::search::Searcher;
 use teloxide::prelude::*;
 use teloxide::types::{
     InlineKeyboardButton, InlineKeyboardMarkup, InlineQueryResult, InlineQueryResultArticle,
     InputMessageContent, InputMessageContentText, ParseMode,
 };
+use teloxide::utils::html;
 
 use crate::entries::search_group;
 use crate::prelude::*;
+use crate::utils::labels::display_stage;
 use crate::utils::payload::Payload;
+use crate::utils::text::truncate_words;
 
+use super::markup::{Action, Actions};
 use super::InlineRequest;
 
 impl<'a> InlineRequest<'a> {
@@ -21,32 +26,33 @@ impl<'a> InlineRequest<'a> {
             .ok_or(UnkError::unknown("No user meta"))?
             .clone();
 
-        let pairs: Vec<_> = user_meta
+        let mut matches: Vec<_> = user_meta
             .pending_orders
             .iter()
             .chain(user_meta.completed_orders.iter())
             .filter_map(|order_id| self.warehouse.orders.by_id.get(order_id).cloned())
             // Filter by query
-            .filter(|order| {
-                let mut pass = self
-                    .warehouse
-                    .items
-                    .search
-                    .get(&order.item_id)
-                    .unwrap()
-                    .search_all(search_group::USER, self.query.iter());
-
-                pass |= self
-                    .warehouse
-                    .orders
-                    .search
-                    .get(&order.id)
-                    .unwrap()
-                    .search_all(search_group::USER, self.query.iter());
-
-                pass
-            })
             .filter_map(|order| {
+                // Merchants also search by the item id of their incoming orders
+                let group = if order.merchant == self.user.name {
+                    search_group::MERCHANT
+                } else {
+                    search_group::USER
+                };
+
+                let item = self.warehouse.items.search.get(&order.item_id)?;
+                let fields = self.warehouse.orders.search.get(&order.id)?;
+
+                order_match(item, fields, group, &self.query).map(|rank| (rank, order))
+            })
+            .collect();
+
+        // Stable, so orders of the same rank keep their order
+        matches.sort_by_key(|(rank, _)| *rank);
+
+        let pairs: Vec<_> = matches
+            .into_iter()
+            .filter_map(|(_, order)| {
                 self.warehouse
                     .products
                     .by_id
@@ -75,10 +81,7 @@ impl<'a> InlineRequest<'a> {
 
         results.truncate(50);
 
-        self.bot
-            .answer_inline_query(&self.q.id, results)
-            .cache_time(0)
-            .await?;
+        self.answer(results, 0).await?;
 
         Ok(())
     }
@@ -89,15 +92,16 @@ impl<'a> InlineRequest<'a> {
         product: &Product,
         item: &Item,
     ) -> Result<InlineQueryResultArticle> {
-        Ok(InlineQueryResultArticle::new(
+        let article = InlineQueryResultArticle::new(
             format!("o?{}", order.id),
-            localize!(self.warehouse, &self.lang_code, item.name.to_owned()),
+            localize_static!(self.warehouse, &self.lang_code, item.name),
             self.make_content(order, item).await,
         )
         .description(self.make_description(order, item).await)
         .reply_markup(self.make_markup(order, product).await)
-        .hide_url(true)
-        .thumb_url(item.image_url.clone().parse().unwrap()))
+        .hide_url(true);
+
+        Ok(self.with_thumb(article, &item.image_url))
     }
 
     async fn make_content(&mut self, order: &Order, item: &Item) -> InputMessageContent {
@@ -105,14 +109,23 @@ impl<'a> InlineRequest<'a> {
             OrderStage::Negotiated | OrderStage::WaitForPayment | OrderStage::Cancelled => {
                 "-".to_owned()
             }
-            _ => order.currency.format(&order.cost.to_string()),
+            _ => order.currency.format_amount(order.cost),
         };
 
+        let stage = display_stage(self.warehouse, &self.lang_code, &order.stage).await;
+        let merchant = self
+            .warehouse
+            .merchants
+            .by_name
+            .get(&order.merchant)
+            .map_or(order.merchant.as_str(), Merchant::display_name);
+        let merchant = html::escape(merchant);
+
         let text = [
             localize!(self.warehouse, &self.lang_code, "• Product: {product}", "product" => item.name),
-            localize!(self.warehouse, &self.lang_code, "• Merchant: {merchant}", "merchant" => order.merchant),
+            localize!(self.warehouse, &self.lang_code, "• Merchant: {merchant}", "merchant" => merchant),
             localize!(self.warehouse, &self.lang_code, "• Customer: {customer}", "customer" => order.customer),
-            localize!(self.warehouse, &self.lang_code, "• Stage: {stage}", "stage" => format!("{:?}", order.stage)),
+            localize!(self.warehouse, &self.lang_code, "• Stage: {stage}", "stage" => stage),
             localize!(self.warehouse, &self.lang_code, "• Amount: {amount}", "amount" => order.amount),
             localize!(self.warehouse, &self.lang_code, "• Paid: {paid}", "paid" => paid),
             localize!(self.warehouse, &self.lang_code, "• Date: {date}", "date" => order.date),
@@ -124,8 +137,11 @@ impl<'a> InlineRequest<'a> {
     }
 
     async fn make_description(&mut self, order: &Order, item: &Item) -> String {
-        // Assign with an inline description
-        let description = localize!(self.warehouse, &self.lang_code, item.inline_desc.clone());
+        // Assign with an inline description, Telegram cuts long ones unpredictably
+        let description = truncate_words(
+            &localize_static!(self.warehouse, &self.lang_code, item.inline_desc),
+            self.warehouse.inline_description_limit,
+        );
         let mut info = vec![];
 
         // Add merchant
@@ -156,70 +172,243 @@ impl<'a> InlineRequest<'a> {
         info.push(localize!(self.warehouse, &self.lang_code,
             "{amount} piece{end}",
             "amount" => order.amount,
-            "end" => if order.amount > 1 { "s" } else { "" }
+            "end" => if order.amount > 1.0 { "s" } else { "" }
         ));
 
         format!("{}\n{}", description, info.join(" • "))
     }
 
     async fn make_markup(&mut self, order: &Order, product: &Product) -> InlineKeyboardMarkup {
-        let mut markup = InlineKeyboardMarkup::default();
-
-        if self.user.name == order.merchant {
-            markup = match order.stage {
-                OrderStage::Negotiated => markup.append_row(vec![
-                    InlineKeyboardButton::callback(
-                        localize!(self.warehouse, &self.lang_code, "Cancel"),
-                        Payload::cancel_order(order.id.clone()).to_string(),
-                    ),
-                    InlineKeyboardButton::callback(
-                        localize!(self.warehouse, &self.lang_code, "Specify price"),
-                        Payload::specify_order_price(order.id.clone()).to_string(),
-                    ),
-                ]),
-                OrderStage::WaitForPayment => markup.append_row(vec![
-                    InlineKeyboardButton::callback(
-                        localize!(self.warehouse, &self.lang_code, "Cancel"),
-                        Payload::cancel_order(order.id.clone()).to_string(),
-                    ),
-                    InlineKeyboardButton::callback(
-                        localize!(self.warehouse, &self.lang_code, "Complete"),
-                        Payload::complete_order(order.id.clone()).to_string(),
-                    ),
-                ]),
-                OrderStage::Paid => markup.append_row(vec![InlineKeyboardButton::callback(
-                    localize!(self.warehouse, &self.lang_code, "Complete"),
-                    Payload::complete_order(order.id.clone()).to_string(),
-                )]),
-                _ => markup,
-            };
+        let actions = order_actions(&self.user.name, order, product);
+        let mut markup = self.localize_actions(actions).await;
+
+        let other = if self.user.name == order.merchant {
+            Some(&order.customer)
         } else if self.user.name == order.customer {
-            markup = match order.stage {
-                OrderStage::Negotiated => markup.append_row(vec![InlineKeyboardButton::callback(
-                    localize!(self.warehouse, &self.lang_code, "Cancel"),
-                    Payload::cancel_order(order.id.clone()).to_string(),
-                )]),
-                OrderStage::WaitForPayment => {
-                    let mut buttons = vec![];
-
-                    if product.payment_method.supports_card() {
-                        buttons.push(InlineKeyboardButton::callback(
-                            localize!(self.warehouse, &self.lang_code, "Pay with card"),
-                            Payload::pay_for_order(order.id.clone()).to_string(),
-                        ));
-                    }
-
-                    buttons.push(InlineKeyboardButton::callback(
-                        localize!(self.warehouse, &self.lang_code, "Cancel"),
-                        Payload::cancel_order(order.id.clone()).to_string(),
-                    ));
-
-                    markup.append_row(buttons)
-                }
-                _ => markup,
-            };
+            Some(&order.merchant)
+        } else {
+            None
+        };
+
+        if let Some(other) = other {
+            markup = markup.append_row(vec![InlineKeyboardButton::callback(
+                localize!(self.warehouse, &self.lang_code, "Message {name}", "name" => other),
+                Payload::message_order(order.id.clone()).to_string(),
+            )]);
         }
 
         markup
     }
 }
+
+/// How an order matches the query, better matches go first.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum OrderMatch {
+    /// Every word is found in the item, e.g. its name.
+    Item,
+    /// Every word is found in the order fields, e.g. the stage or the customer.
+    Fields,
+    /// Every word is found in either of them.
+    Mixed,
+}
+
+fn order_match(
+    item: &Searcher,
+    fields: &Searcher,
+    group: &str,
+    query: &[String],
+) -> Option<OrderMatch> {
+    if item.search_all(group, query.iter()) {
+        Some(OrderMatch::Item)
+    } else if fields.search_all(group, query.iter()) {
+        Some(OrderMatch::Fields)
+    } else if query
+        .iter()
+        .all(|word| item.search_one(group, word) || fields.search_one(group, word))
+    {
+        Some(OrderMatch::Mixed)
+    } else {
+        None
+    }
+}
+
+/// Buttons of the order card for one of its participants.
+fn order_actions(username: &str, order: &Order, product: &Product) -> Actions {
+    let is_merchant = username == order.merchant;
+    let is_customer = !is_merchant && username == order.customer;
+    let stage = &order.stage;
+
+    Actions::new().row(vec![
+        Action::new(
+            "Pay with card",
+            Payload::pay_for_order(order.id.clone()),
+            is_customer
+                && *stage == OrderStage::WaitForPayment
+                && product.payment_method.supports_card(),
+        ),
+        Action::new(
+            "Cancel",
+            Payload::cancel_order(order.id.clone()),
+            (is_merchant || is_customer)
+                && matches!(stage, OrderStage::Negotiated | OrderStage::WaitForPayment),
+        ),
+        Action::new(
+            "Specify price",
+            Payload::specify_order_price(order.id.clone()),
+            is_merchant && *stage == OrderStage::Negotiated,
+        ),
+        Action::new(
+            "Complete",
+            Payload::complete_order(order.id.clone()),
+            is_merchant && matches!(stage, OrderStage::WaitForPayment | OrderStage::Paid),
+        ),
+        Action::new(
+            "Reorder",
+            Payload::reorder(order.id.clone()),
+            is_customer && *stage == OrderStage::Completed,
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::*;
+    use crate::entries::fixtures;
+    use crate::inline::thumb_url;
+
+    fn item(image_url: &str) -> Item {
+        Item {
+            id: "item".to_owned(),
+            name: "Item".to_owned(),
+            inline_desc: "-".to_owned(),
+            full_desc: "-".to_owned(),
+            image_url: image_url.to_owned(),
+            deleted: false,
+        }
+    }
+
+    fn labels(actions: Actions) -> Vec<Vec<String>> {
+        actions
+            .build(|key| key.to_owned())
+            .inline_keyboard
+            .into_iter()
+            .map(|row| row.into_iter().map(|button| button.text).collect())
+            .collect()
+    }
+
+    #[test]
+    fn order_buttons_by_participant_and_stage() {
+        let product = fixtures::product();
+        let order = |stage| Order {
+            stage,
+            cost: 0.0,
+            ..fixtures::order()
+        };
+
+        let cases = [
+            (
+                "merchant",
+                OrderStage::Negotiated,
+                vec!["Cancel", "Specify price"],
+            ),
+            (
+                "merchant",
+                OrderStage::WaitForPayment,
+                vec!["Cancel", "Complete"],
+            ),
+            ("merchant", OrderStage::Paid, vec!["Complete"]),
+            ("customer", OrderStage::Negotiated, vec!["Cancel"]),
+            (
+                "customer",
+                OrderStage::WaitForPayment,
+                vec!["Pay with card", "Cancel"],
+            ),
+            ("customer", OrderStage::Paid, vec![]),
+            ("customer", OrderStage::Completed, vec!["Reorder"]),
+            ("merchant", OrderStage::Completed, vec![]),
+            ("stranger", OrderStage::Negotiated, vec![]),
+        ];
+
+        for (username, stage, expected) in cases {
+            let expected: Vec<Vec<String>> = if expected.is_empty() {
+                vec![]
+            } else {
+                vec![expected.into_iter().map(str::to_owned).collect()]
+            };
+            assert_eq!(
+                labels(order_actions(username, &order(stage), &product)),
+                expected
+            );
+        }
+
+        let cash_only = Product {
+            payment_method: PaymentMethod::Cash,
+            ..product.clone()
+        };
+        assert_eq!(
+            labels(order_actions(
+                "customer",
+                &order(OrderStage::WaitForPayment),
+                &cash_only
+            )),
+            vec![vec!["Cancel".to_owned()]]
+        );
+    }
+
+    fn words(query: &str) -> Vec<String> {
+        query.split(' ').map(|s| s.to_owned()).collect()
+    }
+
+    #[test]
+    fn orders_match_by_product_name_and_fields() {
+        let item = Searcher::from(Item {
+            name: "Green Apple".to_owned(),
+            ..item("")
+        });
+        let fields = Searcher::from(Order {
+            stage: OrderStage::Completed,
+            cost: 0.0,
+            ..fixtures::order()
+        });
+        let find = |group, query| order_match(&item, &fields, group, &words(query));
+
+        assert_eq!(find(search_group::USER, "apple"), Some(OrderMatch::Item));
+        assert_eq!(
+            find(search_group::USER, "completed"),
+            Some(OrderMatch::Fields)
+        );
+        assert_eq!(
+            find(search_group::USER, "apple customer"),
+            Some(OrderMatch::Mixed)
+        );
+        assert_eq!(find(search_group::USER, "pear"), None);
+
+        // Only merchants see the item ids
+        assert_eq!(
+            find(search_group::MERCHANT, "id:item"),
+            Some(OrderMatch::Item)
+        );
+        assert_eq!(find(search_group::USER, "id:item"), None);
+
+        assert!(OrderMatch::Item < OrderMatch::Fields);
+        assert!(OrderMatch::Fields < OrderMatch::Mixed);
+    }
+
+    #[test]
+    fn broken_order_thumb_uses_placeholder() {
+        let placeholder: Url = "https://example.com/placeholder.png".parse().unwrap();
+        let image: Url = "https://example.com/hat.png".parse().unwrap();
+
+        assert_eq!(
+            thumb_url(&item(image.as_str()).image_url, Some(&placeholder)),
+            Some(image)
+        );
+        assert_eq!(
+            thumb_url(&item("not an url").image_url, Some(&placeholder)),
+            Some(placeholder)
+        );
+        assert_eq!(thumb_url(&item("").image_url, None), None);
+    }
+}
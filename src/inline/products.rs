@@ -2,30 +2,70 @@ use itertools::Itertools;
 use tables::prelude::*;
 use teloxide::prelude::*;
 use teloxide::types::{
-    InlineKeyboardButton, InlineKeyboardMarkup, InlineQueryResult, InlineQueryResultArticle,
-    InputMessageContent, InputMessageContentText, ParseMode,
+    InlineKeyboardMarkup, InlineQueryResult, InlineQueryResultArticle, InputMessageContent,
+    InputMessageContentText, ParseMode,
 };
+use teloxide::utils::html;
 
 use crate::entries::search_group;
 use crate::prelude::*;
+use crate::utils::labels::display_label;
 use crate::utils::payload::Payload;
+use crate::utils::text::truncate_words;
 
+use super::markup::Actions;
 use super::InlineRequest;
 
+const LOCATION_PREFIX: &str = "loc:";
+
+/// Splits `loc:<name>` tokens off the query, returning the locations
+/// and the remaining search words.
+fn split_location_filter(query: &[String]) -> (Vec<String>, Vec<String>) {
+    let (locations, words): (Vec<_>, Vec<_>) = query
+        .iter()
+        .partition(|word| word.starts_with(LOCATION_PREFIX));
+
+    let locations = locations
+        .into_iter()
+        .map(|word| word[LOCATION_PREFIX.len()..].to_owned())
+        .filter(|location| !location.is_empty())
+        .collect();
+
+    (locations, words.into_iter().cloned().collect())
+}
+
+fn matches_location(merchant: &Merchant, locations: &[String]) -> bool {
+    if locations.is_empty() {
+        return true;
+    }
+
+    let location = tables::search::normalize(&merchant.location);
+    locations.iter().any(|loc| location.contains(loc.as_str()))
+}
+
 impl<'a> InlineRequest<'a> {
     pub async fn make_products(&mut self) -> Result<()> {
+        let (locations, query) = split_location_filter(&self.query);
+        let reserved = self.warehouse.reserved_stock()?;
+        let paused = self.warehouse.paused_merchants(self.user);
+
         let pairs: Vec<_> = self
             .warehouse
             .products
             .inner
             .read()?
-            .filter(|p| p.is_visible_to(self.user) && p.amount_left > 0)
+            .filter(|p| {
+                p.is_visible_to(self.user)
+                    && !paused.contains(&p.merchant)
+                    && available_of(p, &reserved) > 0.0
+            })
             // Map item to the iterator
             .filter_map(|product| {
                 self.warehouse
                     .items
                     .by_id
                     .get(&product.item_id)
+                    .filter(|item| !item.deleted && item.check_renderable().is_ok())
                     .map(|item| (product, item))
             })
             // Map merchants to the iterator
@@ -44,6 +84,8 @@ impl<'a> InlineRequest<'a> {
                 }
                 vec
             })
+            // Filter by location
+            .filter(|(merchant, _, _)| matches_location(merchant, &locations))
             // Filter by query
             .filter_map(|(merchant, product, item)| {
                 let item_searcher = self.warehouse.items.search.get(&item.id).unwrap();
@@ -51,16 +93,16 @@ impl<'a> InlineRequest<'a> {
                     self.warehouse.merchants.search.get(&merchant.name).unwrap();
 
                 let item_all_passsed =
-                    item_searcher.search_all(search_group::USER, self.query.iter());
+                    item_searcher.search_all(search_group::USER, query.iter());
 
                 let merchant_all_passed =
-                    merchant_searcher.search_all(search_group::USER, self.query.iter());
+                    merchant_searcher.search_all(search_group::USER, query.iter());
 
                 let item_any_passsed =
-                    item_searcher.search_any(search_group::USER, self.query.iter());
+                    item_searcher.search_any(search_group::USER, query.iter());
 
                 let merchant_any_passed =
-                    merchant_searcher.search_any(search_group::USER, self.query.iter());
+                    merchant_searcher.search_any(search_group::USER, query.iter());
 
                 let priority = match (
                     item_all_passsed,
@@ -97,10 +139,8 @@ impl<'a> InlineRequest<'a> {
 
         self.process_results(&mut results).await;
 
-        self.bot
-            .answer_inline_query(&self.q.id, results)
-            .cache_time(60)
-            .await?;
+        let cache_time = self.warehouse.listing_cache_time();
+        self.answer(results, cache_time).await?;
 
         Ok(())
     }
@@ -111,15 +151,16 @@ impl<'a> InlineRequest<'a> {
         product: &Product,
         item: &Item,
     ) -> Result<InlineQueryResultArticle> {
-        Ok(InlineQueryResultArticle::new(
+        let article = InlineQueryResultArticle::new(
             format!("p?{}{}", product.id(), merchant.location),
-            localize!(self.warehouse, &self.lang_code, item.name.to_owned()),
+            localize_static!(self.warehouse, &self.lang_code, item.name),
             self.make_product_content(merchant, item, product).await,
         )
         .description(self.make_product_description(merchant, item, product).await)
         .reply_markup(self.make_product_markup(product).await)
-        .hide_url(true)
-        .thumb_url(item.image_url.clone().parse().unwrap()))
+        .hide_url(true);
+
+        Ok(self.with_thumb(article, &item.image_url))
     }
 
     async fn make_product_content(
@@ -131,14 +172,11 @@ impl<'a> InlineRequest<'a> {
         let price = if product.negotiated_price {
             localize!(self.warehouse, &self.lang_code, "Negotiated").to_string()
         } else {
-            product.currency.format(&product.price.to_string())
+            product.currency.format_amount(product.price)
         };
 
-        let payment_method = match product.payment_method {
-            PaymentMethod::Cash => localize!(self.warehouse, &self.lang_code, "Cash"),
-            PaymentMethod::Card => localize!(self.warehouse, &self.lang_code, "Card"),
-            PaymentMethod::Both => localize!(self.warehouse, &self.lang_code, "Cash or Card"),
-        };
+        let payment_method =
+            display_label(self.warehouse, &self.lang_code, &product.payment_method).await;
 
         const GOOGLE_MAPS_URL: &str = "https://www.google.com/maps/search/?api=1&query=";
         let address_encoded = urlencoding::encode(&merchant.address).to_string();
@@ -152,6 +190,7 @@ impl<'a> InlineRequest<'a> {
         let details = [
             "<b>Details</b>".to_string(),
             localize!(self.warehouse, &self.lang_code, "• Price: <b>{price}</b>", "price" => price),
+            localize!(self.warehouse, &self.lang_code, "• Seller: {seller}", "seller" => seller(merchant)),
             localize!(self.warehouse, &self.lang_code, "• Payment method: {payment_method}", "payment_method" => payment_method),
             localize!(self.warehouse, &self.lang_code, "• Location: {location}", "location" => location),
         ].join("\n");
@@ -169,20 +208,27 @@ impl<'a> InlineRequest<'a> {
         item: &Item,
         product: &Product,
     ) -> String {
-        // Assign with an inline description
-        let description = localize!(self.warehouse, &self.lang_code, item.inline_desc.clone());
+        // Assign with an inline description, Telegram cuts long ones unpredictably
+        let description = truncate_words(
+            &localize_static!(self.warehouse, &self.lang_code, item.inline_desc),
+            self.warehouse.inline_description_limit,
+        );
         let mut info = vec![];
 
         // Add merchant's 🛒
         if product.merchant == self.user.name {
             info.push(localize!(self.warehouse, &self.lang_code, "🛒 Your"));
+
+            if merchant.vacation || !product.active {
+                info.push(localize!(self.warehouse, &self.lang_code, "⏸ Paused"));
+            }
         }
 
         // Add price
         let price = if product.negotiated_price {
             localize!(self.warehouse, &self.lang_code, "Negotiated")
         } else {
-            product.currency.format(&product.price.to_string())
+            product.currency.format_amount(product.price)
         };
 
         match product.payment_method {
@@ -199,22 +245,147 @@ impl<'a> InlineRequest<'a> {
     }
 
     async fn make_product_markup(&mut self, product: &Product) -> InlineKeyboardMarkup {
-        let mut markup = InlineKeyboardMarkup::default();
+        let reportable = self.warehouse.reports.is_some();
+        let actions = product_actions(&self.user.name, product, reportable);
+        self.localize_actions(actions).await
+    }
+}
 
-        if self.user.name == product.merchant && !product.negotiated_price {
-            markup = markup.append_row(vec![InlineKeyboardButton::callback(
-                localize!(self.warehouse, &self.lang_code, "Redeem"),
-                Payload::redeem(product.id()).to_string(),
-            )]);
-        }
+/// Seller of the product card, escaped as the card is HTML.
+fn seller(merchant: &Merchant) -> String {
+    html::escape(merchant.display_name())
+}
+
+/// Buttons of the product card, the owner manages it and everyone else may buy it.
+/// Reporting is offered only when the reports sheet is configured.
+fn product_actions(username: &str, product: &Product, reportable: bool) -> Actions {
+    let is_owner = username == product.merchant;
+
+    Actions::new()
+        .button(
+            "Redeem",
+            Payload::redeem(product),
+            is_owner && !product.negotiated_price,
+        )
+        .button(
+            "Publish",
+            Payload::publish(product.id()),
+            is_owner && product.is_draft(),
+        )
+        .button(
+            "Pause",
+            Payload::toggle_active(product.id()),
+            is_owner && product.active,
+        )
+        .button(
+            "Resume",
+            Payload::toggle_active(product.id()),
+            is_owner && !product.active,
+        )
+        .button("Purchase", Payload::purchase(product), !is_owner)
+        .button("Report", Payload::report(product), !is_owner && reportable)
+}
 
-        if self.user.name != product.merchant {
-            markup = markup.append_row(vec![InlineKeyboardButton::callback(
-                localize!(self.warehouse, &self.lang_code, "Purchase"),
-                Payload::purchase(product.id()).to_string(),
-            )]);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::fixtures;
+
+    fn words(query: &str) -> Vec<String> {
+        query.split(' ').map(|s| s.to_owned()).collect()
+    }
+
+    fn merchant(location: &str) -> Merchant {
+        Merchant {
+            location: location.to_owned(),
+            address: "-".to_owned(),
+            ..fixtures::merchant()
         }
+    }
 
-        markup
+    #[test]
+    fn shows_display_name_over_username() {
+        let mut merchant = merchant("Prague");
+        assert_eq!(merchant.display_name(), "merchant");
+
+        merchant.display_name = Some("Mountain Hats".to_owned());
+        assert_eq!(merchant.display_name(), "Mountain Hats");
+        assert_eq!(merchant.name, "merchant");
+
+        // An emptied cell is the same as an unset one
+        merchant.display_name = Some(" ".to_owned());
+        assert_eq!(merchant.display_name(), "merchant");
+    }
+
+    #[test]
+    fn card_shows_escaped_display_name() {
+        let mut merchant = merchant("Prague");
+        assert_eq!(seller(&merchant), "merchant");
+
+        merchant.display_name = Some("Hats & <Caps>".to_owned());
+        assert_eq!(seller(&merchant), "Hats &amp; &lt;Caps&gt;");
+        assert_eq!(merchant.contact(), "Hats & <Caps> (@merchant)");
+
+        merchant.display_name = None;
+        assert_eq!(merchant.contact(), "@merchant");
+    }
+
+    #[test]
+    fn split_location_tokens() {
+        let (locations, query) = split_location_filter(&words("hat loc:prague red"));
+        assert_eq!(locations, vec!["prague".to_owned()]);
+        assert_eq!(query, words("hat red"));
+    }
+
+    #[test]
+    fn split_ignores_empty_location() {
+        let (locations, query) = split_location_filter(&words("loc: hat"));
+        assert!(locations.is_empty());
+        assert_eq!(query, words("hat"));
+    }
+
+    #[test]
+    fn location_filter_matches() {
+        assert!(matches_location(&merchant("Prague"), &[]));
+        assert!(matches_location(&merchant("Prague"), &["prague".to_owned()]));
+        assert!(matches_location(&merchant("Old Prague"), &["prague".to_owned()]));
+        assert!(!matches_location(&merchant("Brno"), &["prague".to_owned()]));
+        assert!(matches_location(
+            &merchant("Brno"),
+            &["prague".to_owned(), "brno".to_owned()]
+        ));
+    }
+
+    #[test]
+    fn product_buttons_for_owner_and_customer() {
+        let product = Product {
+            visibility: ProductVisibility::Draft,
+            ..fixtures::product()
+        };
+        let labels = |username: &str, product: &Product| {
+            product_actions(username, product, true)
+                .label_keys()
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            labels("merchant", &product),
+            vec!["Redeem", "Publish", "Pause"]
+        );
+        assert_eq!(labels("customer", &product), vec!["Purchase", "Report"]);
+        assert_eq!(
+            product_actions("customer", &product, false)
+                .label_keys()
+                .collect::<Vec<_>>(),
+            vec!["Purchase"]
+        );
+
+        let negotiated = Product {
+            negotiated_price: true,
+            visibility: ProductVisibility::All,
+            active: false,
+            ..product
+        };
+        assert_eq!(labels("merchant", &negotiated), vec!["Resume"]);
     }
 }
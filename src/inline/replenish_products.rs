@@ -16,6 +16,7 @@ impl<'a> InlineRequest<'a> {
             .products
             .inner
             .read()?
+            .filter(|product| !product.deleted)
             // Map item to the iterator
             .filter_map(|product| {
                 self.warehouse
@@ -54,10 +55,7 @@ impl<'a> InlineRequest<'a> {
 
         self.process_results(&mut results).await;
 
-        self.bot
-            .answer_inline_query(&self.q.id, results)
-            .cache_time(0)
-            .await?;
+        self.answer(results, 0).await?;
 
         Ok(())
     }
@@ -73,14 +71,15 @@ impl<'a> InlineRequest<'a> {
             InputMessageContentText::new(content).parse_mode(ParseMode::Html),
         );
 
-        Ok(InlineQueryResultArticle::new(
+        let article = InlineQueryResultArticle::new(
             format!("p?{}", product.id()),
             item.name.to_owned(),
             content,
         )
         .description(self.make_repl_description(product).await)
-        .hide_url(true)
-        .thumb_url(item.image_url.clone().parse().unwrap()))
+        .hide_url(true);
+
+        Ok(self.with_thumb(article, &item.image_url))
     }
 
     async fn make_repl_description(&mut self, product: &Product) -> String {
@@ -99,7 +98,7 @@ impl<'a> InlineRequest<'a> {
         let price = if product.negotiated_price {
             localize!(self.warehouse, &self.lang_code, "Negotiated")
         } else {
-            product.currency.format(&product.price.to_string())
+            product.currency.format_amount(product.price)
         };
 
         match product.payment_method {
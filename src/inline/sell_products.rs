@@ -18,7 +18,7 @@ impl<'a> InlineRequest<'a> {
             .inner
             .read()?
             // Filter out other merchants' products
-            .filter(|product| product.merchant == self.user.name)
+            .filter(|product| product.merchant == self.user.name && !product.deleted)
             // Map item to iterator
             .filter_map(|product| {
                 self.warehouse
@@ -57,10 +57,7 @@ impl<'a> InlineRequest<'a> {
 
         self.process_results(&mut results).await;
 
-        self.bot
-            .answer_inline_query(&self.q.id, results)
-            .cache_time(0)
-            .await?;
+        self.answer(results, 0).await?;
 
         Ok(())
     }
@@ -76,14 +73,15 @@ impl<'a> InlineRequest<'a> {
             InputMessageContentText::new(content).parse_mode(ParseMode::Html),
         );
 
-        Ok(InlineQueryResultArticle::new(
+        let article = InlineQueryResultArticle::new(
             format!("p?{}", product.id()),
             item.name.to_owned(),
             content,
         )
         .description(self.make_sell_description(product, item).await)
-        .hide_url(true)
-        .thumb_url(item.image_url.clone().parse().unwrap()))
+        .hide_url(true);
+
+        Ok(self.with_thumb(article, &item.image_url))
     }
 
     async fn make_sell_description(&mut self, product: &Product, item: &Item) -> String {
@@ -95,7 +93,7 @@ impl<'a> InlineRequest<'a> {
         let price = if product.negotiated_price {
             localize!(self.warehouse, &self.lang_code, "Negotiated".to_owned())
         } else {
-            product.currency.format(&product.price.to_string())
+            product.currency.format_amount(product.price)
         };
 
         match product.payment_method {
@@ -1,6 +1,10 @@
 extern crate tables;
 
+mod alert;
+mod bootstrap;
+mod busy;
 mod callbacks;
+mod changes;
 mod commands;
 #[macro_use]
 mod common;
@@ -8,21 +12,21 @@ mod config;
 mod dialogues;
 mod entries;
 mod inline;
+mod refresh;
+mod usage;
 mod utils;
 mod warehouse;
 
+use std::error::Error as StdError;
 use std::fmt::Display;
-use std::io::Read;
-use std::str::FromStr;
 use std::sync::Arc;
-use std::{error::Error as StdError, fs::File};
+use std::time::Duration;
 
-use config::Config;
+use alert::{Alerter, Severity};
 use futures::future::BoxFuture;
-use google_sheets4::oauth2::ServiceAccountKey;
 use log::debug;
 use teloxide::error_handlers::ErrorHandler;
-use toml;
+use tokio::sync::Mutex;
 
 use teloxide::{
     dispatching::{DpHandlerDescription, UpdateHandler},
@@ -74,15 +78,30 @@ impl Display for UnkError {
 
 impl StdError for UnkError {}
 
-struct DisplayErrorHandler;
+struct DisplayErrorHandler {
+    operator: Option<(Bot, ChatId, Mutex<Alerter>)>,
+}
 
-impl<E> ErrorHandler<E> for DisplayErrorHandler
-where
-    E: Display,
-{
-    fn handle_error(self: Arc<Self>, error: E) -> BoxFuture<'static, ()> {
+impl ErrorHandler<BoxedError> for DisplayErrorHandler {
+    fn handle_error(self: Arc<Self>, error: BoxedError) -> BoxFuture<'static, ()> {
         log::error!("An error occurred: {}", error);
-        Box::pin(async {})
+
+        Box::pin(async move {
+            let Some((bot, chat_id, alerter)) = &self.operator else {
+                return;
+            };
+
+            let text = alerter
+                .lock()
+                .await
+                .prepare(error.as_ref(), tokio::time::Instant::now());
+
+            if let Some(text) = text {
+                if let Err(e) = bot.send_message(*chat_id, text).await {
+                    log::error!("Failed to alert the operator chat: {}", e);
+                }
+            }
+        })
     }
 }
 
@@ -90,15 +109,94 @@ where
 async fn main() {
     pretty_env_logger::init();
 
-    let key = read_key();
-    let config = read_config(key.clone());
-    let creds = read_service_account_key(key);
+    let (mut config, creds) = match bootstrap::load() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Unable to start: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-    let warehouse = self::warehouse::build(&config, creds).await;
-    let bot = Bot::new(config.telegram.bot_token);
+    let warehouse = match self::warehouse::build(&mut config, creds).await {
+        Ok(warehouse) => warehouse,
+        Err(e) => {
+            eprintln!("Unable to start: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = warehouse.write().await.replay_wal().await {
+        eprintln!("Unable to start: {}", bootstrap::BootstrapError::Replay(e));
+        std::process::exit(1);
+    }
+
+    // Colliding ids are unlikely, but would silently merge two products.
+    // The check is advisory, the bot starts even when it can't be made
+    if let Err(e) = warehouse.write().await.warn_id_collisions().await {
+        log::error!("Unable to check the product ids for collisions: {}", e);
+    }
+
+    if let Some(interval) = refresh::interval(config.sheets.refresh_interval) {
+        tokio::spawn(refresh::run(warehouse.clone(), interval));
+    }
+
+    let usage = Arc::new(usage::Usage::default());
+    if let (Some(_), Some(interval)) = (
+        &config.sheets.usage,
+        refresh::interval(config.sheets.usage_flush_interval),
+    ) {
+        tokio::spawn(usage::run_flush(usage.clone(), warehouse.clone(), interval));
+    }
+
+    let bot = Bot::new(config.telegram.bot_token.clone());
+
+    // The checks fetch the tables themselves, so they don't need the background refresh
+    let change_check_interval = config
+        .sheets
+        .change_check_interval
+        .or(config.sheets.refresh_interval);
+    if let Some(interval) = refresh::interval(change_check_interval) {
+        let debounce = config.telegram.change_notice_debounce.unwrap_or(600);
+        tokio::spawn(changes::run(
+            bot.clone(),
+            warehouse.clone(),
+            chrono::Duration::seconds(debounce as i64),
+            interval,
+        ));
+    }
+
+    if let (Some(chat_id), Some(interval)) = (
+        config.telegram.changelog_chat_id,
+        refresh::interval(change_check_interval),
+    ) {
+        tokio::spawn(changes::run_changelog(
+            bot.clone(),
+            warehouse.clone(),
+            ChatId(chat_id),
+            config
+                .telegram
+                .changelog_lang_code
+                .clone()
+                .unwrap_or("en".to_owned()),
+            interval,
+        ));
+    }
+
+    let error_handler = DisplayErrorHandler {
+        operator: config.telegram.operator_chat_id.map(|chat_id| {
+            let alerter = Alerter::new(
+                config.telegram.alert_severity.unwrap_or(Severity::High),
+                Duration::from_secs(config.telegram.alert_interval.unwrap_or(60)),
+            );
+            (bot.clone(), ChatId(chat_id), Mutex::new(alerter))
+        }),
+    };
 
     let mut deps = DependencyMap::default();
     deps.insert(warehouse);
+    deps.insert(usage);
+    deps.insert(busy::BusyTimeout(Duration::from_secs(
+        config.telegram.busy_timeout.unwrap_or(5),
+    )));
     dialogues::write_deps(&mut deps);
 
     Dispatcher::builder(bot, schema())
@@ -107,7 +205,7 @@ async fn main() {
         .default_handler(|upd| async move {
             debug!("Unhandled update: {:?}", upd);
         })
-        .error_handler(Arc::new(DisplayErrorHandler))
+        .error_handler(Arc::new(error_handler))
         .build()
         .dispatch()
         .await;
@@ -115,43 +213,11 @@ async fn main() {
 
 fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
     dptree::entry()
+        .chain(busy::gate())
+        .branch(dialogues::particular::purchase::invoice::handler())
         .branch(dialogues::handler())
         .branch(inline::handler())
         .branch(commands::handler())
         .branch(callbacks::handler())
         .endpoint(common::default_handler)
 }
-
-fn read_config(key: age::x25519::Identity) -> Config {
-    let encrypted_config = File::open("config.toml.enc").expect("Can't read config");
-    let config = decrypt(encrypted_config, key);
-    toml::from_str(&config).expect("Can't parse config")
-}
-
-fn read_service_account_key(key: age::x25519::Identity) -> ServiceAccountKey {
-    let encrypted_credentials = File::open("credentials.json.enc").expect("Can't read credentials");
-    let creds = decrypt(encrypted_credentials, key);
-
-    serde_json::from_str(&creds).expect("Can't parse credentials")
-}
-
-fn decrypt(encrypted: impl Read, key: age::x25519::Identity) -> String {
-    let decryptor = match age::Decryptor::new(encrypted).expect("Can't initialize decryptor") {
-        age::Decryptor::Recipients(d) => d,
-        age::Decryptor::Passphrase(_) => unreachable!(),
-    };
-
-    let mut reader = decryptor
-        .decrypt(std::iter::once(&key as &dyn age::Identity))
-        .unwrap();
-
-    let mut data = String::new();
-    reader.read_to_string(&mut data).unwrap();
-
-    data
-}
-
-fn read_key() -> age::x25519::Identity {
-    let key = std::env::var("AGE_PRIVATE_KEY").expect("Can't find AGE_PRIVATE_KEY");
-    age::x25519::Identity::from_str(&key).expect("Unable to parse key.")
-}
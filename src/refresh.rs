@@ -0,0 +1,201 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::{debug, error};
+use tokio::{
+    sync::RwLock,
+    time::{self, Instant, Interval, MissedTickBehavior},
+};
+
+use crate::{
+    prelude::*,
+    warehouse::{DetachedTables, FetchedTables},
+};
+
+/// Source of refresh ticks. Returns `false` when the loop should stop.
+#[async_trait]
+pub trait Ticker: Send {
+    async fn tick(&mut self) -> bool;
+}
+
+#[async_trait]
+impl Ticker for Interval {
+    async fn tick(&mut self) -> bool {
+        Interval::tick(self).await;
+        true
+    }
+}
+
+/// Target of the background refresh. The tables are fetched apart from the
+/// target, so its lock is held only to detach and to install them.
+#[async_trait]
+pub trait RefreshAll: Send + Sync {
+    type Detached: Send;
+    type Fetched: Send;
+
+    fn detach(&self) -> Self::Detached;
+    async fn fetch(detached: Self::Detached) -> Result<Self::Fetched>;
+    /// Returns the number of tables left for the next refresh.
+    async fn install(&mut self, fetched: Self::Fetched) -> Result<usize>;
+}
+
+#[async_trait]
+impl RefreshAll for Warehouse {
+    type Detached = DetachedTables;
+    type Fetched = FetchedTables;
+
+    fn detach(&self) -> DetachedTables {
+        self.detach_tables()
+    }
+
+    async fn fetch(detached: DetachedTables) -> Result<FetchedTables> {
+        detached.fetch().await
+    }
+
+    async fn install(&mut self, fetched: FetchedTables) -> Result<usize> {
+        self.install_tables(fetched).await
+    }
+}
+
+/// Builds a ticker for the configured interval, `None` disables the refresh.
+pub fn interval(secs: Option<u64>) -> Option<Interval> {
+    let period = match secs {
+        Some(secs) if secs > 0 => Duration::from_secs(secs),
+        _ => return None,
+    };
+
+    // The warehouse is fresh right after the start, so skip the immediate tick
+    let mut interval = time::interval_at(Instant::now() + period, period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    Some(interval)
+}
+
+pub async fn run<T: Ticker, R: RefreshAll>(target: Arc<RwLock<R>>, mut ticker: T) {
+    while ticker.tick().await {
+        debug!("Refreshing warehouse...");
+        let now = Instant::now();
+
+        match refresh(&target).await {
+            Ok(0) => debug!("Refreshed warehouse in {:?}", now.elapsed()),
+            Ok(skipped) => debug!(
+                "Refreshed warehouse in {:?}, {} tables written meanwhile are left for the next time",
+                now.elapsed(),
+                skipped
+            ),
+            Err(e) => error!("Background refresh failed: {}", e),
+        }
+    }
+}
+
+/// Fetches the tables without the lock and installs them, returns the number
+/// of tables left for the next refresh.
+pub async fn refresh<R: RefreshAll>(target: &RwLock<R>) -> Result<usize> {
+    let detached = target.read().await.detach();
+    let fetched = R::fetch(detached).await?;
+    target.write().await.install(fetched).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::Notify;
+
+    struct CountdownTicker(usize);
+
+    #[async_trait]
+    impl Ticker for CountdownTicker {
+        async fn tick(&mut self) -> bool {
+            if self.0 == 0 {
+                return false;
+            }
+            self.0 -= 1;
+            true
+        }
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        refreshed: usize,
+        fail: bool,
+        /// Signals the start of the fetch and waits for the second notify to end it.
+        gates: Option<(Arc<Notify>, Arc<Notify>)>,
+    }
+
+    #[async_trait]
+    impl RefreshAll for Counter {
+        type Detached = Option<(Arc<Notify>, Arc<Notify>)>;
+        type Fetched = ();
+
+        fn detach(&self) -> Self::Detached {
+            self.gates.clone()
+        }
+
+        async fn fetch(gates: Self::Detached) -> Result<()> {
+            if let Some((started, finish)) = gates {
+                started.notify_one();
+                finish.notified().await;
+            }
+            Ok(())
+        }
+
+        async fn install(&mut self, _: ()) -> Result<usize> {
+            self.refreshed += 1;
+            if self.fail {
+                return Err(Box::new(UnkError::tables("refresh failed")));
+            }
+            Ok(0)
+        }
+    }
+
+    #[tokio::test]
+    async fn refreshes_on_every_tick() {
+        let counter = Arc::new(RwLock::new(Counter::default()));
+        run(counter.clone(), CountdownTicker(3)).await;
+        assert_eq!(counter.read().await.refreshed, 3);
+    }
+
+    #[tokio::test]
+    async fn keeps_running_after_failure() {
+        let counter = Arc::new(RwLock::new(Counter {
+            fail: true,
+            ..Default::default()
+        }));
+        run(counter.clone(), CountdownTicker(2)).await;
+        assert_eq!(counter.read().await.refreshed, 2);
+    }
+
+    #[tokio::test]
+    async fn fetches_without_the_lock() {
+        let (started, finish) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+        let counter = Arc::new(RwLock::new(Counter {
+            gates: Some((started.clone(), finish.clone())),
+            ..Default::default()
+        }));
+        let refresh = tokio::spawn(run(counter.clone(), CountdownTicker(1)));
+
+        started.notified().await;
+        assert!(counter.try_write().is_ok());
+
+        finish.notify_one();
+        refresh.await.unwrap();
+        assert_eq!(counter.read().await.refreshed, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ticks_once_per_interval() {
+        let start = Instant::now();
+        let mut ticker = interval(Some(60)).unwrap();
+
+        assert!(Ticker::tick(&mut ticker).await);
+        assert_eq!(start.elapsed(), Duration::from_secs(60));
+        assert!(Ticker::tick(&mut ticker).await);
+        assert_eq!(start.elapsed(), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn disabled_without_interval() {
+        assert!(interval(None).is_none());
+        assert!(interval(Some(0)).is_none());
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, RwLock,
+};
+
+use chrono::Utc;
+use log::{debug, error};
+use tables::prelude::*;
+
+use crate::entries::UsageRecord;
+use crate::prelude::*;
+use crate::refresh::Ticker;
+
+#[derive(Default)]
+struct Counter {
+    total: AtomicU64,
+    flushed: AtomicU64,
+}
+
+/// In-memory hit counters by command. The map is only locked for writing on the
+/// first hit of a command, later hits bump an atomic under a shared lock.
+#[derive(Default)]
+pub struct Usage {
+    counters: RwLock<HashMap<String, Counter>>,
+}
+
+impl Usage {
+    pub fn hit(&self, key: &str) {
+        if let Some(counter) = self.counters.read().unwrap().get(key) {
+            counter.total.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        self.counters
+            .write()
+            .unwrap()
+            .entry(key.to_owned())
+            .or_default()
+            .total
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Hits since the start by command, the most used go first.
+    pub fn snapshot(&self) -> Vec<(String, u64)> {
+        let mut snapshot: Vec<_> = self
+            .counters
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(key, counter)| (key.clone(), counter.total.load(Ordering::Relaxed)))
+            .collect();
+
+        snapshot.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        snapshot
+    }
+
+    /// Hits since the previous call by command, commands without new hits are left out.
+    pub fn take_new(&self) -> Vec<(String, u64)> {
+        let mut new: Vec<_> = self
+            .counters
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|(key, counter)| {
+                let total = counter.total.load(Ordering::Relaxed);
+                let new = total - counter.flushed.swap(total, Ordering::Relaxed);
+                (new > 0).then(|| (key.clone(), new))
+            })
+            .collect();
+
+        new.sort();
+        new
+    }
+}
+
+pub fn render(snapshot: &[(String, u64)]) -> String {
+    snapshot
+        .iter()
+        .map(|(key, count)| format!("• {}: {}", key, count))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Appends new hits to the usage sheet on every tick.
+pub async fn run_flush<T: Ticker>(usage: Arc<Usage>, warehouse: SharedWarehouse, mut ticker: T) {
+    while ticker.tick().await {
+        let date = Utc::now();
+        let records: Vec<_> = usage
+            .take_new()
+            .into_iter()
+            .map(|(command, count)| UsageRecord {
+                date,
+                command,
+                count,
+            })
+            .collect();
+
+        if records.is_empty() {
+            continue;
+        }
+
+        let mut warehouse = warehouse.write().await;
+        let Some(usage_log) = warehouse.usage_log.as_mut() else {
+            return;
+        };
+
+        match usage_log.extend(&records).await {
+            Ok(_) => debug!("Flushed {} usage counters", records.len()),
+            Err(e) => error!("Failed to flush usage counters: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_increment() {
+        let usage = Usage::default();
+        usage.hit("/restock");
+        usage.hit("inline Products");
+        usage.hit("/restock");
+        usage.hit("Purchase");
+        usage.hit("inline Products");
+        usage.hit("/restock");
+
+        assert_eq!(
+            usage.snapshot(),
+            vec![
+                ("/restock".to_owned(), 3),
+                ("inline Products".to_owned(), 2),
+                ("Purchase".to_owned(), 1),
+            ]
+        );
+        assert_eq!(
+            render(&usage.snapshot()),
+            "• /restock: 3\n• inline Products: 2\n• Purchase: 1"
+        );
+    }
+
+    #[test]
+    fn take_only_new_hits() {
+        let usage = Usage::default();
+        usage.hit("/usage");
+        usage.hit("/usage");
+        usage.hit("/start");
+
+        assert_eq!(
+            usage.take_new(),
+            vec![("/start".to_owned(), 1), ("/usage".to_owned(), 2)]
+        );
+        assert!(usage.take_new().is_empty());
+
+        usage.hit("/usage");
+        assert_eq!(usage.take_new(), vec![("/usage".to_owned(), 1)]);
+
+        // Totals are kept
+        assert_eq!(usage.snapshot()[0], ("/usage".to_owned(), 3));
+    }
+}
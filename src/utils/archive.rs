@@ -0,0 +1,136 @@
+use tables::prelude::*;
+
+use crate::prelude::*;
+
+/// Collects entries matching the predicate together with their rows.
+pub fn expired_rows<'a, E: Clone + 'a>(
+    entries: impl IntoIterator<Item = &'a E>,
+    is_expired: impl Fn(&E) -> bool,
+) -> Vec<(usize, E)> {
+    entries
+        .into_iter()
+        .enumerate()
+        .filter(|(_, entry)| is_expired(entry))
+        .map(|(row, entry)| (row, entry.clone()))
+        .collect()
+}
+
+/// Appends the rows to the archive and only after the archive has flushed them
+/// deletes them from the source, so a failed archive write never loses rows.
+pub async fn move_rows<E, S, A>(
+    source: &mut S,
+    archive: &mut A,
+    rows: Vec<(usize, E)>,
+) -> Result<usize>
+where
+    E: Send + Sync,
+    S: TableDelete + Send,
+    A: TableExtend<E> + TableFlush + Send,
+{
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let (rows, entries): (Vec<_>, Vec<_>) = rows.into_iter().unzip();
+
+    archive
+        .extend(&entries)
+        .await
+        .map_err(|e| UnkError::tables(&format!("archive write failed: {}", e)))?;
+
+    // Writes land in the background, wait for them before dropping the source rows
+    archive
+        .flush()
+        .await
+        .map_err(|e| UnkError::tables(&format!("archive write failed: {}", e)))?;
+
+    source
+        .delete(rows)
+        .await
+        .map_err(|e| UnkError::tables(&format!("source delete failed: {}", e)))?;
+
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Display;
+
+    use async_trait::async_trait;
+    use tables::in_mem::InMemTable;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct WriteError;
+
+    impl Display for WriteError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("write error")
+        }
+    }
+
+    impl std::error::Error for WriteError {}
+
+    /// Fails the write right away, or accepts it and fails the flush.
+    struct FailingTable {
+        on_flush: bool,
+    }
+
+    #[async_trait]
+    impl TableExtend<u32> for FailingTable {
+        type Ok = ();
+        type Error = WriteError;
+
+        async fn extend<'a, T>(&'a mut self, _: T) -> std::result::Result<(), WriteError>
+        where
+            T: IntoIterator<Item = &'a u32> + Clone + Send + Sync,
+            u32: 'a,
+        {
+            match self.on_flush {
+                true => Ok(()),
+                false => Err(WriteError),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl TableFlush for FailingTable {
+        type Error = WriteError;
+
+        async fn flush(&mut self) -> std::result::Result<(), WriteError> {
+            Err(WriteError)
+        }
+    }
+
+    #[tokio::test]
+    async fn old_rows_move_to_archive() {
+        let mut source: InMemTable<u32> = [1, 10, 2, 20].into();
+        let mut archive: InMemTable<u32> = [5].into();
+
+        let rows = expired_rows(source.read().unwrap(), |x| *x < 10);
+        assert_eq!(rows, vec![(0, 1), (2, 2)]);
+
+        let moved = move_rows(&mut source, &mut archive, rows).await.unwrap();
+        assert_eq!(moved, 2);
+
+        let source: Vec<_> = source.read().unwrap().cloned().collect();
+        let archive: Vec<_> = archive.read().unwrap().cloned().collect();
+        assert_eq!(source, vec![10, 20]);
+        assert_eq!(archive, vec![5, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn rows_stay_when_archive_fails() {
+        let mut source: InMemTable<u32> = [1, 10, 2, 20].into();
+
+        for on_flush in [false, true] {
+            let rows = expired_rows(source.read().unwrap(), |x| *x < 10);
+            let mut archive = FailingTable { on_flush };
+            assert!(move_rows(&mut source, &mut archive, rows).await.is_err());
+
+            let source: Vec<_> = source.read().unwrap().cloned().collect();
+            assert_eq!(source, vec![1, 10, 2, 20]);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::prelude::*;
+
+/// Longest window `/deadstock <days>` accepts, a longer one reaches before any sale.
+pub const MAX_WINDOW_DAYS: i64 = 3650;
+
+/// Window of the `/deadstock <days>` argument, `None` unless it's from 1 to [`MAX_WINDOW_DAYS`].
+pub fn parse_window(days: &str) -> Option<Duration> {
+    match days.parse::<i64>() {
+        Ok(days) if (1..=MAX_WINDOW_DAYS).contains(&days) => Some(Duration::days(days)),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeadStockEntry {
+    pub merchant: String,
+    pub item_id: String,
+    pub amount_left: f64,
+    /// Date of the latest sale, `None` if the product was never sold.
+    pub last_sale: Option<DateTime<Utc>>,
+}
+
+/// Date of the latest sale for every (merchant, item_id) up to `now`.
+pub fn last_sales<'a>(
+    sales: impl IntoIterator<Item = &'a Sale>,
+    now: DateTime<Utc>,
+) -> HashMap<(String, String), DateTime<Utc>> {
+    let mut last = HashMap::<(String, String), DateTime<Utc>>::new();
+
+    for sale in sales.into_iter().filter(|sale| sale.date <= now) {
+        let date = last
+            .entry((sale.merchant.clone(), sale.item_id.clone()))
+            .or_insert(sale.date);
+        *date = (*date).max(sale.date);
+    }
+
+    last
+}
+
+/// Products in stock without a single sale over the window before `now`,
+/// the ones never sold go first, then the ones idle for the longest.
+pub fn dead_stock_report<'a>(
+    products: impl IntoIterator<Item = &'a Product>,
+    sales: impl IntoIterator<Item = &'a Sale>,
+    now: DateTime<Utc>,
+    window: Duration,
+) -> Vec<DeadStockEntry> {
+    // A window reaching before the earliest date leaves only the products never sold
+    let since = now.checked_sub_signed(window);
+    let last = last_sales(sales, now);
+
+    let mut report: Vec<_> = products
+        .into_iter()
+        .filter(|product| product.amount_left > 0.0)
+        .map(|product| DeadStockEntry {
+            merchant: product.merchant.clone(),
+            item_id: product.item_id.clone(),
+            amount_left: product.amount_left,
+            last_sale: last
+                .get(&(product.merchant.clone(), product.item_id.clone()))
+                .copied(),
+        })
+        .filter(|entry| match entry.last_sale {
+            Some(date) => since.is_some_and(|since| date <= since),
+            None => true,
+        })
+        .collect();
+
+    report.sort_by(|a, b| a.last_sale.cmp(&b.last_sale));
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::fixtures;
+
+    fn product(item_id: &str, amount_left: f64) -> Product {
+        Product {
+            item_id: item_id.to_owned(),
+            amount_granted: amount_left,
+            amount_left,
+            ..fixtures::product()
+        }
+    }
+
+    fn sale(item_id: &str, date: DateTime<Utc>) -> Sale {
+        Sale {
+            merchant: "merchant".to_owned(),
+            sale_type: SaleType::HandToHand,
+            customer: "customer".to_owned(),
+            item_id: item_id.to_owned(),
+            comment: "".to_owned(),
+            amount: 1.0,
+            revenue: 1.0,
+            currency: Currency::EUR,
+            share: 0.0,
+            date,
+            base_amount: None,
+            base_currency: None,
+        }
+    }
+
+    #[test]
+    fn latest_sale_per_product() {
+        let now = Utc::now();
+        let sales = [
+            sale("hat", now - Duration::days(5)),
+            sale("hat", now - Duration::days(1)),
+            // Not happened yet
+            sale("hat", now + Duration::days(1)),
+        ];
+
+        let last = last_sales(&sales, now);
+        assert_eq!(
+            last[&("merchant".to_owned(), "hat".to_owned())],
+            now - Duration::days(1)
+        );
+    }
+
+    #[test]
+    fn report_classifies_products() {
+        let now = Utc::now();
+        let products = [
+            product("hat", 5.0),
+            product("scarf", 5.0),
+            product("gloves", 5.0),
+            product("socks", 0.0),
+        ];
+        let sales = [
+            sale("hat", now - Duration::days(2)),
+            sale("scarf", now - Duration::days(40)),
+            sale("socks", now - Duration::days(40)),
+        ];
+
+        let report = dead_stock_report(&products, &sales, now, Duration::days(30));
+
+        let summary: Vec<_> = report
+            .iter()
+            .map(|entry| (entry.item_id.as_str(), entry.last_sale))
+            .collect();
+
+        // Hat sells, socks are out of stock anyway
+        assert_eq!(
+            summary,
+            vec![("gloves", None), ("scarf", Some(now - Duration::days(40)))]
+        );
+    }
+
+    #[test]
+    fn window_is_bounded() {
+        assert_eq!(parse_window("30"), Some(Duration::days(30)));
+        assert_eq!(parse_window("4000000000"), None);
+        assert_eq!(parse_window("0"), None);
+        assert_eq!(parse_window("-5"), None);
+        assert_eq!(parse_window("week"), None);
+
+        // A window before the earliest date doesn't overflow
+        let now = Utc::now();
+        let products = [product("hat", 5.0), product("scarf", 5.0)];
+        let sales = [sale("hat", now - Duration::days(2))];
+        let report = dead_stock_report(&products, &sales, now, Duration::days(1_000_000_000));
+        let items: Vec<_> = report.iter().map(|entry| entry.item_id.as_str()).collect();
+        assert_eq!(items, vec!["scarf"]);
+    }
+}
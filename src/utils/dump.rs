@@ -0,0 +1,94 @@
+use std::{error::Error as StdError, fmt::Debug};
+
+use tables::TableFetch;
+
+use crate::Result;
+
+/// Fetches the table and renders its first `limit` rows, one per line.
+/// Returns the rendered rows and the number of rows in the table.
+///
+/// Mark the cache as dirty first to get past it. Fetching a fork rebuilds its
+/// indices too, so the bot goes on with the rows the dump shows.
+pub async fn dump<T>(table: &mut T, limit: usize) -> Result<(String, usize)>
+where
+    T: TableFetch,
+    for<'a> T::Entry<'a>: Debug,
+    T::Error: StdError + Send + Sync + 'static,
+{
+    let mut lines = vec![];
+    let mut total = 0;
+    for (idx, entry) in table.fetch().await?.into_iter().enumerate() {
+        if idx < limit {
+            lines.push(format!("{}. {:?}", idx, entry));
+        }
+        total += 1;
+    }
+
+    Ok((lines.join("\n"), total))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use async_trait::async_trait;
+    use tables::{cache::Cache, in_mem::InMemTable, TableVersion};
+
+    use super::*;
+    use crate::prelude::*;
+
+    /// Origin whose version never changes, like a clock within its TTL.
+    struct Origin(Vec<Localization>);
+
+    #[async_trait]
+    impl TableFetch for Origin {
+        type Entry<'a> = Localization;
+        type Ok<'a> = Vec<Localization>;
+        type Error = Infallible;
+
+        async fn fetch(&mut self) -> std::result::Result<Vec<Localization>, Infallible> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[async_trait]
+    impl TableVersion for Origin {
+        type Error = Infallible;
+
+        async fn version(&mut self) -> std::result::Result<u64, Infallible> {
+            Ok(u64::MAX)
+        }
+    }
+
+    fn phrase(key_phrase: &str) -> Localization {
+        Localization {
+            key_phrase: key_phrase.to_owned(),
+            en: key_phrase.to_owned(),
+            ru: "-".to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn dump_refetches_stale_cache() {
+        let cache: InMemTable<Localization> = InMemTable::new(vec![]);
+        let mut table = Cache::new(Origin(vec![phrase("Cancel")]), cache);
+        table.refresh().await.unwrap();
+
+        // The sheet was edited, but its version didn't change
+        let origin = table.origin_mut();
+        origin.0.extend([phrase("Details"), phrase("Reorder")]);
+
+        table.mark_as_dirty();
+        let (rendered, total) = dump(&mut table, 2).await.unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(
+            rendered,
+            [
+                format!("0. {:?}", phrase("Cancel")),
+                format!("1. {:?}", phrase("Details")),
+            ]
+            .join("\n")
+        );
+        assert_eq!(table.read().unwrap().count(), 3);
+    }
+}
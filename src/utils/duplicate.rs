@@ -0,0 +1,100 @@
+use crate::prelude::*;
+
+/// Moderators clone between any merchants, a merchant only from and to themselves.
+pub fn may_clone(user: &User, merchant: &str, target: &str) -> bool {
+    user.role == Role::Moderator || (merchant == user.name && target == user.name)
+}
+
+/// Copies a product for another merchant. Everything but the merchant and
+/// the stock is kept, the copy starts with `amount` units, no sales and no backorders.
+/// A pause is the source merchant's, so the copy is listed right away.
+pub fn duplicate_product(source: &Product, merchant: &str, amount: u32) -> Product {
+    Product {
+        merchant: merchant.to_owned(),
+        amount_granted: amount as f64,
+        amount_sold: 0.0,
+        amount_left: amount as f64,
+        backordered: 0.0,
+        deleted: false,
+        active: true,
+        ..source.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tables::{in_mem::InMemTable, prelude::*};
+
+    use super::*;
+    use crate::entries::fixtures;
+
+    fn product() -> Product {
+        Product {
+            merchant: "prague".to_owned(),
+            item_id: "hat".to_owned(),
+            price: 12.5,
+            currency: Currency::CZK,
+            payment_method: PaymentMethod::Card,
+            negotiated_price: true,
+            share: 0.1,
+            visibility: ProductVisibility::Merchants,
+            amount_granted: 10.0,
+            amount_sold: 4.0,
+            amount_left: 6.0,
+            ..fixtures::product()
+        }
+    }
+
+    #[test]
+    fn merchants_clone_only_for_themselves() {
+        let mut user = User {
+            name: "prague".to_owned(),
+            role: Role::Merchant,
+            ..fixtures::user()
+        };
+
+        assert!(may_clone(&user, "prague", "prague"));
+        assert!(!may_clone(&user, "prague", "brno"));
+        assert!(!may_clone(&user, "brno", "prague"));
+
+        user.role = Role::Moderator;
+        assert!(may_clone(&user, "brno", "ostrava"));
+    }
+
+    #[tokio::test]
+    async fn copies_all_but_overridden_fields() {
+        let source = Product {
+            backordered: 2.0,
+            active: false,
+            ..product()
+        };
+        let copy = duplicate_product(&source, "brno", 3);
+
+        assert_eq!(copy.merchant, "brno");
+        assert_eq!(copy.item_id, source.item_id);
+        assert_eq!(copy.price, source.price);
+        assert_eq!(copy.currency, source.currency);
+        assert_eq!(copy.payment_method, source.payment_method);
+        assert_eq!(copy.negotiated_price, source.negotiated_price);
+        assert_eq!(copy.share, source.share);
+        assert!(matches!(copy.visibility, ProductVisibility::Merchants));
+        assert_eq!(
+            (copy.amount_granted, copy.amount_sold, copy.amount_left),
+            (3.0, 0.0, 3.0)
+        );
+        assert_eq!(copy.backordered, 0.0);
+        assert!(copy.active);
+        assert_ne!(copy.id(), source.id());
+
+        let mut table: InMemTable<Product> = [source].into();
+        table.extend(&[copy]).await.unwrap();
+
+        let merchants: Vec<_> = table
+            .fetch()
+            .await
+            .unwrap()
+            .map(|product| product.merchant.as_str())
+            .collect();
+        assert_eq!(merchants, vec!["prague", "brno"]);
+    }
+}
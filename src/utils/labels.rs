@@ -0,0 +1,146 @@
+use crate::prelude::*;
+
+/// Key phrase an enum is shown to users with, translated by the localization sheet.
+pub trait Label {
+    fn label(&self) -> &'static str;
+}
+
+impl Label for OrderStage {
+    fn label(&self) -> &'static str {
+        match self {
+            OrderStage::Paid => "Paid",
+            OrderStage::Negotiated => "Negotiated",
+            OrderStage::WaitForPayment => "Waiting for payment",
+            OrderStage::Completed => "Completed",
+            OrderStage::Cancelled => "Cancelled",
+        }
+    }
+}
+
+impl Label for PaymentMethod {
+    fn label(&self) -> &'static str {
+        match self {
+            PaymentMethod::Cash => "Cash",
+            PaymentMethod::Card => "Card",
+            PaymentMethod::Both => "Cash or Card",
+        }
+    }
+}
+
+impl Label for ProductVisibility {
+    fn label(&self) -> &'static str {
+        match self {
+            ProductVisibility::All => "Visible to everyone",
+            ProductVisibility::Personal => "Visible to you only",
+            ProductVisibility::Merchants => "Visible to merchants",
+            ProductVisibility::Draft => "Draft",
+        }
+    }
+}
+
+impl Label for SaleType {
+    fn label(&self) -> &'static str {
+        match self {
+            SaleType::HandToHand => "Hand-to-hand",
+            SaleType::Order => "Order",
+            SaleType::Redeem => "Redeem",
+        }
+    }
+}
+
+/// Label of the value in the language of the user.
+pub async fn display_label(
+    warehouse: &mut Warehouse,
+    lang_code: &str,
+    value: &impl Label,
+) -> String {
+    localize!(warehouse, lang_code, value.label())
+}
+
+pub async fn display_stage(
+    warehouse: &mut Warehouse,
+    lang_code: &str,
+    stage: &OrderStage,
+) -> String {
+    display_label(warehouse, lang_code, stage).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use num_traits::FromPrimitive;
+
+    use super::*;
+
+    /// Labels of every variant, a variant added later shows up without touching the test.
+    fn labels_of<T: Label + FromPrimitive>() -> Vec<&'static str> {
+        (0..)
+            .map_while(T::from_u8)
+            .map(|variant| variant.label())
+            .collect()
+    }
+
+    fn labels() -> Vec<&'static str> {
+        [
+            labels_of::<OrderStage>(),
+            labels_of::<PaymentMethod>(),
+            labels_of::<ProductVisibility>(),
+            labels_of::<SaleType>(),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn every_variant_is_localized() {
+        let ru = HashMap::from([
+            ("Paid", "Оплачен"),
+            ("Negotiated", "Цена по договорённости"),
+            ("Waiting for payment", "Ожидает оплаты"),
+            ("Completed", "Завершён"),
+            ("Cancelled", "Отменён"),
+            ("Cash", "Наличные"),
+            ("Card", "Карта"),
+            ("Cash or Card", "Наличные или карта"),
+            ("Visible to everyone", "Виден всем"),
+            ("Visible to you only", "Виден только вам"),
+            ("Visible to merchants", "Виден продавцам"),
+            ("Draft", "Черновик"),
+            ("Hand-to-hand", "Из рук в руки"),
+            ("Order", "Заказ"),
+            ("Redeem", "Погашение"),
+        ]);
+
+        let labels = labels();
+        let unique: HashSet<_> = labels.iter().collect();
+        assert_eq!(unique.len(), labels.len(), "Labels of two variants collide");
+
+        for label in labels {
+            let translated = ru
+                .get(label)
+                .unwrap_or_else(|| panic!("\"{}\" has no translation", label));
+            let loc = Localization {
+                key_phrase: label.to_owned(),
+                en: label.to_owned(),
+                ru: translated.to_string(),
+            };
+
+            assert_eq!(loc.get("en"), label);
+            assert_eq!(loc.get("ru"), *translated);
+        }
+    }
+
+    #[test]
+    fn labels_are_not_identifiers() {
+        assert_eq!(OrderStage::WaitForPayment.label(), "Waiting for payment");
+        assert_eq!(SaleType::HandToHand.label(), "Hand-to-hand");
+
+        // An untranslated label is still readable
+        let loc = Localization {
+            key_phrase: PaymentMethod::Both.label().to_owned(),
+            en: "-".to_owned(),
+            ru: "-".to_owned(),
+        };
+        assert_eq!(loc.get("ru"), "Cash or Card");
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+use crate::prelude::*;
+
+/// Bucket of the users without a usable language code.
+pub const UNKNOWN: &str = "unknown";
+
+/// Primary language of a code, `en-US` and `EN_us` both count as `en`.
+pub fn primary_language(lang_code: &str) -> &str {
+    let primary = lang_code
+        .trim()
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default();
+
+    match primary.len() {
+        2..=3 if primary.chars().all(|c| c.is_ascii_alphabetic()) => primary,
+        _ => UNKNOWN,
+    }
+}
+
+/// Number of users per primary language, the most used go first.
+pub fn language_distribution<'a>(
+    users: impl IntoIterator<Item = &'a User>,
+) -> Vec<(String, usize)> {
+    let mut counts = HashMap::<String, usize>::new();
+
+    for user in users {
+        let lang = primary_language(&user.lang_code).to_lowercase();
+        *counts.entry(lang).or_default() += 1;
+    }
+
+    let mut distribution: Vec<_> = counts.into_iter().collect();
+    distribution.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    distribution
+}
+
+pub fn render(distribution: &[(String, usize)]) -> String {
+    let total: usize = distribution.iter().map(|(_, count)| count).sum();
+
+    distribution
+        .iter()
+        .map(|(lang, count)| {
+            format!(
+                "• {}: {} ({:.0}%)",
+                lang,
+                count,
+                *count as f64 * 100.0 / total as f64
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::fixtures;
+
+    fn user(lang_code: &str) -> User {
+        User {
+            name: "user".to_owned(),
+            lang_code: lang_code.to_owned(),
+            ..fixtures::user()
+        }
+    }
+
+    #[test]
+    fn buckets_regional_and_broken_codes() {
+        let users: Vec<_> = [
+            "en", "en-US", "EN_gb", "ru", "ru", "cs", "", "  ", "x", "12", "uk",
+        ]
+        .into_iter()
+        .map(user)
+        .collect();
+
+        assert_eq!(
+            language_distribution(&users),
+            vec![
+                ("unknown".to_owned(), 4),
+                ("en".to_owned(), 3),
+                ("ru".to_owned(), 2),
+                ("cs".to_owned(), 1),
+                ("uk".to_owned(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn renders_shares_of_all_users() {
+        let distribution = vec![("en".to_owned(), 3), ("ru".to_owned(), 1)];
+
+        assert_eq!(render(&distribution), "• en: 3 (75%)\n• ru: 1 (25%)");
+    }
+}
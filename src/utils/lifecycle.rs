@@ -0,0 +1,396 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+
+use crate::{prelude::*, BoxedError};
+
+/// Attempts of a single meta write. A whole row is rewritten, so repeating it is harmless.
+const META_WRITE_ATTEMPTS: usize = 2;
+
+/// A new order for the amount of the product, priced unless the price is negotiated.
+pub fn new_order(
+    id: OrderId,
+    customer: String,
+    stage: OrderStage,
+    product: &Product,
+    amount: f64,
+    note: Option<String>,
+    now: DateTime<Utc>,
+) -> Order {
+    Order {
+        id,
+        customer,
+        merchant: product.merchant.clone(),
+        stage,
+        item_id: product.item_id.clone(),
+        amount,
+        cost: if product.negotiated_price {
+            0f64
+        } else {
+            product.price * amount
+        },
+        currency: product.currency,
+        date: now,
+        note,
+        completed_at: None,
+        base_amount: None,
+        base_currency: None,
+    }
+}
+
+/// Takes the amount of the order from the product and lists the order as
+/// pending for both participants. False and untouched if the product can't
+/// cover the amount, the oversell allowance included.
+pub fn open_order(
+    order: &Order,
+    product: &mut Product,
+    customer: &mut UserMeta,
+    merchant: &mut UserMeta,
+) -> bool {
+    if !product.take_for_sale(order.amount) {
+        return false;
+    }
+
+    customer.pending_orders.push(order.id.clone());
+    merchant.pending_orders.push(order.id.clone());
+    true
+}
+
+/// Completes the order unless it was already completed by this process,
+/// or its stage doesn't allow it.
+pub fn complete_once(
+    order: &mut Order,
+    completed: &mut HashSet<OrderId>,
+    now: DateTime<Utc>,
+) -> bool {
+    if completed.contains(&order.id) || !order.mark_completed(now) {
+        return false;
+    }
+
+    completed.insert(order.id.clone());
+    true
+}
+
+/// Forgets the completions the fetched orders already show, or whose orders
+/// were archived, a stale read can't bring those back.
+pub fn prune_completed<'a>(
+    completed: &mut HashSet<OrderId>,
+    orders: impl IntoIterator<Item = &'a Order>,
+) {
+    let pending: HashSet<_> = orders
+        .into_iter()
+        .filter(|order| order.completed_at.is_none())
+        .map(|order| &order.id)
+        .collect();
+
+    completed.retain(|id| pending.contains(id));
+}
+
+/// Writes the metas of both participants of an order as a unit, each is given
+/// as its row with the entry before and after the change. When the second write
+/// fails the first one is reverted, so an order is never left pending for one
+/// participant and completed for the other.
+pub async fn update_metas<T>(
+    metas: &mut T,
+    customer: (usize, &UserMeta, &UserMeta),
+    merchant: (usize, &UserMeta, &UserMeta),
+) -> Result<()>
+where
+    T: TableUpdate<UserMeta> + TableFlush + Send,
+    <T as TableUpdate<UserMeta>>::Error: Sync + 'static,
+    <T as TableFlush>::Error: Sync + 'static,
+{
+    let (row, before, after) = customer;
+    write_meta(metas, row, after).await?;
+
+    if let Err(e) = write_meta(metas, merchant.0, merchant.2).await {
+        if let Err(rollback) = write_meta(metas, row, before).await {
+            error!(
+                "Failed to roll back the meta of {}: {}",
+                before.name, rollback
+            );
+        }
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Writes the meta and waits until it has landed, retrying a failed write.
+async fn write_meta<T>(metas: &mut T, row: usize, meta: &UserMeta) -> Result<()>
+where
+    T: TableUpdate<UserMeta> + TableFlush + Send,
+    <T as TableUpdate<UserMeta>>::Error: Sync + 'static,
+    <T as TableFlush>::Error: Sync + 'static,
+{
+    let mut attempt = 1;
+    loop {
+        let written = match metas.update_one(row, meta).await {
+            Ok(_) => metas.flush().await.map_err(|e| Box::new(e) as BoxedError),
+            Err(e) => Err(Box::new(e) as BoxedError),
+        };
+
+        match written {
+            Ok(_) => return Ok(()),
+            Err(e) if attempt >= META_WRITE_ATTEMPTS => return Err(e),
+            Err(e) => {
+                warn!("Retrying the write of the meta of {}: {}", meta.name, e);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use async_trait::async_trait;
+    use chrono::Duration;
+    use tables::Validate;
+
+    use super::*;
+    use crate::entries::fixtures;
+
+    fn product() -> Product {
+        Product {
+            price: 2.5,
+            share: 0.1,
+            amount_granted: 10.0,
+            amount_left: 10.0,
+            ..fixtures::product()
+        }
+    }
+
+    /// Metas sheet which rejects every write to one of its rows.
+    struct BrokenRow {
+        rows: Vec<UserMeta>,
+        broken: usize,
+    }
+
+    #[async_trait]
+    impl TableUpdate<UserMeta> for BrokenRow {
+        type Ok = ();
+        type Error = io::Error;
+
+        async fn update<'a, T>(&'a mut self, from_row: usize, entries: T) -> io::Result<()>
+        where
+            T: IntoIterator<Item = &'a UserMeta> + Clone + Send + Sync,
+            UserMeta: 'a,
+        {
+            if from_row == self.broken {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "unreachable"));
+            }
+
+            for (idx, meta) in entries.into_iter().enumerate() {
+                self.rows[from_row + idx] = meta.clone();
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl TableFlush for BrokenRow {
+        type Error = io::Error;
+
+        async fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn meta(name: &str) -> UserMeta {
+        UserMeta {
+            name: name.to_owned(),
+            chat_id: None,
+            ..fixtures::user_meta()
+        }
+    }
+
+    #[tokio::test]
+    async fn purchase_pay_complete_records_sale() {
+        let placed_at = Utc::now();
+        let completed_at = placed_at + Duration::hours(1);
+
+        let mut product = product();
+        let mut customer = meta("customer");
+        let mut merchant = meta("merchant");
+        let mut completed = HashSet::new();
+        let mut sales = vec![];
+
+        // Purchase
+        let mut order = new_order(
+            "order".to_owned(),
+            "customer".to_owned(),
+            OrderStage::WaitForPayment,
+            &product,
+            4.0,
+            None,
+            placed_at,
+        );
+        assert!(open_order(
+            &order,
+            &mut product,
+            &mut customer,
+            &mut merchant
+        ));
+
+        assert_eq!(order.cost, 10.0);
+        assert_eq!(product.amount_left, 6.0);
+        assert_eq!(customer.pending_orders, vec!["order".to_owned()]);
+        assert_eq!(merchant.pending_orders, vec!["order".to_owned()]);
+
+        // Pay
+        assert!(order.mark_paid());
+        assert_eq!(order.stage, OrderStage::Paid);
+
+        // Complete the way `Verify::finish` does, a redelivered completion records nothing
+        let mut metas = BrokenRow {
+            rows: vec![customer.clone(), merchant.clone()],
+            broken: usize::MAX,
+        };
+        for _ in 0..2 {
+            if complete_once(&mut order, &mut completed, completed_at) {
+                product.count_sold(order.amount);
+
+                let (mut customer_after, mut merchant_after) = (customer.clone(), merchant.clone());
+                customer_after.close_order(&order.id);
+                merchant_after.close_order(&order.id);
+                update_metas(
+                    &mut metas,
+                    (0, &customer, &customer_after),
+                    (1, &merchant, &merchant_after),
+                )
+                .await
+                .unwrap();
+
+                sales.push(order.clone().into_sale(product.share, completed_at));
+            }
+        }
+
+        assert_eq!(order.stage, OrderStage::Completed);
+        assert_eq!(order.completed_at, Some(completed_at));
+        assert_eq!(product.amount_left, 6.0);
+        assert_eq!(product.amount_sold, 4.0);
+        assert!(product.validate(false).is_empty());
+        for meta in &metas.rows {
+            assert!(meta.pending_orders.is_empty());
+            assert_eq!(meta.completed_orders, vec!["order".to_owned()]);
+        }
+        assert_eq!(sales.len(), 1);
+        let sale = &sales[0];
+        assert!(matches!(sale.sale_type, SaleType::Order));
+        assert_eq!(
+            (sale.merchant.as_str(), sale.customer.as_str()),
+            ("merchant", "customer")
+        );
+        assert_eq!(sale.item_id, "item");
+        assert_eq!((sale.amount, sale.revenue, sale.share), (4.0, 10.0, 0.1));
+        assert_eq!(sale.currency, Currency::EUR);
+        assert_eq!(sale.date, completed_at);
+    }
+
+    #[tokio::test]
+    async fn failed_merchant_meta_rolls_back_customer() {
+        let mut customer = meta("customer");
+        customer.pending_orders.push("order".to_owned());
+        let mut merchant = meta("merchant");
+        merchant.pending_orders.push("order".to_owned());
+
+        let mut metas = BrokenRow {
+            rows: vec![customer.clone(), merchant.clone()],
+            broken: 1,
+        };
+        let (mut customer_after, mut merchant_after) = (customer.clone(), merchant.clone());
+        customer_after.close_order(&"order".to_owned());
+        merchant_after.close_order(&"order".to_owned());
+
+        let updated = update_metas(
+            &mut metas,
+            (0, &customer, &customer_after),
+            (1, &merchant, &merchant_after),
+        )
+        .await;
+
+        assert!(updated.is_err());
+        for meta in &metas.rows {
+            assert_eq!(meta.pending_orders, vec!["order".to_owned()]);
+            assert!(meta.completed_orders.is_empty());
+        }
+
+        metas.broken = usize::MAX;
+        update_metas(
+            &mut metas,
+            (0, &customer, &customer_after),
+            (1, &merchant, &merchant_after),
+        )
+        .await
+        .unwrap();
+
+        for meta in &metas.rows {
+            assert!(meta.pending_orders.is_empty());
+            assert_eq!(meta.completed_orders, vec!["order".to_owned()]);
+        }
+    }
+
+    #[test]
+    fn order_beyond_stock_is_not_opened() {
+        let mut product = product();
+        let mut customer = meta("customer");
+        let mut merchant = meta("merchant");
+
+        let order = new_order(
+            "order".to_owned(),
+            "customer".to_owned(),
+            OrderStage::WaitForPayment,
+            &product,
+            12.0,
+            None,
+            Utc::now(),
+        );
+        assert!(!open_order(
+            &order,
+            &mut product,
+            &mut customer,
+            &mut merchant
+        ));
+        assert_eq!(product.amount_left, 10.0);
+        assert!(customer.pending_orders.is_empty());
+
+        // The allowance lets the order through, the rest is backordered
+        product.oversell_allowance = 2.0;
+        assert!(open_order(
+            &order,
+            &mut product,
+            &mut customer,
+            &mut merchant
+        ));
+        assert_eq!((product.amount_left, product.backordered), (0.0, 2.0));
+        assert_eq!(merchant.pending_orders, vec!["order".to_owned()]);
+    }
+
+    #[test]
+    fn completions_pruned_once_fetched() {
+        let paid = |id: &str| {
+            new_order(
+                id.to_owned(),
+                "customer".to_owned(),
+                OrderStage::Paid,
+                &product(),
+                1.0,
+                None,
+                Utc::now(),
+            )
+        };
+        let mut completed: HashSet<_> = ["stale", "written", "archived"]
+            .into_iter()
+            .map(str::to_owned)
+            .collect();
+
+        let mut written = paid("written");
+        assert!(written.mark_completed(Utc::now()));
+        prune_completed(&mut completed, &[paid("stale"), written, paid("other")]);
+
+        assert_eq!(completed, ["stale".to_owned()].into());
+    }
+}
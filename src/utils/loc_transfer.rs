@@ -0,0 +1,201 @@
+use std::{collections::HashSet, fmt::Display};
+
+use crate::prelude::*;
+
+#[derive(Debug, PartialEq)]
+pub enum ImportError {
+    Malformed(String),
+    EmptyKey(usize),
+    DuplicateKey(String),
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Malformed(e) => write!(f, "malformed JSON: {}", e),
+            ImportError::EmptyKey(idx) => write!(f, "entry {} has an empty key phrase", idx),
+            ImportError::DuplicateKey(key) => write!(f, "key phrase \"{}\" is repeated", key),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Every localization as a JSON array, in the order of the sheet.
+pub fn export<'a>(entries: impl IntoIterator<Item = &'a Localization>) -> Result<String> {
+    let entries: Vec<_> = entries.into_iter().collect();
+    Ok(serde_json::to_string_pretty(&entries)?)
+}
+
+/// Parses a document made by [`export`]. Nothing is imported unless every
+/// entry is valid, so a broken file never lands in the sheet halfway.
+pub fn parse(json: &str) -> std::result::Result<Vec<Localization>, ImportError> {
+    let entries: Vec<Localization> =
+        serde_json::from_str(json).map_err(|e| ImportError::Malformed(e.to_string()))?;
+
+    let mut keys = HashSet::new();
+    for (idx, entry) in entries.iter().enumerate() {
+        if entry.key_phrase.trim().is_empty() {
+            return Err(ImportError::EmptyKey(idx));
+        }
+        if !keys.insert(&entry.key_phrase) {
+            return Err(ImportError::DuplicateKey(entry.key_phrase.clone()));
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Writes an import makes to the localization table.
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportPlan {
+    /// Rows whose translations change, by row.
+    pub updates: Vec<(usize, Localization)>,
+    /// Key phrases the table doesn't have yet.
+    pub additions: Vec<Localization>,
+    pub unchanged: usize,
+}
+
+impl ImportPlan {
+    /// Merges the imported entries into the table by key phrase, `lookup`
+    /// finds the row of a key phrase like the `by_key_phrase` index does.
+    pub fn new<'a>(
+        imported: Vec<Localization>,
+        lookup: impl Fn(&String) -> Option<&'a (usize, Localization)>,
+    ) -> Self {
+        let mut plan = Self::default();
+
+        for entry in imported {
+            match lookup(&entry.key_phrase) {
+                Some((_, existing)) if *existing == entry => plan.unchanged += 1,
+                Some((row, _)) => plan.updates.push((*row, entry)),
+                None => plan.additions.push(entry),
+            }
+        }
+
+        plan.updates.sort_by_key(|(row, _)| *row);
+        plan
+    }
+
+    /// Updates grouped into runs of adjacent rows, one batch write each.
+    pub fn update_runs(&self) -> Vec<(usize, Vec<&Localization>)> {
+        let mut runs: Vec<(usize, Vec<&Localization>)> = vec![];
+
+        for (row, entry) in &self.updates {
+            match runs.last_mut() {
+                Some((from_row, run)) if *from_row + run.len() == *row => run.push(entry),
+                _ => runs.push((*row, vec![entry])),
+            }
+        }
+
+        runs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loc(key_phrase: &str, en: &str, ru: &str) -> Localization {
+        Localization {
+            key_phrase: key_phrase.to_owned(),
+            en: en.to_owned(),
+            ru: ru.to_owned(),
+        }
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let entries = vec![loc("Cancel", "Cancel", "Отмена"), loc("Details", "-", "-")];
+
+        let json = export(&entries).unwrap();
+        assert!(json.contains("\"key_phrase\": \"Cancel\""));
+        assert_eq!(parse(&json).unwrap(), entries);
+    }
+
+    #[test]
+    fn import_merges_by_key_phrase() {
+        let table = vec![
+            (0, loc("Cancel", "Cancel", "-")),
+            (1, loc("Details", "Details", "Подробнее")),
+            (2, loc("Paid", "Paid", "-")),
+            (4, loc("Draft", "Draft", "-")),
+        ];
+        let lookup = |key: &String| table.iter().find(|(_, entry)| &entry.key_phrase == key);
+
+        let plan = ImportPlan::new(
+            vec![
+                loc("Draft", "Draft", "Черновик"),
+                loc("Details", "Details", "Подробнее"),
+                loc("Cancel", "Cancel", "Отмена"),
+                loc("Reorder", "Reorder", "Повторить"),
+                loc("Paid", "Paid", "Оплачен"),
+            ],
+            lookup,
+        );
+
+        assert_eq!(plan.unchanged, 1);
+        assert_eq!(plan.additions, vec![loc("Reorder", "Reorder", "Повторить")]);
+        assert_eq!(
+            plan.updates,
+            vec![
+                (0, loc("Cancel", "Cancel", "Отмена")),
+                (2, loc("Paid", "Paid", "Оплачен")),
+                (4, loc("Draft", "Draft", "Черновик")),
+            ]
+        );
+
+        let runs: Vec<_> = plan
+            .update_runs()
+            .into_iter()
+            .map(|(row, run)| (row, run.len()))
+            .collect();
+        assert_eq!(runs, vec![(0, 1), (2, 1), (4, 1)]);
+    }
+
+    #[test]
+    fn adjacent_updates_share_a_write() {
+        let plan = ImportPlan {
+            updates: vec![
+                (3, loc("a", "a", "-")),
+                (4, loc("b", "b", "-")),
+                (6, loc("c", "c", "-")),
+            ],
+            ..Default::default()
+        };
+
+        let runs: Vec<_> = plan
+            .update_runs()
+            .into_iter()
+            .map(|(row, run)| (row, run.len()))
+            .collect();
+        assert_eq!(runs, vec![(3, 2), (6, 1)]);
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        assert!(matches!(
+            parse("[{\"key_phrase\": "),
+            Err(ImportError::Malformed(_))
+        ));
+        assert!(matches!(
+            parse("{\"Cancel\": \"Отмена\"}"),
+            Err(ImportError::Malformed(_))
+        ));
+        assert!(matches!(
+            parse("[{\"key_phrase\": \"Cancel\", \"en\": \"Cancel\"}]"),
+            Err(ImportError::Malformed(_))
+        ));
+
+        assert_eq!(
+            parse("[{\"key_phrase\": \" \", \"en\": \"-\", \"ru\": \"-\"}]"),
+            Err(ImportError::EmptyKey(0))
+        );
+
+        let repeated = export(&[loc("Cancel", "-", "-"), loc("Cancel", "Cancel", "-")]).unwrap();
+        assert_eq!(
+            parse(&repeated),
+            Err(ImportError::DuplicateKey("Cancel".to_owned()))
+        );
+    }
+}
@@ -1,4 +1,19 @@
+pub mod archive;
+pub mod deadstock;
+pub mod dump;
+pub mod duplicate;
+pub mod labels;
+pub mod lang_stats;
+pub mod lifecycle;
+pub mod loc_transfer;
+pub mod outcome;
 pub mod payload;
+pub mod pending;
+pub mod preview;
+pub mod raw_row;
+pub mod restock;
+pub mod revenue;
 pub mod row;
+pub mod text;
 #[allow(dead_code)]
 pub mod verify;
@@ -0,0 +1,158 @@
+use log::warn;
+
+use crate::prelude::*;
+
+/// Participant of an order who acted on it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Party {
+    Customer,
+    Merchant,
+}
+
+impl Party {
+    pub fn of(order: &Order, username: &str) -> Self {
+        if order.merchant == username {
+            Party::Merchant
+        } else {
+            Party::Customer
+        }
+    }
+}
+
+/// Flow whose change is committed before the other participant is told about it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flow {
+    Purchase,
+    Complete(Party),
+    Cancel(Party),
+}
+
+/// How a flow ended for the user. Telling the other participant is best effort,
+/// its failure doesn't undo the change, so it isn't reported as an error the
+/// user would retry and duplicate the change over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Done,
+    Unnotified,
+}
+
+impl Outcome {
+    /// Outcome of the committed flow given how notifying went.
+    pub fn after<T>(flow: Flow, notified: Result<T>) -> Self {
+        match notified {
+            Ok(_) => Outcome::Done,
+            Err(e) => {
+                warn!(
+                    "{:?} is committed, but the other participant wasn't notified: {}",
+                    flow, e
+                );
+                Outcome::Unnotified
+            }
+        }
+    }
+
+    /// Key phrase the user is told the outcome with.
+    pub fn message(self, flow: Flow) -> &'static str {
+        match (self, flow) {
+            (Outcome::Done, Flow::Purchase) => "Your order is placed.",
+            (Outcome::Done, Flow::Complete(_)) => "Order successfully completed.",
+            (Outcome::Done, Flow::Cancel(_)) => "Order successfully cancelled.",
+            (Outcome::Unnotified, Flow::Purchase) => concat!(
+                "Your order is placed, but we couldn't notify the seller right now. ",
+                "You can contact {merchant} yourself."
+            ),
+            (Outcome::Unnotified, Flow::Complete(Party::Merchant)) => concat!(
+                "Order completed, but we couldn't notify the customer right now. ",
+                "They will find it among their orders."
+            ),
+            (Outcome::Unnotified, Flow::Complete(Party::Customer)) => concat!(
+                "Order completed, but we couldn't notify the seller right now. ",
+                "They will find it among their orders."
+            ),
+            (Outcome::Unnotified, Flow::Cancel(Party::Merchant)) => concat!(
+                "Order cancelled, but we couldn't notify the customer right now. ",
+                "They will find it among their orders."
+            ),
+            (Outcome::Unnotified, Flow::Cancel(Party::Customer)) => concat!(
+                "Order cancelled, but we couldn't notify the seller right now. ",
+                "They will find it among their orders."
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BoxedError;
+
+    /// Cancels like the callback does: the change is committed first, then the
+    /// other participant is told about it.
+    async fn cancel(
+        stage: &mut OrderStage,
+        by: Party,
+        notice: impl std::future::Future<Output = Result<()>>,
+    ) -> Result<&'static str> {
+        if *stage == OrderStage::Completed {
+            return Err("Sorry, this order is already completed.".into());
+        }
+        *stage = OrderStage::Cancelled;
+
+        let flow = Flow::Cancel(by);
+        Ok(Outcome::after(flow, notice.await).message(flow))
+    }
+
+    #[tokio::test]
+    async fn cancel_reports_partial_success() {
+        let mut stage = OrderStage::WaitForPayment;
+
+        let message = cancel(&mut stage, Party::Customer, async {
+            Err::<(), BoxedError>("Forbidden: bot was blocked by the user".into())
+        })
+        .await
+        .expect("A failed notice failed the committed cancel");
+
+        assert_eq!(stage, OrderStage::Cancelled);
+        assert!(message.starts_with("Order cancelled, but we couldn't notify the seller"));
+
+        let mut stage = OrderStage::Paid;
+        let message = cancel(&mut stage, Party::Merchant, async {
+            Ok::<(), BoxedError>(())
+        })
+        .await
+        .unwrap();
+        assert_eq!(message, "Order successfully cancelled.");
+    }
+
+    #[test]
+    fn unnotified_wording_depends_on_who_acted() {
+        let merchant = Outcome::Unnotified.message(Flow::Complete(Party::Merchant));
+        let customer = Outcome::Unnotified.message(Flow::Complete(Party::Customer));
+
+        assert!(merchant.contains("notify the customer"));
+        assert!(customer.contains("notify the seller"));
+    }
+
+    #[test]
+    fn failed_notification_is_partial_success() {
+        for flow in [
+            Flow::Purchase,
+            Flow::Complete(Party::Merchant),
+            Flow::Cancel(Party::Customer),
+        ] {
+            let outcome = Outcome::after(flow, Err::<(), BoxedError>("chat not found".into()));
+
+            assert_eq!(outcome, Outcome::Unnotified);
+            assert_ne!(outcome.message(flow), Outcome::Done.message(flow));
+        }
+
+        let outcome = Outcome::after(Flow::Purchase, Err::<(), BoxedError>("blocked".into()));
+        assert!(outcome
+            .message(Flow::Purchase)
+            .starts_with("Your order is placed"));
+        assert_eq!(
+            Outcome::after(Flow::Cancel(Party::Merchant), Ok(())),
+            Outcome::Done
+        );
+    }
+}
@@ -11,12 +11,16 @@ lazy_static! {
     pub static ref PAYLOAD_RE: Regex = Regex::new(concat!(
         r"^(?<op>\d*)",
         r"(\sp(?<product_id>\d*))?",
+        r"(\sk(?<product_key>[^\s]*))?",
         r"(\so(?<order_id>[^\s]*))?",
         r"(\sa(?<amount>\d*))?"
     ))
     .unwrap();
 }
 
+/// Telegram rejects buttons whose callback data is longer, in bytes.
+pub const CALLBACK_DATA_LIMIT: usize = 64;
+
 macro_rules! write_arg {
     ($args:expr, $prefix:expr, $item:expr) => {
         if let Some(some) = $item.as_ref() {
@@ -24,29 +28,103 @@ macro_rules! write_arg {
         };
     };
 }
+
+/// Merchant and item of a listing. Unlike the hashed [`ProductId`] it names
+/// the exact listing, even when several merchants offer the same item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProductKey {
+    pub merchant: String,
+    pub item_id: String,
+}
+
+impl ProductKey {
+    pub fn of(product: &Product) -> Self {
+        Self {
+            merchant: product.merchant.clone(),
+            item_id: product.item_id.clone(),
+        }
+    }
+
+    /// Payload arguments are split by whitespace, so a key holding any
+    /// can't be read back.
+    pub fn is_encodable(&self) -> bool {
+        !self.merchant.contains(char::is_whitespace) && !self.item_id.contains(char::is_whitespace)
+    }
+
+    pub fn id(&self) -> ProductId {
+        Product::id_from(&self.merchant, &self.item_id)
+    }
+
+    /// Picks the listing among the products of the item.
+    pub fn find_in<'p>(&self, listings: &'p [(usize, Product)]) -> Option<&'p (usize, Product)> {
+        listings.iter().find(|(_, product)| {
+            product.merchant == self.merchant && product.item_id == self.item_id
+        })
+    }
+}
+
+impl Display for ProductKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.merchant, self.item_id)
+    }
+}
+
+impl FromStr for ProductKey {
+    type Err = ();
+
+    /// Usernames can't contain a slash, so the first one separates the item.
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        match key.split_once('/') {
+            Some((merchant, item_id)) if !merchant.is_empty() && !item_id.is_empty() => Ok(Self {
+                merchant: merchant.to_owned(),
+                item_id: item_id.to_owned(),
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct Payload {
     pub op: PayloadOp,
     pub product_id: Option<ProductId>,
+    pub product_key: Option<ProductKey>,
     pub order_id: Option<OrderId>,
     pub amount: Option<u32>,
 }
 
 impl Payload {
-    pub fn purchase(product_id: ProductId) -> Self {
-        Self {
-            op: PayloadOp::Purchase,
-            product_id: Some(product_id),
+    /// Names the listing by its [`ProductKey`] when the key fits the callback
+    /// data, the hashed id otherwise.
+    fn for_product(op: PayloadOp, product: &Product) -> Self {
+        let key = ProductKey::of(product);
+        let keyed = Self {
+            op,
+            product_key: Some(key.clone()),
             ..Default::default()
+        };
+
+        if key.is_encodable() && keyed.to_string().len() <= CALLBACK_DATA_LIMIT {
+            keyed
+        } else {
+            Self {
+                op,
+                product_id: Some(key.id()),
+                ..Default::default()
+            }
         }
     }
 
-    pub fn redeem(product_id: ProductId) -> Self {
-        Self {
-            op: PayloadOp::Redeem,
-            product_id: Some(product_id),
-            ..Default::default()
-        }
+    pub fn purchase(product: &Product) -> Self {
+        Self::for_product(PayloadOp::Purchase, product)
+    }
+
+    pub fn redeem(product: &Product) -> Self {
+        Self::for_product(PayloadOp::Redeem, product)
+    }
+
+    pub fn report(product: &Product) -> Self {
+        Self::for_product(PayloadOp::ReportProduct, product)
     }
 
     pub fn checkout(order_id: OrderId) -> Self {
@@ -88,6 +166,55 @@ impl Payload {
             ..Default::default()
         }
     }
+
+    pub fn publish(product_id: ProductId) -> Self {
+        Self {
+            op: PayloadOp::PublishProduct,
+            product_id: Some(product_id),
+            ..Default::default()
+        }
+    }
+
+    /// Pauses or resumes the product, see [`Product::toggle_active`].
+    pub fn toggle_active(product_id: ProductId) -> Self {
+        Self {
+            op: PayloadOp::ToggleProduct,
+            product_id: Some(product_id),
+            ..Default::default()
+        }
+    }
+
+    pub fn message_order(order_id: OrderId) -> Self {
+        Self {
+            op: PayloadOp::MessageOrder,
+            order_id: Some(order_id),
+            ..Default::default()
+        }
+    }
+
+    /// Places a completed order again, see the purchase dialogue.
+    pub fn reorder(order_id: OrderId) -> Self {
+        Self {
+            op: PayloadOp::Reorder,
+            order_id: Some(order_id),
+            ..Default::default()
+        }
+    }
+
+    /// Completes every paid order of the merchant, see `/pending`.
+    pub fn complete_all_paid() -> Self {
+        Self {
+            op: PayloadOp::CompleteAllPaid,
+            ..Default::default()
+        }
+    }
+
+    pub fn cancel_dialogue() -> Self {
+        Self {
+            op: PayloadOp::CancelDialogue,
+            ..Default::default()
+        }
+    }
 }
 
 impl ToString for Payload {
@@ -95,6 +222,7 @@ impl ToString for Payload {
         let mut args = vec![self.op.to_string()];
 
         write_arg!(args, "p", self.product_id);
+        write_arg!(args, "k", self.product_key);
         write_arg!(args, "o", self.order_id);
         write_arg!(args, "a", self.amount);
 
@@ -126,6 +254,12 @@ impl FromStr for Payload {
                 .transpose()
                 .map_err(|_| PayloadError::InvalidProductId(payload.clone()))?;
 
+            let product_key = captures
+                .name("product_key")
+                .map(|s| s.as_str().parse::<ProductKey>())
+                .transpose()
+                .map_err(|_| PayloadError::InvalidProductId(payload.clone()))?;
+
             let order_id = captures
                 .name("order_id")
                 .map(|s| s.as_str().parse::<OrderId>())
@@ -141,6 +275,7 @@ impl FromStr for Payload {
             Ok(Payload {
                 op,
                 product_id,
+                product_key,
                 order_id,
                 amount,
             })
@@ -161,11 +296,19 @@ pub enum PayloadOp {
     CompleteOrder,
     PayOrder,
     SpecifyOrderPrice,
+    CancelDialogue,
+    PublishProduct,
+    MessageOrder,
+    ReportProduct,
+    Reorder,
+    CompleteAllPaid,
+    ToggleProduct,
 }
 
 impl PayloadOp {
+    /// Checks the leading op of the payload, so that "10" doesn't match op 1.
     pub fn is_in_payload(&self, haystack: &str) -> bool {
-        haystack.starts_with(&self.to_string())
+        haystack.split(' ').next() == Some(self.to_string().as_str())
     }
 }
 
@@ -203,3 +346,78 @@ impl Display for PayloadError {
 }
 
 impl std::error::Error for PayloadError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::fixtures;
+
+    fn product(merchant: &str) -> Product {
+        Product {
+            merchant: merchant.to_owned(),
+            item_id: "apple".to_owned(),
+            ..fixtures::product()
+        }
+    }
+
+    #[test]
+    fn product_key_resolves_the_listing() {
+        let listings = vec![(0, product("alice")), (1, product("bob"))];
+
+        let payload: Payload = Payload::purchase(&listings[1].1)
+            .to_string()
+            .parse()
+            .unwrap();
+        assert_eq!(payload.op, PayloadOp::Purchase);
+
+        let key = payload.product_key.unwrap();
+        assert_eq!(key, ProductKey::of(&listings[1].1));
+        assert_eq!(key.id(), listings[1].1.id());
+        assert_eq!(key.find_in(&listings).map(|(row, _)| *row), Some(1));
+
+        let gone = ProductKey {
+            merchant: "carol".to_owned(),
+            item_id: "apple".to_owned(),
+        };
+        assert!(gone.find_in(&listings).is_none());
+    }
+
+    #[test]
+    fn falls_back_to_hashed_id() {
+        let spaced = Product {
+            item_id: "green apple".to_owned(),
+            ..product("alice")
+        };
+        let long = Product {
+            item_id: "a".repeat(CALLBACK_DATA_LIMIT),
+            ..product("alice")
+        };
+
+        for product in [spaced, long] {
+            let data = Payload::purchase(&product).to_string();
+            assert!(data.len() <= CALLBACK_DATA_LIMIT);
+
+            let payload: Payload = data.parse().unwrap();
+            assert_eq!(payload.product_key, None);
+            assert_eq!(payload.product_id, Some(product.id()));
+        }
+    }
+
+    #[test]
+    fn legacy_product_id_still_parses() {
+        let payload: Payload = "1 p42 a3".parse().unwrap();
+        assert_eq!(payload.product_id, Some(42));
+        assert_eq!(payload.product_key, None);
+        assert_eq!(payload.amount, Some(3));
+    }
+
+    #[test]
+    fn ops_match_whole_token() {
+        let payload = Payload::message_order("order".to_owned()).to_string();
+
+        assert!(PayloadOp::MessageOrder.is_in_payload(&payload));
+        assert!(!PayloadOp::Purchase.is_in_payload(&payload));
+        assert!(PayloadOp::Purchase.is_in_payload("1 p10 a2"));
+        assert!(PayloadOp::CancelDialogue.is_in_payload("8"));
+    }
+}
@@ -0,0 +1,125 @@
+use crate::prelude::*;
+
+/// What the merchant has to do to move an order on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PendingAction {
+    SpecifyPrice,
+    Complete,
+}
+
+impl PendingAction {
+    pub fn of(order: &Order) -> Option<Self> {
+        match order.stage {
+            OrderStage::Negotiated => Some(PendingAction::SpecifyPrice),
+            OrderStage::WaitForPayment | OrderStage::Paid => Some(PendingAction::Complete),
+            OrderStage::Completed | OrderStage::Cancelled => None,
+        }
+    }
+}
+
+/// Orders of the merchant waiting for them, oldest first.
+pub fn pending_orders<'a>(
+    orders: impl IntoIterator<Item = &'a Order>,
+    merchant: &str,
+) -> Vec<(&'a Order, PendingAction)> {
+    let mut pending: Vec<_> = orders
+        .into_iter()
+        .filter(|order| order.merchant == merchant)
+        .filter_map(|order| Some((order, PendingAction::of(order)?)))
+        .collect();
+
+    pending.sort_by_key(|(order, _)| order.date);
+    pending
+}
+
+/// Paid orders of the merchant, the ones the bulk action completes.
+pub fn paid_order_ids<'a>(
+    orders: impl IntoIterator<Item = &'a Order>,
+    merchant: &str,
+) -> Vec<OrderId> {
+    orders
+        .into_iter()
+        .filter(|order| order.merchant == merchant && order.stage == OrderStage::Paid)
+        .map(|order| order.id.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::entries::fixtures;
+    use crate::utils::lifecycle::complete_once;
+
+    fn order(id: &str, merchant: &str, stage: OrderStage, age: i64) -> Order {
+        Order {
+            id: id.to_owned(),
+            merchant: merchant.to_owned(),
+            stage,
+            cost: 2.0,
+            date: Utc::now() - Duration::hours(age),
+            ..fixtures::order()
+        }
+    }
+
+    fn orders() -> Vec<Order> {
+        vec![
+            order("paid", "merchant", OrderStage::Paid, 1),
+            order("negotiated", "merchant", OrderStage::Negotiated, 3),
+            order("waiting", "merchant", OrderStage::WaitForPayment, 2),
+            order("completed", "merchant", OrderStage::Completed, 4),
+            order("cancelled", "merchant", OrderStage::Cancelled, 5),
+            order("foreign", "other", OrderStage::Paid, 6),
+        ]
+    }
+
+    #[test]
+    fn lists_actionable_orders_only() {
+        let orders = orders();
+
+        let pending: Vec<_> = pending_orders(&orders, "merchant")
+            .into_iter()
+            .map(|(order, action)| (order.id.as_str(), action))
+            .collect();
+
+        assert_eq!(
+            pending,
+            vec![
+                ("negotiated", PendingAction::SpecifyPrice),
+                ("waiting", PendingAction::Complete),
+                ("paid", PendingAction::Complete),
+            ]
+        );
+    }
+
+    #[test]
+    fn bulk_complete_affects_paid_orders_only() {
+        let mut orders = orders();
+        let paid = paid_order_ids(&orders, "merchant");
+        assert_eq!(paid, vec!["paid".to_owned()]);
+
+        let mut completed = HashSet::new();
+        for order in orders.iter_mut().filter(|order| paid.contains(&order.id)) {
+            assert!(complete_once(order, &mut completed, Utc::now()));
+        }
+
+        let stages: Vec<_> = orders
+            .iter()
+            .map(|order| (order.id.as_str(), order.stage.clone()))
+            .collect();
+        assert_eq!(
+            stages,
+            vec![
+                ("paid", OrderStage::Completed),
+                ("negotiated", OrderStage::Negotiated),
+                ("waiting", OrderStage::WaitForPayment),
+                ("completed", OrderStage::Completed),
+                ("cancelled", OrderStage::Cancelled),
+                ("foreign", OrderStage::Paid),
+            ]
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use crate::{prelude::*, utils::text};
+
+/// A localization rendered the way the bot would show it.
+#[derive(Debug, PartialEq)]
+pub struct Preview {
+    pub rendered: String,
+    /// Placeholders of the key phrase the translation doesn't show.
+    pub missing: Vec<String>,
+    /// Placeholders the bot has no value for, they are dropped from the text.
+    pub unfilled: Vec<String>,
+}
+
+/// Renders the localization in the language, every placeholder of the key
+/// phrase is filled with a sample value named after it.
+pub fn preview(loc: &Localization, lang: &str) -> Preview {
+    let expected = text::placeholders(&loc.key_phrase);
+    let template = loc.get(lang);
+    let used = text::placeholders(&template);
+
+    let args: HashMap<_, _> = expected
+        .iter()
+        .map(|key| (key.clone(), format!("[{}]", key)))
+        .collect();
+
+    Preview {
+        rendered: text::fill_placeholders(&template, &args),
+        missing: expected
+            .iter()
+            .filter(|key| !used.contains(key))
+            .cloned()
+            .collect(),
+        unfilled: used
+            .into_iter()
+            .filter(|key| !expected.contains(key))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loc(ru: &str) -> Localization {
+        Localization {
+            key_phrase: "Your order for {name} has been successfully completed.".to_owned(),
+            en: "-".to_owned(),
+            ru: ru.to_owned(),
+        }
+    }
+
+    #[test]
+    fn renders_with_sample_args() {
+        let loc = loc("Ваш заказ {name} успешно завершён.");
+
+        assert_eq!(
+            preview(&loc, "en"),
+            Preview {
+                rendered: "Your order for [name] has been successfully completed.".to_owned(),
+                missing: vec![],
+                unfilled: vec![],
+            }
+        );
+        assert_eq!(
+            preview(&loc, "ru").rendered,
+            "Ваш заказ [name] успешно завершён."
+        );
+    }
+
+    #[test]
+    fn flags_mismatched_placeholders() {
+        let preview = preview(&loc("Ваш заказ {nmae} успешно завершён."), "ru");
+
+        assert_eq!(preview.rendered, "Ваш заказ успешно завершён.");
+        assert_eq!(preview.missing, vec!["name".to_owned()]);
+        assert_eq!(preview.unfilled, vec!["nmae".to_owned()]);
+    }
+}
@@ -0,0 +1,146 @@
+use serde::{
+    de::{self, DeserializeOwned, Visitor},
+    forward_to_deserialize_any, Deserializer,
+};
+use serde_json::Value as CellValue;
+
+use crate::entries::REDACTED;
+
+/// Columns holding secrets, see [`Secret`](crate::entries::Secret).
+const SECRET_FIELDS: &[&str] = &["provider_token"];
+
+/// Field names of a struct, in the order they are stored in the sheet.
+pub fn field_names<E: DeserializeOwned>() -> &'static [&'static str] {
+    let mut fields = None;
+    let _ = E::deserialize(FieldsDeserializer(&mut fields));
+    fields.unwrap_or(&[])
+}
+
+/// Renders the cells one per line, named after the struct fields where possible.
+/// Secret cells are shown as redacted.
+pub fn render_row(fields: &[&str], cells: &[CellValue]) -> String {
+    let len = fields.len().max(cells.len());
+
+    (0..len)
+        .map(|idx| {
+            let name = fields
+                .get(idx)
+                .map(|name| name.to_string())
+                .unwrap_or(format!("#{}", idx));
+
+            let value = match cells.get(idx) {
+                None | Some(CellValue::Null) => "".to_owned(),
+                Some(CellValue::String(s)) if s.is_empty() => "".to_owned(),
+                Some(_) if SECRET_FIELDS.contains(&name.as_str()) => REDACTED.to_owned(),
+                Some(CellValue::String(s)) => s.clone(),
+                Some(cell) => cell.to_string(),
+            };
+
+            format!("{}. {}: {}", idx, name, value)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Column index by field name or by the number itself.
+pub fn resolve_column(fields: &[&str], column: &str) -> Option<usize> {
+    fields
+        .iter()
+        .position(|name| *name == column)
+        .or_else(|| column.parse::<usize>().ok())
+}
+
+/// Only records the fields of the struct it's asked to deserialize.
+struct FieldsDeserializer<'a>(&'a mut Option<&'static [&'static str]>);
+
+impl<'de, 'a> Deserializer<'de> for FieldsDeserializer<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::custom("expected a struct"))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        *self.0 = Some(fields);
+        Err(de::Error::custom("fields collected"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Entry {
+        name: String,
+        amount: u32,
+        visible: bool,
+    }
+
+    #[test]
+    fn struct_field_names() {
+        assert_eq!(field_names::<Entry>(), &["name", "amount", "visible"]);
+        assert!(field_names::<u32>().is_empty());
+    }
+
+    #[test]
+    fn row_rendering() {
+        let fields = field_names::<Entry>();
+
+        assert_eq!(
+            render_row(fields, &[json!("Hat"), json!(3)]),
+            "0. name: Hat\n1. amount: 3\n2. visible: "
+        );
+        assert_eq!(
+            render_row(
+                fields,
+                &[json!("Hat"), json!("3"), json!(true), json!("extra")]
+            ),
+            "0. name: Hat\n1. amount: 3\n2. visible: true\n3. #3: extra"
+        );
+    }
+
+    #[test]
+    fn secret_cells_are_redacted() {
+        let fields = ["name", "provider_token"];
+
+        assert_eq!(
+            render_row(&fields, &[json!("alice"), json!("284685063:TEST:token")]),
+            "0. name: alice\n1. provider_token: <redacted>"
+        );
+        assert_eq!(
+            render_row(&fields, &[json!("alice"), json!("")]),
+            "0. name: alice\n1. provider_token: "
+        );
+    }
+
+    #[test]
+    fn column_by_name_or_index() {
+        let fields = field_names::<Entry>();
+
+        assert_eq!(resolve_column(fields, "visible"), Some(2));
+        assert_eq!(resolve_column(fields, "1"), Some(1));
+        assert_eq!(resolve_column(fields, "price"), None);
+    }
+}
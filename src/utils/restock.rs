@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::prelude::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RestockEntry {
+    pub merchant: String,
+    pub item_id: String,
+    pub amount_left: f64,
+    /// Units sold per day over the window.
+    pub velocity: f64,
+    /// Estimated days until the product runs out, `None` if it doesn't sell.
+    pub days_left: Option<f64>,
+    pub needs_restock: bool,
+}
+
+/// Units sold per day for every (merchant, item_id) over the window before `now`.
+pub fn sales_velocity<'a>(
+    sales: impl IntoIterator<Item = &'a Sale>,
+    now: DateTime<Utc>,
+    window: Duration,
+) -> HashMap<(String, String), f64> {
+    let since = now - window;
+    let days = (window.num_seconds() as f64 / 86_400.0).max(1.0);
+
+    let mut sold = HashMap::<(String, String), f64>::new();
+    for sale in sales
+        .into_iter()
+        .filter(|sale| sale.date > since && sale.date <= now)
+    {
+        *sold
+            .entry((sale.merchant.clone(), sale.item_id.clone()))
+            .or_default() += sale.amount;
+    }
+
+    sold.into_iter()
+        .map(|(key, amount)| (key, amount / days))
+        .collect()
+}
+
+pub fn days_to_stockout(amount_left: f64, velocity: f64) -> Option<f64> {
+    if amount_left <= 0.0 {
+        Some(0.0)
+    } else if velocity > 0.0 {
+        Some(amount_left / velocity)
+    } else {
+        None
+    }
+}
+
+/// Products that sell or are out of stock, the ones running out first go first.
+pub fn restock_report<'a>(
+    products: impl IntoIterator<Item = &'a Product>,
+    sales: impl IntoIterator<Item = &'a Sale>,
+    now: DateTime<Utc>,
+    window: Duration,
+    horizon: Duration,
+) -> Vec<RestockEntry> {
+    let velocity = sales_velocity(sales, now, window);
+    let horizon = horizon.num_seconds() as f64 / 86_400.0;
+
+    let mut report: Vec<_> = products
+        .into_iter()
+        .map(|product| {
+            let velocity = velocity
+                .get(&(product.merchant.clone(), product.item_id.clone()))
+                .copied()
+                .unwrap_or(0.0);
+            let days_left = days_to_stockout(product.amount_left, velocity);
+
+            RestockEntry {
+                merchant: product.merchant.clone(),
+                item_id: product.item_id.clone(),
+                amount_left: product.amount_left,
+                velocity,
+                days_left,
+                needs_restock: days_left.map_or(false, |days| days <= horizon),
+            }
+        })
+        .filter(|entry| entry.days_left.is_some())
+        .collect();
+
+    report.sort_by(|a, b| a.days_left.partial_cmp(&b.days_left).unwrap());
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::fixtures;
+
+    fn product(item_id: &str, amount_left: f64) -> Product {
+        Product {
+            item_id: item_id.to_owned(),
+            amount_granted: amount_left,
+            amount_left,
+            ..fixtures::product()
+        }
+    }
+
+    fn sale(item_id: &str, amount: f64, date: DateTime<Utc>) -> Sale {
+        Sale {
+            merchant: "merchant".to_owned(),
+            sale_type: SaleType::HandToHand,
+            customer: "customer".to_owned(),
+            item_id: item_id.to_owned(),
+            comment: "".to_owned(),
+            amount,
+            revenue: amount,
+            currency: Currency::EUR,
+            share: 0.0,
+            date,
+            base_amount: None,
+            base_currency: None,
+        }
+    }
+
+    #[test]
+    fn velocity_over_window() {
+        let now = Utc::now();
+        let sales = [
+            sale("hat", 6.0, now - Duration::days(1)),
+            sale("hat", 4.0, now - Duration::days(9)),
+            // Outside of the window
+            sale("hat", 100.0, now - Duration::days(11)),
+        ];
+
+        let velocity = sales_velocity(&sales, now, Duration::days(10));
+        assert_eq!(velocity[&("merchant".to_owned(), "hat".to_owned())], 1.0);
+    }
+
+    #[test]
+    fn stockout_estimate() {
+        assert_eq!(days_to_stockout(10.0, 2.0), Some(5.0));
+        assert_eq!(days_to_stockout(10.0, 0.0), None);
+        assert_eq!(days_to_stockout(0.0, 0.0), Some(0.0));
+        assert_eq!(days_to_stockout(0.0, 2.0), Some(0.0));
+    }
+
+    #[test]
+    fn report_flags_products() {
+        let now = Utc::now();
+        let products = [
+            product("hat", 20.0),
+            product("scarf", 5.0),
+            product("gloves", 0.0),
+            product("socks", 3.0),
+        ];
+        let sales = [
+            sale("hat", 10.0, now - Duration::days(2)),
+            sale("scarf", 10.0, now - Duration::days(2)),
+        ];
+
+        let report = restock_report(
+            &products,
+            &sales,
+            now,
+            Duration::days(10),
+            Duration::days(7),
+        );
+
+        let summary: Vec<_> = report
+            .iter()
+            .map(|entry| (entry.item_id.as_str(), entry.days_left, entry.needs_restock))
+            .collect();
+
+        // Socks don't sell, so there is nothing to estimate
+        assert_eq!(
+            summary,
+            vec![
+                ("gloves", Some(0.0), true),
+                ("scarf", Some(5.0), true),
+                ("hat", Some(20.0), false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use crate::{entries::ExchangeRates, prelude::*};
+
+/// Revenue of the sales in the base currency. Sales keep the amount pinned
+/// when they were made, only older ones and the ones pinned in another base
+/// currency are converted at the current rates.
+/// Returns the total and the number of sales without a rate.
+pub fn base_revenue<'a>(
+    sales: impl IntoIterator<Item = &'a Sale>,
+    rates: &ExchangeRates,
+) -> (f64, usize) {
+    sales
+        .into_iter()
+        .fold((0.0, 0), |(total, unconverted), sale| {
+            match sale
+                .base_amount
+                .filter(|_| sale.base_currency == Some(rates.base))
+                .or_else(|| rates.convert(sale.revenue, &sale.currency))
+            {
+                Some(amount) => (total + amount, unconverted),
+                None => (total, unconverted + 1),
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::Utc;
+
+    use super::*;
+
+    fn rates(czk: f64) -> ExchangeRates {
+        ExchangeRates::new("EUR", &HashMap::from([("CZK".to_owned(), czk)])).unwrap()
+    }
+
+    fn sale(revenue: f64, currency: Currency) -> Sale {
+        Sale {
+            merchant: "merchant".to_owned(),
+            sale_type: SaleType::HandToHand,
+            customer: "customer".to_owned(),
+            item_id: "item".to_owned(),
+            comment: "".to_owned(),
+            amount: 1.0,
+            revenue,
+            currency,
+            share: 0.0,
+            date: Utc::now(),
+            base_amount: None,
+            base_currency: None,
+        }
+    }
+
+    #[test]
+    fn pinned_amount_survives_rate_changes() {
+        let mut pinned = sale(250.0, Currency::CZK);
+        pinned.pin_base_amount(Some(&rates(0.04)));
+        assert_eq!(pinned.base_amount, Some(10.0));
+
+        let sales = [pinned, sale(5.0, Currency::EUR)];
+
+        // The rate has doubled since the sale
+        assert_eq!(base_revenue(&sales, &rates(0.08)), (15.0, 0));
+    }
+
+    #[test]
+    fn amounts_pinned_in_another_base_are_converted() {
+        let mut pinned = sale(250.0, Currency::CZK);
+        pinned.pin_base_amount(Some(&rates(0.04)));
+        assert_eq!(pinned.base_currency, Some(Currency::EUR));
+
+        let czk = ExchangeRates::new("CZK", &HashMap::new()).unwrap();
+        assert_eq!(base_revenue(&[pinned], &czk), (250.0, 0));
+    }
+
+    #[test]
+    fn converts_unpinned_sales_at_current_rates() {
+        let sales = [sale(250.0, Currency::CZK), sale(1.0, Currency::USD)];
+
+        assert_eq!(base_revenue(&sales, &rates(0.08)), (20.0, 1));
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use log::warn;
+
+const ELLIPSIS: char = '…';
+
+/// Cuts the text to at most `max_chars` characters including the ellipsis,
+/// never splitting a character.
+pub fn truncate_chars(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_owned();
+    }
+
+    let mut truncated: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.push(ELLIPSIS);
+    truncated
+}
+
+/// Like [`truncate_chars`], but counts UTF-16 code units as Telegram does for
+/// the length of messages.
+pub fn truncate_utf16(text: &str, max_units: usize) -> String {
+    if text.encode_utf16().count() <= max_units {
+        return text.to_owned();
+    }
+
+    let budget = max_units.saturating_sub(ELLIPSIS.len_utf16());
+    let mut used = 0;
+    let mut truncated: String = text
+        .chars()
+        .take_while(|c| {
+            used += c.len_utf16();
+            used <= budget
+        })
+        .collect();
+    truncated.push(ELLIPSIS);
+    truncated
+}
+
+/// Like [`truncate_chars`], but cuts at the last word boundary when there is one.
+pub fn truncate_words(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_owned();
+    }
+
+    let prefix: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    // The word right after the cut may still be whole
+    let next_is_boundary = text
+        .chars()
+        .nth(max_chars.saturating_sub(1))
+        .map_or(true, char::is_whitespace);
+
+    let cut = if next_is_boundary {
+        prefix.trim_end()
+    } else {
+        match prefix.rfind(char::is_whitespace) {
+            Some(i) => prefix[..i].trim_end(),
+            None => &prefix,
+        }
+    };
+
+    // A single overlong word is cut anyway
+    let cut = if cut.is_empty() { &prefix } else { cut };
+    format!("{}{}", cut, ELLIPSIS)
+}
+
+/// Fills the `{key}` placeholders of a localized text with the passed values,
+/// other braces are kept as they are. A placeholder without a value is logged
+/// and dropped along with the space before it, so an untranslated key phrase
+/// used as the text never shows a raw `{key}` to the user.
+pub fn fill_placeholders(template: &str, args: &HashMap<String, String>) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start].replace("}}", "}"));
+        let after = &rest[start + 1..];
+
+        if let Some(escaped) = after.strip_prefix('{') {
+            filled.push('{');
+            rest = escaped;
+            continue;
+        }
+
+        let placeholder = after
+            .find('}')
+            .map(|end| (&after[..end], &after[end + 1..]));
+        let key = placeholder.and_then(|(placeholder, rest)| {
+            let key = placeholder.split(':').next().unwrap_or_default();
+            let is_key = !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_');
+            is_key.then_some((key, rest))
+        });
+
+        // Not a placeholder, e.g. a lone brace
+        let Some((key, after)) = key else {
+            filled.push('{');
+            rest = after;
+            continue;
+        };
+
+        match args.get(key) {
+            Some(value) => filled.push_str(value),
+            None => {
+                warn!(
+                    "No value for the {{{}}} placeholder of \"{}\"",
+                    key, template
+                );
+                if filled.ends_with(' ') && after.starts_with([' ', ',', '.', '!', '?']) {
+                    filled.pop();
+                }
+            }
+        }
+        rest = after;
+    }
+    filled.push_str(&rest.replace("}}", "}"));
+
+    filled
+}
+
+/// Keys of the `{key}` placeholders in the order they first appear, escaped
+/// braces and anything else in braces aren't placeholders.
+pub fn placeholders(template: &str) -> Vec<String> {
+    let mut keys: Vec<String> = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        if let Some(escaped) = after.strip_prefix('{') {
+            rest = escaped;
+            continue;
+        }
+
+        let Some(end) = after.find('}') else {
+            break;
+        };
+        let key = after[..end].split(':').next().unwrap_or_default();
+        let is_key = !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_');
+        if is_key && !keys.iter().any(|k| k == key) {
+            keys.push(key.to_owned());
+        }
+        rest = if is_key { &after[end + 1..] } else { after };
+    }
+
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncates_by_chars() {
+        assert_eq!(truncate_chars("short", 10), "short");
+        assert_eq!(truncate_chars("привет мир", 5), "прив…");
+    }
+
+    #[test]
+    fn truncates_by_utf16_units() {
+        assert_eq!(truncate_utf16("short", 5), "short");
+        assert_eq!(truncate_utf16("привет мир", 5), "прив…");
+
+        // Each emoji takes two units and is never split
+        let text = "➕🍵🍵🍵";
+        assert_eq!(text.encode_utf16().count(), 7);
+        assert_eq!(truncate_utf16(text, 7), text);
+        assert_eq!(truncate_utf16(text, 6), "➕🍵🍵…");
+        assert_eq!(truncate_utf16(text, 5), "➕🍵…");
+        assert!(truncate_utf16(text, 4).encode_utf16().count() <= 4);
+    }
+
+    #[test]
+    fn truncates_on_word_boundary() {
+        let description = "A warm woolen hat knitted by hand in the mountains of Slovakia";
+
+        assert_eq!(truncate_words(description, 100), description);
+        assert_eq!(truncate_words(description, 20), "A warm woolen hat…");
+        assert_eq!(truncate_words(description, 19), "A warm woolen hat…");
+        assert_eq!(truncate_words(description, 18), "A warm woolen hat…");
+        assert_eq!(truncate_words(description, 17), "A warm woolen…");
+        assert_eq!(truncate_words("Supercalifragilistic", 6), "Super…");
+
+        for limit in 1..description.len() {
+            assert!(truncate_words(description, limit).chars().count() <= limit);
+        }
+    }
+
+    fn args(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn fills_fallback_with_args() {
+        let key_phrase = "The seller has priced the {name}, you can now pay for it!";
+
+        assert_eq!(
+            fill_placeholders(key_phrase, &args(&[("name", "Tea")])),
+            "The seller has priced the Tea, you can now pay for it!"
+        );
+        assert_eq!(
+            fill_placeholders("{amount}x {name}", &args(&[("amount", "2")])),
+            "2x "
+        );
+    }
+
+    #[test]
+    fn strips_placeholders_without_args() {
+        assert_eq!(
+            fill_placeholders(
+                "The seller has priced the {name}, you can now pay for it!",
+                &args(&[])
+            ),
+            "The seller has priced the, you can now pay for it!"
+        );
+        assert_eq!(
+            fill_placeholders("Hello {name} and welcome", &args(&[])),
+            "Hello and welcome"
+        );
+        assert_eq!(fill_placeholders("Cancel", &args(&[])), "Cancel");
+        assert_eq!(
+            fill_placeholders("Use {{name}} or { as is", &args(&[])),
+            "Use {name} or { as is"
+        );
+    }
+
+    #[test]
+    fn keeps_braces_which_are_not_placeholders() {
+        assert_eq!(
+            fill_placeholders(
+                "Send {\"amount\": {amount}} to {name}",
+                &args(&[("amount", "2"), ("name", "Tea"), ("unused", "-")])
+            ),
+            "Send {\"amount\": 2} to Tea"
+        );
+        assert_eq!(
+            fill_placeholders("{ {name} }", &args(&[("name", "Tea")])),
+            "{ Tea }"
+        );
+    }
+
+    #[test]
+    fn lists_placeholders() {
+        assert_eq!(
+            placeholders("{amount}x {name}, {amount:>3} {{escaped}} { as is"),
+            vec!["amount".to_owned(), "name".to_owned()]
+        );
+        assert!(placeholders("Cancel").is_empty());
+    }
+}
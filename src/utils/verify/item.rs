@@ -1,6 +1,6 @@
 use std::fmt::Display;
 
-use crate::{utils::row::Row, BoxedError};
+use crate::{entries::MalformedItem, utils::row::Row, BoxedError};
 
 use super::*;
 
@@ -20,8 +20,8 @@ impl<'a, N: ErrorNotifier> VerifyDriver<'a, N> {
         }
 
         let item = match self.warehouse.items.by_id.get_with_row(&id) {
-            Some(product) => product,
-            None => {
+            Some(item) if !item.1.deleted => item,
+            _ => {
                 self.notify("Sorry, we can't find your item.").await?;
                 return Err(Box::new(VerifyItemError::NotFound(id)));
             }
@@ -36,10 +36,24 @@ impl<'a, N: ErrorNotifier> VerifyDriver<'a, N> {
     }
 }
 
+impl<'a, N: ErrorNotifier> Verify<'a, N, Row<Item>> {
+    /// Makes sure the item has everything needed to show it, see [`Item::check_renderable`].
+    pub async fn is_renderable(mut self) -> Result<Self> {
+        if let Err(e) = self.obj.check_renderable() {
+            self.notify("Sorry, this item is not available right now.")
+                .await?;
+            return Err(Box::new(VerifyItemError::Malformed(self.obj.id.clone(), e)));
+        }
+
+        Ok(self)
+    }
+}
+
 #[derive(Debug)]
 pub enum VerifyItemError {
     WarehouseRefreshError(BoxedError),
     NotFound(String),
+    Malformed(String, MalformedItem),
 }
 
 impl Display for VerifyItemError {
@@ -51,6 +65,9 @@ impl Display for VerifyItemError {
             VerifyItemError::NotFound(id) => {
                 write!(f, "Item with id {} not found", id)
             }
+            VerifyItemError::Malformed(id, e) => {
+                write!(f, "Item with id {} is malformed: {}", id, e)
+            }
         }
     }
 }
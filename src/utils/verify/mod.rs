@@ -6,9 +6,12 @@ pub mod sale;
 pub mod user;
 pub mod user_meta;
 
+pub use user_meta::send_or_forget;
+
 use crate::prelude::*;
 use async_trait::async_trait;
 use futures::Future;
+use log::warn;
 use teloxide::{prelude::*, types::ParseMode};
 
 pub mod prelude {
@@ -17,7 +20,8 @@ pub mod prelude {
     pub use super::payload::*;
     pub use super::product::*;
     pub use super::{
-        verify_with_callback, verify_with_chat, verify_with_chat_user, verify_with_pre_checkout,
+        send_or_forget, verify_silently, verify_with_callback, verify_with_chat,
+        verify_with_chat_user, verify_with_pre_checkout,
     };
 }
 
@@ -105,11 +109,26 @@ impl<'a, N: ErrorNotifier, T> Verify<'a, N, T> {
     pub async fn notify(&mut self, err: &str) -> Result<()> {
         self.notifier.notify(self.warehouse, err).await
     }
+
+    /// Tells the user about an error which is already in their language.
+    pub async fn notify_localized(&mut self, text: &str) -> Result<()> {
+        self.notifier.send(self.warehouse, text).await
+    }
 }
 
 #[async_trait]
-pub trait ErrorNotifier {
-    async fn notify(&self, warehouse: &mut Warehouse, err: &str) -> Result<()>;
+pub trait ErrorNotifier: Sync {
+    /// Language the user is told about the errors in.
+    fn lang_code(&self) -> &str;
+
+    /// Sends the text as it is.
+    async fn send(&self, warehouse: &mut Warehouse, text: &str) -> Result<()>;
+
+    /// Translates the error by its key phrase and sends it.
+    async fn notify(&self, warehouse: &mut Warehouse, err: &str) -> Result<()> {
+        let text = localize!(warehouse, self.lang_code(), err);
+        self.send(warehouse, &text).await
+    }
 }
 
 pub struct BotMessageWithKbNotifier<'a> {
@@ -121,14 +140,22 @@ pub struct BotMessageWithKbNotifier<'a> {
 
 #[async_trait]
 impl<'a> ErrorNotifier for BotMessageWithKbNotifier<'a> {
-    async fn notify(&self, warehouse: &mut Warehouse, err: &str) -> Result<()> {
+    fn lang_code(&self) -> &str {
+        self.lang_code
+    }
+
+    async fn send(&self, warehouse: &mut Warehouse, text: &str) -> Result<()> {
         self.bot
-            .send_message(self.chat_id, err)
+            .send_message(self.chat_id, text)
             .parse_mode(ParseMode::Html)
             .reply_markup(user_keyboard(warehouse, self.lang_code, self.user).await)
             .await?;
         Ok(())
     }
+
+    async fn notify(&self, warehouse: &mut Warehouse, err: &str) -> Result<()> {
+        self.send(warehouse, err).await
+    }
 }
 
 pub fn verify_with_chat_user<'a>(
@@ -157,9 +184,13 @@ pub struct BotMessageNotifier<'a> {
 
 #[async_trait]
 impl<'a> ErrorNotifier for BotMessageNotifier<'a> {
-    async fn notify(&self, warehouse: &mut Warehouse, err: &str) -> Result<()> {
+    fn lang_code(&self) -> &str {
+        &self.lang_code
+    }
+
+    async fn send(&self, _: &mut Warehouse, text: &str) -> Result<()> {
         self.bot
-            .send_message(self.chat_id, localize!(warehouse, &self.lang_code, err))
+            .send_message(self.chat_id, text)
             .parse_mode(ParseMode::Html)
             .await?;
         Ok(())
@@ -204,10 +235,14 @@ pub struct BotCallbackNotifier<'a> {
 
 #[async_trait]
 impl<'a> ErrorNotifier for BotCallbackNotifier<'a> {
-    async fn notify(&self, warehouse: &mut Warehouse, err: &str) -> Result<()> {
+    fn lang_code(&self) -> &str {
+        &self.lang_code
+    }
+
+    async fn send(&self, _: &mut Warehouse, text: &str) -> Result<()> {
         self.bot
             .answer_callback_query(self.id.clone())
-            .text(localize!(warehouse, &self.lang_code, err))
+            .text(text)
             .show_alert(true)
             .await?;
         Ok(())
@@ -238,10 +273,14 @@ pub struct BotPreCheckoutNotifier<'a> {
 
 #[async_trait]
 impl<'a> ErrorNotifier for BotPreCheckoutNotifier<'a> {
-    async fn notify(&self, warehouse: &mut Warehouse, err: &str) -> Result<()> {
+    fn lang_code(&self) -> &str {
+        &self.lang_code
+    }
+
+    async fn send(&self, _: &mut Warehouse, text: &str) -> Result<()> {
         self.bot
             .answer_pre_checkout_query(&self.id, false)
-            .error_message(localize!(warehouse, &self.lang_code, err))
+            .error_message(text)
             .await?;
         Ok(())
     }
@@ -263,3 +302,30 @@ pub fn verify_with_pre_checkout<'a>(
         warehouse,
     }
 }
+
+/// Keeps the errors to the log. For the best-effort steps after a change is
+/// committed, the flow tells the user about their failure itself.
+pub struct LogNotifier;
+
+#[async_trait]
+impl ErrorNotifier for LogNotifier {
+    fn lang_code(&self) -> &str {
+        "en"
+    }
+
+    async fn send(&self, _: &mut Warehouse, text: &str) -> Result<()> {
+        warn!("Best-effort step failed: {}", text);
+        Ok(())
+    }
+
+    async fn notify(&self, warehouse: &mut Warehouse, err: &str) -> Result<()> {
+        self.send(warehouse, err).await
+    }
+}
+
+pub fn verify_silently(warehouse: &mut Warehouse) -> VerifyDriver<'_, LogNotifier> {
+    VerifyDriver {
+        notifier: LogNotifier,
+        warehouse,
+    }
+}
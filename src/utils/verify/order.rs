@@ -1,6 +1,15 @@
 use std::fmt::{Debug, Display};
 
-use crate::{utils::row::Row, BoxedError};
+use chrono::Utc;
+
+use crate::{
+    utils::{
+        labels::display_stage,
+        lifecycle::{complete_once, update_metas},
+        row::Row,
+    },
+    BoxedError,
+};
 
 use super::*;
 
@@ -64,23 +73,34 @@ impl<'a, N: ErrorNotifier> Verify<'a, N, Row<Order>> {
         self.into_driver().product_by_id(product_id).await
     }
 
+    /// The product of the order, even if it was deleted after the order was placed.
+    pub async fn verify_product_any(self) -> Result<Verify<'a, N, Row<Product>>> {
+        let product_id = self.obj.product_id();
+        self.into_driver().product_by_id_any(product_id).await
+    }
+
     pub async fn verfy_sale(self) -> Result<Verify<'a, N, Row<Sale>>> {
         let (order, driver) = self.split();
 
-        let (product, driver) = driver.with(order.clone()).verify_product().await?.split();
+        let (product, driver) = driver
+            .with(order.clone())
+            .verify_product_any()
+            .await?
+            .split();
 
-        let sale = order.entry.into_sale(product.share);
+        let sale = order.entry.into_sale(product.share, Utc::now());
 
         Ok(driver.with(Row::new(0, sale)))
     }
 
     pub async fn stage_is_not(mut self, stage: OrderStage) -> Result<Verify<'a, N, Row<Order>>> {
         if self.obj.stage == stage {
-            self.notify(&format!(
-                "Sorry, this order is already in the {:?} stage.",
-                stage
-            ))
-            .await?;
+            let lang_code = self.notifier.lang_code().to_owned();
+            let text = localize!(self.warehouse, &lang_code,
+                "Sorry, this order is already in the {stage} stage.",
+                "stage" => display_stage(self.warehouse, &lang_code, &stage).await
+            );
+            self.notify_localized(&text).await?;
 
             return Err(Box::new(VerifyOrderError::WrongStage(self.obj, stage)));
         }
@@ -90,11 +110,12 @@ impl<'a, N: ErrorNotifier> Verify<'a, N, Row<Order>> {
 
     pub async fn stage_is(mut self, stage: OrderStage) -> Result<Verify<'a, N, Row<Order>>> {
         if self.obj.stage != stage {
-            self.notify(&format!(
-                "Sorry, this order is not in the {:?} stage.",
-                stage
-            ))
-            .await?;
+            let lang_code = self.notifier.lang_code().to_owned();
+            let text = localize!(self.warehouse, &lang_code,
+                "Sorry, this order is not in the {stage} stage.",
+                "stage" => display_stage(self.warehouse, &lang_code, &stage).await
+            );
+            self.notify_localized(&text).await?;
 
             return Err(Box::new(VerifyOrderError::WrongStage(self.obj, stage)));
         }
@@ -224,6 +245,138 @@ impl<'a, N: ErrorNotifier> Verify<'a, N, Row<Order>> {
         Ok(self)
     }
 
+    pub async fn invoice_matches(
+        mut self,
+        currency: Currency,
+        total_amount: i32,
+    ) -> Result<Verify<'a, N, Row<Order>>> {
+        if !self.obj.matches_invoice(&currency, total_amount) {
+            self.notify(concat!(
+                "Sorry, the invoice doesn't match your order. ",
+                "The product may have changed over time, please try again."
+            ))
+            .await?;
+
+            return Err(Box::new(VerifyOrderError::WrongCost(self.obj)));
+        }
+
+        Ok(self)
+    }
+
+    pub async fn mark_paid(mut self) -> Result<Verify<'a, N, Row<Order>>> {
+        if !self.obj.mark_paid() {
+            let stage = self.obj.stage.clone();
+            return Err(Box::new(VerifyOrderError::WrongStage(self.obj, stage)));
+        }
+
+        self.update(|_| ()).await
+    }
+
+    /// Completes the order, counts its amount as sold, closes it in the metas
+    /// of both participants and publishes the sale.
+    pub async fn finish(self) -> Result<Verify<'a, N, Row<Order>>> {
+        self.complete()
+            .await?
+            .branch(|v| async move {
+                let amount = v.result().amount;
+                v.verify_product_any()
+                    .await?
+                    .update(|p| p.count_sold(amount))
+                    .await
+            })
+            .await?
+            .close_in_metas()
+            .await?
+            .branch(|v| async move { v.verfy_sale().await?.publish().await })
+            .await
+    }
+
+    /// Completes the order at most once. The order is re-read from the cache and
+    /// checked against the completions made by this process, so an attempt racing
+    /// with a stale read of the sheet is rejected.
+    pub async fn complete(mut self) -> Result<Verify<'a, N, Row<Order>>> {
+        if let Some(current) = self.warehouse.orders.by_id.get_with_row(&self.obj.id) {
+            self.obj = current.clone().into();
+        }
+
+        if !complete_once(
+            &mut self.obj,
+            &mut self.warehouse.completed_orders,
+            Utc::now(),
+        ) {
+            self.notify("Sorry, this order is already closed.").await?;
+            let stage = self.obj.stage.clone();
+            return Err(Box::new(VerifyOrderError::WrongStage(self.obj, stage)));
+        }
+
+        let result = self
+            .warehouse
+            .orders
+            .update_one(self.obj.row, &self.obj.entry)
+            .await;
+
+        if let Err(e) = result {
+            self.warehouse.completed_orders.remove(&self.obj.id);
+            self.notify("We are unable to update your order. Please try again later.")
+                .await?;
+
+            return Err(Box::new(VerifyOrderError::WarehouseUpdateError(Box::new(
+                e,
+            ))));
+        }
+
+        Ok(self)
+    }
+
+    /// Moves the order from pending to completed for both participants, their
+    /// metas are written as a unit.
+    pub async fn close_in_metas(self) -> Result<Verify<'a, N, Row<Order>>> {
+        let (order, driver) = self.split();
+
+        let (customer, driver) = driver
+            .with(order.clone())
+            .verify_customer()
+            .await?
+            .verify_meta()
+            .await?
+            .has_pending_order_id(&order.id)
+            .await?
+            .split();
+
+        let (merchant, driver) = driver
+            .with(order.clone())
+            .verify_merchant()
+            .await?
+            .verify_meta()
+            .await?
+            .has_pending_order_id(&order.id)
+            .await?
+            .split();
+
+        let mut customer_after = customer.entry.clone();
+        customer_after.close_order(&order.id);
+        let mut merchant_after = merchant.entry.clone();
+        merchant_after.close_order(&order.id);
+
+        let mut verify = driver.with(order);
+        let result = update_metas(
+            &mut verify.warehouse.users_meta,
+            (customer.row, &customer.entry, &customer_after),
+            (merchant.row, &merchant.entry, &merchant_after),
+        )
+        .await;
+
+        if let Err(e) = result {
+            verify
+                .notify("We are unable to update your order. Please try again later.")
+                .await?;
+
+            return Err(Box::new(VerifyOrderError::WarehouseUpdateError(e)));
+        }
+
+        Ok(verify)
+    }
+
     pub async fn merchant_has_order(self) -> Result<Verify<'a, N, Row<Order>>> {
         let (order, driver) = self.split();
         Ok(driver
@@ -321,3 +474,86 @@ impl Display for VerifyOrderError {
 }
 
 impl std::error::Error for VerifyOrderError {}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Arc};
+
+    use chrono::Utc;
+    use tokio::sync::RwLock;
+
+    use super::*;
+    use crate::entries::fixtures;
+
+    #[derive(Default)]
+    struct State {
+        completed: HashSet<OrderId>,
+        sales: Vec<Sale>,
+    }
+
+    fn order() -> Order {
+        Order {
+            stage: OrderStage::Paid,
+            amount: 2.0,
+            cost: 10.0,
+            ..fixtures::order()
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_completion_records_one_sale() {
+        let state = Arc::new(RwLock::new(State::default()));
+
+        // Both attempts read the order before either of them wrote it back
+        let attempts: Vec<_> = (0..2)
+            .map(|_| {
+                let state = state.clone();
+                let mut order = order();
+                tokio::spawn(async move {
+                    let mut state = state.write().await;
+                    if complete_once(&mut order, &mut state.completed, Utc::now()) {
+                        state.sales.push(order.clone().into_sale(0.0, Utc::now()));
+                    }
+                    order
+                })
+            })
+            .collect();
+
+        let mut stages = vec![];
+        for attempt in attempts {
+            stages.push(attempt.await.unwrap().stage);
+        }
+        stages.sort_by_key(|stage| *stage == OrderStage::Completed);
+
+        assert_eq!(state.read().await.sales.len(), 1);
+        assert_eq!(stages, vec![OrderStage::Paid, OrderStage::Completed]);
+    }
+
+    #[test]
+    fn redelivered_completion_is_noop() {
+        let mut order = order();
+        assert!(complete_once(&mut order, &mut HashSet::new(), Utc::now()));
+        let completed_at = order.completed_at;
+        assert!(completed_at.is_some());
+
+        // A redelivered callback after a restart, the process forgot the completion
+        order.stage = OrderStage::Paid;
+        let mut completed = HashSet::new();
+        assert!(!complete_once(&mut order, &mut completed, Utc::now()));
+        assert_eq!(order.stage, OrderStage::Paid);
+        assert_eq!(order.completed_at, completed_at);
+        assert!(completed.is_empty());
+    }
+
+    #[test]
+    fn closed_orders_are_not_completed() {
+        let mut completed = HashSet::new();
+
+        for stage in [OrderStage::Completed, OrderStage::Cancelled] {
+            let mut order = order();
+            order.stage = stage;
+            assert!(!complete_once(&mut order, &mut completed, Utc::now()));
+        }
+        assert!(completed.is_empty());
+    }
+}
@@ -54,6 +54,11 @@ impl<'a, N: ErrorNotifier> Verify<'a, N, Payload> {
     }
 
     pub async fn verify_product(mut self) -> Result<Verify<'a, N, Row<Product>>> {
+        // Buttons sent before the keys were introduced only carry the id
+        if let Some(key) = self.obj.product_key.clone() {
+            return self.into_driver().product_by_key(&key).await;
+        }
+
         let Some(product_id) = self.obj.product_id else {
             self
                 .notify("Sorry, we can't identify your item, please try again.")
@@ -91,10 +96,14 @@ impl<'a, N: ErrorNotifier> Verify<'a, N, Payload> {
         let (payload, driver) = self.split();
         let (product, driver) = driver.with(payload.clone()).verify_product().await?.split();
 
-        driver
-            .with(payload)
-            .amount_in_range(0..(product.amount_left + 1))
-            .await
+        let mut _self = driver.with(payload).has_amount().await?;
+
+        if _self.obj.amount.unwrap() as f64 > product.amount_left {
+            _self.notify("Incorrect amount, please try again").await?;
+            return Err(Box::new(VerifyPayloadError::NoAmount(_self.obj)));
+        }
+
+        Ok(_self)
     }
 
     pub async fn amount_in_range(self, range: Range<u32>) -> Result<Verify<'a, N, Payload>> {
@@ -1,11 +1,28 @@
 use std::fmt::Display;
 
-use crate::{utils::row::Row, BoxedError};
+use crate::{
+    utils::{payload::ProductKey, row::Row},
+    BoxedError,
+};
 
 use super::*;
 
 impl<'a, N: ErrorNotifier> VerifyDriver<'a, N> {
-    pub async fn product_by_id(mut self, id: ProductId) -> Result<Verify<'a, N, Row<Product>>> {
+    pub async fn product_by_id(self, id: ProductId) -> Result<Verify<'a, N, Row<Product>>> {
+        self.find_product(id, false).await
+    }
+
+    /// Finds the product even if it was deleted, the orders placed
+    /// before that still have to be finished or cancelled.
+    pub async fn product_by_id_any(self, id: ProductId) -> Result<Verify<'a, N, Row<Product>>> {
+        self.find_product(id, true).await
+    }
+
+    async fn find_product(
+        mut self,
+        id: ProductId,
+        with_deleted: bool,
+    ) -> Result<Verify<'a, N, Row<Product>>> {
         match self.warehouse.products.refresh().await {
             Ok(_) => (),
             Err(e) => {
@@ -18,8 +35,8 @@ impl<'a, N: ErrorNotifier> VerifyDriver<'a, N> {
         }
 
         let product = match self.warehouse.products.by_id.get_with_row(&id) {
-            Some(product) => product,
-            None => {
+            Some(product) if with_deleted || !product.1.deleted => product,
+            _ => {
                 self.notify("Sorry, we can't find your product.").await?;
                 return Err(Box::new(VerifyProductError::NotFound(id)));
             }
@@ -32,6 +49,43 @@ impl<'a, N: ErrorNotifier> VerifyDriver<'a, N> {
             warehouse: self.warehouse,
         })
     }
+
+    /// Finds the exact listing of the merchant, see [`ProductKey`].
+    pub async fn product_by_key(mut self, key: &ProductKey) -> Result<Verify<'a, N, Row<Product>>> {
+        match self.warehouse.products.refresh().await {
+            Ok(_) => (),
+            Err(e) => {
+                self.notify("We are having technical difficulties, please try again later.")
+                    .await?;
+                return Err(Box::new(VerifyProductError::WarehouseRefreshError(
+                    Box::new(e),
+                )));
+            }
+        }
+
+        let listings = self
+            .warehouse
+            .products
+            .by_item_id
+            .group(&key.item_id)
+            .map(|listings| listings.as_slice())
+            .unwrap_or_default();
+
+        let product = match key.find_in(listings) {
+            Some(product) if !product.1.deleted => product,
+            _ => {
+                self.notify("Sorry, we can't find your product.").await?;
+                return Err(Box::new(VerifyProductError::NotFound(key.id())));
+            }
+        }
+        .clone();
+
+        Ok(Verify {
+            notifier: self.notifier,
+            obj: product.into(),
+            warehouse: self.warehouse,
+        })
+    }
 }
 
 impl<'a, N: ErrorNotifier> Verify<'a, N, Row<Product>> {
@@ -78,8 +132,9 @@ impl<'a, N: ErrorNotifier> Verify<'a, N, Row<Product>> {
         driver.user_by_name(&product.merchant).await
     }
 
-    pub async fn left_at_least(mut self, amount: u32) -> Result<Verify<'a, N, Row<Product>>> {
-        if self.obj.amount_left < amount {
+    /// Counts the oversell allowance in, see [`Product::can_sell`].
+    pub async fn left_at_least(mut self, amount: f64) -> Result<Verify<'a, N, Row<Product>>> {
+        if !self.obj.can_sell(amount) {
             self.notify("Sorry, we don't have enough of this product.")
                 .await?;
 
@@ -89,8 +144,36 @@ impl<'a, N: ErrorNotifier> Verify<'a, N, Row<Product>> {
         Ok(self)
     }
 
+    /// Like `left_at_least`, but also accounts for the amounts held by open orders.
+    pub async fn available_at_least(mut self, amount: f64) -> Result<Verify<'a, N, Row<Product>>> {
+        let available = match self.warehouse.available_stock(self.obj.id()) {
+            Ok(available) => available.unwrap_or_else(|| self.obj.sellable()),
+            Err(e) => {
+                self.notify("We are having technical difficulties, please try again later.")
+                    .await?;
+                return Err(Box::new(VerifyProductError::WarehouseRefreshError(e)));
+            }
+        };
+
+        if available < amount {
+            self.notify("Sorry, we don't have enough of this product.")
+                .await?;
+
+            return Err(Box::new(VerifyProductError::NotEnough(self.obj, amount)));
+        }
+
+        Ok(self)
+    }
+
+    /// At least the smallest amount of the product is left.
+    pub async fn in_stock(self) -> Result<Verify<'a, N, Row<Product>>> {
+        let smallest = self.obj.unit.smallest();
+        self.available_at_least(smallest).await
+    }
+
     pub async fn visible_to_user(mut self, user: &User) -> Result<Verify<'a, N, Row<Product>>> {
-        if !self.obj.is_visible_to(user) {
+        let paused = self.warehouse.paused_merchants(user);
+        if !self.obj.is_visible_to(user) || paused.contains(&self.obj.merchant) {
             self.notify("I'm sorry, that product is missing.").await?;
 
             return Err(Box::new(VerifyProductError::InvisibleForUser(
@@ -118,7 +201,8 @@ impl<'a, N: ErrorNotifier> Verify<'a, N, Row<Product>> {
         let product = self.obj.clone();
         let (user, mut driver) = self.into_driver().user_by_name(username).await?.split();
 
-        if !product.is_visible_to(&user.entry) {
+        let paused = driver.warehouse.paused_merchants(&user.entry);
+        if !product.is_visible_to(&user.entry) || paused.contains(&product.merchant) {
             driver.notify("I'm sorry, that product is missing.").await?;
 
             return Err(Box::new(VerifyProductError::InvisibleForUser(
@@ -223,7 +307,7 @@ pub enum VerifyProductError {
     WarehouseRefreshError(BoxedError),
     WarehouseUpdateError(BoxedError),
     NotFound(ProductId),
-    NotEnough(Row<Product>, u32),
+    NotEnough(Row<Product>, f64),
     NoUsername(Row<Product>),
     InvisibleForUser(Row<Product>, User),
     InvalidMerchant(Row<Product>, String),
@@ -1,5 +1,10 @@
 use std::fmt::Display;
 
+use teloxide::{
+    requests::{Output, Request},
+    ApiError, RequestError,
+};
+
 use crate::{utils::row::Row, BoxedError};
 
 use super::*;
@@ -46,39 +51,29 @@ impl<'a, N: ErrorNotifier> Verify<'a, N, Row<UserMeta>> {
         Ok(self)
     }
 
-    pub async fn complete_order_by_id(
-        self,
-        order_id: &OrderId,
+    /// Forgets the chat ID if sending to it failed because the chat is gone,
+    /// the user is re-linked on their next message to the bot.
+    pub async fn forget_stale_chat_id(
+        mut self,
+        err: &RequestError,
     ) -> Result<Verify<'a, N, Row<UserMeta>>> {
-        let mut _self = self.has_pending_order_id(&order_id).await?;
-
-        let pos = _self
-            .obj
-            .pending_orders
-            .iter()
-            .position(|other_id| other_id == order_id)
-            .unwrap();
-
-        let order_id = _self.obj.pending_orders.swap_remove(pos);
-        _self.obj.completed_orders.push(order_id);
+        if !clear_stale_chat_id(&mut self.obj, err) {
+            return Ok(self);
+        }
 
-        let result = _self
+        let result = self
             .warehouse
             .users_meta
-            .update_one(_self.obj.row, &_self.obj.entry)
+            .update_one(self.obj.row, &self.obj.entry)
             .await;
 
-        if result.is_err() {
-            _self
-                .notify("We are unable to update your order. Please try again later.")
-                .await?;
-
+        if let Err(e) = result {
             return Err(Box::new(VerifyUserMetaError::WarehouseUpdateError(
-                Box::new(result.unwrap_err()),
+                Box::new(e),
             )));
         }
 
-        Ok(_self)
+        Ok(self)
     }
 
     pub async fn has_pending_order_id(
@@ -98,6 +93,51 @@ impl<'a, N: ErrorNotifier> Verify<'a, N, Row<UserMeta>> {
     }
 }
 
+/// Sends the request to the user's chat, forgetting the chat ID when the chat
+/// is gone, see [`Verify::forget_stale_chat_id`].
+pub async fn send_or_forget<R>(
+    warehouse: &mut Warehouse,
+    user_name: &str,
+    request: R,
+) -> Result<Output<R>>
+where
+    R: Request<Err = RequestError>,
+{
+    let sent = request.send().await;
+
+    if let Err(e) = &sent {
+        verify_silently(warehouse)
+            .user_meta_by_name(user_name)
+            .await?
+            .forget_stale_chat_id(e)
+            .await?;
+    }
+
+    Ok(sent?)
+}
+
+fn is_chat_unreachable(err: &RequestError) -> bool {
+    matches!(
+        err,
+        RequestError::Api(
+            ApiError::BotBlocked
+                | ApiError::ChatNotFound
+                | ApiError::UserNotFound
+                | ApiError::UserDeactivated
+                | ApiError::CantInitiateConversation
+        )
+    )
+}
+
+fn clear_stale_chat_id(meta: &mut UserMeta, err: &RequestError) -> bool {
+    if meta.chat_id.is_none() || !is_chat_unreachable(err) {
+        return false;
+    }
+
+    meta.chat_id = None;
+    true
+}
+
 #[derive(Debug)]
 pub enum VerifyUserMetaError {
     WarehouseRefreshError(BoxedError),
@@ -132,3 +172,51 @@ impl Display for VerifyUserMetaError {
 }
 
 impl std::error::Error for VerifyUserMetaError {}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::types::ChatId;
+
+    use super::*;
+    use crate::entries::fixtures;
+
+    fn meta() -> UserMeta {
+        UserMeta {
+            name: "user".to_owned(),
+            chat_id: Some(ChatId(42)),
+            ..fixtures::user_meta()
+        }
+    }
+
+    #[test]
+    fn stale_chat_id_is_cleared() {
+        let mut meta = meta();
+
+        assert!(clear_stale_chat_id(
+            &mut meta,
+            &RequestError::Api(ApiError::ChatNotFound)
+        ));
+        assert_eq!(meta.chat_id, None);
+
+        // Nothing left to clear
+        assert!(!clear_stale_chat_id(
+            &mut meta,
+            &RequestError::Api(ApiError::BotBlocked)
+        ));
+    }
+
+    #[test]
+    fn other_failures_keep_chat_id() {
+        let mut meta = meta();
+
+        assert!(!clear_stale_chat_id(
+            &mut meta,
+            &RequestError::Api(ApiError::MessageTextIsEmpty)
+        ));
+        assert!(!clear_stale_chat_id(
+            &mut meta,
+            &RequestError::Api(ApiError::Unknown("Bad Request".to_owned()))
+        ));
+        assert_eq!(meta.chat_id, Some(ChatId(42)));
+    }
+}
@@ -1,26 +1,61 @@
-use chrono::Duration;
+use chrono::{Duration, Utc};
 use google_sheets4::{
     hyper, hyper_rustls,
     oauth2::{ServiceAccountAuthenticator, ServiceAccountKey},
     Sheets,
 };
-use std::sync::Arc;
+use log::warn;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::Arc,
+};
 use tables::{
-    cache::Cache, clock::Clock, fork, google_sheets::Sheet, in_mem::InMemTable, index::Index,
+    cache::{Cache, Detached, Fetched},
+    clock::Clock,
+    fork,
+    google_sheets::{sheet_ids::SheetIds, wal::Wal, Sheet},
+    in_mem::InMemTable,
+    index::Index,
+    prelude::*,
     search::Searcher,
 };
-use tokio::sync::RwLock;
+use teloxide::types::InlineQueryResult;
+use tokio::sync::{Mutex, MutexGuard, OwnedRwLockWriteGuard, RwLock};
+use url::Url;
 
-use crate::{config::Config, entries::*};
+use crate::{
+    bootstrap::{spreadsheet_error, BootstrapError},
+    common::{default_keyboard_layout, ButtonSpec},
+    config::Config,
+    entries::*,
+    inline::cache::InlineCache,
+    utils::lifecycle::prune_completed,
+};
 
 pub mod prelude {
-    pub use super::{SharedWarehouse, Warehouse};
+    pub use super::{available_of, LockedWarehouse, SharedWarehouse, Warehouse};
     pub use tables::prelude::*;
 }
 
 pub type Table<E> = Cache<Clock<Sheet<E>>, InMemTable<E>>;
 pub type SharedWarehouse = Arc<RwLock<Warehouse>>;
 
+/// The warehouse locked for the handling of an update, the handlers of the
+/// update share the guard instead of locking again.
+#[derive(Clone)]
+pub struct LockedWarehouse(Arc<Mutex<OwnedRwLockWriteGuard<Warehouse>>>);
+
+impl LockedWarehouse {
+    pub fn new(guard: OwnedRwLockWriteGuard<Warehouse>) -> Self {
+        Self(Arc::new(Mutex::new(guard)))
+    }
+
+    pub async fn lock(&self) -> MutexGuard<'_, OwnedRwLockWriteGuard<Warehouse>> {
+        self.0.lock().await
+    }
+}
+
 fork!(items_table: ItemTable[Item], 
       inner: Table<Item>,
       by_id: Index<String, Item>, 
@@ -65,9 +100,335 @@ pub struct Warehouse {
     pub replenishments: Table<Replenishment>,
     pub writeoffs: Table<Writeoff>,
     pub localization: LocalizationTable,
+    pub orders_archive: Option<Table<Order>>,
+    pub sales_archive: Option<Table<Sale>>,
+    pub usage_log: Option<Table<UsageRecord>>,
+    pub reports: Option<Table<ProductReport>>,
+    pub archive_after: Duration,
+    pub restock_window: Duration,
+    pub restock_horizon: Duration,
+    pub deadstock_window: Duration,
+    /// Share of products created without an explicit one.
+    pub default_share: f32,
+    /// Rates new orders and sales are converted into the base currency with.
+    pub exchange_rates: Option<ExchangeRates>,
+    pub keyboard_layout: Vec<Vec<ButtonSpec>>,
+    /// Orders completed by this process, guards against stale sheet reads.
+    pub completed_orders: HashSet<OrderId>,
+    /// Recent inline answers by (username, query) with their cache time.
+    pub inline_cache: InlineCache<(String, String), (Vec<InlineQueryResult>, u32)>,
+    /// Placeholder thumbnail for items with a broken image URL.
+    pub fallback_image: Option<Url>,
+    /// Seconds Telegram may cache product listings, see [`Warehouse::listing_cache_time`].
+    pub listing_cache_time: u32,
+    /// Characters of the item description shown in inline results.
+    pub inline_description_limit: usize,
+    /// Currencies the global provider token takes, any when None.
+    pub provider_currencies: Option<Vec<Currency>>,
+}
+
+/// Sums the amounts held by open orders per product.
+pub fn reserved_by_orders<'a>(
+    orders: impl IntoIterator<Item = &'a Order>,
+) -> HashMap<ProductId, f64> {
+    let mut reserved = HashMap::new();
+
+    for order in orders.into_iter().filter(|order| order.is_open()) {
+        *reserved.entry(order.product_id()).or_insert(0.0) += order.amount;
+    }
+
+    reserved
+}
+
+/// Cache time which doesn't outlive the freshness of the data.
+pub fn clamp_cache_time(cache_time: u32, freshness: Duration) -> u32 {
+    let freshness = freshness.num_seconds().clamp(0, u32::MAX as i64) as u32;
+    cache_time.min(freshness)
+}
+
+/// Distinct (merchant, item_id) pairs hashed to the same id, `by_id` would merge
+/// them into one product. The hash is passed in, see [`Product::id_from`].
+pub fn id_collisions<'a>(
+    products: impl IntoIterator<Item = &'a Product>,
+    id_from: impl Fn(&str, &str) -> ProductId,
+) -> Vec<(ProductId, Vec<(String, String)>)> {
+    let mut keys = HashMap::<ProductId, BTreeSet<(String, String)>>::new();
+
+    for product in products {
+        keys.entry(id_from(&product.merchant, &product.item_id))
+            .or_default()
+            .insert((product.merchant.clone(), product.item_id.clone()));
+    }
+
+    let mut collisions: Vec<_> = keys
+        .into_iter()
+        .filter(|(_, keys)| keys.len() > 1)
+        .map(|(id, keys)| (id, keys.into_iter().collect()))
+        .collect();
+    collisions.sort();
+    collisions
+}
+
+/// Stock of the product, the oversell allowance included, minus the amounts
+/// held by its open orders.
+pub fn available_of(product: &Product, reserved: &HashMap<ProductId, f64>) -> f64 {
+    let reserved = reserved.get(&product.id()).cloned().unwrap_or(0.0);
+    round_amount(product.sellable() - reserved).max(0.0)
+}
+
+type Origin<E> = Clock<Sheet<E>>;
+
+/// Origins of the tables [`Warehouse::refresh_all`] reads, fetched without the warehouse.
+pub struct DetachedTables {
+    products: Detached<Origin<Product>>,
+    items: Detached<Origin<Item>>,
+    users: Detached<Origin<User>>,
+    users_meta: Detached<Origin<UserMeta>>,
+    merchants: Detached<Origin<Merchant>>,
+    orders: Detached<Origin<Order>>,
+    localization: Detached<Origin<Localization>>,
+}
+
+pub struct FetchedTables {
+    products: Fetched<Origin<Product>, Product>,
+    items: Fetched<Origin<Item>, Item>,
+    users: Fetched<Origin<User>, User>,
+    users_meta: Fetched<Origin<UserMeta>, UserMeta>,
+    merchants: Fetched<Origin<Merchant>, Merchant>,
+    orders: Fetched<Origin<Order>, Order>,
+    localization: Fetched<Origin<Localization>, Localization>,
+}
+
+impl DetachedTables {
+    pub async fn fetch(self) -> crate::Result<FetchedTables> {
+        Ok(FetchedTables {
+            products: self.products.fetch().await?,
+            items: self.items.fetch().await?,
+            users: self.users.fetch().await?,
+            users_meta: self.users_meta.fetch().await?,
+            merchants: self.merchants.fetch().await?,
+            orders: self.orders.fetch().await?,
+            localization: self.localization.fetch().await?,
+        })
+    }
+}
+
+impl Warehouse {
+    /// Merchants on vacation whose products are hidden from the user.
+    pub fn paused_merchants(&self, user: &User) -> HashSet<String> {
+        self.merchants
+            .by_name
+            .all()
+            .flat_map(|(_, merchants)| merchants)
+            .filter(|(_, merchant)| merchant.hides_products_from(user))
+            .map(|(_, merchant)| merchant.name.clone())
+            .collect()
+    }
+
+    /// The merchant as customers are told to contact them, see [`Merchant::contact`].
+    pub fn merchant_contact(&self, name: &str) -> String {
+        self.merchants
+            .by_name
+            .get(&name.to_owned())
+            .map_or_else(|| format!("@{}", name), Merchant::contact)
+    }
+
+    /// Minimum order value of the merchant in the currency of the order, when
+    /// the total falls short of it.
+    pub async fn min_order_shortfall(
+        &mut self,
+        merchant: &str,
+        total: f64,
+        currency: Currency,
+    ) -> crate::Result<Option<f64>> {
+        self.merchants.refresh().await?;
+        let rates = self.exchange_rates.as_ref();
+
+        let Some(merchant) = self.merchants.by_name.get(&merchant.to_owned()) else {
+            return Ok(None);
+        };
+        if merchant.min_order_value.is_some()
+            && merchant.min_order_value_in(&currency, rates).is_none()
+        {
+            warn!(
+                "No rate to convert the minimum order of {} into {:?}",
+                merchant.name, currency
+            );
+        }
+
+        if merchant.accepts_order_value(total, &currency, rates) {
+            return Ok(None);
+        }
+        Ok(merchant.min_order_value_in(&currency, rates))
+    }
+
+    /// Telegram cache time of product listings, so customers never see results
+    /// older than the products table.
+    pub fn listing_cache_time(&self) -> u32 {
+        let freshness = self.products.inner.origin().freshness(Utc::now());
+        clamp_cache_time(self.listing_cache_time, freshness)
+    }
+
+    /// Amounts held by open orders per product, based on the cached orders.
+    pub fn reserved_stock(&mut self) -> crate::Result<HashMap<ProductId, f64>> {
+        Ok(reserved_by_orders(self.orders.inner.read()?))
+    }
+
+    /// Stock of the product open orders don't hold, see [`available_of`].
+    pub fn available_stock(&mut self, product_id: ProductId) -> crate::Result<Option<f64>> {
+        let reserved = self.reserved_stock()?;
+
+        Ok(self
+            .products
+            .by_id
+            .get(&product_id)
+            .map(|product| available_of(product, &reserved)))
+    }
+
+    /// Drops cached data and fetches every table which is read by the bot.
+    pub async fn refresh_all(&mut self) -> crate::Result<()> {
+        self.products.inner.mark_as_dirty();
+        self.products.refresh().await?;
+        self.items.inner.mark_as_dirty();
+        self.items.refresh().await?;
+        self.users.inner.mark_as_dirty();
+        self.users.refresh().await?;
+        self.users_meta.inner.mark_as_dirty();
+        self.users_meta.refresh().await?;
+        self.merchants.inner.mark_as_dirty();
+        self.merchants.refresh().await?;
+        self.orders.inner.mark_as_dirty();
+        self.orders.refresh().await?;
+        self.localization.inner.mark_as_dirty();
+        self.localization.refresh().await?;
+        self.prune_completed_orders()?;
+        Ok(())
+    }
+
+    /// Drops the completions the cached orders show, see [`prune_completed`].
+    /// Only right after a fetch, before that the cache shows unflushed writes.
+    pub fn prune_completed_orders(&mut self) -> crate::Result<()> {
+        prune_completed(&mut self.completed_orders, self.orders.inner.read()?);
+        Ok(())
+    }
+
+    pub fn detach_tables(&self) -> DetachedTables {
+        DetachedTables {
+            products: self.products.inner.detach(),
+            items: self.items.inner.detach(),
+            users: self.users.inner.detach(),
+            users_meta: self.users_meta.inner.detach(),
+            merchants: self.merchants.inner.detach(),
+            orders: self.orders.inner.detach(),
+            localization: self.localization.inner.detach(),
+        }
+    }
+
+    /// Swaps in the tables fetched by [`DetachedTables::fetch`] and rebuilds their indexes.
+    /// A table written to after the detach keeps its data, returns the number of such tables.
+    pub async fn install_tables(&mut self, fetched: FetchedTables) -> crate::Result<usize> {
+        let mut skipped = 0;
+
+        macro_rules! install {
+            ($($table:ident),+) => {$(
+                let $table = self.$table.inner.install(fetched.$table).await?;
+                if $table {
+                    self.$table.refresh().await?;
+                } else {
+                    skipped += 1;
+                }
+            )+};
+        }
+
+        install!(
+            products,
+            items,
+            users,
+            users_meta,
+            merchants,
+            orders,
+            localization
+        );
+        // Kept orders may hold completions the sheet doesn't show yet
+        if orders {
+            self.prune_completed_orders()?;
+        }
+        Ok(skipped)
+    }
+
+    /// Fresh sales along with the archived ones, if there is an archive.
+    pub async fn all_sales(&mut self) -> crate::Result<Vec<Sale>> {
+        self.sales.refresh().await?;
+        let mut sales: Vec<_> = self.sales.read()?.cloned().collect();
+
+        if let Some(sales_archive) = self.sales_archive.as_mut() {
+            sales_archive.refresh().await?;
+            sales.extend(sales_archive.read()?.cloned());
+        }
+
+        Ok(sales)
+    }
+
+    /// Warns about products sharing an id, returns the number of the ids.
+    pub async fn warn_id_collisions(&mut self) -> crate::Result<usize> {
+        self.products.refresh().await?;
+
+        let collisions = id_collisions(self.products.inner.read()?, Product::id_from);
+        for (id, keys) in &collisions {
+            warn!(
+                "Products {:?} share the id {}, only one of them can be found by it",
+                keys, id
+            );
+        }
+
+        Ok(collisions.len())
+    }
+
+    /// Re-issues sheet writes which didn't land before the last shutdown.
+    pub async fn replay_wal(&mut self) -> crate::Result<usize> {
+        let mut count = 0;
+        count += replay_table(&mut self.items.inner).await?;
+        count += replay_table(&mut self.products.inner).await?;
+        count += replay_table(&mut self.users.inner).await?;
+        count += replay_table(&mut self.users_meta.inner).await?;
+        count += replay_table(&mut self.merchants.inner).await?;
+        count += replay_table(&mut self.orders.inner).await?;
+        count += replay_table(&mut self.localization.inner).await?;
+        count += replay_table(&mut self.sales).await?;
+        count += replay_table(&mut self.replenishments).await?;
+        count += replay_table(&mut self.writeoffs).await?;
+        if let Some(orders_archive) = self.orders_archive.as_mut() {
+            count += replay_table(orders_archive).await?;
+        }
+        if let Some(sales_archive) = self.sales_archive.as_mut() {
+            count += replay_table(sales_archive).await?;
+        }
+        if let Some(usage_log) = self.usage_log.as_mut() {
+            count += replay_table(usage_log).await?;
+        }
+        if let Some(reports) = self.reports.as_mut() {
+            count += replay_table(reports).await?;
+        }
+
+        if count > 0 {
+            self.refresh_all().await?;
+        }
+        Ok(count)
+    }
+}
+
+async fn replay_table<E>(table: &mut Table<E>) -> crate::Result<usize>
+where
+    E: Serialize + DeserializeOwned + Send + Sync + Clone + 'static,
+{
+    Ok(table.origin_mut().inner_mut().replay_wal().await?)
 }
 
-pub async fn build(config: &Config, creds: ServiceAccountKey) -> SharedWarehouse {
+pub async fn build(
+    config: &mut Config,
+    creds: ServiceAccountKey,
+) -> std::result::Result<SharedWarehouse, BootstrapError> {
+    let service_account = creds.client_email.clone();
     let auth = ServiceAccountAuthenticator::builder(creds)
         .build()
         .await
@@ -82,9 +443,42 @@ pub async fn build(config: &Config, creds: ServiceAccountKey) -> SharedWarehouse
 
     let hub = Arc::new(Sheets::new(hyper::Client::builder().build(connector), auth));
 
+    // A wrong id silently sends writes to another tab
+    let sheet_ids = SheetIds::fetch(&hub, &config.sheets.spreadsheet_id)
+        .await
+        .map_err(|e| spreadsheet_error(e, &service_account, &config.sheets.spreadsheet_id))?;
+    for args in config.sheets.sheet_args_mut() {
+        sheet_ids.resolve(args).map_err(BootstrapError::SheetIds)?;
+    }
+
     let clock_ttl = Duration::weeks(config.sheets.clock_ttl as i64);
+    let wal = config
+        .sheets
+        .wal_path
+        .as_ref()
+        .map(Wal::open)
+        .transpose()
+        .map_err(BootstrapError::Wal)?;
+
+    let default_share = config.sheets.default_share.unwrap_or(0.0);
+    let default_share = Product::new_share(None, default_share)
+        .ok_or(BootstrapError::DefaultShare(default_share))?;
+    let exchange_rates = config
+        .sheets
+        .base_currency
+        .as_deref()
+        .map(|base| ExchangeRates::new(base, config.sheets.exchange_rates.iter().flatten()))
+        .transpose()
+        .map_err(BootstrapError::ExchangeRates)?;
+    let provider_currencies = config
+        .telegram
+        .provider_currencies
+        .as_ref()
+        .map(|codes| codes.iter().map(|code| Currency::parse(code)).collect())
+        .transpose()
+        .map_err(BootstrapError::ProviderCurrencies)?;
 
-    Arc::new(RwLock::new(Warehouse {
+    Ok(Arc::new(RwLock::new(Warehouse {
         items: ItemTable::new(
             Table::new(
                 Clock::new(
@@ -92,7 +486,8 @@ pub async fn build(config: &Config, creds: ServiceAccountKey) -> SharedWarehouse
                         hub.clone(),
                         config.sheets.spreadsheet_id.clone(),
                         config.sheets.items.clone(),
-                    ),
+                    )
+                    .with_wal(wal.clone()),
                     clock_ttl,
                 ),
                 [].into(),
@@ -107,7 +502,9 @@ pub async fn build(config: &Config, creds: ServiceAccountKey) -> SharedWarehouse
                         hub.clone(),
                         config.sheets.spreadsheet_id.clone(),
                         config.sheets.products.clone(),
-                    ),
+                    )
+                    .with_wal(wal.clone())
+                    .with_validation(),
                     clock_ttl,
                 ),
                 [].into(),
@@ -123,7 +520,8 @@ pub async fn build(config: &Config, creds: ServiceAccountKey) -> SharedWarehouse
                         hub.clone(),
                         config.sheets.spreadsheet_id.clone(),
                         config.sheets.users.clone(),
-                    ),
+                    )
+                    .with_wal(wal.clone()),
                     clock_ttl,
                 ),
                 [].into(),
@@ -137,7 +535,8 @@ pub async fn build(config: &Config, creds: ServiceAccountKey) -> SharedWarehouse
                         hub.clone(),
                         config.sheets.spreadsheet_id.clone(),
                         config.sheets.users_meta.clone(),
-                    ),
+                    )
+                    .with_wal(wal.clone()),
                     clock_ttl,
                 ),
                 [].into(),
@@ -151,7 +550,8 @@ pub async fn build(config: &Config, creds: ServiceAccountKey) -> SharedWarehouse
                         hub.clone(),
                         config.sheets.spreadsheet_id.clone(),
                         config.sheets.merchants.clone(),
-                    ),
+                    )
+                    .with_wal(wal.clone()),
                     clock_ttl,
                 ),
                 [].into(),
@@ -165,7 +565,8 @@ pub async fn build(config: &Config, creds: ServiceAccountKey) -> SharedWarehouse
                     hub.clone(),
                     config.sheets.spreadsheet_id.clone(),
                     config.sheets.sales.clone(),
-                ),
+                )
+                .with_wal(wal.clone()),
                 clock_ttl,
             ),
             [].into(),
@@ -177,7 +578,8 @@ pub async fn build(config: &Config, creds: ServiceAccountKey) -> SharedWarehouse
                         hub.clone(),
                         config.sheets.spreadsheet_id.clone(),
                         config.sheets.orders.clone(),
-                    ),
+                    )
+                    .with_wal(wal.clone()),
                     clock_ttl,
                 ),
                 [].into(),
@@ -191,7 +593,8 @@ pub async fn build(config: &Config, creds: ServiceAccountKey) -> SharedWarehouse
                     hub.clone(),
                     config.sheets.spreadsheet_id.clone(),
                     config.sheets.replenishments.clone(),
-                ),
+                )
+                .with_wal(wal.clone()),
                 clock_ttl,
             ),
             [].into(),
@@ -202,7 +605,8 @@ pub async fn build(config: &Config, creds: ServiceAccountKey) -> SharedWarehouse
                     hub.clone(),
                     config.sheets.spreadsheet_id.clone(),
                     config.sheets.writeoffs.clone(),
-                ),
+                )
+                .with_wal(wal.clone()),
                 clock_ttl,
             ),
             [].into(),
@@ -214,12 +618,166 @@ pub async fn build(config: &Config, creds: ServiceAccountKey) -> SharedWarehouse
                         hub.clone(),
                         config.sheets.spreadsheet_id.clone(),
                         config.sheets.localization.clone(),
-                    ),
+                    )
+                    .with_wal(wal.clone()),
                     clock_ttl,
                 ),
                 [].into(),
             ),
             by_key_phrase: Index::new(|_, loc| loc.key_phrase.clone()),
         },
-    }))
+        orders_archive: config.sheets.orders_archive.clone().map(|args| {
+            Table::new(
+                Clock::new(
+                    Sheet::new(hub.clone(), config.sheets.spreadsheet_id.clone(), args)
+                        .with_wal(wal.clone()),
+                    clock_ttl,
+                ),
+                [].into(),
+            )
+        }),
+        sales_archive: config.sheets.sales_archive.clone().map(|args| {
+            Table::new(
+                Clock::new(
+                    Sheet::new(hub.clone(), config.sheets.spreadsheet_id.clone(), args)
+                        .with_wal(wal.clone()),
+                    clock_ttl,
+                ),
+                [].into(),
+            )
+        }),
+        usage_log: config.sheets.usage.clone().map(|args| {
+            Table::new(
+                Clock::new(
+                    Sheet::new(hub.clone(), config.sheets.spreadsheet_id.clone(), args)
+                        .with_wal(wal.clone()),
+                    clock_ttl,
+                ),
+                [].into(),
+            )
+        }),
+        reports: config.sheets.reports.clone().map(|args| {
+            Table::new(
+                Clock::new(
+                    Sheet::new(hub.clone(), config.sheets.spreadsheet_id.clone(), args)
+                        .with_wal(wal.clone()),
+                    clock_ttl,
+                ),
+                [].into(),
+            )
+        }),
+        archive_after: Duration::days(config.sheets.archive_after.unwrap_or(90) as i64),
+        restock_window: Duration::days(config.sheets.restock_window.unwrap_or(30) as i64),
+        restock_horizon: Duration::days(config.sheets.restock_horizon.unwrap_or(14) as i64),
+        deadstock_window: Duration::days(config.sheets.deadstock_window.unwrap_or(60) as i64),
+        default_share,
+        exchange_rates,
+        keyboard_layout: config
+            .telegram
+            .keyboard
+            .clone()
+            .unwrap_or_else(default_keyboard_layout),
+        completed_orders: HashSet::new(),
+        inline_cache: InlineCache::new(
+            std::time::Duration::from_secs(config.telegram.inline_cache_ttl.unwrap_or(3)),
+            config.telegram.inline_cache_size.unwrap_or(512),
+        ),
+        fallback_image: config
+            .telegram
+            .fallback_image_url
+            .as_deref()
+            .and_then(|url| url.parse().ok()),
+        listing_cache_time: config.telegram.listing_cache_time.unwrap_or(60),
+        inline_description_limit: config.telegram.inline_description_limit.unwrap_or(120),
+        provider_currencies,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::fixtures;
+
+    fn product(merchant: &str, amount_left: f64) -> Product {
+        Product {
+            merchant: merchant.to_owned(),
+            amount_granted: amount_left,
+            amount_left,
+            ..fixtures::product()
+        }
+    }
+
+    fn order(merchant: &str, stage: OrderStage, amount: f64) -> Order {
+        Order {
+            merchant: merchant.to_owned(),
+            stage,
+            amount,
+            ..fixtures::order()
+        }
+    }
+
+    fn available(product: &Product, orders: &[Order]) -> f64 {
+        available_of(product, &reserved_by_orders(orders))
+    }
+
+    #[test]
+    fn available_without_orders() {
+        let mut listing = product("merchant", 5.0);
+        assert_eq!(available(&listing, &[]), 5.0);
+
+        // Backorders use up the allowance
+        listing.oversell_allowance = 2.0;
+        assert_eq!(available(&listing, &[]), 7.0);
+        assert!(listing.take_for_sale(6.5));
+        assert_eq!(available(&listing, &[]), 0.5);
+    }
+
+    #[test]
+    fn available_with_open_orders() {
+        let orders = [
+            order("merchant", OrderStage::WaitForPayment, 2.0),
+            order("merchant", OrderStage::Negotiated, 0.5),
+            order("merchant", OrderStage::Paid, 1.0),
+            order("merchant", OrderStage::Completed, 1.0),
+            order("merchant", OrderStage::Cancelled, 1.0),
+            order("other", OrderStage::WaitForPayment, 4.0),
+        ];
+
+        assert_eq!(available(&product("merchant", 5.0), &orders), 2.5);
+        assert_eq!(available(&product("merchant", 2.0), &orders), 0.0);
+        assert_eq!(available(&product("other", 3.0), &orders), 0.0);
+    }
+
+    #[test]
+    fn cache_time_clamped_to_freshness() {
+        assert_eq!(clamp_cache_time(60, Duration::weeks(1)), 60);
+        assert_eq!(clamp_cache_time(60, Duration::seconds(15)), 15);
+        assert_eq!(clamp_cache_time(60, Duration::zero()), 0);
+        assert_eq!(clamp_cache_time(0, Duration::seconds(15)), 0);
+    }
+
+    #[test]
+    fn colliding_product_ids_are_reported() {
+        let products = [
+            product("alice", 1.0),
+            product("bob", 1.0),
+            product("carol", 1.0),
+            // The same listing twice isn't a collision
+            product("alice", 2.0),
+        ];
+        // Names of the same length collide
+        let id_from = |merchant: &str, _: &str| merchant.len() as ProductId;
+
+        assert_eq!(
+            id_collisions(&products, id_from),
+            vec![(
+                5,
+                vec![
+                    ("alice".to_owned(), "item".to_owned()),
+                    ("carol".to_owned(), "item".to_owned()),
+                ]
+            )]
+        );
+        assert!(id_collisions(&products, Product::id_from).is_empty());
+    }
 }